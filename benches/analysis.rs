@@ -2,7 +2,9 @@ use std::time::Duration;
 
 use divan::counter::BytesCount;
 use dogstatsd_utils::{
-    analysis::analyze_msgs, dogstatsdreader::DogStatsDReader,
+    analysis::analyze_msgs,
+    corpus::{CorpusGenerator, CorpusProfile, DEFAULT_CORPUS_SEED},
+    dogstatsdreader::DogStatsDReader,
 };
 use lading_payload::dogstatsd::{self, KindWeights, MetricWeights, ValueConf};
 use rand::{rngs::SmallRng, SeedableRng};
@@ -62,3 +64,20 @@ fn analysis_throughput(bencher: divan::Bencher) {
             analyze_msgs(&mut reader).unwrap();
         })
 }
+
+/// Same corpora as `dsd-bench-corpus`, trimmed to 1MB
+#[divan::bench(args = CorpusProfile::ALL)]
+fn analysis_corpus_throughput(bencher: divan::Bencher, profile: CorpusProfile) {
+    let mut corpus = Vec::new();
+    CorpusGenerator::new(profile, DEFAULT_CORPUS_SEED)
+        .write_text(1024 * 1024, &mut corpus)
+        .unwrap();
+
+    bencher
+        .counter(BytesCount::usize(corpus.len()))
+        .bench_local(|| {
+            let cursor = std::io::Cursor::new(&corpus);
+            let mut reader = DogStatsDReader::new(cursor).unwrap();
+            analyze_msgs(&mut reader).unwrap();
+        })
+}
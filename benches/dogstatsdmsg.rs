@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use divan::counter::BytesCount;
-use dogstatsd_utils::dogstatsdmsg::DogStatsDMsg;
+use dogstatsd_utils::dogstatsdmsg::{parse_metric, DogStatsDMsg};
 use lading_payload::dogstatsd::{self, KindWeights, MetricWeights, ValueConf};
 use rand::{rngs::SmallRng, SeedableRng};
 
@@ -22,6 +22,30 @@ fn dogstatsdmsg_parsing() {
     compute(divan::black_box(10))
 }
 
+#[divan::bench]
+fn dogstatsdmsg_parsing_metric_only() {
+    fn compute(n: u64) {
+        for msg in vec!["my.metric:1|g#some:tag"].repeat(n as usize) {
+            let msg = parse_metric(msg);
+            let _ = msg;
+        }
+    }
+
+    compute(divan::black_box(10))
+}
+
+#[divan::bench]
+fn dogstatsdmsg_parsing_from_bytes() {
+    fn compute(n: u64) {
+        for msg in vec![b"my.metric:1|g#some:tag".as_slice()].repeat(n as usize) {
+            let msg = DogStatsDMsg::from_bytes(msg);
+            let _ = msg;
+        }
+    }
+
+    compute(divan::black_box(10))
+}
+
 #[divan::bench(min_time = Duration::from_secs(10))]
 fn dogstatsdmsg_parsing_throughput(bencher: divan::Bencher) {
     let mut rng = SmallRng::seed_from_u64(34512423); // todo use random seed
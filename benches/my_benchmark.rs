@@ -192,6 +192,25 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             }
         })
     });
+    c.bench_function("replay parsing by ref -- more msgs and more lines", |b| {
+        b.iter(|| {
+            let mut replay =
+                DogStatsDReplayReader::new(TWELVE_MSG_THREE_LINES).unwrap();
+
+            for _ in 0..3 {
+                black_box(replay.read_msg_ref().unwrap());
+            }
+        })
+    });
+    c.bench_function("utf8 parsing by ref -- four msgs and four lines", |b| {
+        b.iter(|| {
+            let mut reader = Utf8DogStatsDReader::new(FOUR_MSG_FOUR_LINES_UTF8);
+
+            for _ in 0..3 {
+                black_box(reader.read_msg_ref().unwrap());
+            }
+        })
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -1,5 +1,11 @@
 use std::io::Result;
 fn main() -> Result<()> {
-    prost_build::compile_protos(&["src/UnixDogstatsdMsg.proto"], &["src/"])?;
+    // only the replay reader needs the protos, the bare parser doesn't
+    if std::env::var_os("CARGO_FEATURE_FULL").is_some() {
+        prost_build::compile_protos(
+            &["src/UnixDogstatsdMsg.proto", "src/TaggerState.proto"],
+            &["src/"],
+        )?;
+    }
     Ok(())
 }
@@ -0,0 +1,140 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AddrFilterError {
+    #[error("Invalid IP address or network '{0}', eg '10.0.0.1' or '10.0.0.0/8'")]
+    InvalidNetwork(String),
+}
+
+/// An IP address, or a network of them in CIDR notation, eg '10.0.0.0/8'
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = AddrFilterError;
+
+    /// A bare address is a network of just that address
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || AddrFilterError::InvalidNetwork(s.to_string());
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let max_prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_prefix_len)
+                .ok_or_else(invalid)?,
+            None => max_prefix_len,
+        };
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Selects packets by the addresses they were sent from and to, eg one
+/// client's traffic out of a capture of a shared host. A packet must be sent
+/// from one of 'sources' and to one of 'destinations', either may be empty
+/// to match any address.
+#[derive(Debug, Default, Clone)]
+pub struct AddrFilter {
+    pub sources: Vec<IpNetwork>,
+    pub destinations: Vec<IpNetwork>,
+}
+
+impl AddrFilter {
+    /// True when no filter is set and every packet matches
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty() && self.destinations.is_empty()
+    }
+
+    /// Addresses that aren't known, eg of packets that weren't decoded down
+    /// to them, only pass an empty list
+    pub fn matches(&self, source: Option<IpAddr>, destination: Option<IpAddr>) -> bool {
+        let matches = |networks: &[IpNetwork], addr: Option<IpAddr>| {
+            networks.is_empty()
+                || addr.is_some_and(|addr| networks.iter().any(|network| network.contains(addr)))
+        };
+        matches(&self.sources, source) && matches(&self.destinations, destination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn networks_contain_their_addresses() {
+        let network: IpNetwork = "10.1.0.0/16".parse().unwrap();
+        assert!(network.contains("10.1.2.3".parse().unwrap()));
+        assert!(!network.contains("10.2.0.1".parse().unwrap()));
+        assert!(!network.contains("::ffff:10.1.2.3".parse().unwrap()));
+
+        let host: IpNetwork = "fd00::1".parse().unwrap();
+        assert_eq!(host.to_string(), "fd00::1/128");
+        assert!(host.contains("fd00::1".parse().unwrap()));
+        assert!(!host.contains("fd00::2".parse().unwrap()));
+
+        let any: IpNetwork = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains("192.168.1.1".parse().unwrap()));
+
+        for invalid in ["10.0.0.0/33", "fd00::/129", "10.0.0/8", "host", "10.0.0.1/"] {
+            assert!(invalid.parse::<IpNetwork>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn packets_must_match_both_directions() {
+        let client = "10.0.0.1".parse().ok();
+        let agent = "10.0.0.2".parse().ok();
+        let filter = AddrFilter {
+            sources: vec!["10.0.0.1".parse().unwrap(), "fd00::/8".parse().unwrap()],
+            destinations: vec![],
+        };
+        assert!(filter.matches(client, agent));
+        assert!(filter.matches(client, None));
+        assert!(!filter.matches(agent, client));
+        assert!(!filter.matches(None, agent));
+
+        let filter = AddrFilter {
+            destinations: vec!["10.0.0.0/24".parse().unwrap()],
+            ..filter
+        };
+        assert!(filter.matches(client, agent));
+        assert!(!filter.matches(client, "10.0.1.2".parse().ok()));
+        assert!(AddrFilter::default().matches(None, None));
+    }
+}
@@ -1,22 +1,40 @@
 use sketches_ddsketch::{Config, DDSketch};
 
 use std::{
-    collections::{hash_map::RandomState, BTreeSet, HashMap},
-    hash::{BuildHasher, Hasher},
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    hash::Hash,
     io::Write,
+    ops::AddAssign,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use lading_payload::dogstatsd::{KindWeights, MetricWeights};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
-    dogstatsdmsg::{DogStatsDMetricType, DogStatsDMsg, DogStatsDMsgKind},
-    dogstatsdreader::DogStatsDReader,
+    dogstatsdmsg::{
+        context_key, Cardinality, DogStatsDMetricStr, DogStatsDMetricType, DogStatsDMsg,
+        DogStatsDMsgError, DogStatsDMsgKind,
+    },
+    dogstatsdreader::{
+        merge_compression, CompressionAnalytics, DetectedInput, DogStatsDReader, Transport,
+    },
+    hll::HyperLogLog,
+    interner::{DogStatsDInterner, Symbol},
+    visitor::{visit_msgs, MsgMeta, MsgVisitor},
 };
 
+pub use crate::dogstatsdreader::MIXED_CODECS;
+
 type KindCount = (u32, Option<HashMap<DogStatsDMetricType, u32>>);
 type KindMap = HashMap<DogStatsDMsgKind, KindCount>;
 
+#[derive(Serialize, Deserialize)]
 pub struct DogStatsDBatchStats {
     pub name_length: DDSketch,
     pub num_values: DDSketch,
@@ -26,11 +44,100 @@ pub struct DogStatsDBatchStats {
     pub tag_total_length: DDSketch,
     pub num_unicode_tags: DDSketch,
     pub kind: KindMap,
+    /// Total bytes of the raw messages seen for each kind
+    pub kind_bytes: HashMap<DogStatsDMsgKind, u64>,
+    /// Estimated when the analysis was `AnalysisOptions::approximate_cardinality`
     pub num_contexts: u32,
+    /// Number of contexts of every metric name, the names driving `num_contexts`.
+    /// Empty when the analysis was approximate.
+    pub contexts_by_name: HashMap<String, u32>,
+    /// Context keys of every metric name, so `merge` counts a context seen in
+    /// both stats once. Not cached, stats loaded from the cache merge by
+    /// adding up their contexts.
+    #[serde(skip)]
+    pub context_keys: HashMap<String, HashSet<u64>>,
+    /// Take the place of 'context_keys' and 'unique_tags' for `merge` when
+    /// the analysis was approximate, not cached either
+    #[serde(skip)]
+    pub context_sketch: Option<HyperLogLog>,
+    #[serde(skip)]
+    pub tag_sketch: Option<HyperLogLog>,
+    /// Count of each metric type seen for every metric name
+    pub metric_types_by_name: HashMap<String, HashMap<DogStatsDMetricType, u32>>,
+    /// Count of msgs per tag. Empty when the analysis was approximate, see
+    /// `approximate_unique_tags`.
     pub unique_tags: HashMap<String, u32>,
+    /// Estimated number of distinct tags, when the analysis was
+    /// `AnalysisOptions::approximate_cardinality`
+    pub approximate_unique_tags: Option<u64>,
+    /// Count of msgs per host, from `h:` hostnames and `host:` tags
+    pub hostnames: HashMap<String, u32>,
+    /// Count of msgs per kind that do not identify a host
+    pub msgs_missing_host: HashMap<DogStatsDMsgKind, u32>,
+    /// Count of msgs per `card:` cardinality hint, msgs without one aren't counted
+    pub cardinality_hints: HashMap<Cardinality, u32>,
     pub num_msgs_with_multivalue: u32,
+    /// Msgs that were only analyzed after skipping a malformed field
+    pub num_msgs_with_parse_warnings: u32,
+    /// Count of each skipped malformed field, by reason
+    pub parse_warnings: HashMap<String, u32>,
+    /// Msgs that repeat one of their tags. Not a parse warning, as no field
+    /// of these msgs was skipped.
+    pub num_msgs_with_duplicate_tags: u32,
+    /// Msgs that parsed cleanly but look like a client bug, eg a sample rate of 0
+    pub num_suspicious_msgs: u32,
+    /// Count of each suspicious pattern, by reason
+    pub suspicious: HashMap<String, u32>,
+    /// The first msg seen with each suspicious pattern, by reason
+    pub suspicious_samples: HashMap<String, String>,
+    /// Number of distinct raw metric names grouped under each rollup name.
+    /// Merged stats keep the larger count, as raw names of different stats
+    /// may be the same.
+    pub names_absorbed_by_rollup: HashMap<String, u32>,
     pub num_msgs: u32,
     pub reader_analytics: Option<crate::dogstatsdreader::Analytics>,
+    /// What the input was detected as, see `DogStatsDReader::input_type`
+    pub input_type: Option<DetectedInput>,
+    /// Present when the input was compressed. The codec is MIXED_CODECS
+    /// for merged stats of inputs compressed with different codecs.
+    pub compression: Option<CompressionAnalytics>,
+    /// Time spent reading msgs from the reader, includes any decompression
+    pub read_duration: Duration,
+    /// Time spent parsing msgs
+    pub parse_duration: Duration,
+}
+
+/// Share of the analyzed traffic attributed to a single message kind
+#[derive(Serialize, Debug, PartialEq)]
+pub struct KindShare {
+    pub kind: DogStatsDMsgKind,
+    pub count: u32,
+    /// Percentage of all messages read, including ones that failed to parse
+    pub percent_of_msgs: f64,
+    pub bytes: u64,
+    /// Percentage of the bytes of all successfully parsed messages
+    pub percent_of_bytes: f64,
+    /// Only available when the reader knows the duration of the input
+    pub msgs_per_second: Option<f64>,
+    /// Only available when the reader knows the duration of the input
+    pub bytes_per_second: Option<f64>,
+}
+
+/// A metric name with the number of contexts it was emitted with
+#[derive(Serialize, Debug, PartialEq)]
+pub struct NameContexts {
+    pub name: String,
+    pub contexts: u32,
+    /// Percentage of the contexts of all names
+    pub percent_of_contexts: f64,
+}
+
+/// A metric name that was emitted with more than one metric type
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MetricTypeConflict {
+    pub name: String,
+    /// Every type seen for this name with its count, most frequent first
+    pub types: Vec<(DogStatsDMetricType, u32)>,
 }
 
 #[derive(Error, Debug)]
@@ -43,6 +150,101 @@ pub enum Error {
     NotEnoughInfo,
 }
 
+/// Layout of the lading config emitted by `to_lading_config_str_with_schema`.
+/// Pinning one keeps generated configs stable for tools that consume them when
+/// this crate moves to a newer lading.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LadingSchema {
+    /// Ranges as `constant` or `inclusive` maps, the layout of the lading
+    /// this crate is built against
+    #[default]
+    ConfRange,
+    /// Ranges as `<name>_minimum` and `<name>_maximum` pairs, the layout of
+    /// lading releases from before `ConfRange`
+    MinMax,
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown lading config schema '{0}', expected 'confrange' or 'min-max'")]
+pub struct LadingSchemaError(String);
+
+impl FromStr for LadingSchema {
+    type Err = LadingSchemaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "confrange" => Ok(LadingSchema::ConfRange),
+            "min-max" => Ok(LadingSchema::MinMax),
+            _ => Err(LadingSchemaError(s.to_string())),
+        }
+    }
+}
+
+/// Serializes 'config' as the `generators` section of a lading config file,
+/// laid out for the given lading 'schema'
+pub fn lading_config_str(
+    config: lading::generator::Config,
+    schema: LadingSchema,
+) -> Result<String, Error> {
+    #[derive(serde::Serialize)]
+    struct MyConfig {
+        #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+        generators: Vec<lading::generator::Config>,
+    }
+    let wrapped_config = MyConfig {
+        generators: vec![config],
+    };
+
+    match schema {
+        LadingSchema::ConfRange => Ok(serde_yaml::to_string(&wrapped_config)?),
+        LadingSchema::MinMax => {
+            let mut value = serde_yaml::to_value(&wrapped_config)?;
+            flatten_conf_ranges(&mut value);
+            Ok(serde_yaml::to_string(&value)?)
+        }
+    }
+}
+
+/// Rewrites every `name: {constant: x}` and `name: {inclusive: {min, max}}`
+/// in 'value' into `name_minimum` and `name_maximum` keys
+fn flatten_conf_ranges(value: &mut serde_yaml::Value) {
+    match value {
+        serde_yaml::Value::Mapping(mapping) => {
+            let entries = std::mem::take(mapping);
+            for (key, mut value) in entries {
+                match (key.as_str(), conf_range_bounds(&value)) {
+                    (Some(name), Some((min, max))) => {
+                        mapping.insert(format!("{name}_minimum").into(), min);
+                        mapping.insert(format!("{name}_maximum").into(), max);
+                    }
+                    _ => {
+                        flatten_conf_ranges(&mut value);
+                        mapping.insert(key, value);
+                    }
+                }
+            }
+        }
+        serde_yaml::Value::Sequence(values) => values.iter_mut().for_each(flatten_conf_ranges),
+        _ => {}
+    }
+}
+
+/// The bounds of a serialized `ConfRange`, None for anything else
+fn conf_range_bounds(value: &serde_yaml::Value) -> Option<(serde_yaml::Value, serde_yaml::Value)> {
+    let serde_yaml::Value::Mapping(mapping) = value else {
+        return None;
+    };
+    if mapping.len() != 1 {
+        return None;
+    }
+    if let Some(constant) = mapping.get("constant").filter(|v| v.is_number()) {
+        return Some((constant.clone(), constant.clone()));
+    }
+    let inclusive = mapping.get("inclusive")?;
+    let (min, max) = (inclusive.get("min")?, inclusive.get("max")?);
+    (min.is_number() && max.is_number()).then(|| (min.clone(), max.clone()))
+}
+
 /// Given a DDSketch, return a lading_payload::dogstatsd::ConfRange based on the 20th and 80th percentiles
 /// Returns None if sketch is empty or if either percentile would exceed the given T
 fn sketch_to_confrange<T>(sketch: &DDSketch) -> Option<lading_payload::dogstatsd::ConfRange<T>>
@@ -79,6 +281,24 @@ where
     }
 }
 
+/// Adds the counts of 'other' to those of 'counts'
+fn add_counts<K, V>(counts: &mut HashMap<K, V>, other: &HashMap<K, V>)
+where
+    K: Hash + Eq + Clone,
+    V: AddAssign + Copy + Default,
+{
+    for (key, count) in other {
+        *counts.entry(key.clone()).or_default() += *count;
+    }
+}
+
+fn percentage(part: f64, total: f64) -> f64 {
+    if total == 0.0 {
+        return 0.0;
+    }
+    part / total * 100.0
+}
+
 impl DogStatsDBatchStats {
     fn get_metric_weights(&self) -> MetricWeights {
         // metric weights
@@ -156,18 +376,213 @@ impl DogStatsDBatchStats {
         lading_payload::dogstatsd::KindWeights::new(num_metrics, num_events, num_service_checks)
     }
 
+    /// Returns the per-kind breakdown of messages and bytes, largest kind first.
+    /// Rates are only computed when the reader analytics cover a non-zero duration.
+    pub fn kind_shares(&self) -> Vec<KindShare> {
+        let total_bytes: u64 = self.kind_bytes.values().sum();
+        let duration_secs = self
+            .reader_analytics
+            .as_ref()
+            .map(|a| a.duration().as_secs_f64())
+            .filter(|secs| *secs > 0.0);
+
+        let mut shares: Vec<KindShare> = self
+            .kind
+            .iter()
+            .map(|(kind, (count, _))| {
+                let bytes = *self.kind_bytes.get(kind).unwrap_or(&0);
+                KindShare {
+                    kind: *kind,
+                    count: *count,
+                    percent_of_msgs: percentage(*count as f64, self.num_msgs as f64),
+                    bytes,
+                    percent_of_bytes: percentage(bytes as f64, total_bytes as f64),
+                    msgs_per_second: duration_secs.map(|secs| *count as f64 / secs),
+                    bytes_per_second: duration_secs.map(|secs| bytes as f64 / secs),
+                }
+            })
+            .collect();
+        shares.sort_by(|a, b| b.count.cmp(&a.count).then(b.bytes.cmp(&a.bytes)));
+        shares
+    }
+
+    /// Returns every metric name seen with more than one metric type, these
+    /// usually point to emitter bugs and cause type flapping downstream.
+    /// Sorted by total number of msgs for the name, largest first.
+    pub fn metric_type_conflicts(&self) -> Vec<MetricTypeConflict> {
+        let mut conflicts: Vec<MetricTypeConflict> = self
+            .metric_types_by_name
+            .iter()
+            .filter(|(_, types)| types.len() > 1)
+            .map(|(name, types)| {
+                let mut types: Vec<(DogStatsDMetricType, u32)> =
+                    types.iter().map(|(t, cnt)| (*t, *cnt)).collect();
+                types.sort_by_key(|(t, cnt)| (Reverse(*cnt), t.to_string()));
+                MetricTypeConflict {
+                    name: name.clone(),
+                    types,
+                }
+            })
+            .collect();
+        conflicts.sort_by(|a, b| {
+            let a_total: u32 = a.types.iter().map(|(_, cnt)| cnt).sum();
+            let b_total: u32 = b.types.iter().map(|(_, cnt)| cnt).sum();
+            b_total.cmp(&a_total).then(a.name.cmp(&b.name))
+        });
+        conflicts
+    }
+
+    /// Adds in the stats of 'other', eg of another file or of msgs analyzed
+    /// on another thread, so they are reported as one analysis. Tags and
+    /// contexts seen in both are counted once, unless either stats came from
+    /// the cache, which has no record of which they were.
+    pub fn merge(&mut self, other: &DogStatsDBatchStats) -> Result<(), Error> {
+        let was_empty = self.num_msgs == 0;
+        for (sketch, other) in [
+            (&mut self.name_length, &other.name_length),
+            (&mut self.num_values, &other.num_values),
+            (&mut self.value_range, &other.value_range),
+            (&mut self.num_tags, &other.num_tags),
+            (&mut self.tag_total_length, &other.tag_total_length),
+            (&mut self.num_unicode_tags, &other.num_unicode_tags),
+        ] {
+            sketch.merge(other)?;
+        }
+        self.values_that_are_floats += other.values_that_are_floats;
+        for (kind, (count, per_type)) in &other.kind {
+            let (total, total_per_type) = self.kind.entry(*kind).or_insert((0, None));
+            *total += *count;
+            match (total_per_type.as_mut(), per_type) {
+                (Some(total_per_type), Some(per_type)) => add_counts(total_per_type, per_type),
+                (None, Some(per_type)) => *total_per_type = Some(per_type.clone()),
+                _ => {}
+            }
+        }
+        add_counts(&mut self.kind_bytes, &other.kind_bytes);
+
+        // Stats from the cache have counts per name but no keys
+        let has_keys =
+            |stats: &DogStatsDBatchStats| stats.context_keys.len() == stats.contexts_by_name.len();
+        let mergeable_keys = has_keys(self) && has_keys(other);
+        match (&mut self.context_sketch, &other.context_sketch) {
+            (Some(sketch), Some(other)) => {
+                sketch.merge(other);
+                self.num_contexts = u32::try_from(sketch.estimate()).unwrap_or(u32::MAX);
+            }
+            (None, None) if mergeable_keys => {
+                for (name, keys) in &other.context_keys {
+                    self.context_keys
+                        .entry(name.clone())
+                        .or_default()
+                        .extend(keys);
+                }
+                self.contexts_by_name = self
+                    .context_keys
+                    .iter()
+                    .map(|(name, keys)| (name.clone(), keys.len() as u32))
+                    .collect();
+                self.num_contexts = self.contexts_by_name.values().sum();
+            }
+            _ => {
+                // There is no telling which contexts are the same, so the
+                // keys of one side are of no use in later merges either
+                self.context_keys.clear();
+                self.context_sketch = None;
+                self.num_contexts = self.num_contexts.saturating_add(other.num_contexts);
+                add_counts(&mut self.contexts_by_name, &other.contexts_by_name);
+            }
+        }
+        match (&mut self.tag_sketch, &other.tag_sketch) {
+            (Some(sketch), Some(other)) => {
+                sketch.merge(other);
+                self.approximate_unique_tags = Some(sketch.estimate());
+            }
+            (None, None) => {}
+            _ => {
+                self.approximate_unique_tags =
+                    Some(self.num_unique_tags() + other.num_unique_tags());
+                self.tag_sketch = None;
+            }
+        }
+        add_counts(&mut self.unique_tags, &other.unique_tags);
+
+        for (name, types) in &other.metric_types_by_name {
+            add_counts(
+                self.metric_types_by_name.entry(name.clone()).or_default(),
+                types,
+            );
+        }
+        add_counts(&mut self.hostnames, &other.hostnames);
+        add_counts(&mut self.msgs_missing_host, &other.msgs_missing_host);
+        add_counts(&mut self.cardinality_hints, &other.cardinality_hints);
+        self.num_msgs_with_multivalue += other.num_msgs_with_multivalue;
+        self.num_msgs_with_parse_warnings += other.num_msgs_with_parse_warnings;
+        add_counts(&mut self.parse_warnings, &other.parse_warnings);
+        self.num_msgs_with_duplicate_tags += other.num_msgs_with_duplicate_tags;
+        self.num_suspicious_msgs += other.num_suspicious_msgs;
+        add_counts(&mut self.suspicious, &other.suspicious);
+        for (reason, sample) in &other.suspicious_samples {
+            self.suspicious_samples
+                .entry(reason.clone())
+                .or_insert_with(|| sample.clone());
+        }
+        for (name, raw_names) in &other.names_absorbed_by_rollup {
+            let count = self
+                .names_absorbed_by_rollup
+                .entry(name.clone())
+                .or_default();
+            *count = (*count).max(*raw_names);
+        }
+        self.num_msgs += other.num_msgs;
+
+        match (&mut self.reader_analytics, &other.reader_analytics) {
+            (Some(analytics), Some(other)) => analytics.merge(other),
+            (None, Some(other)) => self.reader_analytics = Some(other.clone()),
+            _ => {}
+        }
+        // Like several inputs read as one, merged stats only have an input
+        // type when they all agree
+        if was_empty {
+            self.input_type = other.input_type.clone();
+        } else if self.input_type != other.input_type {
+            self.input_type = None;
+        }
+        merge_compression(&mut self.compression, &other.compression);
+        self.read_duration += other.read_duration;
+        self.parse_duration += other.parse_duration;
+        Ok(())
+    }
+
+    /// Number of distinct tags, estimated when the analysis was approximate
+    pub fn num_unique_tags(&self) -> u64 {
+        self.approximate_unique_tags
+            .unwrap_or(self.unique_tags.len() as u64)
+    }
+
+    /// Returns the number of contexts of every metric name, most contexts
+    /// first, ties broken by name. The first few are usually what to look at
+    /// when there are more contexts than expected.
+    pub fn contexts_per_name(&self) -> Vec<NameContexts> {
+        let mut names: Vec<NameContexts> = self
+            .contexts_by_name
+            .iter()
+            .map(|(name, contexts)| NameContexts {
+                name: name.clone(),
+                contexts: *contexts,
+                percent_of_contexts: percentage(*contexts as f64, self.num_contexts as f64),
+            })
+            .collect();
+        names.sort_by(|a, b| b.contexts.cmp(&a.contexts).then(a.name.cmp(&b.name)));
+        names
+    }
+
     pub fn to_lading_config_str(&self) -> Result<String, Error> {
-        #[derive(serde::Serialize)]
-        struct MyConfig {
-            #[serde(with = "serde_yaml::with::singleton_map_recursive")]
-            generators: Vec<lading::generator::Config>,
-        }
-        let config = self.to_lading_config()?;
-        let wrapped_config = MyConfig {
-            generators: vec![config],
-        };
+        self.to_lading_config_str_with_schema(LadingSchema::default())
+    }
 
-        Ok(serde_yaml::to_string(&wrapped_config)?)
+    /// Like `to_lading_config_str`, but laid out for the given lading 'schema'
+    pub fn to_lading_config_str_with_schema(&self, schema: LadingSchema) -> Result<String, Error> {
+        lading_config_str(self.to_lading_config()?, schema)
     }
 
     pub fn to_lading_config(&self) -> Result<lading::generator::Config, Error> {
@@ -178,6 +593,25 @@ impl DogStatsDBatchStats {
         Ok(generator_config)
     }
 
+    /// Like `to_lading_config`, but for a udp generator sending to 'addr' at
+    /// 'bytes_per_second'. Since the rate is given rather than measured, this
+    /// also works for inputs that don't record when msgs were captured.
+    pub fn to_lading_udp_config(
+        &self,
+        addr: &str,
+        bytes_per_second: byte_unit::Byte,
+    ) -> Result<lading::generator::Config, Error> {
+        let variant = lading_payload::Config::DogStatsD(self.to_lading_payload_config()?);
+        Ok(lading::generator::Config {
+            general: lading::generator::General { id: None },
+            inner: lading::generator::Inner::Udp(crate::dogstatsdreader::lading_udp_config(
+                addr.to_string(),
+                variant,
+                bytes_per_second,
+            )),
+        })
+    }
+
     /// Given a DogStatsDBatchStats, return a lading_
     /// Correctly populates all payload parameters except for sampling
     pub fn to_lading_generator_config(
@@ -228,6 +662,12 @@ impl DogStatsDBatchStats {
         let kind_weights = self.get_kind_weights();
         let metric_weights = self.get_metric_weights();
 
+        // Stream connections need every payload framed by its length
+        let length_prefix_framed = self
+            .reader_analytics
+            .as_ref()
+            .is_some_and(|analytics| matches!(analytics.transport_type, Transport::UnixStream));
+
         let config = lading_payload::dogstatsd::Config {
             contexts: num_contexts,
             kind_weights,
@@ -238,7 +678,7 @@ impl DogStatsDBatchStats {
             tags_per_msg: tags_per_msg.unwrap_or(dsd_config_defaults.tags_per_msg),
             multivalue_pack_probability,
             multivalue_count: multivalue_count.unwrap_or(dsd_config_defaults.multivalue_count),
-            length_prefix_framed: false,
+            length_prefix_framed,
             sampling_range: dsd_config_defaults.sampling_range,
             sampling_probability: dsd_config_defaults.sampling_probability,
             metric_weights,
@@ -267,74 +707,297 @@ where
     }
 }
 
-pub fn analyze_msgs(reader: &mut DogStatsDReader) -> Result<DogStatsDBatchStats, std::io::Error> {
-    let default_config = Config::defaults();
-    let mut msg_stats = DogStatsDBatchStats {
-        name_length: DDSketch::new(default_config),
-        num_values: DDSketch::new(default_config),
-        value_range: DDSketch::new(default_config),
-        values_that_are_floats: 0,
-        num_tags: DDSketch::new(default_config),
-        tag_total_length: DDSketch::new(default_config),
-        num_unicode_tags: DDSketch::new(default_config),
-        kind: HashMap::new(),
-        unique_tags: HashMap::new(),
-        num_contexts: 0,
-        num_msgs: 0,
-        num_msgs_with_multivalue: 0,
-        reader_analytics: None,
-    };
-
-    let mut metric_type_map = HashMap::new();
-    metric_type_map.insert(DogStatsDMetricType::Count, 0);
-    metric_type_map.insert(DogStatsDMetricType::Gauge, 0);
-    metric_type_map.insert(DogStatsDMetricType::Set, 0);
-    metric_type_map.insert(DogStatsDMetricType::Timer, 0);
-    metric_type_map.insert(DogStatsDMetricType::Histogram, 0);
-    metric_type_map.insert(DogStatsDMetricType::Distribution, 0);
-
-    msg_stats.kind.insert(DogStatsDMsgKind::Event, (0, None));
-    msg_stats
-        .kind
-        .insert(DogStatsDMsgKind::ServiceCheck, (0, None));
-    msg_stats
-        .kind
-        .insert(DogStatsDMsgKind::Metric, (0, Some(metric_type_map)));
-
-    let mut tags_seen: HashMap<String, u32> = HashMap::new();
+/// Like `print_msgs`, but each msg is passed through 'transform' before being written
+pub fn print_msgs_transformed<T, F>(reader: &mut DogStatsDReader, mut out: T, mut transform: F)
+where
+    T: Write,
+    F: FnMut(&str) -> Cow<str>,
+{
     let mut line = String::new();
-    let mut context_map: HashMap<u64, u64> = HashMap::new();
-    let hash_builder = RandomState::new();
-    loop {
-        line.clear();
-        let Ok(num_read) = reader.read_msg(&mut line) else {
-            break;
-        };
+    while let Ok(num_read) = reader.read_msg(&mut line) {
         if num_read == 0 {
             // EOF
             break;
         }
+        out.write_all(transform(&line).as_bytes()).unwrap();
+        out.write_all(b"\n").unwrap();
+        line.clear();
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RollupRuleError {
+    #[error("Rollup rules look like 'REGEX=NAME'")]
+    MissingName,
+    #[error("Invalid rollup regex: {0}")]
+    Regex(#[from] regex::Error),
+}
+
+/// Groups metric names with embedded variability under one canonical name,
+/// eg `shard_\d+=shard_N` rolls `queue.depth.shard_42` up into `queue.depth.shard_N`
+#[derive(Debug, Clone)]
+pub struct RollupRule {
+    pub pattern: Regex,
+    /// Replaces the part of the name matched by 'pattern', `$1` style capture
+    /// group references are expanded
+    pub replacement: String,
+}
+
+impl RollupRule {
+    /// The rolled up name, if 'name' matches this rule
+    pub fn apply(&self, name: &str) -> Option<String> {
+        if !self.pattern.is_match(name) {
+            return None;
+        }
+        Some(
+            self.pattern
+                .replace_all(name, self.replacement.as_str())
+                .into_owned(),
+        )
+    }
+}
+
+impl FromStr for RollupRule {
+    type Err = RollupRuleError;
+
+    /// Parses 'REGEX=NAME', split on the last '='
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, replacement) = s.rsplit_once('=').ok_or(RollupRuleError::MissingName)?;
+        Ok(RollupRule {
+            pattern: Regex::new(pattern)?,
+            replacement: replacement.to_string(),
+        })
+    }
+}
+
+/// Options that change what the analysis counts, rather than how it is read
+#[derive(Debug, Default, Clone)]
+pub struct AnalysisOptions {
+    /// When set, metric tags whose key is not listed are ignored
+    pub include_tags: Option<Vec<String>>,
+    /// Metric tags with one of these keys are ignored, takes precedence over include_tags
+    pub exclude_tags: Vec<String>,
+    /// Applied to metric names before they are counted, the first matching rule wins
+    pub rollups: Vec<RollupRule>,
+    /// Estimate the number of unique tags and contexts with HyperLogLog
+    /// sketches, in fixed memory however many there are, rather than keeping
+    /// every one of them. Estimates are within about 1%, and the per tag
+    /// and per name counts that need every one of them are left empty.
+    pub approximate_cardinality: bool,
+}
+
+impl AnalysisOptions {
+    /// Whether 'tag' counts towards tags and contexts. The key of a tag is
+    /// everything before the first ':', or the whole tag if it has no value.
+    pub fn keeps_tag(&self, tag: &str) -> bool {
+        let key = tag.split_once(':').map_or(tag, |(key, _)| key);
+        if self.exclude_tags.iter().any(|k| k == key) {
+            return false;
+        }
+        match self.include_tags {
+            Some(ref include_tags) => include_tags.iter().any(|k| k == key),
+            None => true,
+        }
+    }
+
+    fn filters_tags(&self) -> bool {
+        self.include_tags.is_some() || !self.exclude_tags.is_empty()
+    }
+
+    /// The name 'name' is counted under, if any rollup rule matches it
+    pub fn rollup_name(&self, name: &str) -> Option<String> {
+        self.rollups.iter().find_map(|rule| rule.apply(name))
+    }
+}
+
+pub fn analyze_msgs(reader: &mut DogStatsDReader) -> Result<DogStatsDBatchStats, std::io::Error> {
+    analyze_msgs_with_options(reader, &AnalysisOptions::default())
+}
+
+/// Like `analyze_msgs`, but tag counts, unique tags and contexts only reflect
+/// the metric tags kept by 'options', as if they had been dropped upstream.
+/// Metric names are rolled up by the rules in 'options' before they are
+/// counted per name and per context.
+pub fn analyze_msgs_with_options(
+    reader: &mut DogStatsDReader,
+    options: &AnalysisOptions,
+) -> Result<DogStatsDBatchStats, std::io::Error> {
+    let input_type = reader.input_type();
+    let mut visitor = BatchStatsVisitor::new(options);
+    visit_msgs(reader, &mut [&mut visitor]).map_err(std::io::Error::other)?;
+    let mut stats = visitor.into_stats(reader);
+    stats.input_type = input_type;
+    Ok(stats)
+}
+
+/// The `MsgVisitor` behind `analyze_msgs`, use it directly to compute the
+/// stats in the same pass as other visitors
+pub struct BatchStatsVisitor<'o> {
+    options: &'o AnalysisOptions,
+    msg_stats: DogStatsDBatchStats,
+    // Names and tags repeat across millions of msgs, count them by id and
+    // only turn them back into Strings once the reader is exhausted
+    interner: DogStatsDInterner,
+    tags_seen: HashMap<Symbol, u32>,
+    /// Timestamps of msgs without a capture time are compared to this
+    started_at: Duration,
+    metric_types_by_name: HashMap<Symbol, HashMap<DogStatsDMetricType, u32>>,
+    /// Name of every context seen, by context key
+    context_map: HashMap<u64, Symbol>,
+    rolled_up_names: HashMap<Symbol, HashSet<Symbol>>,
+    /// Take the place of 'tags_seen' and 'context_map' when the cardinality
+    /// is approximated
+    tag_sketch: Option<HyperLogLog>,
+    context_sketch: Option<HyperLogLog>,
+}
+
+impl<'o> BatchStatsVisitor<'o> {
+    pub fn new(options: &'o AnalysisOptions) -> Self {
+        let default_config = Config::defaults();
+        let mut msg_stats = DogStatsDBatchStats {
+            name_length: DDSketch::new(default_config),
+            num_values: DDSketch::new(default_config),
+            value_range: DDSketch::new(default_config),
+            values_that_are_floats: 0,
+            num_tags: DDSketch::new(default_config),
+            tag_total_length: DDSketch::new(default_config),
+            num_unicode_tags: DDSketch::new(default_config),
+            kind: HashMap::new(),
+            kind_bytes: HashMap::new(),
+            unique_tags: HashMap::new(),
+            approximate_unique_tags: None,
+            hostnames: HashMap::new(),
+            msgs_missing_host: HashMap::new(),
+            cardinality_hints: HashMap::new(),
+            num_contexts: 0,
+            contexts_by_name: HashMap::new(),
+            context_keys: HashMap::new(),
+            context_sketch: None,
+            tag_sketch: None,
+            metric_types_by_name: HashMap::new(),
+            num_msgs: 0,
+            num_msgs_with_multivalue: 0,
+            num_msgs_with_parse_warnings: 0,
+            parse_warnings: HashMap::new(),
+            num_msgs_with_duplicate_tags: 0,
+            num_suspicious_msgs: 0,
+            suspicious: HashMap::new(),
+            suspicious_samples: HashMap::new(),
+            names_absorbed_by_rollup: HashMap::new(),
+            reader_analytics: None,
+            input_type: None,
+            compression: None,
+            read_duration: Duration::ZERO,
+            parse_duration: Duration::ZERO,
+        };
+
+        let mut metric_type_map = HashMap::new();
+        metric_type_map.insert(DogStatsDMetricType::Count, 0);
+        metric_type_map.insert(DogStatsDMetricType::Gauge, 0);
+        metric_type_map.insert(DogStatsDMetricType::Set, 0);
+        metric_type_map.insert(DogStatsDMetricType::Timer, 0);
+        metric_type_map.insert(DogStatsDMetricType::Histogram, 0);
+        metric_type_map.insert(DogStatsDMetricType::Distribution, 0);
+
+        msg_stats.kind.insert(DogStatsDMsgKind::Event, (0, None));
+        msg_stats
+            .kind
+            .insert(DogStatsDMsgKind::ServiceCheck, (0, None));
+        msg_stats
+            .kind
+            .insert(DogStatsDMsgKind::Metric, (0, Some(metric_type_map)));
+
+        Self {
+            options,
+            msg_stats,
+            interner: DogStatsDInterner::new(),
+            tags_seen: HashMap::new(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            metric_types_by_name: HashMap::new(),
+            context_map: HashMap::new(),
+            rolled_up_names: HashMap::new(),
+            tag_sketch: options.approximate_cardinality.then(HyperLogLog::new),
+            context_sketch: options.approximate_cardinality.then(HyperLogLog::new),
+        }
+    }
+
+    /// The stats of every msg visited so far, along with the analytics of
+    /// 'reader' they were read from
+    pub fn into_stats(self, reader: &mut DogStatsDReader) -> DogStatsDBatchStats {
+        let Self {
+            mut msg_stats,
+            interner,
+            tags_seen,
+            metric_types_by_name,
+            context_map,
+            rolled_up_names,
+            tag_sketch,
+            context_sketch,
+            ..
+        } = self;
+        // Have read through the entire reader, lets try to grab the final "Analytics" if it exists
+        msg_stats.reader_analytics = reader
+            .get_analytics()
+            .expect("Error getting analytics from reader");
+        msg_stats.compression = reader.compression_analytics();
+        msg_stats.unique_tags = tags_seen
+            .into_iter()
+            .map(|(tag, count)| (interner.resolve(tag).to_string(), count))
+            .collect();
+        msg_stats.metric_types_by_name = metric_types_by_name
+            .into_iter()
+            .map(|(name, types)| (interner.resolve(name).to_string(), types))
+            .collect();
+        msg_stats.approximate_unique_tags = tag_sketch.as_ref().map(HyperLogLog::estimate);
+        msg_stats.num_contexts = match context_sketch {
+            Some(ref sketch) => u32::try_from(sketch.estimate()).unwrap_or(u32::MAX),
+            None => context_map.len() as u32,
+        };
+        msg_stats.tag_sketch = tag_sketch;
+        msg_stats.context_sketch = context_sketch;
+        let mut context_keys: HashMap<Symbol, HashSet<u64>> = HashMap::new();
+        for (key, name) in context_map {
+            context_keys.entry(name).or_default().insert(key);
+        }
+        msg_stats.context_keys = context_keys
+            .into_iter()
+            .map(|(name, keys)| (interner.resolve(name).to_string(), keys))
+            .collect();
+        msg_stats.contexts_by_name = msg_stats
+            .context_keys
+            .iter()
+            .map(|(name, keys)| (name.clone(), keys.len() as u32))
+            .collect();
+        msg_stats.names_absorbed_by_rollup = rolled_up_names
+            .into_iter()
+            .map(|(name, raw_names)| (interner.resolve(name).to_string(), raw_names.len() as u32))
+            .collect();
+        msg_stats
+    }
+
+    fn count_msg(&mut self, meta: &MsgMeta) {
+        let msg_stats = &mut self.msg_stats;
         msg_stats.num_msgs += 1;
-        let metric_msg = match DogStatsDMsg::new(&line) {
-            Ok(DogStatsDMsg::Metric(m)) => m,
-            Ok(DogStatsDMsg::Event(_)) => {
-                msg_stats
-                    .kind
-                    .entry(DogStatsDMsgKind::Event)
-                    .and_modify(|(v, _)| *v += 1);
-                continue;
-            }
-            Ok(DogStatsDMsg::ServiceCheck(_)) => {
-                msg_stats
-                    .kind
-                    .entry(DogStatsDMsgKind::ServiceCheck)
-                    .and_modify(|(v, _)| *v += 1);
-                continue;
-            }
-            Err(e) => {
-                println!("Error parsing dogstatsd msg: {}", e);
-                continue;
-            }
+        msg_stats.read_duration += meta.read_duration;
+        msg_stats.parse_duration += meta.parse_duration;
+    }
+
+    fn visit_metric(&mut self, metric_msg: &DogStatsDMetricStr, meta: &MsgMeta) {
+        let options = self.options;
+        let msg_stats = &mut self.msg_stats;
+        let kept_tags: Vec<&str>;
+        let tags: &[&str] = if options.filters_tags() {
+            kept_tags = metric_msg
+                .tags
+                .iter()
+                .copied()
+                .filter(|tag| options.keeps_tag(tag))
+                .collect();
+            &kept_tags
+        } else {
+            &metric_msg.tags
         };
 
         let num_values = metric_msg.values.len() as f64;
@@ -346,19 +1009,40 @@ pub fn analyze_msgs(reader: &mut DogStatsDReader) -> Result<DogStatsDBatchStats,
         }
 
         let mut num_unicode_tags = 0_f64;
-        let num_tags = metric_msg.tags.len() as f64;
-        for tag in &metric_msg.tags {
+        let num_tags = tags.len() as f64;
+        for tag in tags {
             msg_stats.tag_total_length.add(tag.len() as f64);
-            tags_seen
-                .entry(tag.to_string())
-                .and_modify(|e| *e += 1)
-                .or_insert(1);
+            match self.tag_sketch {
+                Some(ref mut sketch) => sketch.insert(*tag),
+                None => {
+                    let tag_id = self.interner.intern(tag);
+                    *self.tags_seen.entry(tag_id).or_default() += 1;
+                }
+            }
             if !tag.is_ascii() {
                 num_unicode_tags += 1.0;
             }
         }
 
         msg_stats.name_length.add(metric_msg.name.len() as f64);
+        let raw_name_id = self.interner.intern(metric_msg.name);
+        let name_id = match options.rollup_name(metric_msg.name) {
+            Some(rollup_name) => {
+                let rollup_name_id = self.interner.intern(&rollup_name);
+                self.rolled_up_names
+                    .entry(rollup_name_id)
+                    .or_default()
+                    .insert(raw_name_id);
+                rollup_name_id
+            }
+            None => raw_name_id,
+        };
+        *self
+            .metric_types_by_name
+            .entry(name_id)
+            .or_default()
+            .entry(metric_msg.metric_type)
+            .or_default() += 1;
         msg_stats.num_tags.add(num_tags);
         msg_stats.num_unicode_tags.add(num_unicode_tags);
         msg_stats.num_values.add(num_values);
@@ -366,21 +1050,13 @@ pub fn analyze_msgs(reader: &mut DogStatsDReader) -> Result<DogStatsDBatchStats,
             msg_stats.num_msgs_with_multivalue += 1;
         }
 
-        let mut metric_context = hash_builder.build_hasher();
-        metric_context.write_usize(metric_msg.name.len());
-        metric_context.write(metric_msg.name.as_bytes());
-        // Use a BTreeSet to ensure that the tags are sorted
-        let labels: BTreeSet<&&str> = metric_msg.tags.iter().collect();
-        let metric_context = labels
-            .iter()
-            .fold(metric_context, |mut hasher, t| {
-                hasher.write_usize(t.len());
-                hasher.write(t.as_bytes());
-                hasher
-            })
-            .finish();
-        let context_entry = context_map.entry(metric_context).or_default();
-        *context_entry += 1;
+        let metric_context = context_key(self.interner.resolve(name_id), tags);
+        match self.context_sketch {
+            Some(ref mut sketch) => sketch.insert_hash(metric_context),
+            None => {
+                self.context_map.insert(metric_context, name_id);
+            }
+        }
 
         msg_stats
             .kind
@@ -393,15 +1069,72 @@ pub fn analyze_msgs(reader: &mut DogStatsDReader) -> Result<DogStatsDBatchStats,
                         .and_modify(|v| *v += 1);
                 }
             });
+        *msg_stats
+            .kind_bytes
+            .entry(DogStatsDMsgKind::Metric)
+            .or_default() += meta.raw.len() as u64;
     }
+}
+
+impl MsgVisitor for BatchStatsVisitor<'_> {
+    fn visit(&mut self, msg: &DogStatsDMsg, meta: &MsgMeta) {
+        self.count_msg(meta);
+        let msg_stats = &mut self.msg_stats;
+        // a single malformed field shouldn't drop the whole msg from the analysis
+        if !meta.warnings.is_empty() {
+            msg_stats.num_msgs_with_parse_warnings += 1;
+        }
+        for warning in meta.warnings {
+            *msg_stats
+                .parse_warnings
+                .entry(format!("{}: {}", warning.kind, warning.reason))
+                .or_default() += 1;
+        }
+        let tags = msg.tags();
+        if tags
+            .iter()
+            .enumerate()
+            .any(|(idx, tag)| tags[..idx].contains(tag))
+        {
+            msg_stats.num_msgs_with_duplicate_tags += 1;
+        }
+        let suspicions = msg.suspicions(meta.timestamp.unwrap_or(self.started_at));
+        if !suspicions.is_empty() {
+            msg_stats.num_suspicious_msgs += 1;
+        }
+        for suspicion in suspicions {
+            let reason = format!("{}: {}", msg.kind(), suspicion.reason());
+            if !msg_stats.suspicious_samples.contains_key(&reason) {
+                msg_stats
+                    .suspicious_samples
+                    .insert(reason.clone(), meta.raw.to_string());
+            }
+            *msg_stats.suspicious.entry(reason).or_default() += 1;
+        }
+        match msg.host() {
+            Some(host) => *msg_stats.hostnames.entry(host.to_string()).or_default() += 1,
+            None => *msg_stats.msgs_missing_host.entry(msg.kind()).or_default() += 1,
+        }
+        if let Some(cardinality) = msg.cardinality() {
+            *msg_stats.cardinality_hints.entry(cardinality).or_default() += 1;
+        }
 
-    // Have read through the entire reader, lets try to grab the final "Analytics" if it exists
-    msg_stats.reader_analytics = reader
-        .get_analytics()
-        .expect("Error getting analytics from reader");
-    msg_stats.unique_tags = tags_seen;
-    msg_stats.num_contexts = context_map.len() as u32;
-    Ok(msg_stats)
+        match msg {
+            DogStatsDMsg::Metric(m) => self.visit_metric(m, meta),
+            DogStatsDMsg::Event(_) | DogStatsDMsg::ServiceCheck(_) => {
+                msg_stats
+                    .kind
+                    .entry(msg.kind())
+                    .and_modify(|(v, _)| *v += 1);
+                *msg_stats.kind_bytes.entry(msg.kind()).or_default() += meta.raw.len() as u64;
+            }
+        }
+    }
+
+    fn visit_unparseable(&mut self, error: &DogStatsDMsgError, meta: &MsgMeta) {
+        self.count_msg(meta);
+        println!("Error parsing dogstatsd msg: {}", error);
+    }
 }
 
 #[cfg(test)]
@@ -418,6 +1151,107 @@ mod tests {
         assert_eq!(res.num_contexts, 3);
     }
 
+    #[test]
+    fn cardinality_can_be_approximated() {
+        let mut payload = String::new();
+        for i in 0..1000 {
+            payload.push_str(&format!("my.metric:1|c|#pod:{},env:prod\n", i % 100));
+            payload.push_str(&format!("other.metric:1|c|#pod:{i}\n"));
+        }
+        let options = AnalysisOptions {
+            approximate_cardinality: true,
+            ..Default::default()
+        };
+        let mut reader = DogStatsDReader::new(payload.as_bytes()).unwrap();
+        let res = analyze_msgs_with_options(&mut reader, &options).unwrap();
+
+        let close = |estimate: u64, exact: f64| (estimate as f64 - exact).abs() / exact < 0.02;
+        assert!(
+            close(res.num_contexts as u64, 1100.0),
+            "{}",
+            res.num_contexts
+        );
+        assert!(
+            close(res.num_unique_tags(), 1001.0),
+            "{}",
+            res.num_unique_tags()
+        );
+        assert!(res.unique_tags.is_empty());
+        assert!(res.contexts_by_name.is_empty());
+        assert_eq!(res.metric_types_by_name.len(), 2);
+
+        let mut reader = DogStatsDReader::new(payload.as_bytes()).unwrap();
+        let exact = analyze_msgs(&mut reader).unwrap();
+        assert_eq!(exact.num_contexts, 1100);
+        assert_eq!(exact.num_unique_tags(), 1001);
+        assert_eq!(exact.approximate_unique_tags, None);
+    }
+
+    #[test]
+    fn stats_merge_as_if_analyzed_together() {
+        let first = b"a:1|c|#env:prod\nb:2|g|#pod:1\n_e{1,1}:a|b\n";
+        let second = b"a:1|c|#env:prod\nb:2|c|#pod:2\n_sc|my.check|0\n";
+        let analyze = |payload: &[u8], options: &AnalysisOptions| {
+            let mut reader = DogStatsDReader::new(payload).unwrap();
+            analyze_msgs_with_options(&mut reader, options).unwrap()
+        };
+
+        let options = AnalysisOptions::default();
+        let mut merged = analyze(first, &options);
+        merged.merge(&analyze(second, &options)).unwrap();
+        let together = analyze(&[&first[..], &second[..]].concat(), &options);
+        assert_eq!(merged.num_msgs, 6);
+        assert_eq!(merged.num_contexts, together.num_contexts);
+        assert_eq!(merged.num_contexts, 3);
+        assert_eq!(merged.contexts_by_name, together.contexts_by_name);
+        assert_eq!(merged.unique_tags, together.unique_tags);
+        assert_eq!(merged.metric_types_by_name, together.metric_types_by_name);
+        assert_eq!(merged.kind, together.kind);
+        assert_eq!(merged.kind_bytes, together.kind_bytes);
+        assert_eq!(merged.name_length.count(), together.name_length.count());
+        assert_eq!(merged.input_type, together.input_type);
+
+        // stats from the cache have no context keys, their contexts add up
+        let mut cached: DogStatsDBatchStats =
+            serde_json::from_str(&serde_json::to_string(&analyze(first, &options)).unwrap())
+                .unwrap();
+        cached.merge(&analyze(second, &options)).unwrap();
+        assert_eq!(cached.num_contexts, 4);
+        assert_eq!(cached.contexts_by_name["a"], 2);
+
+        let options = AnalysisOptions {
+            approximate_cardinality: true,
+            ..Default::default()
+        };
+        let mut merged = analyze(first, &options);
+        merged.merge(&analyze(second, &options)).unwrap();
+        assert_eq!(merged.num_contexts, 3);
+        assert_eq!(merged.approximate_unique_tags, Some(3));
+    }
+
+    #[test]
+    fn counting_contexts_per_name() {
+        let payload = b"b.metric:1|g|#pod:a
+b.metric:1|g|#pod:b
+b.metric:1|g|#pod:b
+a.metric:1|c
+c.metric:1|c|#pod:a
+c.metric:1|d|#pod:c
+_sc|my.check|0
+";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let res = analyze_msgs(&mut reader).unwrap();
+
+        assert_eq!(res.num_contexts, 5);
+        let names: Vec<(&str, u32)> = res
+            .contexts_per_name()
+            .iter()
+            .map(|n| (n.name.as_str(), n.contexts))
+            .collect();
+        assert_eq!(names, [("b.metric", 2), ("c.metric", 2), ("a.metric", 1)]);
+        assert_eq!(res.contexts_per_name()[0].percent_of_contexts, 40.0);
+    }
+
     #[test]
     fn counting_contexts_name_variations() {
         let payload =
@@ -505,6 +1339,250 @@ mod tests {
         assert_eq!(res.num_contexts, 6);
     }
 
+    #[test]
+    fn kind_shares_bytes_and_percentages() {
+        let payload = b"my.metric:1|g\nmy.metric:2|g\n_sc|ab|2\n_e{1,1}:a|b\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let res = analyze_msgs(&mut reader).unwrap();
+
+        let shares = res.kind_shares();
+        assert_eq!(shares.len(), 3);
+
+        let metric = &shares[0];
+        assert_eq!(metric.kind, DogStatsDMsgKind::Metric);
+        assert_eq!(metric.count, 2);
+        assert_eq!(metric.bytes, 26);
+        assert_eq!(metric.percent_of_msgs, 50.0);
+        assert_eq!(metric.percent_of_bytes, 26.0 / 45.0 * 100.0);
+        // utf8 input has no timing information
+        assert_eq!(metric.msgs_per_second, None);
+
+        let event = shares
+            .iter()
+            .find(|s| s.kind == DogStatsDMsgKind::Event)
+            .unwrap();
+        assert_eq!(event.count, 1);
+        assert_eq!(event.bytes, 11);
+        assert_eq!(event.percent_of_msgs, 25.0);
+    }
+
+    #[test]
+    fn tag_filters_scope_contexts() {
+        let payload = b"my.metric:1|g|#env:prod,pod:a\nmy.metric:1|g|#env:prod,pod:b\nmy.metric:1|g|#env:dev,pod:c,debug\n";
+        let analyze = |options: AnalysisOptions| {
+            let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+            analyze_msgs_with_options(&mut reader, &options).unwrap()
+        };
+
+        assert_eq!(analyze(AnalysisOptions::default()).num_contexts, 3);
+
+        let res = analyze(AnalysisOptions {
+            exclude_tags: vec!["pod".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(res.num_contexts, 2);
+        assert_eq!(res.unique_tags.len(), 3);
+
+        let res = analyze(AnalysisOptions {
+            include_tags: Some(vec!["env".to_string(), "debug".to_string()]),
+            exclude_tags: vec!["debug".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(res.num_contexts, 2);
+        assert_eq!(
+            res.unique_tags,
+            HashMap::from([("env:prod".to_string(), 2), ("env:dev".to_string(), 1)])
+        );
+        assert_eq!(res.num_tags.max(), Some(1.0));
+    }
+
+    #[test]
+    fn rollups_group_names() {
+        let payload = b"queue.depth.shard_1:1|g|#env:prod\nqueue.depth.shard_2:1|g|#env:prod\nqueue.depth.shard_2:1|g|#env:prod\nuser.42.logins:1|c\nother:1|c\n";
+        let options = AnalysisOptions {
+            rollups: vec![
+                r"shard_\d+=shard_N".parse().unwrap(),
+                r"^user\.\d+\.(\w+)$=user.$1".parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let res = analyze_msgs_with_options(&mut reader, &options).unwrap();
+
+        assert_eq!(res.num_contexts, 3);
+        let mut names: Vec<&String> = res.metric_types_by_name.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["other", "queue.depth.shard_N", "user.logins"]);
+        assert_eq!(res.contexts_by_name["queue.depth.shard_N"], 1);
+        assert_eq!(
+            res.metric_types_by_name["queue.depth.shard_N"][&DogStatsDMetricType::Gauge],
+            3
+        );
+        assert_eq!(
+            res.names_absorbed_by_rollup,
+            HashMap::from([
+                ("queue.depth.shard_N".to_string(), 2),
+                ("user.logins".to_string(), 1)
+            ])
+        );
+
+        assert!("no-equals".parse::<RollupRule>().is_err());
+        assert!("(=name".parse::<RollupRule>().is_err());
+    }
+
+    #[test]
+    fn malformed_fields_are_skipped() {
+        let payload =
+            b"my.metric:1|g|@half\nmy.metric:2|g|T123|#env:prod\n_sc|ab|2|d:soon\nnonsense\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let res = analyze_msgs(&mut reader).unwrap();
+
+        assert_eq!(res.num_msgs, 4);
+        assert_eq!(res.kind[&DogStatsDMsgKind::Metric].0, 2);
+        assert_eq!(res.kind[&DogStatsDMsgKind::ServiceCheck].0, 1);
+        assert_eq!(res.num_msgs_with_parse_warnings, 2);
+        assert_eq!(
+            res.parse_warnings,
+            HashMap::from([
+                ("Metric: Invalid sample rate found".to_string(), 1),
+                ("ServiceCheck: Invalid timestamp found".to_string(), 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn duplicate_tags_are_not_parse_warnings() {
+        let payload = b"a:1|c|#env:prod,env:prod\nb:1|c|#env:prod\n_sc|my.check|0|#x|#x\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let res = analyze_msgs(&mut reader).unwrap();
+
+        assert_eq!(res.num_msgs_with_duplicate_tags, 2);
+        assert_eq!(res.num_msgs_with_parse_warnings, 0);
+        assert!(res.parse_warnings.is_empty());
+    }
+
+    #[test]
+    fn suspicious_msgs_are_counted_with_a_sample() {
+        let payload = b"a:1|c|@0\nb:2|c|@0|#env:\nc:3|c\n_sc|my.check|0|#team:\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let res = analyze_msgs(&mut reader).unwrap();
+
+        assert_eq!(res.num_suspicious_msgs, 3);
+        assert_eq!(res.num_msgs_with_parse_warnings, 0);
+        assert_eq!(
+            res.suspicious,
+            HashMap::from([
+                ("Metric: sample rate of 0".to_string(), 2),
+                ("Metric: empty tag value".to_string(), 1),
+                ("ServiceCheck: empty tag value".to_string(), 1),
+            ])
+        );
+        assert_eq!(
+            res.suspicious_samples["Metric: sample rate of 0"],
+            "a:1|c|@0"
+        );
+    }
+
+    #[test]
+    fn hostnames_and_missing_hosts() {
+        let payload =
+            b"a:1|g|#host:web-1\nb:1|g\n_sc|check|0|h:web-2\n_sc|check|0\n_e{1,1}:a|b|h:web-1\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let res = analyze_msgs(&mut reader).unwrap();
+
+        assert_eq!(res.hostnames.len(), 2);
+        assert_eq!(res.hostnames.get("web-1"), Some(&2));
+        assert_eq!(res.hostnames.get("web-2"), Some(&1));
+        assert_eq!(
+            res.msgs_missing_host.get(&DogStatsDMsgKind::Metric),
+            Some(&1)
+        );
+        assert_eq!(
+            res.msgs_missing_host.get(&DogStatsDMsgKind::ServiceCheck),
+            Some(&1)
+        );
+        assert_eq!(res.msgs_missing_host.get(&DogStatsDMsgKind::Event), None);
+    }
+
+    #[test]
+    fn cardinality_hints_are_counted() {
+        let payload = b"a:1|g|card:high\nb:1|g\n_sc|check|0|card:low\nc:1|c|card:high\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let res = analyze_msgs(&mut reader).unwrap();
+
+        assert_eq!(res.cardinality_hints.len(), 2);
+        assert_eq!(res.cardinality_hints.get(&Cardinality::High), Some(&2));
+        assert_eq!(res.cardinality_hints.get(&Cardinality::Low), Some(&1));
+    }
+
+    #[test]
+    fn metric_type_conflicts_are_reported() {
+        let payload = b"requests:1|c\nrequests:2|c\nrequests:5|g\nlatency:1|d\nlatency:2|d\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let res = analyze_msgs(&mut reader).unwrap();
+
+        assert_eq!(
+            res.metric_type_conflicts(),
+            vec![MetricTypeConflict {
+                name: "requests".to_string(),
+                types: vec![
+                    (DogStatsDMetricType::Count, 2),
+                    (DogStatsDMetricType::Gauge, 1)
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn compression_recorded_for_zstd_input() {
+        // "my.metric:1|g" zstd compressed
+        let payload = &[
+            0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x58, 0x69, 0x00, 0x00, 0x6d, 0x79, 0x2e, 0x6d, 0x65,
+            0x74, 0x72, 0x69, 0x63, 0x3a, 0x31, 0x7c, 0x67, 0x1e, 0xc8, 0x48, 0xb4,
+        ];
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let res = analyze_msgs(&mut reader).unwrap();
+
+        assert_eq!(res.num_msgs, 1);
+        let compression = res
+            .compression
+            .expect("zstd input has compression analytics");
+        assert_eq!(compression.compressed_bytes, 26);
+        assert_eq!(compression.decompressed_bytes, 13);
+        assert_eq!(
+            res.input_type.unwrap().to_string(),
+            "zstd compressed utf-8 text"
+        );
+
+        let payload = b"my.metric:1|g\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let res = analyze_msgs(&mut reader).unwrap();
+        assert!(res.compression.is_none());
+    }
+
+    #[test]
+    fn merged_compression_of_different_codecs_is_mixed() {
+        let analyze = |payload: &[u8]| {
+            let mut reader = DogStatsDReader::new(payload).unwrap();
+            analyze_msgs(&mut reader).unwrap()
+        };
+        let zstd = zstd::encode_all(&b"my.metric:1|g\n"[..], 0).unwrap();
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(b"my.metric:2|g\n").unwrap();
+        let gzip = gzip.finish().unwrap();
+
+        let mut merged = analyze(&zstd);
+        merged.merge(&analyze(&zstd)).unwrap();
+        let compression = merged.compression.as_ref().unwrap();
+        assert_eq!(compression.codec, "zstd");
+        assert_eq!(compression.decompressed_bytes, 28);
+
+        merged.merge(&analyze(&gzip)).unwrap();
+        let compression = merged.compression.unwrap();
+        assert_eq!(compression.codec, MIXED_CODECS);
+        assert_eq!(compression.decompressed_bytes, 42);
+    }
+
     #[test]
     fn batch_stats_to_lading_config() {
         let config = Config::defaults();
@@ -514,14 +1592,35 @@ mod tests {
             tag_total_length: DDSketch::new(config),
             num_unicode_tags: DDSketch::new(config),
             kind: HashMap::new(),
+            kind_bytes: HashMap::new(),
             unique_tags: HashMap::new(),
+            approximate_unique_tags: None,
+            context_keys: HashMap::new(),
+            context_sketch: None,
+            tag_sketch: None,
             num_contexts: 1,
+            contexts_by_name: HashMap::new(),
+            metric_types_by_name: HashMap::new(),
+            hostnames: HashMap::new(),
+            msgs_missing_host: HashMap::new(),
+            cardinality_hints: HashMap::new(),
             num_values: DDSketch::new(config),
             value_range: DDSketch::new(config),
             values_that_are_floats: 0,
             num_msgs: 4,
             num_msgs_with_multivalue: 0,
+            num_msgs_with_parse_warnings: 0,
+            parse_warnings: HashMap::new(),
+            num_msgs_with_duplicate_tags: 0,
+            num_suspicious_msgs: 0,
+            suspicious: HashMap::new(),
+            suspicious_samples: HashMap::new(),
+            names_absorbed_by_rollup: HashMap::new(),
             reader_analytics: None,
+            input_type: None,
+            compression: None,
+            read_duration: Duration::ZERO,
+            parse_duration: Duration::ZERO,
         };
 
         stats.name_length.add(10.0);
@@ -536,6 +1635,81 @@ mod tests {
         );
     }
 
+    /// Compares 'actual' to the snapshot `src/snapshots/<name>`. The snapshot is
+    /// only (re)written when `UPDATE_SNAPSHOTS=1` is set, review and commit the
+    /// result. A missing snapshot fails, so CI can't pass by writing its own.
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/snapshots")
+            .join(name);
+        if std::env::var("UPDATE_SNAPSHOTS").as_deref() == Ok("1") {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        let Ok(expected) = std::fs::read_to_string(&path) else {
+            panic!(
+                "{} is missing, run with UPDATE_SNAPSHOTS=1 to write it\n{}",
+                path.display(),
+                actual
+            );
+        };
+        assert!(
+            expected == actual,
+            "{} changed, run with UPDATE_SNAPSHOTS=1 if this is intended\n--- expected\n{}\n--- actual\n{}",
+            path.display(),
+            expected,
+            actual
+        );
+    }
+
+    /// Stats of a fixed payload, read over 10 seconds of udp traffic
+    fn lading_fixture_stats() -> DogStatsDBatchStats {
+        let payload = b"my.metric:1|g|#env:prod\nmy.metric:2.5|g|#env:dev,team:core\nother.metric:20:30|d|#env:staging\nother.thing:10|c\n_sc|my.check|0\n_e{5,4}:title|text\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let mut stats = analyze_msgs(&mut reader).unwrap();
+        let mut analytics =
+            crate::dogstatsdreader::Analytics::new(crate::dogstatsdreader::Transport::Udp);
+        analytics.record_packet(Duration::from_secs(1_700_000_000), 100);
+        analytics.record_packet(Duration::from_secs(1_700_000_010), 100);
+        stats.reader_analytics = Some(analytics);
+        stats
+    }
+
+    #[test]
+    fn lading_config_snapshots() {
+        let stats = lading_fixture_stats();
+        assert_snapshot(
+            "lading_config_confrange.yaml",
+            &stats.to_lading_config_str().unwrap(),
+        );
+        assert_snapshot(
+            "lading_config_min_max.yaml",
+            &stats
+                .to_lading_config_str_with_schema(LadingSchema::MinMax)
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn conf_ranges_are_flattened_for_min_max() {
+        let mut value: serde_yaml::Value = serde_yaml::from_str(
+            "contexts: {constant: 10}\nname_length: {inclusive: {min: 1, max: 200}}\nvalue: {float_probability: 0.5, range: {constant: 3}}\nkind: {constant: abc}\n",
+        )
+        .unwrap();
+        flatten_conf_ranges(&mut value);
+        let expected: serde_yaml::Value = serde_yaml::from_str(
+            "contexts_minimum: 10\ncontexts_maximum: 10\nname_length_minimum: 1\nname_length_maximum: 200\nvalue: {float_probability: 0.5, range_minimum: 3, range_maximum: 3}\nkind: {constant: abc}\n",
+        )
+        .unwrap();
+        assert_eq!(value, expected);
+        assert_eq!(
+            "min-max".parse::<LadingSchema>().unwrap(),
+            LadingSchema::MinMax
+        );
+        assert!("v2".parse::<LadingSchema>().is_err());
+    }
+
     #[test]
     fn stats_lading_metric_weights() {
         let payload =
@@ -559,14 +1733,35 @@ mod tests {
             tag_total_length: DDSketch::new(config),
             num_unicode_tags: DDSketch::new(config),
             kind: HashMap::new(),
+            kind_bytes: HashMap::new(),
             unique_tags: HashMap::new(),
+            approximate_unique_tags: None,
+            context_keys: HashMap::new(),
+            context_sketch: None,
+            tag_sketch: None,
             num_contexts: 0,
+            contexts_by_name: HashMap::new(),
+            metric_types_by_name: HashMap::new(),
+            hostnames: HashMap::new(),
+            msgs_missing_host: HashMap::new(),
+            cardinality_hints: HashMap::new(),
             num_values: DDSketch::new(config),
             value_range: DDSketch::new(config),
             values_that_are_floats: 0,
             num_msgs: 4,
             num_msgs_with_multivalue: 0,
+            num_msgs_with_parse_warnings: 0,
+            parse_warnings: HashMap::new(),
+            num_msgs_with_duplicate_tags: 0,
+            num_suspicious_msgs: 0,
+            suspicious: HashMap::new(),
+            suspicious_samples: HashMap::new(),
+            names_absorbed_by_rollup: HashMap::new(),
             reader_analytics: None,
+            input_type: None,
+            compression: None,
+            read_duration: Duration::ZERO,
+            parse_duration: Duration::ZERO,
         };
 
         let mut metric_map = HashMap::new();
@@ -0,0 +1,580 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{self, Write},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    dogstatsdmsg::{context_key, DogStatsDMsg, DogStatsDMsgError},
+    validation::{ValidationRules, Violation},
+    visitor::{MsgMeta, MsgVisitor},
+};
+
+/// Sorts counts largest first, ties broken by name, and keeps the first 'top'
+fn top_counts(counts: impl Iterator<Item = (String, usize)>, top: usize) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = counts.collect();
+    counts.sort_by(|a, b| Reverse(a.1).cmp(&Reverse(b.1)).then(a.0.cmp(&b.0)));
+    counts.truncate(top);
+    counts
+}
+
+/// Contexts per metric name and distinct values per tag key, to find what
+/// is driving the number of contexts
+pub struct CardinalityVisitor {
+    contexts_by_name: HashMap<String, HashSet<u64>>,
+    values_by_tag_key: HashMap<String, HashSet<String>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CardinalityReport {
+    /// Metric names with the most contexts, with their number of contexts
+    pub names: Vec<(String, usize)>,
+    /// Tag keys with the most distinct values, with their number of values
+    pub tag_keys: Vec<(String, usize)>,
+}
+
+impl CardinalityVisitor {
+    pub fn new() -> Self {
+        Self {
+            contexts_by_name: HashMap::new(),
+            values_by_tag_key: HashMap::new(),
+        }
+    }
+
+    /// The 'top' names and tag keys with the highest cardinality
+    pub fn report(&self, top: usize) -> CardinalityReport {
+        CardinalityReport {
+            names: top_counts(
+                self.contexts_by_name
+                    .iter()
+                    .map(|(name, contexts)| (name.clone(), contexts.len())),
+                top,
+            ),
+            tag_keys: top_counts(
+                self.values_by_tag_key
+                    .iter()
+                    .map(|(key, values)| (key.clone(), values.len())),
+                top,
+            ),
+        }
+    }
+}
+
+impl Default for CardinalityVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MsgVisitor for CardinalityVisitor {
+    fn visit(&mut self, msg: &DogStatsDMsg, _meta: &MsgMeta) {
+        let DogStatsDMsg::Metric(metric) = msg else {
+            return;
+        };
+        let context = context_key(metric.name, &metric.tags);
+        match self.contexts_by_name.get_mut(metric.name) {
+            Some(contexts) => {
+                contexts.insert(context);
+            }
+            None => {
+                self.contexts_by_name
+                    .insert(metric.name.to_string(), HashSet::from([context]));
+            }
+        }
+
+        for tag in &metric.tags {
+            let (key, value) = tag.split_once(':').unwrap_or((tag, ""));
+            let values = self.values_by_tag_key.entry(key.to_string()).or_default();
+            if !values.contains(value) {
+                values.insert(value.to_string());
+            }
+        }
+    }
+}
+
+/// A distinct metric context with the number of msgs it was seen in
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContextCount {
+    pub name: String,
+    /// Sorted and deduplicated, as they are for `context_key`
+    pub tags: Vec<String>,
+    pub msgs: u64,
+}
+
+/// Every distinct metric context, to list the contexts themselves rather
+/// than only count them
+pub struct ContextsVisitor {
+    contexts: HashMap<u64, ContextCount>,
+}
+
+impl ContextsVisitor {
+    pub fn new() -> Self {
+        Self {
+            contexts: HashMap::new(),
+        }
+    }
+
+    pub fn num_contexts(&self) -> usize {
+        self.contexts.len()
+    }
+
+    /// The 'top' contexts with the most msgs, ties broken by name then tags
+    pub fn top(&self, top: usize) -> Vec<ContextCount> {
+        let mut contexts: Vec<&ContextCount> = self.contexts.values().collect();
+        contexts.sort_by(|a, b| {
+            b.msgs
+                .cmp(&a.msgs)
+                .then(a.name.cmp(&b.name))
+                .then(a.tags.cmp(&b.tags))
+        });
+        contexts.into_iter().take(top).cloned().collect()
+    }
+}
+
+impl Default for ContextsVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MsgVisitor for ContextsVisitor {
+    fn visit(&mut self, msg: &DogStatsDMsg, _meta: &MsgMeta) {
+        let DogStatsDMsg::Metric(metric) = msg else {
+            return;
+        };
+        let context = context_key(metric.name, &metric.tags);
+        self.contexts
+            .entry(context)
+            .or_insert_with(|| {
+                let mut tags: Vec<String> = metric.tags.iter().map(|t| t.to_string()).collect();
+                tags.sort_unstable();
+                tags.dedup();
+                ContextCount {
+                    name: metric.name.to_string(),
+                    tags,
+                    msgs: 0,
+                }
+            })
+            .msgs += 1;
+    }
+}
+
+/// Quotes 'field' if it contains a character that is special in csv
+fn csv_field(field: &str) -> String {
+    if field.contains(['"', ',', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes 'contexts' as csv with a header row, tags are joined with ','
+pub fn write_contexts_csv(contexts: &[ContextCount], mut out: impl Write) -> io::Result<()> {
+    writeln!(out, "msgs,name,tags")?;
+    for context in contexts {
+        writeln!(
+            out,
+            "{},{},{}",
+            context.msgs,
+            csv_field(&context.name),
+            csv_field(&context.tags.join(","))
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes 'contexts' as one json object per line
+pub fn write_contexts_jsonl(contexts: &[ContextCount], mut out: impl Write) -> io::Result<()> {
+    for context in contexts {
+        serde_json::to_writer(&mut out, context)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Msgs and bytes per interval of capture time
+pub struct TimeseriesVisitor {
+    interval: Duration,
+    buckets: BTreeMap<u128, TimeseriesBucket>,
+    untimed_msgs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeseriesBucket {
+    /// Capture time the bucket starts at, as a duration since the epoch
+    pub start: Duration,
+    pub msgs: u64,
+    pub bytes: u64,
+}
+
+impl TimeseriesVisitor {
+    pub fn new(interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "Timeseries interval must not be zero");
+        Self {
+            interval,
+            buckets: BTreeMap::new(),
+            untimed_msgs: 0,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Every interval that saw at least one msg, oldest first
+    pub fn buckets(&self) -> Vec<TimeseriesBucket> {
+        self.buckets.values().cloned().collect()
+    }
+
+    /// Msgs that had no capture time, eg from a text input
+    pub fn untimed_msgs(&self) -> u64 {
+        self.untimed_msgs
+    }
+
+    fn record(&mut self, meta: &MsgMeta) {
+        let Some(timestamp) = meta.timestamp else {
+            self.untimed_msgs += 1;
+            return;
+        };
+        let index = timestamp.as_nanos() / self.interval.as_nanos();
+        let interval = self.interval;
+        let bucket = self
+            .buckets
+            .entry(index)
+            .or_insert_with(|| TimeseriesBucket {
+                start: Duration::from_nanos((index * interval.as_nanos()) as u64),
+                msgs: 0,
+                bytes: 0,
+            });
+        bucket.msgs += 1;
+        bucket.bytes += meta.raw.len() as u64;
+    }
+}
+
+impl MsgVisitor for TimeseriesVisitor {
+    fn visit(&mut self, _msg: &DogStatsDMsg, meta: &MsgMeta) {
+        self.record(meta);
+    }
+
+    fn visit_unparseable(&mut self, _error: &DogStatsDMsgError, meta: &MsgMeta) {
+        self.record(meta);
+    }
+}
+
+/// New and expired metric contexts per interval of capture time, approximating
+/// how the agent's context map grows and shrinks. Like in the agent, a context
+/// expires once it wasn't seen for 'expiry', and is new again when it is seen
+/// after that.
+pub struct ChurnVisitor {
+    interval: Duration,
+    expiry: Duration,
+    /// Capture time every context was last seen at
+    last_seen: HashMap<u64, Duration>,
+    /// Windows with new contexts, or with contexts that expired on being
+    /// seen again. Expiries of contexts that weren't seen again are only
+    /// known once every msg was visited.
+    windows: BTreeMap<u128, ChurnWindow>,
+    /// Latest capture time seen, contexts expiring after it are still live
+    latest: Duration,
+    untimed_msgs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChurnWindow {
+    /// Capture time the window starts at, as a duration since the epoch
+    pub start: Duration,
+    /// Contexts seen for the first time, or again after they expired
+    pub new_contexts: u64,
+    pub expired_contexts: u64,
+    /// Contexts that hadn't expired by the end of the window
+    pub live_contexts: u64,
+}
+
+/// The window of 'windows' that 'timestamp' falls into
+fn churn_window(
+    windows: &mut BTreeMap<u128, ChurnWindow>,
+    interval: Duration,
+    timestamp: Duration,
+) -> &mut ChurnWindow {
+    let index = timestamp.as_nanos() / interval.as_nanos();
+    windows.entry(index).or_insert_with(|| ChurnWindow {
+        start: Duration::from_nanos((index * interval.as_nanos()) as u64),
+        new_contexts: 0,
+        expired_contexts: 0,
+        live_contexts: 0,
+    })
+}
+
+impl ChurnVisitor {
+    pub fn new(interval: Duration, expiry: Duration) -> Self {
+        assert!(!interval.is_zero(), "Churn interval must not be zero");
+        Self {
+            interval,
+            expiry,
+            last_seen: HashMap::new(),
+            windows: BTreeMap::new(),
+            latest: Duration::ZERO,
+            untimed_msgs: 0,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn expiry(&self) -> Duration {
+        self.expiry
+    }
+
+    /// Every window that saw contexts being added or expiring, oldest first
+    pub fn windows(&self) -> Vec<ChurnWindow> {
+        let mut windows = self.windows.clone();
+        for last_seen in self.last_seen.values() {
+            let expires_at = *last_seen + self.expiry;
+            if expires_at <= self.latest {
+                churn_window(&mut windows, self.interval, expires_at).expired_contexts += 1;
+            }
+        }
+        let mut live = 0;
+        windows
+            .into_values()
+            .map(|mut window| {
+                live += window.new_contexts;
+                live -= window.expired_contexts;
+                window.live_contexts = live;
+                window
+            })
+            .collect()
+    }
+
+    /// Metric msgs that had no capture time, eg from a text input
+    pub fn untimed_msgs(&self) -> u64 {
+        self.untimed_msgs
+    }
+}
+
+impl MsgVisitor for ChurnVisitor {
+    fn visit(&mut self, msg: &DogStatsDMsg, meta: &MsgMeta) {
+        let DogStatsDMsg::Metric(metric) = msg else {
+            return;
+        };
+        let Some(timestamp) = meta.timestamp else {
+            self.untimed_msgs += 1;
+            return;
+        };
+        self.latest = self.latest.max(timestamp);
+        let context = context_key(metric.name, &metric.tags);
+        match self.last_seen.insert(context, timestamp) {
+            Some(last_seen) if timestamp.saturating_sub(last_seen) <= self.expiry => {
+                // Packets out of order don't take the last sighting back
+                if last_seen > timestamp {
+                    self.last_seen.insert(context, last_seen);
+                }
+            }
+            Some(last_seen) => {
+                churn_window(&mut self.windows, self.interval, last_seen + self.expiry)
+                    .expired_contexts += 1;
+                churn_window(&mut self.windows, self.interval, timestamp).new_contexts += 1;
+            }
+            None => churn_window(&mut self.windows, self.interval, timestamp).new_contexts += 1,
+        }
+    }
+}
+
+/// Checks every msg against `ValidationRules`
+pub struct SchemaVisitor {
+    rules: ValidationRules,
+    num_msgs: u64,
+    msgs_with_violations: u64,
+    /// Count of each broken rule with the first violation seen
+    violations: HashMap<&'static str, (u64, Violation)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SchemaReport {
+    pub num_msgs: u64,
+    pub msgs_with_violations: u64,
+    /// Every broken rule with how often it was broken and an example, most
+    /// common first
+    pub violations: Vec<(&'static str, u64, Violation)>,
+}
+
+impl SchemaVisitor {
+    pub fn new(rules: ValidationRules) -> Self {
+        Self {
+            rules,
+            num_msgs: 0,
+            msgs_with_violations: 0,
+            violations: HashMap::new(),
+        }
+    }
+
+    pub fn report(&self) -> SchemaReport {
+        let mut violations: Vec<(&'static str, u64, Violation)> = self
+            .violations
+            .iter()
+            .map(|(rule, (count, example))| (*rule, *count, example.clone()))
+            .collect();
+        violations.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        SchemaReport {
+            num_msgs: self.num_msgs,
+            msgs_with_violations: self.msgs_with_violations,
+            violations,
+        }
+    }
+}
+
+impl MsgVisitor for SchemaVisitor {
+    fn visit(&mut self, msg: &DogStatsDMsg, _meta: &MsgMeta) {
+        self.num_msgs += 1;
+        let violations = msg.validate(&self.rules);
+        if violations.is_empty() {
+            return;
+        }
+        self.msgs_with_violations += 1;
+        for violation in violations {
+            self.violations
+                .entry(violation.rule())
+                .or_insert((0, violation))
+                .0 += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dogstatsdreader::DogStatsDReader, visitor::visit_msgs};
+
+    #[test]
+    fn analyzers_share_one_pass() {
+        let payload = b"a:1|c|#env:prod,pod:1\na:1|c|#pod:2,env:prod\na:1|c|#env:prod,pod:1\nb:1|g|#env:dev\n1b:1|g\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let mut cardinality = CardinalityVisitor::new();
+        let mut timeseries = TimeseriesVisitor::new(Duration::from_secs(1));
+        let mut schema = SchemaVisitor::new(ValidationRules::default());
+        visit_msgs(
+            &mut reader,
+            &mut [&mut cardinality, &mut timeseries, &mut schema],
+        )
+        .unwrap();
+
+        assert_eq!(
+            cardinality.report(2),
+            CardinalityReport {
+                names: vec![("a".to_string(), 2), ("1b".to_string(), 1)],
+                tag_keys: vec![("env".to_string(), 2), ("pod".to_string(), 2)],
+            }
+        );
+
+        // text inputs have no capture time
+        assert!(timeseries.buckets().is_empty());
+        assert_eq!(timeseries.untimed_msgs(), 5);
+
+        let report = schema.report();
+        assert_eq!(report.num_msgs, 5);
+        assert_eq!(report.msgs_with_violations, 1);
+        assert_eq!(
+            report.violations,
+            vec![(
+                "name doesn't start with a letter",
+                1,
+                Violation::NameInvalidStart('1')
+            )]
+        );
+    }
+
+    #[test]
+    fn contexts_churn_as_they_expire() {
+        let mut churn = ChurnVisitor::new(Duration::from_secs(10), Duration::from_secs(20));
+        let msgs = [
+            (0, "a:1|c"),
+            (1, "b:1|c|#pod:1"),
+            (12, "a:1|c"),
+            (15, "b:1|c|#pod:2"),
+            // seen again after it expired at 21s
+            (45, "b:1|c|#pod:1"),
+            (60, "a:1|c"),
+        ];
+        for (secs, raw) in msgs {
+            let meta = MsgMeta {
+                raw,
+                warnings: &[],
+                read_duration: Duration::ZERO,
+                parse_duration: Duration::ZERO,
+                timestamp: Some(Duration::from_secs(secs)),
+            };
+            churn.visit(&DogStatsDMsg::new(raw).unwrap(), &meta);
+        }
+
+        let counts: Vec<(u64, u64, u64, u64)> = churn
+            .windows()
+            .iter()
+            .map(|w| {
+                (
+                    w.start.as_secs(),
+                    w.new_contexts,
+                    w.expired_contexts,
+                    w.live_contexts,
+                )
+            })
+            .collect();
+        // a expires at 32s, b|pod:2 at 35s, b|pod:1 again at 65s, after the
+        // last msg, so it is still live
+        assert_eq!(
+            counts,
+            vec![
+                (0, 2, 0, 2),
+                (10, 1, 0, 3),
+                (20, 0, 1, 2),
+                (30, 0, 2, 0),
+                (40, 1, 0, 1),
+                (60, 1, 0, 2),
+            ]
+        );
+        assert_eq!(churn.untimed_msgs(), 0);
+    }
+
+    #[test]
+    fn contexts_are_listed_and_exported() {
+        let payload =
+            b"a:1|c|#env:prod,pod:1\na:1|c|#pod:1,env:prod\nb:1|g\na:1|c|#pod:2\n_sc|a|0\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let mut contexts = ContextsVisitor::new();
+        visit_msgs(&mut reader, &mut [&mut contexts]).unwrap();
+
+        assert_eq!(contexts.num_contexts(), 3);
+        let top = contexts.top(2);
+        assert_eq!(
+            top,
+            vec![
+                ContextCount {
+                    name: "a".to_string(),
+                    tags: vec!["env:prod".to_string(), "pod:1".to_string()],
+                    msgs: 2,
+                },
+                ContextCount {
+                    name: "a".to_string(),
+                    tags: vec!["pod:2".to_string()],
+                    msgs: 1,
+                },
+            ]
+        );
+
+        let mut csv = Vec::new();
+        write_contexts_csv(&top, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "msgs,name,tags\n2,a,\"env:prod,pod:1\"\n1,a,pod:2\n"
+        );
+        let mut jsonl = Vec::new();
+        write_contexts_jsonl(&top[1..], &mut jsonl).unwrap();
+        assert_eq!(
+            String::from_utf8(jsonl).unwrap(),
+            "{\"name\":\"a\",\"tags\":[\"pod:2\"],\"msgs\":1}\n"
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use arbitrary::{Arbitrary, Error, Result, Unstructured};
+
+use crate::builder::{BuilderError, EventBuilder, MetricBuilder, ServiceCheckBuilder};
+use crate::dogstatsdmsg::{
+    Cardinality, DogStatsDEventOwned, DogStatsDMetricOwned, DogStatsDMetricType, DogStatsDMsgOwned,
+    DogStatsDServiceCheckOwned, EventAlert, ServiceCheckStatus,
+};
+
+// Msgs are built rather than filled in field by field, so every generated msg
+// is valid and its raw_msg parses back to the same msg. Strings are drawn from
+// small alphabets to stay clear of the separators the builders reject.
+const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const NAME_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789_.";
+const TAG_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789_.:/-";
+const WORD_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_.:!?-\n";
+
+const MAX_NAME_LEN: usize = 32;
+const MAX_WORD_LEN: usize = 12;
+const MAX_WORDS: usize = 8;
+const MAX_TAGS: usize = 8;
+const MAX_VALUES: usize = 4;
+
+fn string(u: &mut Unstructured, alphabet: &[u8], max_len: usize) -> Result<String> {
+    let len = u.int_in_range(1..=max_len)?;
+    (0..len)
+        .map(|_| u.choose(alphabet).map(|c| *c as char))
+        .collect()
+}
+
+/// Starts with a letter, so a metric name can't be mistaken for an event
+/// (`_e{`) or service check (`_sc`)
+fn name(u: &mut Unstructured) -> Result<String> {
+    let first = *u.choose(LETTERS)? as char;
+    Ok(format!(
+        "{}{}",
+        first,
+        string(u, NAME_CHARS, MAX_NAME_LEN - 1)?
+    ))
+}
+
+/// Words separated by single spaces, the parser trims whitespace at the end
+/// of a msg so text must not end with one
+fn text(u: &mut Unstructured) -> Result<String> {
+    let num_words = u.int_in_range(1..=MAX_WORDS)?;
+    let words = (0..num_words)
+        .map(|_| string(u, WORD_CHARS, MAX_WORD_LEN))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(words.join(" "))
+}
+
+fn opt_string(u: &mut Unstructured, alphabet: &[u8], max_len: usize) -> Result<Option<String>> {
+    if u.arbitrary()? {
+        string(u, alphabet, max_len).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+fn tags(u: &mut Unstructured) -> Result<Vec<String>> {
+    let num_tags = u.int_in_range(0..=MAX_TAGS)?;
+    (0..num_tags)
+        .map(|_| string(u, TAG_CHARS, MAX_NAME_LEN))
+        .collect()
+}
+
+/// The generated parts are always valid, a builder error is a bug here
+fn built<T>(msg: std::result::Result<T, BuilderError>) -> Result<T> {
+    msg.map_err(|_| Error::IncorrectFormat)
+}
+
+impl<'a> Arbitrary<'a> for DogStatsDMetricType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            DogStatsDMetricType::Count,
+            DogStatsDMetricType::Gauge,
+            DogStatsDMetricType::Histogram,
+            DogStatsDMetricType::Timer,
+            DogStatsDMetricType::Set,
+            DogStatsDMetricType::Distribution,
+        ])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for ServiceCheckStatus {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            ServiceCheckStatus::Ok,
+            ServiceCheckStatus::Warning,
+            ServiceCheckStatus::Critical,
+            ServiceCheckStatus::Unknown,
+        ])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for EventAlert {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            EventAlert::Error,
+            EventAlert::Warning,
+            EventAlert::Info,
+            EventAlert::Success,
+        ])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Cardinality {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            Cardinality::Low,
+            Cardinality::Orchestrator,
+            Cardinality::High,
+        ])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for DogStatsDMetricOwned {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let metric_type: DogStatsDMetricType = u.arbitrary()?;
+        let mut builder = MetricBuilder::new(&name(u)?, metric_type);
+        if metric_type == DogStatsDMetricType::Set {
+            builder = builder.set_value(&string(u, NAME_CHARS, MAX_NAME_LEN)?);
+        } else {
+            for _ in 0..u.int_in_range(1..=MAX_VALUES)? {
+                let value: f64 = u.arbitrary()?;
+                builder = builder.value(if value.is_finite() { value } else { 0.0 });
+            }
+        }
+        if u.arbitrary()? {
+            builder = builder.sample_rate(f64::from(u.int_in_range(1..=100u8)?) / 100.0);
+        }
+        if u.arbitrary()? {
+            builder = builder.timestamp(u.arbitrary()?);
+        }
+        if let Some(container_id) = opt_string(u, TAG_CHARS, MAX_NAME_LEN)? {
+            builder = builder.container_id(&container_id);
+        }
+        if let Some(external_data) = opt_string(u, TAG_CHARS, MAX_NAME_LEN)? {
+            builder = builder.external_data(&external_data);
+        }
+        if let Some(cardinality) = u.arbitrary()? {
+            builder = builder.cardinality(cardinality);
+        }
+        for tag in tags(u)? {
+            builder = builder.tag(&tag);
+        }
+        built(builder.build())
+    }
+}
+
+impl<'a> Arbitrary<'a> for DogStatsDEventOwned {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut builder = EventBuilder::new(&text(u)?, &text(u)?).alert_type(u.arbitrary()?);
+        if u.arbitrary()? {
+            builder = builder.timestamp(u.arbitrary()?);
+        }
+        if let Some(hostname) = opt_string(u, NAME_CHARS, MAX_NAME_LEN)? {
+            builder = builder.hostname(&hostname);
+        }
+        if u.arbitrary()? {
+            builder = builder.priority(u.choose(&["normal", "low"])?);
+        }
+        if let Some(aggregation_key) = opt_string(u, NAME_CHARS, MAX_NAME_LEN)? {
+            builder = builder.aggregation_key(&aggregation_key);
+        }
+        if let Some(source_type_name) = opt_string(u, NAME_CHARS, MAX_NAME_LEN)? {
+            builder = builder.source_type_name(&source_type_name);
+        }
+        if let Some(external_data) = opt_string(u, TAG_CHARS, MAX_NAME_LEN)? {
+            builder = builder.external_data(&external_data);
+        }
+        if let Some(cardinality) = u.arbitrary()? {
+            builder = builder.cardinality(cardinality);
+        }
+        for tag in tags(u)? {
+            builder = builder.tag(&tag);
+        }
+        built(builder.build())
+    }
+}
+
+impl<'a> Arbitrary<'a> for DogStatsDServiceCheckOwned {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut builder = ServiceCheckBuilder::new(&name(u)?, u.arbitrary()?);
+        if u.arbitrary()? {
+            builder = builder.timestamp(u.arbitrary()?);
+        }
+        if let Some(hostname) = opt_string(u, NAME_CHARS, MAX_NAME_LEN)? {
+            builder = builder.hostname(&hostname);
+        }
+        if u.arbitrary()? {
+            builder = builder.message(&text(u)?);
+        }
+        if let Some(external_data) = opt_string(u, TAG_CHARS, MAX_NAME_LEN)? {
+            builder = builder.external_data(&external_data);
+        }
+        if let Some(cardinality) = u.arbitrary()? {
+            builder = builder.cardinality(cardinality);
+        }
+        for tag in tags(u)? {
+            builder = builder.tag(&tag);
+        }
+        built(builder.build())
+    }
+}
+
+impl<'a> Arbitrary<'a> for DogStatsDMsgOwned {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=2)? {
+            0 => DogStatsDMsgOwned::Metric(u.arbitrary()?),
+            1 => DogStatsDMsgOwned::Event(u.arbitrary()?),
+            _ => DogStatsDMsgOwned::ServiceCheck(u.arbitrary()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dogstatsdmsg::DogStatsDMsg;
+
+    #[test]
+    fn arbitrary_msgs_parse_back() {
+        // any bytes will do, Unstructured falls back to defaults once they run out
+        let bytes: Vec<u8> = (0..64 * 1024u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        let mut u = Unstructured::new(&bytes);
+        let mut num_msgs = 0;
+        while !u.is_empty() {
+            let msg: DogStatsDMsgOwned = u.arbitrary().unwrap();
+            let parsed = DogStatsDMsg::new(msg.raw_msg()).unwrap();
+            assert_eq!(DogStatsDMsgOwned::from(&parsed), msg);
+            num_msgs += 1;
+        }
+        assert!(num_msgs > 100);
+    }
+}
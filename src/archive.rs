@@ -0,0 +1,271 @@
+use std::cell::{Cell, RefCell};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use thiserror::Error;
+use tracing::{debug, info};
+
+use crate::{gzip::is_gzip, xz::is_xz, zstd::is_zstd};
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("Invalid archive entry glob: {0}")]
+    Pattern(#[from] glob::PatternError),
+    #[error("IO Error")]
+    Io(#[from] std::io::Error),
+    #[error("No archive entries matched '{0}'")]
+    NoMatchingEntries(String),
+}
+
+/// Tar archives are laid out in blocks of this many bytes
+const BLOCK_LEN: u64 = 512;
+
+/// The decoded archive, shared by `MatchingEntries` and the entry being read
+#[derive(Clone)]
+struct SharedStream<'a>(Rc<RefCell<Box<dyn Read + 'a>>>);
+
+impl Read for SharedStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+/// The contents of a single archive entry, read straight out of the archive
+pub struct ArchiveEntry<'a> {
+    stream: SharedStream<'a>,
+    /// Bytes of the entry not read yet, zeroed once `MatchingEntries` moves
+    /// past the entry
+    remaining: Rc<Cell<u64>>,
+}
+
+impl Read for ArchiveEntry<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.remaining.get();
+        let len = buf
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        if len == 0 {
+            return Ok(0);
+        }
+        let num_read = self.stream.read(&mut buf[..len])?;
+        self.remaining.set(remaining - num_read as u64);
+        Ok(num_read)
+    }
+}
+
+/// The regular file entries of a tar archive whose path matches a glob, in
+/// archive order. Tar archives can only be walked sequentially, so each entry
+/// is read out of the archive as it is reached instead of being buffered, and
+/// whatever is left of it is skipped once the next one is asked for.
+pub struct MatchingEntries<'a> {
+    stream: SharedStream<'a>,
+    pattern: glob::Pattern,
+    entry_glob: String,
+    /// Unread bytes of the current entry
+    current: Rc<Cell<u64>>,
+    /// Bytes after the current entry that pad it to a whole block
+    padding: u64,
+    found: bool,
+}
+
+impl<'a> MatchingEntries<'a> {
+    /// The next matching entry with its path. Empty entries are skipped. An
+    /// archive without a single matching entry is a `NoMatchingEntries` error.
+    pub fn next_entry(&mut self) -> Result<Option<(PathBuf, ArchiveEntry<'a>)>, ArchiveError> {
+        loop {
+            let skip = self.current.replace(0) + self.padding;
+            self.padding = 0;
+            io::copy(&mut self.stream.clone().take(skip), &mut io::sink())?;
+
+            // A fresh archive per entry only ever reads its headers (long
+            // names and pax extensions included), the entry data is left in
+            // the stream for the `ArchiveEntry`
+            let mut archive = tar::Archive::new(self.stream.clone());
+            let (path, size, is_file) = match archive.entries()?.next() {
+                Some(entry) => {
+                    let entry = entry?;
+                    (
+                        entry.path()?.into_owned(),
+                        entry.size(),
+                        entry.header().entry_type().is_file(),
+                    )
+                }
+                None if self.found => return Ok(None),
+                None => return Err(ArchiveError::NoMatchingEntries(self.entry_glob.clone())),
+            };
+            self.current = Rc::new(Cell::new(size));
+            self.padding = (BLOCK_LEN - size % BLOCK_LEN) % BLOCK_LEN;
+
+            if !is_file {
+                continue;
+            }
+            if !self.pattern.matches_path(&path) {
+                debug!("Skipping archive entry {}", path.display());
+                continue;
+            }
+            if size == 0 {
+                debug!("Skipping empty archive entry {}", path.display());
+                continue;
+            }
+            info!("Found archive entry {} ({} bytes)", path.display(), size);
+            self.found = true;
+            let entry = ArchiveEntry {
+                stream: self.stream.clone(),
+                remaining: self.current.clone(),
+            };
+            return Ok(Some((path, entry)));
+        }
+    }
+}
+
+/// Reads a tar archive, optionally gzip, zstd or xz compressed, and returns
+/// the regular files whose path matches `entry_glob`, in archive order. See
+/// `MatchingEntries`.
+pub fn matching_entries<'a>(
+    archive_reader: impl Read + 'a,
+    entry_glob: &str,
+) -> Result<MatchingEntries<'a>, ArchiveError> {
+    let pattern = glob::Pattern::new(entry_glob)?;
+
+    let mut buf_reader = BufReader::new(archive_reader);
+    let header = buf_reader.fill_buf()?;
+    let decoded: Box<dyn Read + 'a> = if header.len() >= 4 && is_zstd(&header[0..4]) {
+        info!("Detected zstd compressed archive.");
+        Box::new(zstd::Decoder::new(buf_reader)?)
+    } else if is_gzip(header) {
+        info!("Detected gzip compressed archive.");
+        Box::new(flate2::read::GzDecoder::new(buf_reader))
+    } else if is_xz(header) {
+        info!("Detected xz compressed archive.");
+        Box::new(xz2::read::XzDecoder::new(buf_reader))
+    } else {
+        Box::new(buf_reader)
+    };
+
+    Ok(MatchingEntries {
+        stream: SharedStream(Rc::new(RefCell::new(decoded))),
+        pattern,
+        entry_glob: entry_glob.to_string(),
+        current: Rc::new(Cell::new(0)),
+        padding: 0,
+        found: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn build_tar(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn read_all(archive: &[u8], entry_glob: &str) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut entries = matching_entries(archive, entry_glob).unwrap();
+        let mut read = Vec::new();
+        while let Some((path, mut entry)) = entries.next_entry().unwrap() {
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).unwrap();
+            read.push((path, contents));
+        }
+        read
+    }
+
+    #[test]
+    fn plain_tar_entries_are_filtered_by_glob() {
+        let archive = build_tar(&[
+            ("captures/a.txt", "my.metric:1|g\n"),
+            ("captures/b.txt", "my.metric:2|g\n"),
+            ("README.md", "not a capture"),
+        ]);
+
+        let entries = read_all(&archive, "captures/*.txt");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, PathBuf::from("captures/a.txt"));
+        assert_eq!(entries[0].1, b"my.metric:1|g\n");
+        assert_eq!(entries[1].0, PathBuf::from("captures/b.txt"));
+    }
+
+    #[test]
+    fn gzip_tar_entries_are_read() {
+        let archive = build_tar(&[("a.txt", "my.metric:1|g\n")]);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&archive).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let entries = read_all(&compressed, "*");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].1, b"my.metric:1|g\n");
+    }
+
+    #[test]
+    fn zstd_tar_entries_are_read() {
+        let archive = build_tar(&[("a.txt", "my.metric:1|g\n")]);
+        let compressed = zstd::encode_all(&archive[..], 0).unwrap();
+
+        let entries = read_all(&compressed, "*.txt");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].1, b"my.metric:1|g\n");
+    }
+
+    #[test]
+    fn xz_tar_entries_are_read() {
+        let archive = build_tar(&[("a.txt", "my.metric:1|g\n")]);
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(&archive).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let entries = read_all(&compressed, "*.txt");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].1, b"my.metric:1|g\n");
+    }
+
+    #[test]
+    fn no_matching_entries_is_an_error() {
+        let archive = build_tar(&[("a.txt", "my.metric:1|g\n")]);
+        let mut entries = matching_entries(&archive[..], "*.pcap").unwrap();
+        let err = entries.next_entry().unwrap_err();
+        assert!(matches!(err, ArchiveError::NoMatchingEntries(_)));
+    }
+
+    #[test]
+    fn unread_entry_data_is_skipped() {
+        let long_name = format!("captures/{}.txt", "a".repeat(200));
+        let big = "my.metric:1|g\n".repeat(100);
+        let archive = build_tar(&[
+            ("captures/big.txt", big.as_str()),
+            (long_name.as_str(), "my.metric:2|g\n"),
+            ("captures/c.txt", "my.metric:3|g\n"),
+        ]);
+
+        let mut entries = matching_entries(&archive[..], "captures/*").unwrap();
+        let (_, mut big_entry) = entries.next_entry().unwrap().unwrap();
+        let mut partial = [0; 20];
+        big_entry.read_exact(&mut partial).unwrap();
+
+        let (path, _) = entries.next_entry().unwrap().unwrap();
+        assert_eq!(path, PathBuf::from(&long_name));
+        // Moving on leaves nothing for the previous entry to read
+        assert_eq!(big_entry.read(&mut partial).unwrap(), 0);
+
+        let (path, mut entry) = entries.next_entry().unwrap().unwrap();
+        assert_eq!(path, PathBuf::from("captures/c.txt"));
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "my.metric:3|g\n");
+        assert!(entries.next_entry().unwrap().is_none());
+    }
+}
@@ -0,0 +1,437 @@
+use std::{collections::VecDeque, io, pin::Pin, str::Utf8Error, time::Duration};
+
+use bytes::Bytes;
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+use tracing::warn;
+
+use crate::{
+    dogstatsdreader::{
+        input_type_of, Analytics, Codec, DogStatsDReaderOptions, InputType, Transport, HEADER_LEN,
+    },
+    dogstatsdreplayreader::payload_lines,
+    replay::{
+        check_record_length, decode_record, msg_credentials, replay_version, CaptureFileVersion,
+        ReplayReaderError, UnixCredentials,
+    },
+};
+
+/// Every frame starts with its length as a little-endian u32
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum AsyncDogStatsDReaderError {
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+    #[error("DSD Replay")]
+    Replay(#[from] ReplayReaderError),
+    #[error("Invalid UTF-8 sequence found in msg")]
+    InvalidUtf8Sequence(Utf8Error),
+    #[error("Msg of {size} bytes is larger than the max msg size of {max} bytes")]
+    MsgTooLarge { size: usize, max: usize },
+    #[error("Unsupported Operation: {0}")]
+    UnsupportedOperation(String),
+}
+
+enum AsyncInput {
+    Utf8,
+    Framed,
+    /// 'best_effort' is set when an unsupported version is read as a forced
+    /// one, records that don't decode are then skipped
+    Replay {
+        version: CaptureFileVersion,
+        best_effort: bool,
+        analytics: Box<Analytics>,
+        ended: bool,
+    },
+}
+
+/// Reads msgs from an `AsyncBufRead` without blocking the thread it runs on,
+/// the async counterpart of `DogStatsDReader`. Text, length prefix framed and
+/// replay inputs are detected the same way, pcaps and compressed inputs are
+/// only read by `DogStatsDReader`.
+pub struct AsyncDogStatsDReader<'a> {
+    reader: Pin<Box<dyn AsyncBufRead + Send + 'a>>,
+    input: AsyncInput,
+    current_messages: VecDeque<String>,
+    /// Credentials of the record `current_messages` were read from
+    current_credentials: Option<UnixCredentials>,
+    /// Capture time of the record `current_messages` were read from
+    current_timestamp: Option<Duration>,
+    buf: Vec<u8>,
+    max_msg_size: usize,
+}
+
+impl<'a> AsyncDogStatsDReader<'a> {
+    /// 'reader' should point to the beginning of a utf-8 encoded stream of
+    /// DogStatsD messages, a length prefix framed stream or a DogStatsD
+    /// Replay/Capture file
+    pub async fn new(
+        reader: impl AsyncBufRead + Send + 'a,
+    ) -> Result<Self, AsyncDogStatsDReaderError> {
+        Self::new_with_options(reader, &DogStatsDReaderOptions::default()).await
+    }
+
+    /// Like `new`, but reads with the given 'options' instead of the defaults
+    pub async fn new_with_options(
+        reader: impl AsyncBufRead + Send + 'a,
+        options: &DogStatsDReaderOptions,
+    ) -> Result<Self, AsyncDogStatsDReaderError> {
+        let mut reader: Pin<Box<dyn AsyncBufRead + Send + 'a>> = Box::pin(reader);
+        // Input may arrive in small pieces, so keep reading until the full
+        // header is available or the stream ends
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        while header.len() < HEADER_LEN {
+            let available = reader.fill_buf().await?;
+            if available.is_empty() {
+                break;
+            }
+            let num_read = available.len().min(HEADER_LEN - header.len());
+            header.extend_from_slice(&available[..num_read]);
+            reader.consume(num_read);
+        }
+        if header.is_empty() {
+            return Err(AsyncDogStatsDReaderError::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Input stream is empty",
+            )));
+        }
+        if let Some(codec) = Codec::detect(&header) {
+            return Err(AsyncDogStatsDReaderError::UnsupportedOperation(format!(
+                "{} compressed input, only DogStatsDReader decompresses",
+                codec.name()
+            )));
+        }
+
+        // Every binary format has a longer header, so a shorter stream can
+        // only be a msg or two, eg 'a:1|c'
+        let format = if header.len() < HEADER_LEN {
+            InputType::Utf8
+        } else {
+            input_type_of(
+                Bytes::copy_from_slice(&header),
+                options.force_replay_version,
+            )
+        };
+        let input = match format {
+            InputType::Replay => {
+                // The header has been read in full, records follow it
+                let (version, best_effort) = match (
+                    replay_version(Bytes::copy_from_slice(&header)),
+                    options.force_replay_version,
+                ) {
+                    (Ok(version), _) => (version, false),
+                    (Err(_), Some(forced)) => match CaptureFileVersion::from_number(forced) {
+                        Some(version) => {
+                            warn!("Replay file is of an unsupported version, decoding it as version {forced}. Records that don't decode are skipped and the results may be incomplete.");
+                            (version, true)
+                        }
+                        None => {
+                            return Err(ReplayReaderError::UnsupportedReplayVersion(forced).into())
+                        }
+                    },
+                    (Err(e), None) => return Err(e.into()),
+                };
+                AsyncInput::Replay {
+                    version,
+                    best_effort,
+                    analytics: Box::new(Analytics::new(Transport::UnixDatagram)),
+                    ended: false,
+                }
+            }
+            InputType::Pcap => {
+                return Err(AsyncDogStatsDReaderError::UnsupportedOperation(
+                    "pcap input, only DogStatsDReader reads pcaps".to_string(),
+                ))
+            }
+            InputType::Framed => AsyncInput::Framed,
+            InputType::Utf8 => AsyncInput::Utf8,
+        };
+        // The header of text and framed inputs is part of the first msg
+        if !matches!(input, AsyncInput::Replay { .. }) {
+            reader = Box::pin(std::io::Cursor::new(header).chain(reader));
+        }
+
+        Ok(Self {
+            reader,
+            input,
+            current_messages: VecDeque::new(),
+            current_credentials: None,
+            current_timestamp: None,
+            buf: Vec::new(),
+            max_msg_size: options.max_msg_size,
+        })
+    }
+
+    /// read_msg populates the given String with a dogstatsd message
+    /// and returns the number of messages read (currently always 1)
+    pub async fn read_msg(&mut self, s: &mut String) -> Result<usize, AsyncDogStatsDReaderError> {
+        loop {
+            if let Some(line) = self.current_messages.pop_front() {
+                s.insert_str(0, &line);
+                if let AsyncInput::Replay { analytics, .. } = &mut self.input {
+                    analytics.total_messages += 1;
+                    analytics.message_length.add(line.len() as f64);
+                    analytics.record_credentials(self.current_credentials);
+                }
+                return Ok(1);
+            }
+            let more = match self.input {
+                AsyncInput::Utf8 => return self.read_line(s).await,
+                AsyncInput::Framed => self.read_frame().await?,
+                AsyncInput::Replay { .. } => self.read_record().await?,
+            };
+            if !more {
+                return Ok(0);
+            }
+        }
+    }
+
+    /// Same rules as `Utf8DogStatsDReader`, an empty line ends the input
+    async fn read_line(&mut self, s: &mut String) -> Result<usize, AsyncDogStatsDReaderError> {
+        let start = s.len();
+        // Room for a CRLF line ending, past that the line is too long
+        let limit = self.max_msg_size + 2;
+        let num_read = (&mut self.reader).take(limit as u64).read_line(s).await?;
+        if num_read == 0 {
+            return Ok(0);
+        }
+        let cut_off = num_read == limit && !s.ends_with('\n');
+        let new_len = s.trim_end().len();
+        s.truncate(new_len);
+        if cut_off || new_len.saturating_sub(start) > self.max_msg_size {
+            s.truncate(start);
+            return Err(AsyncDogStatsDReaderError::MsgTooLarge {
+                size: new_len.saturating_sub(start),
+                max: self.max_msg_size,
+            });
+        }
+        if new_len == 0 {
+            return Ok(0);
+        }
+        Ok(1)
+    }
+
+    /// Reads the msgs of the next frame, false at the end of the stream
+    async fn read_frame(&mut self) -> Result<bool, AsyncDogStatsDReaderError> {
+        let Some(len) = self.read_length_prefix().await? else {
+            return Ok(false);
+        };
+        if len > self.max_msg_size {
+            return Err(AsyncDogStatsDReaderError::MsgTooLarge {
+                size: len,
+                max: self.max_msg_size,
+            });
+        }
+        self.buf.resize(len, 0);
+        if !self.read_exact_or_truncated().await? {
+            return Ok(false);
+        }
+        let payload = std::str::from_utf8(&self.buf)
+            .map_err(AsyncDogStatsDReaderError::InvalidUtf8Sequence)?;
+        self.current_messages.extend(
+            payload
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        );
+        Ok(true)
+    }
+
+    /// Reads the msgs of the next replay record, false once all records are read
+    async fn read_record(&mut self) -> Result<bool, AsyncDogStatsDReaderError> {
+        let AsyncInput::Replay {
+            version,
+            best_effort,
+            ended,
+            ..
+        } = self.input
+        else {
+            unreachable!("only called for replay inputs");
+        };
+        if ended {
+            return Ok(false);
+        }
+        let len = match self.read_length_prefix().await? {
+            // 0 separates the records from the tagger state
+            Some(0) | None => {
+                self.end_records();
+                return Ok(false);
+            }
+            Some(len) => len,
+        };
+        check_record_length(len, self.max_msg_size)?;
+        self.buf.resize(len, 0);
+        if !self.read_exact_or_truncated().await? {
+            self.end_records();
+            return Ok(false);
+        }
+        let msg = match decode_record(&self.buf, self.max_msg_size) {
+            Ok(msg) => msg,
+            Err(ReplayReaderError::ProtoDecode(e)) if best_effort => {
+                warn!("Skipping record that doesn't decode as the forced replay version: {e}");
+                return Ok(true);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let timestamp = version.record_timestamp(msg.timestamp);
+        self.current_credentials = msg_credentials(&msg);
+        self.current_timestamp = Some(timestamp);
+        let (lines, is_stream) =
+            payload_lines(&msg.payload).map_err(AsyncDogStatsDReaderError::InvalidUtf8Sequence)?;
+        if let AsyncInput::Replay { analytics, .. } = &mut self.input {
+            analytics.record_packet(timestamp, msg.payload.len() as u64);
+            if is_stream {
+                analytics.transport_type = Transport::UnixStream;
+            }
+        }
+        self.current_messages
+            .extend(lines.into_iter().map(String::from));
+        Ok(true)
+    }
+
+    fn end_records(&mut self) {
+        if let AsyncInput::Replay { ended, .. } = &mut self.input {
+            *ended = true;
+        }
+    }
+
+    /// None at the end of the stream, which may only end between frames
+    async fn read_length_prefix(&mut self) -> Result<Option<usize>, AsyncDogStatsDReaderError> {
+        if self.reader.fill_buf().await?.is_empty() {
+            return Ok(None);
+        }
+        self.buf.resize(LENGTH_PREFIX_SIZE, 0);
+        if !self.read_exact_or_truncated().await? {
+            return Ok(None);
+        }
+        let prefix = self.buf[..LENGTH_PREFIX_SIZE]
+            .try_into()
+            .expect("prefix is 4 bytes");
+        Ok(Some(u32::from_le_bytes(prefix) as usize))
+    }
+
+    /// Fills `buf`, false when the stream ended first
+    async fn read_exact_or_truncated(&mut self) -> Result<bool, AsyncDogStatsDReaderError> {
+        match self.reader.read_exact(&mut self.buf).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                warn!("Encountered unexpected Eof, likely a truncated stream. Stream is incomplete and processing is done.");
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns a snapshot of the current analytics, only available for replay inputs
+    pub fn get_analytics(&self) -> Option<Analytics> {
+        match &self.input {
+            AsyncInput::Replay { analytics, .. } => Some(analytics.as_ref().clone()),
+            AsyncInput::Utf8 | AsyncInput::Framed => None,
+        }
+    }
+
+    /// Credentials of the process that sent the most recently read msg.
+    /// Only available for replay files captured with credentials.
+    pub fn last_msg_credentials(&self) -> Option<UnixCredentials> {
+        self.current_credentials
+    }
+
+    /// When the packet of the most recently read msg was captured. Only
+    /// available for replay inputs.
+    pub fn last_msg_timestamp(&self) -> Option<Duration> {
+        self.current_timestamp
+    }
+
+    /// What the input was detected as, eg 'dogstatsd-replay'
+    pub fn input_description(&self) -> String {
+        match self.input {
+            AsyncInput::Utf8 => InputType::Utf8.to_string(),
+            AsyncInput::Framed => InputType::Framed.to_string(),
+            AsyncInput::Replay { .. } => InputType::Replay.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dogstatsdreplayreader::dogstatsd::unix::UnixDogstatsdMsg;
+    use crate::replay::ReplayWriter;
+
+    async fn read_all(reader: &mut AsyncDogStatsDReader<'_>) -> Vec<String> {
+        let mut msgs = Vec::new();
+        let mut s = String::new();
+        while reader.read_msg(&mut s).await.unwrap() > 0 {
+            msgs.push(s.clone());
+            s.clear();
+        }
+        msgs
+    }
+
+    #[tokio::test]
+    async fn reads_text() {
+        let input: &[u8] = b"my.metric:1|g\n_sc|my.check|0\nmy.metric:2|c";
+        let mut reader = AsyncDogStatsDReader::new(input).await.unwrap();
+        assert_eq!(reader.input_description(), "utf-8 text");
+        assert_eq!(
+            read_all(&mut reader).await,
+            ["my.metric:1|g", "_sc|my.check|0", "my.metric:2|c"]
+        );
+        assert!(reader.get_analytics().is_none());
+
+        // shorter than any binary header
+        let mut reader = AsyncDogStatsDReader::new(&b"a:1|c"[..]).await.unwrap();
+        assert_eq!(reader.input_description(), "utf-8 text");
+        assert_eq!(read_all(&mut reader).await, ["a:1|c"]);
+    }
+
+    #[tokio::test]
+    async fn reads_framed() {
+        let mut input = Vec::new();
+        for payload in ["my.metric:1|c", "a:1|g\nb:2|g\n"] {
+            input.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            input.extend_from_slice(payload.as_bytes());
+        }
+        let mut reader = AsyncDogStatsDReader::new(input.as_slice()).await.unwrap();
+        assert_eq!(reader.input_description(), "length prefix framed");
+        assert_eq!(
+            read_all(&mut reader).await,
+            ["my.metric:1|c", "a:1|g", "b:2|g"]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_replay() {
+        let mut writer = ReplayWriter::new(Vec::new()).unwrap();
+        for (timestamp, payload) in [(1, "a:1|c\nb:1|c\n"), (2, "c:1|c\n")] {
+            writer
+                .write_msg(&UnixDogstatsdMsg {
+                    timestamp,
+                    payload: payload.as_bytes().to_vec(),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let replay_file = writer.finish(b"tagger state").unwrap();
+
+        let mut reader = AsyncDogStatsDReader::new(replay_file.as_slice())
+            .await
+            .unwrap();
+        assert_eq!(reader.input_description(), "dogstatsd-replay");
+        assert_eq!(read_all(&mut reader).await, ["a:1|c", "b:1|c", "c:1|c"]);
+        assert_eq!(reader.last_msg_timestamp(), Some(Duration::from_nanos(2)));
+        let analytics = reader.get_analytics().unwrap();
+        assert_eq!(analytics.total_packets, 2);
+        assert_eq!(analytics.total_messages, 3);
+    }
+
+    #[tokio::test]
+    async fn compressed_input_is_unsupported() {
+        let compressed = zstd::encode_all(&b"my.metric:1|g\n"[..], 0).unwrap();
+        assert!(matches!(
+            AsyncDogStatsDReader::new(compressed.as_slice()).await,
+            Err(AsyncDogStatsDReaderError::UnsupportedOperation(_))
+        ));
+    }
+}
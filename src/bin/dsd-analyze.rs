@@ -1,12 +1,31 @@
 use chrono::{NaiveDateTime, TimeZone, Utc};
 use human_bytes::human_bytes;
 use std::time::Duration;
-use tracing::error;
+use tracing::{error, info, warn};
 
-use clap::Parser;
-use dogstatsd_utils::analysis::analyze_msgs;
-use dogstatsd_utils::dogstatsdreader::DogStatsDReader;
+use clap::{Parser, ValueEnum};
+use dogstatsd_utils::addrfilter::{AddrFilter, IpNetwork};
+use dogstatsd_utils::analysis::{
+    AnalysisOptions, BatchStatsVisitor, DogStatsDBatchStats, LadingSchema, RollupRule,
+};
+use dogstatsd_utils::analyzers::{
+    write_contexts_csv, write_contexts_jsonl, CardinalityVisitor, ChurnVisitor, ContextsVisitor,
+    SchemaVisitor, TimeseriesVisitor,
+};
+use dogstatsd_utils::cache::{self, CacheError};
+use dogstatsd_utils::dogstatsdmsg::DogStatsDMsgKind;
+use dogstatsd_utils::dogstatsdreader::{
+    dir_files, DirOrder, DogStatsDReader, DogStatsDReaderOptions, ReaderCheckpoint,
+    DEFAULT_MAX_MSG_SIZE,
+};
+use dogstatsd_utils::filter::MsgFilter;
 use dogstatsd_utils::init_logging;
+use dogstatsd_utils::profiling::{serve_pprof, ProfilingError};
+use dogstatsd_utils::rate::{parse_interval, parse_timestamp};
+use dogstatsd_utils::report::{privatize, render_report, PrivacyOptions, ReportError};
+use dogstatsd_utils::validation::ValidationRules;
+use dogstatsd_utils::visitor::{visit_msgs_with_mode, MsgVisitor, ParseMode};
+use regex::Regex;
 use sketches_ddsketch::DDSketch;
 
 use std::fs::{self};
@@ -24,22 +43,324 @@ pub enum AnalyzeError {
     Serde(#[from] serde_yaml::Error),
     #[error("Serde Error json")]
     SerdeJSON(#[from] serde_json::Error),
+    #[error("Analysis cache")]
+    Cache(#[from] CacheError),
+    #[error("Could not render report")]
+    Report(#[from] ReportError),
+    #[error("Could not serve profiles")]
+    Profiling(#[from] ProfilingError),
+    #[error("Invalid arguments: {0}")]
+    InvalidArgs(String),
+}
+
+/// Order the files of a directory input are read in
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FileOrder {
+    /// By file name
+    Name,
+    /// Least recently modified first
+    Modified,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Kind {
+    Metric,
+    Event,
+    ServiceCheck,
+}
+
+/// Analyses that can run alongside the summary in the same read pass
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Analysis {
+    /// Metric names with the most contexts and tag keys with the most values
+    Cardinality,
+    /// Msgs and bytes per --timeseries-interval of capture time
+    Timeseries,
+    /// Msgs breaking the documented name and tag limits
+    Schema,
+    /// Contexts added and expired per --timeseries-interval of capture time
+    Churn,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum ContextsFormat {
+    /// A header row, then 'msgs,name,tags' per context
+    Csv,
+    /// One json object per context per line
+    Jsonl,
 }
 
 /// Analyze DogStatsD traffic messages
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// File(s) containing dogstatsd data
+    /// File(s) containing dogstatsd data, the files of a directory are read
+    /// one after the other
     input: Vec<String>,
 
+    /// Order the files of a directory input are read in
+    #[arg(long, value_enum, default_value_t = FileOrder::Name)]
+    dir_order: FileOrder,
+
     /// Emit lading DSD config
     #[arg(long, short, default_value_t = false)]
     lading_config: bool,
 
+    /// Layout of the emitted lading config, 'confrange' for the lading this
+    /// tool is built against or 'min-max' for releases from before ConfRange
+    #[arg(long, default_value = "confrange")]
+    lading_schema: LadingSchema,
+
     /// Show all unique tags with count
     #[arg(long, short, default_value_t = false)]
     print_unique_tags: bool,
+
+    /// Treat input(s) as tar archives (optionally gzip, zstd or xz compressed)
+    /// and analyze every entry matching this glob, eg 'captures/*.zstd'
+    #[arg(long)]
+    archive_glob: Option<String>,
+
+    /// Bind a unix datagram socket at PATH and analyze msgs as clients send
+    /// them, like the agent's dogstatsd_socket, instead of reading input(s)
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["input", "archive_glob"])]
+    listen_unix: Option<String>,
+
+    /// Like --listen-unix, but bind a unix stream socket that clients send
+    /// length prefixed msgs over, like the agent's dogstatsd_stream_socket
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["input", "archive_glob", "listen_unix"])]
+    listen_unix_stream: Option<String>,
+
+    /// Keep reading the input as it grows, like 'tail -f', and print the
+    /// analysis once it stopped growing for --idle-timeout
+    #[arg(short, long, requires = "input", conflicts_with_all = ["archive_glob"])]
+    follow: bool,
+
+    /// Memory map the input file(s) instead of reading them through a
+    /// buffer, faster for repeatedly analyzing large uncompressed captures
+    #[arg(long, requires = "input", conflicts_with_all = ["archive_glob", "follow"])]
+    mmap: bool,
+
+    /// Resume from where the checkpoint in this file got to, if it exists,
+    /// and write where this analysis got to back to it, eg to only analyze
+    /// what was added to a growing capture since the last run. A single
+    /// replay or pcap input only, --skip only applies to the first run.
+    #[arg(long, value_name = "PATH", requires = "input", conflicts_with_all = ["archive_glob", "follow", "mmap"])]
+    checkpoint: Option<String>,
+
+    /// When listening or following, stop and print the analysis once no msgs
+    /// arrived for this long, eg '30s'
+    #[arg(long, default_value = "10s")]
+    idle_timeout: String,
+
+    /// Only count metric tags with these keys towards tags and contexts, eg to
+    /// match the tags a downstream pipeline keeps
+    #[arg(long, value_delimiter = ',')]
+    include_tags: Option<Vec<String>>,
+
+    /// Ignore metric tags with these keys when counting tags and contexts
+    #[arg(long, value_delimiter = ',')]
+    exclude_tags: Vec<String>,
+
+    /// Count metric names matching REGEX under NAME, eg 'shard_\d+=shard_N'.
+    /// '$1' in NAME refers to the first capture group. Can be given multiple
+    /// times, the first matching rule wins.
+    #[arg(long, value_name = "REGEX=NAME")]
+    rollup: Vec<RollupRule>,
+
+    /// Estimate the number of unique tags and contexts in fixed memory instead
+    /// of counting them exactly, for captures with too many to keep every one.
+    /// Estimates are within about 1%, per tag and per name counts are left out.
+    #[arg(long, default_value_t = false, conflicts_with = "print_unique_tags")]
+    approximate_cardinality: bool,
+
+    /// Only analyze msgs whose metric or service check name, or event title,
+    /// matches this regex
+    #[arg(long, value_name = "REGEX")]
+    name: Option<Regex>,
+
+    /// Only analyze msgs carrying this tag, 'key:value' or just 'key'. Can be
+    /// given multiple times, msgs must carry every one of them.
+    #[arg(long)]
+    tag: Vec<String>,
+
+    /// Only analyze msgs of these kinds, eg 'metric,event'
+    #[arg(long, value_enum, value_delimiter = ',')]
+    kind: Vec<Kind>,
+
+    /// Only analyze packets captured at or after this time, rfc3339 (eg
+    /// '2024-05-01T12:00:00Z') or unix seconds. Replay and pcap inputs only.
+    #[arg(long, value_name = "TIME")]
+    start: Option<String>,
+
+    /// Only analyze packets captured before this time, like --start
+    #[arg(long, value_name = "TIME")]
+    end: Option<String>,
+
+    /// Only analyze packets sent from this IP address or network, eg '10.0.0.1'
+    /// or '10.0.0.0/8'. Can be given multiple times, packets must be sent
+    /// from one of them. Pcap inputs only.
+    #[arg(long, value_name = "ADDR")]
+    src: Vec<IpNetwork>,
+
+    /// Only analyze packets sent to this IP address or network, like --src
+    #[arg(long, value_name = "ADDR")]
+    dst: Vec<IpNetwork>,
+
+    /// Skip the first N msgs (that match the filters), eg to page through a
+    /// huge input along with --limit
+    #[arg(long, value_name = "N")]
+    skip: Option<u64>,
+
+    /// Stop after analyzing N msgs
+    #[arg(long, value_name = "N")]
+    limit: Option<u64>,
+
+    /// Always analyze the input(s), even if a cached analysis of the same
+    /// content exists. Results are cached per content in $XDG_CACHE_HOME.
+    #[arg(long, default_value_t = false)]
+    no_cache: bool,
+
+    /// Msgs (or packets, for replay and pcap inputs) larger than this many
+    /// bytes are an error rather than being read
+    #[arg(long, default_value_t = DEFAULT_MAX_MSG_SIZE)]
+    max_msg_size: usize,
+
+    /// Decode replay files of a newer, unsupported version as if they were
+    /// version N. Best effort, records that don't decode are skipped.
+    #[arg(long, value_name = "N")]
+    force_replay_version: Option<u8>,
+
+    /// Treat msgs with a malformed field or separator (a double or trailing
+    /// pipe, an empty '|#' tag section) as unparseable instead of skipping
+    /// the field
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+
+    /// Render the analysis with this minijinja (jinja2 syntax) template
+    /// instead of printing the default summary, eg to produce markdown for a ticket
+    #[arg(long)]
+    report_template: Option<String>,
+
+    /// Add laplace noise with this epsilon to per-name counts (metric names,
+    /// tags and hosts) before printing, smaller values add more noise
+    #[arg(long, value_name = "EPSILON")]
+    noise_epsilon: Option<f64>,
+
+    /// Leave out metric names, tags and hosts seen fewer than this many times,
+    /// use with --noise-epsilon to share a report outside the org
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    redact_below: u32,
+
+    /// Also run these analyses over the same read pass, eg
+    /// 'cardinality,timeseries,schema'
+    #[arg(long, value_delimiter = ',')]
+    analyses: Vec<Analysis>,
+
+    /// Bucket width of the timeseries and churn analyses, eg '10s' or '1m'
+    #[arg(long, default_value = "1m")]
+    timeseries_interval: String,
+
+    /// Contexts expire in the churn analysis once they weren't seen for this
+    /// long, like with the agent's dogstatsd_context_expiry_seconds
+    #[arg(long, default_value = "20s")]
+    context_expiry: String,
+
+    /// Write every distinct metric context (name and sorted tags) with its
+    /// number of msgs to this file, most msgs first. Contexts are exported as
+    /// they are, so this can't be combined with the privacy options.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["noise_epsilon", "redact_below"])]
+    export_contexts: Option<String>,
+
+    /// Format of the --export-contexts file
+    #[arg(long, value_enum, default_value_t = ContextsFormat::Csv)]
+    export_contexts_format: ContextsFormat,
+
+    /// Only export this many contexts, the ones with the most msgs
+    #[arg(long, value_name = "N", default_value_t = 10_000)]
+    export_contexts_limit: usize,
+
+    /// Serve cpu profiles of this tool on this address, eg 'localhost:6060', for
+    /// `go tool pprof http://localhost:6060/debug/pprof/profile`. Needs the 'pprof' feature.
+    #[arg(long, value_name = "ADDR")]
+    pprof: Option<String>,
+}
+
+fn analysis_options(args: &Args) -> AnalysisOptions {
+    AnalysisOptions {
+        include_tags: args.include_tags.clone(),
+        exclude_tags: args.exclude_tags.clone(),
+        rollups: args.rollup.clone(),
+        approximate_cardinality: args.approximate_cardinality,
+    }
+}
+
+fn dir_order(order: FileOrder) -> DirOrder {
+    match order {
+        FileOrder::Name => DirOrder::Name,
+        FileOrder::Modified => DirOrder::Modified,
+    }
+}
+
+/// 'inputs' with every directory replaced by its files, so they can be
+/// hashed for the cache like any other input
+fn input_files(inputs: &[String], order: DirOrder) -> Result<Vec<String>, AnalyzeError> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if !Path::new(input).is_dir() {
+            files.push(input.clone());
+            continue;
+        }
+        let in_dir = dir_files(input, order)?;
+        if in_dir.is_empty() {
+            return Err(AnalyzeError::InvalidArgs(format!(
+                "no files in directory '{}'",
+                input
+            )));
+        }
+        files.extend(
+            in_dir
+                .into_iter()
+                .map(|file| file.to_string_lossy().into_owned()),
+        );
+    }
+    Ok(files)
+}
+
+fn msg_filter(args: &Args) -> MsgFilter {
+    MsgFilter {
+        name: args.name.clone(),
+        tags: args.tag.clone(),
+        kinds: args
+            .kind
+            .iter()
+            .map(|kind| match kind {
+                Kind::Metric => DogStatsDMsgKind::Metric,
+                Kind::Event => DogStatsDMsgKind::Event,
+                Kind::ServiceCheck => DogStatsDMsgKind::ServiceCheck,
+            })
+            .collect(),
+    }
+}
+
+/// Every option that changes the analysis itself, rather than just how it is
+/// printed, must be part of this so cached results are invalidated
+fn cache_options(args: &Args) -> String {
+    format!(
+        "archive_glob={:?} dir_order={:?} max_msg_size={} force_replay_version={:?} strict={} start={:?} end={:?} src={:?} dst={:?} skip={:?} limit={:?} {:?} {:?}",
+        args.archive_glob,
+        args.dir_order,
+        args.max_msg_size,
+        args.force_replay_version,
+        args.strict,
+        args.start,
+        args.end,
+        args.src,
+        args.dst,
+        args.skip,
+        args.limit,
+        msg_filter(args),
+        analysis_options(args)
+    )
 }
 
 /// Prints out a quick summary of a given sketch
@@ -70,21 +391,392 @@ fn epoch_duration_to_datetime(epoch: Duration) -> chrono::DateTime<chrono::Utc>
     Utc.from_utc_datetime(&naive_datetime)
 }
 
+/// The optional analyses requested with --analyses and --export-contexts
+struct ExtraAnalyses {
+    cardinality: Option<CardinalityVisitor>,
+    timeseries: Option<TimeseriesVisitor>,
+    schema: Option<SchemaVisitor>,
+    churn: Option<ChurnVisitor>,
+    contexts: Option<ContextsVisitor>,
+}
+
+impl ExtraAnalyses {
+    fn new(args: &Args) -> Result<Self, AnalyzeError> {
+        let interval = || {
+            parse_interval(&args.timeseries_interval)
+                .filter(|interval| !interval.is_zero())
+                .ok_or_else(|| {
+                    AnalyzeError::InvalidArgs(format!(
+                        "timeseries interval '{}'",
+                        args.timeseries_interval
+                    ))
+                })
+        };
+        let timeseries = if args.analyses.contains(&Analysis::Timeseries) {
+            Some(TimeseriesVisitor::new(interval()?))
+        } else {
+            None
+        };
+        // the cardinality report names rare metrics and tag keys, with exact counts
+        if privacy_options(args).is_enabled() && args.analyses.contains(&Analysis::Cardinality) {
+            return Err(AnalyzeError::InvalidArgs(
+                "the cardinality analysis can't be combined with --noise-epsilon or --redact-below"
+                    .to_string(),
+            ));
+        }
+        let churn = if args.analyses.contains(&Analysis::Churn) {
+            let expiry = parse_interval(&args.context_expiry).ok_or_else(|| {
+                AnalyzeError::InvalidArgs(format!("context expiry '{}'", args.context_expiry))
+            })?;
+            Some(ChurnVisitor::new(interval()?, expiry))
+        } else {
+            None
+        };
+        Ok(Self {
+            cardinality: args
+                .analyses
+                .contains(&Analysis::Cardinality)
+                .then(CardinalityVisitor::new),
+            timeseries,
+            schema: args
+                .analyses
+                .contains(&Analysis::Schema)
+                .then(|| SchemaVisitor::new(ValidationRules::default())),
+            churn,
+            contexts: args.export_contexts.is_some().then(ContextsVisitor::new),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.cardinality.is_none()
+            && self.timeseries.is_none()
+            && self.schema.is_none()
+            && self.churn.is_none()
+            && self.contexts.is_none()
+    }
+
+    fn export_contexts(&self, args: &Args) -> Result<(), AnalyzeError> {
+        let (Some(contexts), Some(path)) = (&self.contexts, &args.export_contexts) else {
+            return Ok(());
+        };
+        let top = contexts.top(args.export_contexts_limit);
+        let out = io::BufWriter::new(fs::File::create(path)?);
+        match args.export_contexts_format {
+            ContextsFormat::Csv => write_contexts_csv(&top, out)?,
+            ContextsFormat::Jsonl => write_contexts_jsonl(&top, out)?,
+        }
+        info!(
+            "Exported {} of {} contexts to {}",
+            top.len(),
+            contexts.num_contexts(),
+            path
+        );
+        Ok(())
+    }
+
+    fn print(&self) {
+        if let Some(ref cardinality) = self.cardinality {
+            let report = cardinality.report(10);
+            println!();
+            println!("Cardinality:");
+            println!("\tMetric names by # of contexts:");
+            for (name, cnt) in report.names {
+                println!("\t\t{}: {}", name, cnt);
+            }
+            println!("\tTag keys by # of distinct values:");
+            for (key, cnt) in report.tag_keys {
+                println!("\t\t{}: {}", key, cnt);
+            }
+        }
+
+        if let Some(ref timeseries) = self.timeseries {
+            println!();
+            println!("Timeseries (per {:?}):", timeseries.interval());
+            for bucket in timeseries.buckets() {
+                println!(
+                    "\t{}: {} msgs, {}",
+                    epoch_duration_to_datetime(bucket.start).to_rfc3339(),
+                    bucket.msgs,
+                    human_bytes(bucket.bytes as f64)
+                );
+            }
+            if timeseries.untimed_msgs() > 0 {
+                println!(
+                    "\t{} msgs without a capture time (text inputs have none)",
+                    timeseries.untimed_msgs()
+                );
+            }
+        }
+
+        if let Some(ref schema) = self.schema {
+            let report = schema.report();
+            println!();
+            println!("Schema:");
+            println!(
+                "\t{} of {} msgs break a rule",
+                report.msgs_with_violations, report.num_msgs
+            );
+            for (rule, cnt, example) in report.violations {
+                println!("\t{}: {} (eg {})", rule, cnt, example);
+            }
+        }
+
+        if let Some(ref churn) = self.churn {
+            println!();
+            println!(
+                "Context Churn (per {:?}, contexts expire after {:?}):",
+                churn.interval(),
+                churn.expiry()
+            );
+            let windows = churn.windows();
+            for window in &windows {
+                println!(
+                    "\t{}: {} new, {} expired, {} live",
+                    epoch_duration_to_datetime(window.start).to_rfc3339(),
+                    window.new_contexts,
+                    window.expired_contexts,
+                    window.live_contexts
+                );
+            }
+            if let Some(peak) = windows.iter().map(|w| w.live_contexts).max() {
+                println!("\tPeak live contexts: {}", peak);
+            }
+            if churn.untimed_msgs() > 0 {
+                println!(
+                    "\t{} metric msgs without a capture time (text inputs have none)",
+                    churn.untimed_msgs()
+                );
+            }
+        }
+    }
+}
+
+fn privacy_options(args: &Args) -> PrivacyOptions {
+    PrivacyOptions {
+        noise_epsilon: args.noise_epsilon,
+        redact_below: args.redact_below,
+    }
+}
+
+/// The checkpoint in the file at 'path', None if there is no such file yet
+fn load_checkpoint(path: &str) -> Result<Option<ReaderCheckpoint>, AnalyzeError> {
+    match fs::read_to_string(path) {
+        Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn analyze(args: &Args, extras: &mut ExtraAnalyses) -> Result<DogStatsDBatchStats, AnalyzeError> {
+    let reader_options = DogStatsDReaderOptions {
+        max_msg_size: args.max_msg_size,
+        force_replay_version: args.force_replay_version,
+    };
+    let idle_timeout = || {
+        parse_interval(&args.idle_timeout)
+            .filter(|idle_timeout| !idle_timeout.is_zero())
+            .ok_or_else(|| {
+                AnalyzeError::InvalidArgs(format!("idle timeout '{}'", args.idle_timeout))
+            })
+    };
+    let mut resumed = false;
+    let mut reader = if let Some(ref socket_path) = args.listen_unix {
+        DogStatsDReader::from_unix_datagram_with_options(
+            socket_path,
+            Some(idle_timeout()?),
+            &reader_options,
+        )
+    } else if let Some(ref socket_path) = args.listen_unix_stream {
+        DogStatsDReader::from_unix_stream_with_options(
+            socket_path,
+            Some(idle_timeout()?),
+            &reader_options,
+        )
+    } else if args.follow {
+        let [input] = &args.input[..] else {
+            return Err(AnalyzeError::InvalidArgs(
+                "--follow reads a single input".to_string(),
+            ));
+        };
+        DogStatsDReader::follow_with_options(input, Some(idle_timeout()?), &reader_options)
+    } else if let Some(ref checkpoint_path) = args.checkpoint {
+        let [input] = &args.input[..] else {
+            return Err(AnalyzeError::InvalidArgs(
+                "--checkpoint reads a single input".to_string(),
+            ));
+        };
+        match load_checkpoint(checkpoint_path)? {
+            Some(checkpoint) => {
+                info!("Resuming after {} msgs of {}", checkpoint.msgs_read, input);
+                resumed = true;
+                DogStatsDReader::resume_from_path_with_options(input, &checkpoint, &reader_options)
+            }
+            None => DogStatsDReader::new_with_options(fs::File::open(input)?, &reader_options),
+        }
+    } else if let Some(ref entry_glob) = args.archive_glob {
+        match args.input.len() {
+            0 => DogStatsDReader::from_archive_with_options(
+                io::stdin().lock(),
+                entry_glob,
+                &reader_options,
+            ),
+            _ => {
+                let mut readers = Vec::new();
+                for input in &args.input {
+                    let file = fs::File::open(Path::new(input))?;
+                    readers.push(DogStatsDReader::from_archive_with_options(
+                        file,
+                        entry_glob,
+                        &reader_options,
+                    )?);
+                }
+                Ok(DogStatsDReader::from_readers(readers))
+            }
+        }
+    } else {
+        match args.input.len() {
+            1 if args.mmap => {
+                DogStatsDReader::from_path_mmap_with_options(&args.input[0], &reader_options)
+            }
+            1 => {
+                let file_path = Path::new(&args.input[0]);
+                let file = fs::File::open(file_path)?;
+                DogStatsDReader::new_with_options(file, &reader_options)
+            }
+            0 => DogStatsDReader::new_with_options(io::stdin().lock(), &reader_options),
+            _ if args.mmap => args
+                .input
+                .iter()
+                .map(|input| DogStatsDReader::from_path_mmap_with_options(input, &reader_options))
+                .collect::<Result<_, _>>()
+                .map(DogStatsDReader::from_readers),
+            _ => DogStatsDReader::from_paths_with_options(&args.input, &reader_options),
+        }
+    }?;
+    if args.start.is_some() || args.end.is_some() {
+        let parse = |timestamp: Option<&str>, default| match timestamp {
+            Some(timestamp) => parse_timestamp(timestamp)
+                .ok_or_else(|| AnalyzeError::InvalidArgs(format!("timestamp '{}'", timestamp))),
+            None => Ok(default),
+        };
+        reader = reader.with_time_range(
+            parse(args.start.as_deref(), Duration::ZERO)?,
+            parse(args.end.as_deref(), Duration::MAX)?,
+        )?;
+    }
+    if !args.src.is_empty() || !args.dst.is_empty() {
+        reader = reader.with_addr_filter(&AddrFilter {
+            sources: args.src.clone(),
+            destinations: args.dst.clone(),
+        })?;
+    }
+    let filter = msg_filter(args);
+    if !filter.is_empty() {
+        reader = reader.with_filter(filter);
+    }
+    if let Some(n) = args.skip.filter(|_| !resumed) {
+        reader = reader.skip_msgs(n);
+    }
+    if let Some(n) = args.limit {
+        reader = reader.limit_msgs(n);
+    }
+    // several inputs are forgotten as they are read, so detect up front
+    let input_type = reader.input_type();
+    let options = analysis_options(args);
+    let mut stats = BatchStatsVisitor::new(&options);
+    let mut visitors: Vec<&mut dyn MsgVisitor> = vec![&mut stats];
+    if let Some(ref mut cardinality) = extras.cardinality {
+        visitors.push(cardinality);
+    }
+    if let Some(ref mut timeseries) = extras.timeseries {
+        visitors.push(timeseries);
+    }
+    if let Some(ref mut schema) = extras.schema {
+        visitors.push(schema);
+    }
+    if let Some(ref mut churn) = extras.churn {
+        visitors.push(churn);
+    }
+    if let Some(ref mut contexts) = extras.contexts {
+        visitors.push(contexts);
+    }
+    let mode = if args.strict {
+        ParseMode::Strict
+    } else {
+        ParseMode::Lenient
+    };
+    // A partial analysis would be cached as if it covered the whole input
+    visit_msgs_with_mode(&mut reader, &mut visitors, mode)?;
+    if let Some(ref checkpoint_path) = args.checkpoint {
+        let checkpoint = reader.checkpoint()?;
+        fs::write(checkpoint_path, serde_json::to_string(&checkpoint)?)?;
+    }
+    let mut msg_stats = stats.into_stats(&mut reader);
+    msg_stats.input_type = input_type;
+    Ok(msg_stats)
+}
+
 fn main() -> Result<(), AnalyzeError> {
     init_logging();
-    let args = Args::parse();
+    let mut args = Args::parse();
+    args.input = input_files(&args.input, dir_order(args.dir_order))?;
+    if let Some(ref addr) = args.pprof {
+        serve_pprof(addr)?;
+    }
+
+    // stdin and sockets can't be hashed without consuming them, so only files are
+    // cached, and followed or checkpointed files are still growing
+    let cache_dir =
+        if args.no_cache || args.input.is_empty() || args.follow || args.checkpoint.is_some() {
+            None
+        } else {
+            cache::default_cache_dir()
+        };
+    let cache_key = match cache_dir {
+        Some(_) => Some(cache::cache_key(&args.input, &cache_options(&args))?),
+        None => None,
+    };
+    let mut extras = ExtraAnalyses::new(&args)?;
+    // Only the summary is cached, the other analyses need a fresh pass
+    let cached = match (&cache_dir, &cache_key) {
+        (Some(dir), Some(key)) if extras.is_empty() => cache::load(dir, key),
+        _ => None,
+    };
 
-    let mut reader = match args.input.len() {
-        1 => {
-            let file_path = Path::new(&args.input[0]);
-            let file = fs::File::open(file_path)?;
-            DogStatsDReader::new(file)
+    let mut msg_stats = match cached {
+        Some(msg_stats) => {
+            info!("Using cached analysis, pass --no-cache to re-analyze");
+            msg_stats
         }
-        0 => DogStatsDReader::new(io::stdin().lock()),
-        _ => DogStatsDReader::from_paths(args.input),
-    }?;
+        None => {
+            let msg_stats = analyze(&args, &mut extras)?;
+            if let (Some(dir), Some(key)) = (&cache_dir, &cache_key) {
+                if let Err(e) = cache::store(dir, key, &msg_stats) {
+                    warn!("Could not cache analysis in {}: {}", dir.display(), e);
+                }
+            }
+            msg_stats
+        }
+    };
+
+    let privacy_options = privacy_options(&args);
+    if privacy_options.is_enabled() {
+        let redacted = privatize(&mut msg_stats, &privacy_options, &mut rand::thread_rng());
+        info!("Redacted {} rare names", redacted);
+    }
+
+    extras.export_contexts(&args)?;
+
+    if let Some(ref template_path) = args.report_template {
+        let template = fs::read_to_string(template_path)?;
+        print!("{}", render_report(&template, &msg_stats)?);
+        return Ok(());
+    }
+
+    if let Some(ref input_type) = msg_stats.input_type {
+        println!("Input Type: {}", input_type);
+    }
 
-    let msg_stats = analyze_msgs(&mut reader)?;
     if let Some(ref reader_analytics) = msg_stats.reader_analytics {
         println!("Reader Analytics:");
         let first_timestamp = epoch_duration_to_datetime(reader_analytics.earliest_timestamp);
@@ -109,6 +801,84 @@ fn main() -> Result<(), AnalyzeError> {
             "\tMessage Length:\n{}",
             sketch_to_string(&reader_analytics.message_length)
         );
+
+        println!("Capture Health:");
+        println!("\tLargest Gap: {:?}", reader_analytics.largest_gap);
+        println!(
+            "\tOut of Order Packets: {}",
+            reader_analytics.out_of_order_packets
+        );
+        println!(
+            "\tTruncated Packets: {}",
+            reader_analytics.truncated_packets
+        );
+        for gap in &reader_analytics.suspicious_gaps {
+            println!(
+                "\tSuspicious gap of {:?} after {}",
+                gap.length,
+                epoch_duration_to_datetime(gap.start).to_rfc3339()
+            );
+        }
+        let warnings = reader_analytics.capture_health_warnings();
+        if warnings.is_empty() {
+            println!("\tNo gaps or ordering problems detected");
+        } else {
+            for warning in warnings {
+                println!("\tWARNING: {}", warning);
+            }
+            println!("\tWARNING: The capture itself may be incomplete");
+        }
+
+        if !reader_analytics.msgs_per_uid.is_empty() {
+            println!("Senders:");
+            let mut uids: Vec<(&u32, &u64)> = reader_analytics.msgs_per_uid.iter().collect();
+            uids.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            for (uid, cnt) in uids {
+                println!("\tuid {}: {} msgs", uid, cnt);
+            }
+            let mut pids: Vec<(&i32, &u64)> = reader_analytics.msgs_per_pid.iter().collect();
+            pids.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            for (pid, cnt) in pids.iter().take(10) {
+                println!("\tpid {}: {} msgs", pid, cnt);
+            }
+            if pids.len() > 10 {
+                println!("\t... and {} more pids", pids.len() - 10);
+            }
+            println!(
+                "\tMsgs without credentials: {}",
+                reader_analytics.msgs_without_credentials
+            );
+        }
+    }
+
+    if let Some(ref compression) = msg_stats.compression {
+        println!("Compression:");
+        println!("\tCodec: {}", compression.codec);
+        println!(
+            "\tCompressed Bytes: {}",
+            human_bytes(compression.compressed_bytes as f64)
+        );
+        println!(
+            "\tDecompressed Bytes: {}",
+            human_bytes(compression.decompressed_bytes as f64)
+        );
+        println!(
+            "\tCompression Ratio: {:.2}x",
+            compression.compression_ratio()
+        );
+        println!("\tDecompression Time: {:?}", compression.decompression_time);
+    }
+
+    println!("Timing:");
+    println!("\tRead Time: {:?}", msg_stats.read_duration);
+    println!("\tParse Time: {:?}", msg_stats.parse_duration);
+    if let Some(ref compression) = msg_stats.compression {
+        let dominant = if compression.decompression_time > msg_stats.parse_duration {
+            "decompression"
+        } else {
+            "parsing"
+        };
+        println!("\tRuntime is dominated by {}", dominant);
     }
 
     println!("Traffic Analytics:");
@@ -126,23 +896,169 @@ fn main() -> Result<(), AnalyzeError> {
         "# unicode tags per msg:\n{}",
         sketch_to_string(&msg_stats.num_unicode_tags)
     );
-    println!("# of Unique Tags:\n\t{}", msg_stats.unique_tags.len());
-    println!("# of Contexts:\n\t{}", msg_stats.num_contexts);
+    let approximate = if msg_stats.approximate_unique_tags.is_some() {
+        " (approximate)"
+    } else {
+        ""
+    };
+    println!(
+        "# of Unique Tags{}:\n\t{}",
+        approximate,
+        msg_stats.num_unique_tags()
+    );
+    println!(
+        "# of Contexts{}:\n\t{}",
+        approximate, msg_stats.num_contexts
+    );
     println!(
         "Unique Tag / # Contexts ratio:\n\t{:.2}",
-        (msg_stats.unique_tags.len() as f64) / (msg_stats.num_contexts as f64)
+        (msg_stats.num_unique_tags() as f64) / (msg_stats.num_contexts as f64)
     );
+    let contexts_per_name = msg_stats.contexts_per_name();
+    if !contexts_per_name.is_empty() {
+        println!("Contexts per Metric Name:");
+        for name in contexts_per_name.iter().take(10) {
+            println!(
+                "\t{}: {} ({:.2}% of contexts)",
+                name.name, name.contexts, name.percent_of_contexts
+            );
+        }
+        if contexts_per_name.len() > 10 {
+            println!("\t... and {} more names", contexts_per_name.len() - 10);
+        }
+    }
 
     println!();
     println!("Message Kind Breakdown:");
-    for (kind, (cnt, per_type)) in msg_stats.kind.iter() {
-        println!("\t{}: {}", kind, cnt);
-        if let Some(per_type) = per_type {
-            for (t, cnt) in per_type.iter() {
-                println!("\t\t{}: {}", t, cnt);
+    for share in msg_stats.kind_shares() {
+        let rates = match (share.msgs_per_second, share.bytes_per_second) {
+            (Some(msgs_per_second), Some(bytes_per_second)) => format!(
+                ", {:.2} msgs/sec, {}/sec",
+                msgs_per_second,
+                human_bytes(bytes_per_second)
+            ),
+            _ => String::new(),
+        };
+        println!(
+            "\t{}: {} ({:.2}% of msgs, {} / {:.2}% of bytes{})",
+            share.kind,
+            share.count,
+            share.percent_of_msgs,
+            human_bytes(share.bytes as f64),
+            share.percent_of_bytes,
+            rates
+        );
+        if let Some((_, Some(per_type))) = msg_stats.kind.get(&share.kind) {
+            let mut per_type: Vec<_> = per_type.iter().collect();
+            per_type.sort_by(|a, b| b.1.cmp(a.1));
+            for (t, cnt) in per_type {
+                let percent_of_kind = if share.count == 0 {
+                    0.0
+                } else {
+                    *cnt as f64 / share.count as f64 * 100.0
+                };
+                println!("\t\t{}: {} ({:.2}%)", t, cnt, percent_of_kind);
             }
         }
     }
+    println!();
+    println!("Host Identification:");
+    println!("\t# of Distinct Hosts: {}", msg_stats.hostnames.len());
+    let mut hostnames: Vec<(&String, &u32)> = msg_stats.hostnames.iter().collect();
+    hostnames.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    for (host, cnt) in hostnames.iter().take(10) {
+        println!("\t\t{}: {}", host, cnt);
+    }
+    if hostnames.len() > 10 {
+        println!("\t\t... and {} more", hostnames.len() - 10);
+    }
+    for (kind, cnt) in &msg_stats.msgs_missing_host {
+        println!("\t{} msgs missing a host: {}", kind, cnt);
+    }
+
+    if !msg_stats.cardinality_hints.is_empty() {
+        println!();
+        println!("Cardinality Hints:");
+        let mut hints: Vec<_> = msg_stats.cardinality_hints.iter().collect();
+        hints.sort_by(|a, b| b.1.cmp(a.1).then(a.0.wire_str().cmp(b.0.wire_str())));
+        for (cardinality, cnt) in hints {
+            println!(
+                "\t{}: {} ({:.2}% of msgs)",
+                cardinality,
+                cnt,
+                *cnt as f64 / msg_stats.num_msgs as f64 * 100.0
+            );
+        }
+    }
+
+    if msg_stats.num_msgs_with_parse_warnings > 0 {
+        println!();
+        println!(
+            "Malformed Fields ({} msgs analyzed after skipping a field):",
+            msg_stats.num_msgs_with_parse_warnings
+        );
+        let mut warnings: Vec<(&String, &u32)> = msg_stats.parse_warnings.iter().collect();
+        warnings.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (reason, cnt) in warnings {
+            println!("\t{}: {}", reason, cnt);
+        }
+    }
+
+    if msg_stats.num_msgs_with_duplicate_tags > 0 {
+        println!();
+        println!(
+            "Duplicate Tags: {} msgs ({:.2}% of msgs) repeat one of their tags",
+            msg_stats.num_msgs_with_duplicate_tags,
+            msg_stats.num_msgs_with_duplicate_tags as f64 / msg_stats.num_msgs as f64 * 100.0
+        );
+    }
+
+    if msg_stats.num_suspicious_msgs > 0 {
+        println!();
+        println!(
+            "Suspicious Msgs ({} valid msgs that look like client bugs):",
+            msg_stats.num_suspicious_msgs
+        );
+        let mut suspicious: Vec<(&String, &u32)> = msg_stats.suspicious.iter().collect();
+        suspicious.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (reason, cnt) in suspicious {
+            println!("\t{}: {}", reason, cnt);
+            // samples are raw msgs, which privacy options may not let out
+            if privacy_options.is_enabled() {
+                continue;
+            }
+            if let Some(sample) = msg_stats.suspicious_samples.get(reason) {
+                println!("\t\teg {}", sample);
+            }
+        }
+    }
+
+    if !msg_stats.names_absorbed_by_rollup.is_empty() {
+        println!();
+        println!("Name Rollups:");
+        let mut rollups: Vec<(&String, &u32)> = msg_stats.names_absorbed_by_rollup.iter().collect();
+        rollups.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (name, cnt) in rollups {
+            println!("\t{}: {} raw names", name, cnt);
+        }
+    }
+
+    let conflicts = msg_stats.metric_type_conflicts();
+    if !conflicts.is_empty() {
+        println!();
+        println!("Metric Type Conflicts:");
+        for conflict in conflicts {
+            let types: Vec<String> = conflict
+                .types
+                .iter()
+                .map(|(t, cnt)| format!("{}: {}", t, cnt))
+                .collect();
+            println!("\t{} ({})", conflict.name, types.join(", "));
+        }
+    }
+
+    extras.print();
+
     if args.print_unique_tags {
         println!("Unique tags:");
         let mut unique_tags: Vec<(&String, &u32)> = msg_stats.unique_tags.iter().collect();
@@ -157,7 +1073,7 @@ fn main() -> Result<(), AnalyzeError> {
 
     if args.lading_config {
         let str_lading_config = msg_stats
-            .to_lading_config_str()
+            .to_lading_config_str_with_schema(args.lading_schema)
             .expect("Error converting to lading config");
         println!("Lading Config:\n---\n{}---", str_lading_config);
     }
@@ -0,0 +1,81 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::PathBuf;
+
+use byte_unit::Byte;
+use clap::Parser;
+use thiserror::Error;
+use tracing::info;
+
+use dogstatsd_utils::corpus::{write_replay, CorpusGenerator, CorpusProfile, DEFAULT_CORPUS_SEED};
+use dogstatsd_utils::init_logging;
+
+/// Write a standardized benchmark corpus as text, zstd compressed text and a
+/// replay file. Corpora of the same profile, size and seed are identical, so
+/// they can be shared to reproduce performance issues.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Shape of the traffic: heavy-tags, many-contexts or events-heavy
+    #[arg(long)]
+    profile: CorpusProfile,
+
+    /// Size of the text corpus, eg '100MB' or '1GB'
+    #[arg(long, default_value = "100MB")]
+    size: String,
+
+    /// Directory the corpus files are written to
+    #[arg(short, long, default_value = ".")]
+    output_dir: PathBuf,
+
+    /// Seed for the msg generator
+    #[arg(long, default_value_t = DEFAULT_CORPUS_SEED)]
+    seed: u64,
+}
+
+#[derive(Error, Debug)]
+pub enum BenchCorpusError {
+    #[error("Invalid size, expected something like '100MB'")]
+    InvalidSize,
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+}
+
+fn main() -> Result<(), BenchCorpusError> {
+    init_logging();
+    let args = Args::parse();
+
+    let size = Byte::from_str(&args.size)
+        .map_err(|_| BenchCorpusError::InvalidSize)?
+        .get_bytes() as u64;
+    fs::create_dir_all(&args.output_dir)?;
+    let text_path = args.output_dir.join(format!("{}.txt", args.profile));
+    let zstd_path = args.output_dir.join(format!("{}.txt.zstd", args.profile));
+    let replay_path = args.output_dir.join(format!("{}.replay", args.profile));
+
+    info!("Writing {}", text_path.display());
+    let num_msgs = CorpusGenerator::new(args.profile, args.seed)
+        .write_text(size, BufWriter::new(File::create(&text_path)?))?;
+
+    info!("Writing {}", zstd_path.display());
+    zstd::stream::copy_encode(
+        BufReader::new(File::open(&text_path)?),
+        BufWriter::new(File::create(&zstd_path)?),
+        3,
+    )?;
+
+    info!("Writing {}", replay_path.display());
+    let num_records = write_replay(
+        BufReader::new(File::open(&text_path)?),
+        BufWriter::new(File::create(&replay_path)?),
+    )?;
+
+    println!(
+        "Wrote {} msgs ({} replay records) of the {} profile to {}",
+        num_msgs,
+        num_records,
+        args.profile,
+        args.output_dir.display()
+    );
+    Ok(())
+}
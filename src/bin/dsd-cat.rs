@@ -1,28 +1,161 @@
+use std::borrow::Cow;
 use std::fs;
 use std::fs::File;
-use std::io::stdout;
+use std::io::{stdout, Write};
 
 use std::io::{self};
 use std::path::Path;
+use std::time::Duration;
 use thiserror::Error;
 
-use dogstatsd_utils::analysis::print_msgs;
-use dogstatsd_utils::dogstatsdreader::DogStatsDReader;
+use dogstatsd_utils::addrfilter::{AddrFilter, IpNetwork};
+use dogstatsd_utils::analysis::{print_msgs, print_msgs_transformed};
+use dogstatsd_utils::dogstatsdmsg::{DogStatsDMsg, DogStatsDMsgKind, DogStatsDMsgOwned};
+use dogstatsd_utils::dogstatsdreader::{
+    DirOrder, DogStatsDReader, DogStatsDReaderOptions, DEFAULT_MAX_MSG_SIZE,
+};
+use dogstatsd_utils::filter::MsgFilter;
+use dogstatsd_utils::plan::ExecutionPlan;
+use dogstatsd_utils::rate::{parse_interval, parse_timestamp};
+use dogstatsd_utils::transform::inject_default_host;
 
-use clap::Parser;
+use clap::{ArgGroup, Parser, ValueEnum};
 use dogstatsd_utils::init_logging;
+use regex::Regex;
+use tracing::warn;
+
+/// Order the files of a directory input are read in
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FileOrder {
+    /// By file name
+    Name,
+    /// Least recently modified first
+    Modified,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Kind {
+    Metric,
+    Event,
+    ServiceCheck,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    /// dogstatsd wire format, one msg per line
+    Text,
+    /// One json object per msg per line, msgs that fail to parse are skipped
+    Json,
+}
 
 /// Take data from the specified input file and write it either to stdout or to a specified file.
-/// Data can be raw utf-8 text or a dogstatsd-replay file, optionally zstd encoded.
+/// Data can be raw utf-8 text or a dogstatsd-replay file, optionally zstd, gzip, lz4, xz or snappy encoded.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(group(ArgGroup::new("listen").args(["listen_unix", "listen_unix_stream", "follow"])))]
 struct Args {
-    /// File containing dogstatsd data
+    /// File containing dogstatsd data, or a directory whose files are read
+    /// one after the other
     input: Option<String>,
 
+    /// Order the files of a directory input are read in
+    #[arg(long, value_enum, default_value_t = FileOrder::Name)]
+    dir_order: FileOrder,
+
     /// Where output dogstatsd messages should go
     #[arg(short, long)]
     output: Option<String>,
+
+    /// Treat input as a tar archive (optionally gzip, zstd or xz compressed)
+    /// and read every entry matching this glob, eg 'captures/*.zstd'
+    #[arg(long)]
+    archive_glob: Option<String>,
+
+    /// Bind a unix datagram socket at PATH and read msgs as clients send
+    /// them, like the agent's dogstatsd_socket, instead of reading an input
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["input", "archive_glob"])]
+    listen_unix: Option<String>,
+
+    /// Like --listen-unix, but bind a unix stream socket that clients send
+    /// length prefixed msgs over, like the agent's dogstatsd_stream_socket
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["input", "archive_glob"])]
+    listen_unix_stream: Option<String>,
+
+    /// Keep reading INPUT as it grows, like 'tail -f', eg a capture the agent
+    /// is still writing
+    #[arg(short, long, requires = "input", conflicts_with_all = ["archive_glob"])]
+    follow: bool,
+
+    /// When listening or following, stop once no msgs arrived for this long,
+    /// eg '30s'. Without it msgs are read until interrupted.
+    #[arg(long, requires = "listen")]
+    idle_timeout: Option<String>,
+
+    /// Add this host to every msg that doesn't identify one, as a 'host:' tag
+    /// for metrics and as the 'h:' field for events and service checks
+    #[arg(long)]
+    default_host: Option<String>,
+
+    /// Only keep msgs whose metric or service check name, or event title,
+    /// matches this regex
+    #[arg(long, value_name = "REGEX")]
+    name: Option<Regex>,
+
+    /// Only keep msgs carrying this tag, 'key:value' or just 'key'. Can be
+    /// given multiple times, msgs must carry every one of them.
+    #[arg(long)]
+    tag: Vec<String>,
+
+    /// Only keep msgs of these kinds, eg 'metric,event'
+    #[arg(long, value_enum, value_delimiter = ',')]
+    kind: Vec<Kind>,
+
+    /// Only read packets captured at or after this time, rfc3339 (eg
+    /// '2024-05-01T12:00:00Z') or unix seconds. Replay and pcap inputs only.
+    #[arg(long, value_name = "TIME")]
+    start: Option<String>,
+
+    /// Only read packets captured before this time, like --start
+    #[arg(long, value_name = "TIME")]
+    end: Option<String>,
+
+    /// Only read packets sent from this IP address or network, eg '10.0.0.1'
+    /// or '10.0.0.0/8'. Can be given multiple times, packets must be sent
+    /// from one of them. Pcap inputs only.
+    #[arg(long, value_name = "ADDR")]
+    src: Vec<IpNetwork>,
+
+    /// Only read packets sent to this IP address or network, like --src
+    #[arg(long, value_name = "ADDR")]
+    dst: Vec<IpNetwork>,
+
+    /// Skip the first N msgs (that match the filters), eg to page through a
+    /// huge input along with --limit
+    #[arg(long, value_name = "N")]
+    skip: Option<u64>,
+
+    /// Stop after reading N msgs
+    #[arg(long, value_name = "N")]
+    limit: Option<u64>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Msgs (or packets, for replay and pcap inputs) larger than this many
+    /// bytes are an error rather than being read
+    #[arg(long, default_value_t = DEFAULT_MAX_MSG_SIZE)]
+    max_msg_size: usize,
+
+    /// Decode replay files of a newer, unsupported version as if they were
+    /// version N. Best effort, records that don't decode are skipped.
+    #[arg(long, value_name = "N")]
+    force_replay_version: Option<u8>,
+
+    /// Detect the input format and print what would be done, without
+    /// reading any msgs or creating the output
+    #[arg(long)]
+    dry_run: bool,
 }
 
 #[derive(Error, Debug)]
@@ -31,30 +164,183 @@ pub enum CatError {
     ReaderFailure(#[from] dogstatsd_utils::dogstatsdreader::DogStatsDReaderError),
     #[error("IO Error")]
     Io(#[from] io::Error),
+    #[error("Serde Error json")]
+    SerdeJSON(#[from] serde_json::Error),
+    #[error("Invalid arguments: {0}")]
+    InvalidArgs(String),
+}
+
+fn dir_order(order: FileOrder) -> DirOrder {
+    match order {
+        FileOrder::Name => DirOrder::Name,
+        FileOrder::Modified => DirOrder::Modified,
+    }
+}
+
+fn msg_filter(args: &Args) -> MsgFilter {
+    MsgFilter {
+        name: args.name.clone(),
+        tags: args.tag.clone(),
+        kinds: args
+            .kind
+            .iter()
+            .map(|kind| match kind {
+                Kind::Metric => DogStatsDMsgKind::Metric,
+                Kind::Event => DogStatsDMsgKind::Event,
+                Kind::ServiceCheck => DogStatsDMsgKind::ServiceCheck,
+            })
+            .collect(),
+    }
+}
+
+/// Writes every msg as a json `DogStatsDMsgOwned`, one per line
+fn print_msgs_json(
+    reader: &mut DogStatsDReader,
+    mut out: impl Write,
+    default_host: Option<&str>,
+) -> Result<(), CatError> {
+    let mut line = String::new();
+    while reader.read_msg(&mut line)? > 0 {
+        let msg = match default_host {
+            Some(host) => match DogStatsDMsg::new(&line) {
+                Ok(msg) => inject_default_host(&msg, host),
+                Err(_) => Cow::Borrowed(line.as_str()),
+            },
+            None => Cow::Borrowed(line.as_str()),
+        };
+        match DogStatsDMsg::new(&msg) {
+            Ok(parsed) => {
+                serde_json::to_writer(&mut out, &DogStatsDMsgOwned::from(&parsed))?;
+                out.write_all(b"\n")?;
+            }
+            Err(e) => warn!("Skipping msg: {}", e),
+        }
+        line.clear();
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), CatError> {
     init_logging();
     let args = Args::parse();
 
-    let mut reader = if let Some(input_file) = args.input {
-        let file_path = Path::new(&input_file);
-
-        let file = fs::File::open(file_path)?;
-        DogStatsDReader::new(file)
+    let reader_options = DogStatsDReaderOptions {
+        max_msg_size: args.max_msg_size,
+        force_replay_version: args.force_replay_version,
+    };
+    let idle_timeout = match args.idle_timeout.as_deref() {
+        Some(idle_timeout) => Some(
+            parse_interval(idle_timeout)
+                .filter(|idle_timeout| !idle_timeout.is_zero())
+                .ok_or_else(|| CatError::InvalidArgs(format!("idle timeout '{}'", idle_timeout)))?,
+        ),
+        None => None,
+    };
+    let mut reader = if let Some(ref socket_path) = args.listen_unix {
+        DogStatsDReader::from_unix_datagram_with_options(socket_path, idle_timeout, &reader_options)
+    } else if let Some(ref socket_path) = args.listen_unix_stream {
+        DogStatsDReader::from_unix_stream_with_options(socket_path, idle_timeout, &reader_options)
     } else {
-        DogStatsDReader::new(io::stdin().lock())
+        match (args.input.as_deref(), args.archive_glob.as_deref()) {
+            (Some(input_file), None) => {
+                let file_path = Path::new(input_file);
+                if args.follow {
+                    DogStatsDReader::follow_with_options(file_path, idle_timeout, &reader_options)
+                } else if file_path.is_dir() {
+                    DogStatsDReader::from_dir_with_options(
+                        file_path,
+                        dir_order(args.dir_order),
+                        &reader_options,
+                    )
+                } else {
+                    let file = fs::File::open(file_path)?;
+                    DogStatsDReader::new_with_options(file, &reader_options)
+                }
+            }
+            (None, None) => DogStatsDReader::new_with_options(io::stdin().lock(), &reader_options),
+            (Some(input_file), Some(entry_glob)) => {
+                let file = fs::File::open(Path::new(input_file))?;
+                DogStatsDReader::from_archive_with_options(file, entry_glob, &reader_options)
+            }
+            (None, Some(entry_glob)) => DogStatsDReader::from_archive_with_options(
+                io::stdin().lock(),
+                entry_glob,
+                &reader_options,
+            ),
+        }
     }?;
+    if args.start.is_some() || args.end.is_some() {
+        let parse = |timestamp: Option<&str>, default| match timestamp {
+            Some(timestamp) => parse_timestamp(timestamp)
+                .ok_or_else(|| CatError::InvalidArgs(format!("timestamp '{}'", timestamp))),
+            None => Ok(default),
+        };
+        reader = reader.with_time_range(
+            parse(args.start.as_deref(), Duration::ZERO)?,
+            parse(args.end.as_deref(), Duration::MAX)?,
+        )?;
+    }
+    if !args.src.is_empty() || !args.dst.is_empty() {
+        reader = reader.with_addr_filter(&AddrFilter {
+            sources: args.src.clone(),
+            destinations: args.dst.clone(),
+        })?;
+    }
+    let filter = msg_filter(&args);
+    if !filter.is_empty() {
+        reader = reader.with_filter(filter);
+    }
+    if let Some(n) = args.skip {
+        reader = reader.skip_msgs(n);
+    }
+    if let Some(n) = args.limit {
+        reader = reader.limit_msgs(n);
+    }
 
-    if let Some(outpath) = args.output {
-        if outpath == "-" {
-            print_msgs(&mut reader, stdout());
-        } else {
-            print_msgs(&mut reader, File::create(outpath)?);
+    if args.dry_run {
+        let mut input = args
+            .input
+            .as_deref()
+            .or(args.listen_unix.as_deref())
+            .or(args.listen_unix_stream.as_deref())
+            .unwrap_or("stdin")
+            .to_string();
+        if let Some(entry_glob) = args.archive_glob.as_deref() {
+            input = format!("{} (archive entries matching '{}')", input, entry_glob);
         }
-    } else {
-        print_msgs(&mut reader, stdout());
+        let plan = ExecutionPlan::new("dsd-cat")
+            .step("input", input)
+            .step("input format", reader.input_description())
+            .step("max msg size", args.max_msg_size)
+            .step(
+                "default host",
+                args.default_host.as_deref().unwrap_or("none"),
+            )
+            .step("output", args.output.as_deref().unwrap_or("stdout"))
+            .step("output format", format!("{:?}", args.format).to_lowercase());
+        print!("{}", plan);
+        return Ok(());
+    }
+
+    let out: Box<dyn Write> = match args.output.as_deref() {
+        Some("-") | None => Box::new(stdout()),
+        Some(outpath) => Box::new(File::create(outpath)?),
     };
 
+    if let OutputFormat::Json = args.format {
+        return print_msgs_json(&mut reader, out, args.default_host.as_deref());
+    }
+
+    match args.default_host {
+        Some(host) => print_msgs_transformed(&mut reader, out, |line| {
+            match DogStatsDMsg::new(line) {
+                Ok(msg) => inject_default_host(&msg, &host),
+                // unparseable msgs are passed through as-is
+                Err(_) => Cow::Borrowed(line),
+            }
+        }),
+        None => print_msgs(&mut reader, out),
+    }
+
     Ok(())
 }
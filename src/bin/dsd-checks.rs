@@ -0,0 +1,107 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use clap::Parser;
+use thiserror::Error;
+
+use dogstatsd_utils::checks::{CheckSummaries, CheckSummary};
+use dogstatsd_utils::dogstatsdmsg::{DogStatsDMsg, ServiceCheckStatus};
+use dogstatsd_utils::dogstatsdreader::{DogStatsDReader, DogStatsDReaderError};
+use dogstatsd_utils::init_logging;
+
+/// Summarize the service checks in dogstatsd data as a health table, one row per check
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// File containing dogstatsd data
+    input: Option<String>,
+
+    /// Treat input as a tar archive (optionally gzip, zstd or xz compressed)
+    /// and read every entry matching this glob, eg 'captures/*.zstd'
+    #[arg(long)]
+    archive_glob: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum ChecksError {
+    #[error("Could not read dogstatsd from provided source")]
+    ReaderFailure(#[from] DogStatsDReaderError),
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+}
+
+fn status_counts(check: &CheckSummary) -> String {
+    [
+        ServiceCheckStatus::Ok,
+        ServiceCheckStatus::Warning,
+        ServiceCheckStatus::Critical,
+        ServiceCheckStatus::Unknown,
+    ]
+    .iter()
+    .filter(|status| check.count(**status) > 0)
+    .map(|status| format!("{}: {}", status, check.count(*status)))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+fn main() -> Result<(), ChecksError> {
+    init_logging();
+    let args = Args::parse();
+
+    let mut reader = match (args.input, args.archive_glob) {
+        (Some(input_file), None) => DogStatsDReader::new(fs::File::open(Path::new(&input_file))?),
+        (None, None) => DogStatsDReader::new(io::stdin().lock()),
+        (Some(input_file), Some(entry_glob)) => {
+            let file = fs::File::open(Path::new(&input_file))?;
+            DogStatsDReader::from_archive(file, &entry_glob)
+        }
+        (None, Some(entry_glob)) => DogStatsDReader::from_archive(io::stdin().lock(), &entry_glob),
+    }?;
+
+    let mut summaries = CheckSummaries::default();
+    let mut line = String::new();
+    while reader.read_msg(&mut line)? > 0 {
+        if let Ok(msg) = DogStatsDMsg::new(&line) {
+            summaries.record(&msg);
+        }
+        line.clear();
+    }
+
+    let checks = summaries.into_sorted();
+    if checks.is_empty() {
+        println!("No service checks found");
+        return Ok(());
+    }
+
+    let name_width = checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    println!(
+        "{:<name_width$}  {:<8}  {:<8}  {:>6}  {:>5}  COUNTS",
+        "CHECK", "LATEST", "WORST", "MSGS", "HOSTS"
+    );
+    for check in &checks {
+        println!(
+            "{:<name_width$}  {:<8}  {:<8}  {:>6}  {:>5}  {}",
+            check.name,
+            check.latest_status.to_string(),
+            check.worst_status.to_string(),
+            check.total(),
+            check.hosts.len(),
+            status_counts(check)
+        );
+    }
+
+    for check in &checks {
+        println!();
+        println!("{}", check.name);
+        if !check.hosts.is_empty() {
+            let hosts: Vec<&str> = check.hosts.iter().map(String::as_str).collect();
+            println!("\thosts: {}", hosts.join(", "));
+        }
+        if let Some(ref message) = check.last_message {
+            println!("\tlast message: {}", message);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::fs::File;
+use std::io;
+use std::process::ExitCode;
+
+use clap::{Parser, ValueEnum};
+use thiserror::Error;
+
+use dogstatsd_utils::analysis::{analyze_msgs, DogStatsDBatchStats};
+use dogstatsd_utils::diff::{diff_captures, CaptureDiff, RateUnit};
+use dogstatsd_utils::dogstatsdreader::{DogStatsDReader, DogStatsDReaderError};
+use dogstatsd_utils::init_logging;
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Compare the metric names seen in two dogstatsd captures
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Capture to compare against, eg from production
+    baseline: String,
+
+    /// Capture being evaluated, eg from staging
+    candidate: String,
+
+    /// Output format, the json schema is versioned and stable
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Relative rate change at which a name is flagged as significant, eg 0.5 for 50%
+    #[arg(long, default_value_t = 0.5)]
+    threshold: f64,
+
+    /// Changes are only significant when the chance of them being noise is
+    /// below this, based on a poisson rate comparison
+    #[arg(long, default_value_t = 0.01)]
+    significance_level: f64,
+
+    /// Exit with a non-zero status when names were added or removed, or any
+    /// rate changed significantly
+    #[arg(long, default_value_t = false)]
+    fail_on_change: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum DiffError {
+    #[error("Could not read dogstatsd from provided source")]
+    ReaderFailure(#[from] DogStatsDReaderError),
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+    #[error("Serde Error json")]
+    SerdeJSON(#[from] serde_json::Error),
+}
+
+fn analyze(path: &str) -> Result<DogStatsDBatchStats, DiffError> {
+    let mut reader = DogStatsDReader::new(File::open(path)?)?;
+    Ok(analyze_msgs(&mut reader)?)
+}
+
+fn print_text(diff: &CaptureDiff) {
+    let unit = match diff.rate_unit {
+        RateUnit::MsgsPerSecond => "msgs/sec",
+        RateUnit::Msgs => "msgs",
+    };
+    println!(
+        "Baseline: {} msgs, {} metric names",
+        diff.baseline.num_msgs, diff.baseline.num_metric_names
+    );
+    println!(
+        "Candidate: {} msgs, {} metric names",
+        diff.candidate.num_msgs, diff.candidate.num_metric_names
+    );
+
+    println!("Added ({}):", diff.added.len());
+    for name in &diff.added {
+        println!("\t+ {}", name);
+    }
+    println!("Removed ({}):", diff.removed.len());
+    for name in &diff.removed {
+        println!("\t- {}", name);
+    }
+
+    let significant: Vec<_> = diff.deltas.iter().filter(|d| d.significant).collect();
+    println!(
+        "Significant rate changes ({}, threshold {:.0}%, p < {}):",
+        significant.len(),
+        diff.significance_threshold * 100.0,
+        diff.significance_level
+    );
+    for delta in significant {
+        println!(
+            "\t{}: {:.2} -> {:.2} {} ({:+.1}%, p = {:.2e})",
+            delta.name,
+            delta.baseline_rate,
+            delta.candidate_rate,
+            unit,
+            delta.relative_delta * 100.0,
+            delta.p_value
+        );
+    }
+}
+
+fn main() -> Result<ExitCode, DiffError> {
+    init_logging();
+    let args = Args::parse();
+
+    let baseline = analyze(&args.baseline)?;
+    let candidate = analyze(&args.candidate)?;
+    let diff = diff_captures(
+        &baseline,
+        &candidate,
+        args.threshold,
+        args.significance_level,
+    );
+
+    match args.format {
+        OutputFormat::Text => print_text(&diff),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+    }
+
+    if args.fail_on_change && diff.has_significant_changes() {
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,98 @@
+use std::fs::{self, File};
+use std::io::{self, stdout, BufWriter, Write};
+use std::path::Path;
+
+use clap::{Parser, ValueEnum};
+use regex::Regex;
+use thiserror::Error;
+
+use dogstatsd_utils::dogstatsdmsg::DogStatsDMsg;
+use dogstatsd_utils::dogstatsdreader::{DogStatsDReader, DogStatsDReaderError};
+use dogstatsd_utils::events::{render_event, EventFilter, EventPriority};
+use dogstatsd_utils::init_logging;
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Priority {
+    Normal,
+    Low,
+}
+
+/// Extract the events from dogstatsd data and print them in a readable
+/// multi-line format, with unescaped text and resolved timestamps
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// File containing dogstatsd data
+    input: Option<String>,
+
+    /// Where the rendered events should go
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Treat input as a tar archive (optionally gzip, zstd or xz compressed)
+    /// and read every entry matching this glob, eg 'captures/*.zstd'
+    #[arg(long)]
+    archive_glob: Option<String>,
+
+    /// Only show events whose title matches this regex
+    #[arg(long)]
+    title: Option<Regex>,
+
+    /// Only show events whose unescaped text matches this regex
+    #[arg(long)]
+    text: Option<Regex>,
+
+    /// Only show events of this priority
+    #[arg(long, value_enum)]
+    priority: Option<Priority>,
+}
+
+#[derive(Error, Debug)]
+pub enum EventsError {
+    #[error("Could not read dogstatsd from provided source")]
+    ReaderFailure(#[from] DogStatsDReaderError),
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+}
+
+fn main() -> Result<(), EventsError> {
+    init_logging();
+    let args = Args::parse();
+
+    let mut reader = match (args.input, args.archive_glob) {
+        (Some(input_file), None) => DogStatsDReader::new(fs::File::open(Path::new(&input_file))?),
+        (None, None) => DogStatsDReader::new(io::stdin().lock()),
+        (Some(input_file), Some(entry_glob)) => {
+            let file = fs::File::open(Path::new(&input_file))?;
+            DogStatsDReader::from_archive(file, &entry_glob)
+        }
+        (None, Some(entry_glob)) => DogStatsDReader::from_archive(io::stdin().lock(), &entry_glob),
+    }?;
+
+    let mut out: Box<dyn Write> = match args.output.as_deref() {
+        Some("-") | None => Box::new(BufWriter::new(stdout())),
+        Some(outpath) => Box::new(BufWriter::new(File::create(outpath)?)),
+    };
+
+    let filter = EventFilter {
+        title: args.title,
+        text: args.text,
+        priority: args.priority.map(|p| match p {
+            Priority::Normal => EventPriority::Normal,
+            Priority::Low => EventPriority::Low,
+        }),
+    };
+
+    let mut line = String::new();
+    while reader.read_msg(&mut line)? > 0 {
+        if let Ok(DogStatsDMsg::Event(event)) = DogStatsDMsg::new(&line) {
+            if filter.matches(&event) {
+                out.write_all(render_event(&event).as_bytes())?;
+            }
+        }
+        line.clear();
+    }
+    out.flush()?;
+
+    Ok(())
+}
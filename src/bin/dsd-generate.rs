@@ -1,6 +1,17 @@
-use std::{num::NonZeroU32, time::Duration};
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, BufWriter, Write},
+    net::{ToSocketAddrs, UdpSocket},
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
 
-use dogstatsd_utils::{rate::{parse_rate, RateSpecification}, init_logging};
+use dogstatsd_utils::{rate::{parse_interval, parse_rate, RateSpecification}, init_logging};
+use dogstatsd_utils::asyncdogstatsdreader::{AsyncDogStatsDReader, AsyncDogStatsDReaderError};
+use dogstatsd_utils::datagram::{DatagramBatcher, DEFAULT_MAX_DATAGRAM_SIZE};
+use dogstatsd_utils::plan::ExecutionPlan;
+use dogstatsd_utils::profiling::{serve_pprof, ProfilingError};
 use lading_throttle::Throttle;
 use rand::{rngs::SmallRng, SeedableRng};
 use thiserror::Error;
@@ -19,6 +30,11 @@ struct Args {
     #[arg(short, long)]
     num_msgs: Option<u32>,
 
+    /// Emit the msgs of this file instead of random ones, eg to send a capture
+    /// at a given rate. Text, length prefix framed and replay files are read.
+    #[arg(short, long, conflicts_with_all = ["num_contexts", "metric_types"])]
+    input: Option<String>,
+
     /// Emit this number of unique contexts
     #[arg(long)]
     num_contexts: Option<u32>,
@@ -32,15 +48,207 @@ struct Args {
     #[arg(short, long)]
     rate: Option<String>,
 
-    /// Where output dogstatsd messages should go
+    /// Where output dogstatsd messages should go, a file, '-' for stdout or
+    /// 'udp://host:port' to send them to a dogstatsd server
     #[arg(short, long)]
     output: Option<String>,
+
+    /// Msgs sent over udp are batched into datagrams of at most this many bytes,
+    /// a msg is never split across datagrams
+    #[arg(long, default_value_t = DEFAULT_MAX_DATAGRAM_SIZE)]
+    max_datagram_size: usize,
+
+    /// Output is buffered and flushed at most this often, eg '100ms' or '1s'
+    #[arg(long, default_value = "100ms")]
+    flush_interval: String,
+
+    /// Serve cpu profiles of this tool on this address, eg 'localhost:6060', for
+    /// `go tool pprof http://localhost:6060/debug/pprof/profile`. Needs the 'pprof' feature.
+    #[arg(long, value_name = "ADDR")]
+    pprof: Option<String>,
+
+    /// Validate the options and resolve the output address, then print what
+    /// would be generated without opening the output or sending anything
+    #[arg(long)]
+    dry_run: bool,
 }
 
 #[derive(Error, Debug)]
 pub enum DSDGenerateError {
     #[error("Invalid arguments specified")]
     InvalidArgs,
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+    #[error("Could not serve profiles")]
+    Profiling(#[from] ProfilingError),
+    #[error("Could not read input")]
+    Input(#[from] AsyncDogStatsDReaderError),
+}
+
+/// Where msgs come from, random ones by default or the msgs of '--input'
+enum MsgSource<'a> {
+    Random(Box<dogstatsd::DogStatsD>, SmallRng),
+    Input(AsyncDogStatsDReader<'a>),
+}
+
+impl MsgSource<'_> {
+    /// None once the input has no msgs left, random msgs never run out
+    async fn next_msg(&mut self) -> Result<Option<String>, DSDGenerateError> {
+        match self {
+            MsgSource::Random(dd, rng) => Ok(Some(dd.generate(rng).unwrap().to_string())),
+            MsgSource::Input(reader) => {
+                let mut line = String::new();
+                match reader.read_msg(&mut line).await? {
+                    0 => Ok(None),
+                    _ => Ok(Some(line)),
+                }
+            }
+        }
+    }
+}
+
+/// Writes msgs line-by-line through a buffer, flushing once 'flush_interval'
+/// has elapsed since the previous flush rather than after every line.
+struct MsgWriter<W: Write> {
+    out: BufWriter<W>,
+    flush_interval: Duration,
+    last_flush: Instant,
+}
+
+impl<W: Write> MsgWriter<W> {
+    fn new(out: W, flush_interval: Duration) -> Self {
+        Self {
+            out: BufWriter::new(out),
+            flush_interval,
+            last_flush: Instant::now(),
+        }
+    }
+
+    fn write_msg(&mut self, msg: impl Display) -> io::Result<()> {
+        writeln!(self.out, "{}", msg)?;
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        self.out.flush()
+    }
+}
+
+/// Sends msgs to a udp socket, packed into datagrams that respect the max
+/// datagram size. A partially filled datagram is sent once 'flush_interval'
+/// has elapsed so slow rates aren't held back.
+struct UdpMsgWriter {
+    socket: UdpSocket,
+    batcher: DatagramBatcher,
+    flush_interval: Duration,
+    last_flush: Instant,
+}
+
+impl UdpMsgWriter {
+    fn new(addr: &str, max_datagram_size: usize, flush_interval: Duration) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        Ok(Self {
+            socket,
+            batcher: DatagramBatcher::new(max_datagram_size),
+            flush_interval,
+            last_flush: Instant::now(),
+        })
+    }
+
+    fn write_msg(&mut self, msg: impl Display) -> io::Result<()> {
+        let socket = &self.socket;
+        self.batcher
+            .push(msg.to_string().as_bytes(), |d| socket.send(d).map(|_| ()))?;
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        let socket = &self.socket;
+        self.batcher.finish(|d| socket.send(d).map(|_| ()))
+    }
+}
+
+enum Output {
+    Stream(MsgWriter<Box<dyn Write>>),
+    Udp(UdpMsgWriter),
+}
+
+impl Output {
+    fn write_msg(&mut self, msg: impl Display) -> io::Result<()> {
+        match self {
+            Output::Stream(w) => w.write_msg(msg),
+            Output::Udp(w) => w.write_msg(msg),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stream(w) => w.flush(),
+            Output::Udp(w) => w.flush(),
+        }
+    }
+}
+
+fn print_plan(args: &Args, flush_interval: Duration) -> Result<(), DSDGenerateError> {
+    let msgs = match (args.num_msgs, args.rate.as_deref()) {
+        (Some(num_msgs), _) => format!("{} msgs", num_msgs),
+        (None, Some(rate)) => match parse_rate(rate) {
+            Some(RateSpecification::TimerBased(hz_value)) => {
+                format!("{} msgs per second until interrupted", hz_value)
+            }
+            Some(RateSpecification::ThroughputBased(bytes_per_second)) => {
+                format!("{} bytes per second until interrupted", bytes_per_second)
+            }
+            None => return Err(DSDGenerateError::InvalidArgs),
+        },
+        (None, None) if args.input.is_some() => "every msg of the input".to_string(),
+        (None, None) => "1 msg".to_string(),
+    };
+    let output = match args.output.as_deref() {
+        Some(addr) if addr.starts_with("udp://") => {
+            // resolving doesn't bind or connect a socket
+            let resolved: Vec<String> = addr["udp://".len()..]
+                .to_socket_addrs()?
+                .map(|a| a.to_string())
+                .collect();
+            format!(
+                "{} ({}), in datagrams of at most {} bytes",
+                addr,
+                resolved.join(", "),
+                args.max_datagram_size
+            )
+        }
+        Some("-") | None => "stdout".to_string(),
+        Some(path) => path.to_string(),
+    };
+    let plan = ExecutionPlan::new("dsd-generate")
+        .step("input", args.input.as_deref().unwrap_or("random msgs"))
+        .step("msgs", msgs)
+        .step(
+            "contexts",
+            args.num_contexts
+                .map_or("100 to 500".to_string(), |n| n.to_string()),
+        )
+        .step(
+            "metric types",
+            args.metric_types
+                .as_ref()
+                .map_or("all".to_string(), |types| types.join(", ")),
+        )
+        .step("output", output)
+        .step("flush interval", format!("{:?}", flush_interval))
+        .step("pprof", args.pprof.as_deref().unwrap_or("off"));
+    print!("{}", plan);
+    Ok(())
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -51,6 +259,29 @@ async fn main() -> Result<(), DSDGenerateError> {
     if args.num_msgs.is_some() && args.rate.is_some() {
         return Err(DSDGenerateError::InvalidArgs);
     }
+    let flush_interval =
+        parse_interval(&args.flush_interval).ok_or(DSDGenerateError::InvalidArgs)?;
+    if args.dry_run {
+        return print_plan(&args, flush_interval);
+    }
+    if let Some(ref addr) = args.pprof {
+        serve_pprof(addr)?;
+    }
+    let mut out = match args.output.as_deref() {
+        Some(addr) if addr.starts_with("udp://") => Output::Udp(UdpMsgWriter::new(
+            &addr["udp://".len()..],
+            args.max_datagram_size,
+            flush_interval,
+        )?),
+        Some("-") | None => Output::Stream(MsgWriter::new(
+            Box::new(io::stdout().lock()),
+            flush_interval,
+        )),
+        Some(path) => Output::Stream(MsgWriter::new(
+            Box::new(File::create(path)?),
+            flush_interval,
+        )),
+    };
 
     let mut rng = SmallRng::seed_from_u64(34512423);
     let mut metric_weights = MetricWeights::default();
@@ -135,29 +366,41 @@ async fn main() -> Result<(), DSDGenerateError> {
     )
     .expect("Failed to create dogstatsd generator");
 
+    let mut source = match args.input.as_deref() {
+        Some(path) => {
+            let file = tokio::io::BufReader::new(tokio::fs::File::open(path).await?);
+            MsgSource::Input(AsyncDogStatsDReader::new(file).await?)
+        }
+        None => MsgSource::Random(Box::new(dd), rng),
+    };
+
     if let Some(num_msgs) = args.num_msgs {
         for _ in 0..num_msgs {
-            println!("{}", dd.generate(&mut rng).unwrap());
+            let Some(msg) = source.next_msg().await? else {
+                break;
+            };
+            out.write_msg(msg)?;
         }
     } else if let Some(rate) = args.rate {
         match parse_rate(&rate) {
-            Some(RateSpecification::TimerBased(hz_value)) => loop {
+            Some(RateSpecification::TimerBased(hz_value)) => {
                 let sleep_in_ms = 1000 / (hz_value as u64);
-                sleep(Duration::from_millis(sleep_in_ms)).await;
-                println!("{}", dd.generate(&mut rng).unwrap());
-            },
+                while let Some(msg) = source.next_msg().await? {
+                    sleep(Duration::from_millis(sleep_in_ms)).await;
+                    out.write_msg(msg)?;
+                }
+            }
             Some(RateSpecification::ThroughputBased(bytes_per_second)) => {
                 let mut throttle = Throttle::new_with_config(
                     lading_throttle::Config::default(),
                     NonZeroU32::new(bytes_per_second).unwrap(),
                 );
-                loop {
-                    let msg = dd.generate(&mut rng).unwrap();
-                    let msg_str = msg.to_string();
+                while let Some(msg) = source.next_msg().await? {
+                    // msgs of an input can be empty
                     let _ = throttle
-                        .wait_for(NonZeroU32::new(msg_str.len() as u32).unwrap())
+                        .wait_for(NonZeroU32::new(msg.len().max(1) as u32).unwrap())
                         .await;
-                    println!("{}", msg_str);
+                    out.write_msg(msg)?;
                 }
             }
             None => {
@@ -165,8 +408,15 @@ async fn main() -> Result<(), DSDGenerateError> {
             }
         }
     } else {
-        println!("{}", dd.generate(&mut rng).unwrap());
+        // A single random msg, or every msg of the input
+        while let Some(msg) = source.next_msg().await? {
+            out.write_msg(msg)?;
+            if args.input.is_none() {
+                break;
+            }
+        }
     }
 
+    out.flush()?;
     Ok(())
 }
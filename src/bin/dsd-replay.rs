@@ -0,0 +1,279 @@
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use byte_unit::Byte;
+use clap::{Parser, Subcommand};
+use prost::Message;
+use thiserror::Error;
+use tracing::info;
+
+use dogstatsd_utils::init_logging;
+use dogstatsd_utils::plan::ExecutionPlan;
+use dogstatsd_utils::rate::parse_interval;
+use dogstatsd_utils::replay::{ReplayReader, ReplayReaderError, ReplayWriter};
+use dogstatsd_utils::zstd::is_zstd;
+
+/// Work with dogstatsd replay (capture) files
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Split a replay file into multiple valid replay files on record boundaries.
+    /// Each chunk gets its own header and a copy of the tagger state.
+    Split {
+        /// Replay file to split, optionally zstd encoded
+        input: String,
+
+        /// Start a new chunk once this much capture time has elapsed, eg '5m'
+        #[arg(long, conflicts_with = "size", required_unless_present = "size")]
+        interval: Option<String>,
+
+        /// Start a new chunk before it would grow past this size, eg '100MB'.
+        /// Sizes include the copy of the tagger state every chunk ends with.
+        #[arg(long)]
+        size: Option<String>,
+
+        /// Directory chunks are written to, defaults to the current directory
+        #[arg(short, long)]
+        output_dir: Option<String>,
+
+        /// Check the input is a replay file and print how it would be split,
+        /// without writing any chunks
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print the tagger state stored at the end of a replay file, ie the tags
+    /// the agent held for every entity and the entity of every sending pid
+    TaggerState {
+        /// Replay file, optionally zstd encoded
+        input: String,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("Could not read replay file")]
+    Replay(#[from] ReplayReaderError),
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+    #[error("Invalid arguments specified: {0}")]
+    InvalidArgs(String),
+}
+
+enum SplitStrategy {
+    Interval(Duration),
+    Size(u64),
+}
+
+impl Display for SplitStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplitStrategy::Interval(interval) => {
+                write!(f, "a new chunk every {:?} of capture time", interval)
+            }
+            SplitStrategy::Size(max_bytes) => write!(
+                f,
+                "a new chunk before one grows past {}",
+                Byte::from_bytes(*max_bytes as u128).get_appropriate_unit(false)
+            ),
+        }
+    }
+}
+
+fn chunk_path(output_dir: &Path, stem: &str, index: usize) -> PathBuf {
+    output_dir.join(format!("{stem}.part{index:04}"))
+}
+
+/// Opens the replay file at 'input', along with whether it was zstd compressed
+fn open_replay(input: &str) -> Result<(ReplayReader<'static>, bool), ReplayError> {
+    let mut buf_reader: BufReader<Box<dyn Read>> = BufReader::new(Box::new(File::open(input)?));
+    let start_buf = buf_reader.fill_buf()?;
+    let compressed = start_buf.len() >= 4 && is_zstd(&start_buf[0..4]);
+    if compressed {
+        info!("Detected zstd compression.");
+        let zstd_decoder = zstd::Decoder::new(buf_reader)?;
+        buf_reader = BufReader::new(Box::new(zstd_decoder));
+    }
+    Ok((ReplayReader::new(buf_reader)?, compressed))
+}
+
+/// Reads every record of 'input' to get to the tagger state at its end
+fn read_raw_tagger_state(input: &str) -> Result<Vec<u8>, ReplayError> {
+    let (mut reader, _) = open_replay(input)?;
+    while reader.read_msg()?.is_some() {}
+    Ok(reader.read_tagger_state()?)
+}
+
+fn split(
+    input: String,
+    strategy: SplitStrategy,
+    output_dir: Option<String>,
+    dry_run: bool,
+) -> Result<(), ReplayError> {
+    let output_dir = PathBuf::from(output_dir.unwrap_or_else(|| ".".to_string()));
+    let stem = Path::new(&input)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "replay".to_string());
+
+    let (mut reader, compressed) = open_replay(&input)?;
+
+    if dry_run {
+        let input_format = if compressed {
+            "zstd compressed dogstatsd-replay"
+        } else {
+            "dogstatsd-replay"
+        };
+        let plan = ExecutionPlan::new("dsd-replay split")
+            .step("input", &input)
+            .step("input format", input_format)
+            .step("split into", strategy)
+            .step(
+                "chunks",
+                output_dir.join(format!("{stem}.partNNNN")).display(),
+            );
+        print!("{}", plan);
+        return Ok(());
+    }
+
+    // Every chunk ends with the tagger state, which lives at the end of the
+    // source file. It is read in a first pass, so chunks can be finished as
+    // they fill up and their size accounts for it.
+    let tagger_state = read_raw_tagger_state(&input)?;
+    // 0-length record separator, tagger state and tagger state length
+    let trailer_len = 4 + tagger_state.len() as u64 + 4;
+    if let SplitStrategy::Size(max_bytes) = strategy {
+        // 8 byte header
+        if 8 + trailer_len >= max_bytes {
+            return Err(ReplayError::InvalidArgs(format!(
+                "size {max_bytes} leaves no room for records next to the {} byte tagger state",
+                tagger_state.len()
+            )));
+        }
+    }
+    fs::create_dir_all(&output_dir)?;
+
+    let mut chunk_paths = Vec::new();
+    let mut writer: Option<ReplayWriter<BufWriter<File>>> = None;
+    let mut chunk_start = Duration::ZERO;
+    let version = reader.version();
+    while let Some(mut msg) = reader.read_msg()? {
+        let timestamp = version.record_timestamp(msg.timestamp);
+        // chunks are written as v3, which records nanoseconds
+        msg.timestamp = timestamp.as_nanos() as i64;
+        let needs_new_chunk = match (&writer, &strategy) {
+            (None, _) => true,
+            (Some(_), SplitStrategy::Interval(interval)) => {
+                timestamp.saturating_sub(chunk_start) >= *interval
+            }
+            (Some(w), SplitStrategy::Size(max_bytes)) => {
+                // 4 byte length prefix for this record
+                w.bytes_written() + 4 + msg.encoded_len() as u64 + trailer_len > *max_bytes
+            }
+        };
+
+        if needs_new_chunk {
+            if let Some(w) = writer.take() {
+                w.finish(&tagger_state)?;
+            }
+            let path = chunk_path(&output_dir, &stem, chunk_paths.len());
+            info!("Writing chunk {}", path.display());
+            writer = Some(ReplayWriter::new(BufWriter::new(File::create(&path)?))?);
+            chunk_paths.push(path);
+            chunk_start = timestamp;
+        }
+
+        if let Some(w) = writer.as_mut() {
+            w.write_msg(&msg)?;
+        }
+    }
+    if let Some(w) = writer.take() {
+        w.finish(&tagger_state)?;
+    }
+
+    println!(
+        "Wrote {} chunk(s) to {}",
+        chunk_paths.len(),
+        output_dir.display()
+    );
+    Ok(())
+}
+
+fn print_tagger_state(input: String) -> Result<(), ReplayError> {
+    let (mut reader, _) = open_replay(&input)?;
+    // Tagger state lives at the end of the file, after every record
+    while reader.read_msg()?.is_some() {}
+    let Some(state) = reader.tagger_state()? else {
+        println!("No tagger state stored in {}", input);
+        return Ok(());
+    };
+
+    let mut entities: Vec<_> = state.state.iter().collect();
+    entities.sort_by(|a, b| a.0.cmp(b.0));
+    println!("Entities: {}", entities.len());
+    for (key, entity) in entities {
+        println!("{}", key);
+        println!("\tHash: {}", entity.hash);
+        for (cardinality, tags) in [
+            ("Low", &entity.low_cardinality_tags),
+            ("Orchestrator", &entity.orchestrator_cardinality_tags),
+            ("High", &entity.high_cardinality_tags),
+            ("Standard", &entity.standard_tags),
+        ] {
+            if !tags.is_empty() {
+                println!("\t{} Cardinality Tags: {}", cardinality, tags.join(","));
+            }
+        }
+    }
+
+    let mut pids: Vec<_> = state.pid_map.iter().collect();
+    pids.sort();
+    println!("Pids: {}", pids.len());
+    for (pid, entity) in pids {
+        println!("\t{}: {}", pid, entity);
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), ReplayError> {
+    init_logging();
+    let args = Args::parse();
+
+    match args.command {
+        Command::Split {
+            input,
+            interval,
+            size,
+            output_dir,
+            dry_run,
+        } => {
+            let strategy = match (interval, size) {
+                (Some(interval), _) => {
+                    SplitStrategy::Interval(parse_interval(&interval).ok_or_else(|| {
+                        ReplayError::InvalidArgs(format!("interval '{interval}'"))
+                    })?)
+                }
+                (None, Some(size)) => SplitStrategy::Size(
+                    Byte::from_str(&size)
+                        .map_err(|_| ReplayError::InvalidArgs(format!("size '{size}'")))?
+                        .get_bytes() as u64,
+                ),
+                (None, None) => {
+                    return Err(ReplayError::InvalidArgs(
+                        "one of --interval or --size is required".to_string(),
+                    ))
+                }
+            };
+            split(input, strategy, output_dir, dry_run)
+        }
+        Command::TaggerState { input } => print_tagger_state(input),
+    }
+}
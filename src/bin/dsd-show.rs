@@ -0,0 +1,89 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use clap::Parser;
+use thiserror::Error;
+
+use dogstatsd_utils::dogstatsdmsg::DogStatsDMsg;
+use dogstatsd_utils::dogstatsdreader::{DogStatsDReader, DogStatsDReaderError};
+use dogstatsd_utils::init_logging;
+use dogstatsd_utils::show::{msg_has_name, ContextWindow};
+
+/// Show the msgs for a metric or service check name along with the msgs
+/// captured around them, to inspect the traffic a suspicious metric shows up in
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// File containing dogstatsd data
+    input: Option<String>,
+
+    /// Treat input as a tar archive (optionally gzip, zstd or xz compressed)
+    /// and read every entry matching this glob, eg 'captures/*.zstd'
+    #[arg(long)]
+    archive_glob: Option<String>,
+
+    /// Metric or service check name to show msgs around
+    #[arg(long)]
+    around: String,
+
+    /// Number of msgs to show before and after each match, in capture order
+    #[arg(long, short = 'C', default_value_t = 5)]
+    context: usize,
+}
+
+#[derive(Error, Debug)]
+pub enum ShowError {
+    #[error("Could not read dogstatsd from provided source")]
+    ReaderFailure(#[from] DogStatsDReaderError),
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+}
+
+fn main() -> Result<(), ShowError> {
+    init_logging();
+    let args = Args::parse();
+
+    let mut reader = match (args.input, args.archive_glob) {
+        (Some(input_file), None) => DogStatsDReader::new(fs::File::open(Path::new(&input_file))?),
+        (None, None) => DogStatsDReader::new(io::stdin().lock()),
+        (Some(input_file), Some(entry_glob)) => {
+            let file = fs::File::open(Path::new(&input_file))?;
+            DogStatsDReader::from_archive(file, &entry_glob)
+        }
+        (None, Some(entry_glob)) => DogStatsDReader::from_archive(io::stdin().lock(), &entry_glob),
+    }?;
+
+    let mut window = ContextWindow::new(args.context);
+    let mut num_matches = 0;
+    let mut last_shown = None;
+    let mut line = String::new();
+    while reader.read_msg(&mut line)? > 0 {
+        let raw = line.trim_end();
+        let matched = DogStatsDMsg::new(raw).is_ok_and(|msg| msg_has_name(&msg, &args.around));
+        if matched {
+            num_matches += 1;
+        }
+        for msg in window.push(raw, reader.last_msg_timestamp(), matched) {
+            // separate windows that aren't contiguous, like grep does
+            if last_shown.is_some_and(|last| last + 1 != msg.index) {
+                println!("--");
+            }
+            last_shown = Some(msg.index);
+            println!(
+                "{} {:>8}  {}  {}",
+                if msg.matched { '>' } else { ' ' },
+                msg.index,
+                msg.timestamp_str(),
+                msg.raw
+            );
+        }
+        line.clear();
+    }
+
+    if num_matches == 0 {
+        println!("No msgs named '{}' found", args.around);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,289 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::Parser;
+use human_bytes::human_bytes;
+use thiserror::Error;
+
+use dogstatsd_utils::analysis::{self, analyze_msgs, lading_config_str, LadingSchema};
+use dogstatsd_utils::corpus::write_replay_from_reader;
+use dogstatsd_utils::datagram::{DatagramBatcher, DEFAULT_MAX_DATAGRAM_SIZE};
+use dogstatsd_utils::dogstatsdreader::{DogStatsDReader, DogStatsDReaderError};
+use dogstatsd_utils::init_logging;
+use dogstatsd_utils::rate::{parse_interval, parse_rate, RateSpecification};
+
+/// Msgs sent by the trial run, enough to see them arrive at the target
+/// without putting any real load on it
+const TRIAL_RUN_MSGS: usize = 1000;
+
+/// Rate offered when the capture doesn't record when msgs were captured
+const DEFAULT_RATE: &str = "1MB";
+
+/// Walks from a capture to a ready to run load test: analyzes the capture,
+/// asks for the target and duration, then writes a lading config shaped like
+/// the capture along with a replay corpus of it
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// File containing dogstatsd data, answers are read from stdin
+    input: String,
+
+    /// Address the load test sends to, eg '127.0.0.1:8125'. Asked for when not given
+    #[arg(long)]
+    addr: Option<String>,
+
+    /// How long the load test runs, eg '60s' or '5m'. Asked for when not given
+    #[arg(long)]
+    duration: Option<String>,
+
+    /// Bytes per second the load test sends, eg '1MB'. Asked for when not
+    /// given, the capture's own rate is offered when it records capture times
+    #[arg(long)]
+    rate: Option<String>,
+
+    /// Directory to write the lading config and replay corpus to
+    #[arg(short, long, default_value = ".")]
+    output_dir: String,
+
+    /// Layout of the emitted lading config, 'confrange' for the lading this
+    /// tool is built against or 'min-max' for releases from before ConfRange
+    #[arg(long, default_value = "confrange")]
+    lading_schema: LadingSchema,
+
+    /// Once everything is written, send the first msgs of the capture to the
+    /// target as a local dry run of the load test. Asked for when not given
+    #[arg(long)]
+    trial_run: bool,
+
+    /// Take the default for everything not given as an option instead of asking
+    #[arg(short, long)]
+    yes: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum WizardError {
+    #[error("Could not read dogstatsd from provided source")]
+    ReaderFailure(#[from] DogStatsDReaderError),
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+    #[error("Could not build lading config")]
+    Analysis(#[from] analysis::Error),
+    #[error("Invalid {0} '{1}'")]
+    InvalidAnswer(&'static str, String),
+}
+
+/// Asks 'question' on stdout and reads the answer from stdin until 'parse'
+/// accepts it. An empty answer, or stdin being closed, takes 'default'.
+fn ask<T>(
+    question: &str,
+    default: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<T, WizardError> {
+    loop {
+        print!("{} [{}]: ", question, default);
+        io::stdout().flush()?;
+        let mut line = String::new();
+        let answer = match io::stdin().read_line(&mut line)? {
+            0 => {
+                println!();
+                default
+            }
+            _ if line.trim().is_empty() => default,
+            _ => line.trim(),
+        };
+        match parse(answer) {
+            Some(value) => return Ok(value),
+            None if answer == default => {
+                return Err(WizardError::InvalidAnswer("default", default.to_string()))
+            }
+            None => println!("Invalid answer '{}'", answer),
+        }
+    }
+}
+
+/// Uses 'given' when set, takes 'default' with '--yes' and asks otherwise
+fn answer<T>(
+    given: Option<&str>,
+    yes: bool,
+    what: &'static str,
+    question: &str,
+    default: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<T, WizardError> {
+    if given.is_none() && !yes {
+        return ask(question, default, parse);
+    }
+    let value = given.unwrap_or(default);
+    parse(value).ok_or_else(|| WizardError::InvalidAnswer(what, value.to_string()))
+}
+
+fn parse_addr(addr: &str) -> Option<String> {
+    let mut resolved = addr.to_socket_addrs().ok()?;
+    resolved.next().map(|_| addr.to_string())
+}
+
+fn parse_bytes_per_second(rate: &str) -> Option<u32> {
+    match parse_rate(rate)? {
+        RateSpecification::ThroughputBased(bytes_per_second) if bytes_per_second > 0 => {
+            Some(bytes_per_second)
+        }
+        _ => None,
+    }
+}
+
+fn parse_yes_no(answer: &str) -> Option<bool> {
+    match answer.to_lowercase().as_str() {
+        "y" | "yes" => Some(true),
+        "n" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Sends the first TRIAL_RUN_MSGS msgs of 'reader' to 'addr', packed into
+/// datagrams like a client would. Returns the msgs and datagrams sent.
+fn trial_run(reader: &mut DogStatsDReader, addr: &str) -> Result<(usize, usize), WizardError> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(addr)?;
+    let mut batcher = DatagramBatcher::new(DEFAULT_MAX_DATAGRAM_SIZE);
+    let mut num_datagrams = 0;
+    let mut send = |datagram: &[u8]| -> io::Result<()> {
+        socket.send(datagram)?;
+        num_datagrams += 1;
+        Ok(())
+    };
+    let mut num_msgs = 0;
+    let mut line = String::new();
+    while num_msgs < TRIAL_RUN_MSGS && reader.read_msg(&mut line)? > 0 {
+        batcher.push(line.trim_end().as_bytes(), &mut send)?;
+        num_msgs += 1;
+        line.clear();
+    }
+    batcher.finish(&mut send)?;
+    Ok((num_msgs, num_datagrams))
+}
+
+fn main() -> Result<(), WizardError> {
+    init_logging();
+    let args = Args::parse();
+    let input = Path::new(&args.input);
+
+    let mut reader = DogStatsDReader::new(fs::File::open(input)?)?;
+    let msg_stats = analyze_msgs(&mut reader)?;
+
+    println!("Analyzed {} msgs from {}", msg_stats.num_msgs, args.input);
+    println!("\tContexts: {}", msg_stats.num_contexts);
+    for share in msg_stats.kind_shares() {
+        println!(
+            "\t{}: {} ({:.2}% of msgs, {} / {:.2}% of bytes)",
+            share.kind,
+            share.count,
+            share.percent_of_msgs,
+            human_bytes(share.bytes as f64),
+            share.percent_of_bytes
+        );
+    }
+    let capture_rate = msg_stats
+        .reader_analytics
+        .as_ref()
+        .map(|analytics| analytics.average_bytes_per_second())
+        .filter(|bytes_per_second| *bytes_per_second >= 1.0);
+    match capture_rate {
+        Some(bytes_per_second) => {
+            println!("\tCapture rate: {}/sec", human_bytes(bytes_per_second))
+        }
+        None => println!("\tCapture rate: unknown, the input doesn't record capture times"),
+    }
+    println!();
+
+    let addr = answer(
+        args.addr.as_deref(),
+        args.yes,
+        "target address",
+        "Target address",
+        "127.0.0.1:8125",
+        parse_addr,
+    )?;
+    let duration = answer(
+        args.duration.as_deref(),
+        args.yes,
+        "duration",
+        "Duration",
+        "60s",
+        |duration| parse_interval(duration).filter(|d| *d >= Duration::from_secs(1)),
+    )?;
+    let default_rate = capture_rate
+        .map(|bytes_per_second| format!("{}b", bytes_per_second as u64))
+        .unwrap_or_else(|| DEFAULT_RATE.to_string());
+    let bytes_per_second = answer(
+        args.rate.as_deref(),
+        args.yes,
+        "rate",
+        "Bytes per second",
+        &default_rate,
+        parse_bytes_per_second,
+    )?;
+
+    let output_dir = PathBuf::from(&args.output_dir);
+    fs::create_dir_all(&output_dir)?;
+    let stem = input
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "capture".to_string());
+
+    let lading_config = msg_stats
+        .to_lading_udp_config(&addr, byte_unit::Byte::from_bytes(bytes_per_second as u128))?;
+    let lading_path = output_dir.join("lading.yaml");
+    fs::write(
+        &lading_path,
+        lading_config_str(lading_config, args.lading_schema)?,
+    )?;
+
+    let corpus_path = output_dir.join(format!("{}-corpus.replay", stem));
+    let mut reader = DogStatsDReader::new(fs::File::open(input)?)?;
+    let num_records =
+        write_replay_from_reader(&mut reader, BufWriter::new(fs::File::create(&corpus_path)?))?;
+
+    println!();
+    println!("Wrote lading config to {}", lading_path.display());
+    println!(
+        "Wrote replay corpus of {} records to {}",
+        num_records,
+        corpus_path.display()
+    );
+    println!();
+    println!("Run the load test against {} with:", addr);
+    println!(
+        "\tlading --config-path {} --no-target --experiment-duration-seconds {} --capture-path lading-capture.jsonl",
+        lading_path.display(),
+        duration.as_secs()
+    );
+    println!("Or replay the capture itself through a local agent with:");
+    println!("\tagent dogstatsd-replay -f {}", corpus_path.display());
+    println!();
+
+    let run_trial = match (args.trial_run, args.yes) {
+        (true, _) => true,
+        (false, true) => false,
+        (false, false) => ask(
+            &format!(
+                "Send {} msgs to {} as a dry run now? (y/n)",
+                TRIAL_RUN_MSGS, addr
+            ),
+            "n",
+            parse_yes_no,
+        )?,
+    };
+    if run_trial {
+        let mut reader = DogStatsDReader::new(fs::File::open(input)?)?;
+        let (num_msgs, num_datagrams) = trial_run(&mut reader, &addr)?;
+        println!(
+            "Sent {} msgs in {} datagrams to {}",
+            num_msgs, num_datagrams, addr
+        );
+    }
+
+    Ok(())
+}
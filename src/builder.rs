@@ -0,0 +1,530 @@
+use thiserror::Error;
+
+use crate::dogstatsdmsg::{
+    Cardinality, DogStatsDEventOwned, DogStatsDEventStr, DogStatsDMetricOwned, DogStatsDMetricStr,
+    DogStatsDMetricType, DogStatsDMsg, DogStatsDMsgError, DogStatsDMsgOwned,
+    DogStatsDServiceCheckOwned, DogStatsDServiceCheckStr, EventAlert, ServiceCheckStatus,
+};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum BuilderError {
+    #[error("{0} must not be empty")]
+    Empty(&'static str),
+    #[error("{field} '{value}' contains '{invalid}', which is not allowed there")]
+    InvalidChar {
+        field: &'static str,
+        value: String,
+        invalid: char,
+    },
+    #[error("Metrics need at least one value")]
+    NoValues,
+    #[error("Metric values must be finite, got {0}")]
+    NonFiniteValue(f64),
+    #[error("Sample rate must be in (0, 1], got {0}")]
+    InvalidSampleRate(f64),
+    #[error("Event priority must be 'normal' or 'low', got '{0}'")]
+    InvalidPriority(String),
+    #[error("Built msg did not parse: {0}")]
+    Parse(#[from] DogStatsDMsgError),
+}
+
+// Characters that end a field on the wire
+const FIELD_SEPARATORS: &[char] = &['|', '\n'];
+const NAME_SEPARATORS: &[char] = &['|', ':', '\n'];
+const TAG_SEPARATORS: &[char] = &['|', ',', '\n'];
+
+fn check_field(field: &'static str, value: &str, invalid: &[char]) -> Result<(), BuilderError> {
+    if value.is_empty() {
+        return Err(BuilderError::Empty(field));
+    }
+    match value.chars().find(|c| invalid.contains(c)) {
+        Some(invalid) => Err(BuilderError::InvalidChar {
+            field,
+            value: value.to_string(),
+            invalid,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn check_opt_field(
+    field: &'static str,
+    value: &Option<String>,
+    invalid: &[char],
+) -> Result<(), BuilderError> {
+    match value {
+        Some(value) => check_field(field, value, invalid),
+        None => Ok(()),
+    }
+}
+
+fn check_tags(tags: &[String]) -> Result<(), BuilderError> {
+    tags.iter()
+        .try_for_each(|tag| check_field("Tag", tag, TAG_SEPARATORS))
+}
+
+/// Newlines would end the msg, the agent turns '\n' back into a newline
+fn escape_newlines(s: &str) -> String {
+    s.replace('\n', "\\n")
+}
+
+/// Parses a msg the builder produced, so built msgs are exactly what the
+/// parser would produce for the same wire format
+fn parse_owned(wire: &str) -> Result<DogStatsDMsgOwned, BuilderError> {
+    Ok(DogStatsDMsgOwned::from(&DogStatsDMsg::new(wire)?))
+}
+
+/// Builds a single metric msg, eg
+/// `MetricBuilder::new("my.metric", DogStatsDMetricType::Count).value(1.0).tag("env:prod")`
+#[derive(Debug, Clone)]
+pub struct MetricBuilder {
+    name: String,
+    metric_type: DogStatsDMetricType,
+    values: Vec<f64>,
+    set_value: Option<String>,
+    sample_rate: Option<f64>,
+    timestamp: Option<u64>,
+    container_id: Option<String>,
+    external_data: Option<String>,
+    cardinality: Option<Cardinality>,
+    tags: Vec<String>,
+}
+
+impl MetricBuilder {
+    pub fn new(name: &str, metric_type: DogStatsDMetricType) -> Self {
+        Self {
+            name: name.to_string(),
+            metric_type,
+            values: Vec::new(),
+            set_value: None,
+            sample_rate: None,
+            timestamp: None,
+            container_id: None,
+            external_data: None,
+            cardinality: None,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Adds a value, more than one value is sent as a multi-value msg
+    pub fn value(mut self, value: f64) -> Self {
+        self.values.push(value);
+        self
+    }
+
+    /// The member of a set metric, which may be any string
+    pub fn set_value(mut self, member: &str) -> Self {
+        self.set_value = Some(member.to_string());
+        self
+    }
+
+    pub fn sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn container_id(mut self, container_id: &str) -> Self {
+        self.container_id = Some(container_id.to_string());
+        self
+    }
+
+    pub fn external_data(mut self, external_data: &str) -> Self {
+        self.external_data = Some(external_data.to_string());
+        self
+    }
+
+    pub fn cardinality(mut self, cardinality: Cardinality) -> Self {
+        self.cardinality = Some(cardinality);
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    fn validate(&self) -> Result<(), BuilderError> {
+        check_field("Metric name", &self.name, NAME_SEPARATORS)?;
+        match self.set_value {
+            Some(ref member) => check_field("Set value", member, NAME_SEPARATORS)?,
+            None if self.values.is_empty() => return Err(BuilderError::NoValues),
+            None => {}
+        }
+        if let Some(value) = self.values.iter().find(|v| !v.is_finite()) {
+            return Err(BuilderError::NonFiniteValue(*value));
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            if !(sample_rate > 0.0 && sample_rate <= 1.0) {
+                return Err(BuilderError::InvalidSampleRate(sample_rate));
+            }
+        }
+        check_opt_field("Container id", &self.container_id, FIELD_SEPARATORS)?;
+        check_opt_field("External data", &self.external_data, FIELD_SEPARATORS)?;
+        check_tags(&self.tags)
+    }
+
+    /// The msg as a single dogstatsd line, without a trailing newline
+    pub fn to_wire(&self) -> Result<String, BuilderError> {
+        self.validate()?;
+        let metric = DogStatsDMetricStr {
+            name: &self.name,
+            values: self.values.iter().copied().collect(),
+            set_value: self.set_value.as_deref(),
+            sample_rate: self.sample_rate,
+            timestamp: self.timestamp,
+            container_id: self.container_id.as_deref(),
+            external_data: self.external_data.as_deref(),
+            cardinality: self.cardinality,
+            metric_type: self.metric_type,
+            tags: self.tags.iter().map(String::as_str).collect(),
+            raw_msg: "",
+        };
+        Ok(metric.to_string())
+    }
+
+    pub fn build(&self) -> Result<DogStatsDMetricOwned, BuilderError> {
+        match parse_owned(&self.to_wire()?)? {
+            DogStatsDMsgOwned::Metric(metric) => Ok(metric),
+            _ => unreachable!("metric wire format parsed as another kind"),
+        }
+    }
+}
+
+/// Builds a single event msg, newlines in the title and text are escaped
+#[derive(Debug, Clone)]
+pub struct EventBuilder {
+    title: String,
+    text: String,
+    timestamp: Option<u64>,
+    hostname: Option<String>,
+    priority: Option<String>,
+    alert_type: EventAlert,
+    aggregation_key: Option<String>,
+    source_type_name: Option<String>,
+    external_data: Option<String>,
+    cardinality: Option<Cardinality>,
+    tags: Vec<String>,
+}
+
+impl EventBuilder {
+    pub fn new(title: &str, text: &str) -> Self {
+        Self {
+            title: escape_newlines(title),
+            text: escape_newlines(text),
+            timestamp: None,
+            hostname: None,
+            priority: None,
+            alert_type: EventAlert::Info,
+            aggregation_key: None,
+            source_type_name: None,
+            external_data: None,
+            cardinality: None,
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn hostname(mut self, hostname: &str) -> Self {
+        self.hostname = Some(hostname.to_string());
+        self
+    }
+
+    /// Either 'normal' (the default) or 'low'
+    pub fn priority(mut self, priority: &str) -> Self {
+        self.priority = Some(priority.to_string());
+        self
+    }
+
+    pub fn alert_type(mut self, alert_type: EventAlert) -> Self {
+        self.alert_type = alert_type;
+        self
+    }
+
+    pub fn aggregation_key(mut self, aggregation_key: &str) -> Self {
+        self.aggregation_key = Some(aggregation_key.to_string());
+        self
+    }
+
+    pub fn source_type_name(mut self, source_type_name: &str) -> Self {
+        self.source_type_name = Some(source_type_name.to_string());
+        self
+    }
+
+    pub fn external_data(mut self, external_data: &str) -> Self {
+        self.external_data = Some(external_data.to_string());
+        self
+    }
+
+    pub fn cardinality(mut self, cardinality: Cardinality) -> Self {
+        self.cardinality = Some(cardinality);
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    fn validate(&self) -> Result<(), BuilderError> {
+        if self.title.is_empty() {
+            return Err(BuilderError::Empty("Event title"));
+        }
+        if let Some(ref priority) = self.priority {
+            if priority != "normal" && priority != "low" {
+                return Err(BuilderError::InvalidPriority(priority.clone()));
+            }
+        }
+        check_opt_field("Hostname", &self.hostname, FIELD_SEPARATORS)?;
+        check_opt_field("Aggregation key", &self.aggregation_key, FIELD_SEPARATORS)?;
+        check_opt_field("Source type name", &self.source_type_name, FIELD_SEPARATORS)?;
+        check_opt_field("External data", &self.external_data, FIELD_SEPARATORS)?;
+        check_tags(&self.tags)
+    }
+
+    /// The msg as a single dogstatsd line, without a trailing newline
+    pub fn to_wire(&self) -> Result<String, BuilderError> {
+        self.validate()?;
+        let event = DogStatsDEventStr {
+            title: &self.title,
+            text: &self.text,
+            timestamp: self.timestamp,
+            hostname: self.hostname.as_deref(),
+            priority: self.priority.as_deref(),
+            alert_type: self.alert_type,
+            aggregation_key: self.aggregation_key.as_deref(),
+            source_type_name: self.source_type_name.as_deref(),
+            external_data: self.external_data.as_deref(),
+            cardinality: self.cardinality,
+            tags: self.tags.iter().map(String::as_str).collect(),
+            raw_msg: "",
+        };
+        Ok(event.to_string())
+    }
+
+    pub fn build(&self) -> Result<DogStatsDEventOwned, BuilderError> {
+        match parse_owned(&self.to_wire()?)? {
+            DogStatsDMsgOwned::Event(event) => Ok(event),
+            _ => unreachable!("event wire format parsed as another kind"),
+        }
+    }
+}
+
+/// Builds a single service check msg, newlines in the message are escaped
+#[derive(Debug, Clone)]
+pub struct ServiceCheckBuilder {
+    name: String,
+    status: ServiceCheckStatus,
+    timestamp: Option<u64>,
+    hostname: Option<String>,
+    message: Option<String>,
+    external_data: Option<String>,
+    cardinality: Option<Cardinality>,
+    tags: Vec<String>,
+}
+
+impl ServiceCheckBuilder {
+    pub fn new(name: &str, status: ServiceCheckStatus) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            timestamp: None,
+            hostname: None,
+            message: None,
+            external_data: None,
+            cardinality: None,
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn hostname(mut self, hostname: &str) -> Self {
+        self.hostname = Some(hostname.to_string());
+        self
+    }
+
+    pub fn message(mut self, message: &str) -> Self {
+        self.message = Some(escape_newlines(message));
+        self
+    }
+
+    pub fn external_data(mut self, external_data: &str) -> Self {
+        self.external_data = Some(external_data.to_string());
+        self
+    }
+
+    pub fn cardinality(mut self, cardinality: Cardinality) -> Self {
+        self.cardinality = Some(cardinality);
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    fn validate(&self) -> Result<(), BuilderError> {
+        check_field("Service check name", &self.name, FIELD_SEPARATORS)?;
+        check_opt_field("Hostname", &self.hostname, FIELD_SEPARATORS)?;
+        check_opt_field("Service check message", &self.message, FIELD_SEPARATORS)?;
+        check_opt_field("External data", &self.external_data, FIELD_SEPARATORS)?;
+        check_tags(&self.tags)
+    }
+
+    /// The msg as a single dogstatsd line, without a trailing newline
+    pub fn to_wire(&self) -> Result<String, BuilderError> {
+        self.validate()?;
+        let service_check = DogStatsDServiceCheckStr {
+            name: &self.name,
+            status: self.status,
+            timestamp: self.timestamp,
+            hostname: self.hostname.as_deref(),
+            message: self.message.as_deref(),
+            external_data: self.external_data.as_deref(),
+            cardinality: self.cardinality,
+            tags: self.tags.iter().map(String::as_str).collect(),
+            raw_msg: "",
+        };
+        Ok(service_check.to_string())
+    }
+
+    pub fn build(&self) -> Result<DogStatsDServiceCheckOwned, BuilderError> {
+        match parse_owned(&self.to_wire()?)? {
+            DogStatsDMsgOwned::ServiceCheck(service_check) => Ok(service_check),
+            _ => unreachable!("service check wire format parsed as another kind"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_metrics() {
+        let builder = MetricBuilder::new("my.metric", DogStatsDMetricType::Distribution)
+            .value(1.5)
+            .value(2.0)
+            .sample_rate(0.5)
+            .tag("env:prod")
+            .tag("team:core")
+            .timestamp(1656581400);
+        assert_eq!(
+            builder.to_wire().unwrap(),
+            "my.metric:1.5:2|d|@0.5|#env:prod,team:core|T1656581400"
+        );
+        let metric = builder.build().unwrap();
+        assert_eq!(metric.values, vec![1.5, 2.0]);
+        assert_eq!(metric.tags, vec!["env:prod", "team:core"]);
+        assert_eq!(
+            metric.raw_msg,
+            "my.metric:1.5:2|d|@0.5|#env:prod,team:core|T1656581400"
+        );
+
+        let set = MetricBuilder::new("users", DogStatsDMetricType::Set)
+            .set_value("alice")
+            .build()
+            .unwrap();
+        assert_eq!(set.set_value.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn builds_events_and_service_checks() {
+        let event = EventBuilder::new("deploy", "line one\nline two")
+            .alert_type(EventAlert::Warning)
+            .priority("low")
+            .tag("env:prod");
+        assert_eq!(
+            event.to_wire().unwrap(),
+            "_e{6,18}:deploy|line one\\nline two|p:low|t:warning|#env:prod"
+        );
+        assert_eq!(event.build().unwrap().text, "line one\\nline two");
+
+        let check = ServiceCheckBuilder::new("db.up", ServiceCheckStatus::Critical)
+            .hostname("db-1")
+            .cardinality(Cardinality::Orchestrator)
+            .message("connection refused");
+        assert_eq!(
+            check.to_wire().unwrap(),
+            "_sc|db.up|2|h:db-1|card:orchestrator|m:connection refused"
+        );
+        assert_eq!(check.build().unwrap().status, ServiceCheckStatus::Critical);
+    }
+
+    #[test]
+    fn invalid_inputs_are_rejected() {
+        let metric = |name| MetricBuilder::new(name, DogStatsDMetricType::Gauge);
+        assert_eq!(metric("a").to_wire(), Err(BuilderError::NoValues));
+        assert_eq!(
+            metric("").value(1.0).to_wire(),
+            Err(BuilderError::Empty("Metric name"))
+        );
+        assert!(matches!(
+            metric("a|b").value(1.0).to_wire(),
+            Err(BuilderError::InvalidChar { invalid: '|', .. })
+        ));
+        assert!(matches!(
+            metric("a").value(1.0).tag("env:prod,team:core").to_wire(),
+            Err(BuilderError::InvalidChar { invalid: ',', .. })
+        ));
+        assert!(matches!(
+            metric("a").value(f64::NAN).to_wire(),
+            Err(BuilderError::NonFiniteValue(_))
+        ));
+        assert_eq!(
+            metric("a").value(1.0).sample_rate(0.0).to_wire(),
+            Err(BuilderError::InvalidSampleRate(0.0))
+        );
+        assert_eq!(
+            EventBuilder::new("t", "x").priority("high").to_wire(),
+            Err(BuilderError::InvalidPriority("high".to_string()))
+        );
+        assert_eq!(
+            EventBuilder::new("", "x").to_wire(),
+            Err(BuilderError::Empty("Event title"))
+        );
+
+        let check = || ServiceCheckBuilder::new("db.up", ServiceCheckStatus::Ok);
+        assert_eq!(
+            check().message("down|#env:prod").to_wire(),
+            Err(BuilderError::InvalidChar {
+                field: "Service check message",
+                value: "down|#env:prod".to_string(),
+                invalid: '|',
+            })
+        );
+        // newlines in messages are escaped, not rejected
+        assert_eq!(
+            check().message("line one\nline two").to_wire().unwrap(),
+            "_sc|db.up|0|m:line one\\nline two"
+        );
+        assert!(matches!(
+            check().tag("env:prod|m:spoofed").to_wire(),
+            Err(BuilderError::InvalidChar {
+                field: "Tag",
+                invalid: '|',
+                ..
+            })
+        ));
+        assert!(matches!(
+            check().tag("env:prod\n_sc|other|2").to_wire(),
+            Err(BuilderError::InvalidChar {
+                field: "Tag",
+                invalid: '\n',
+                ..
+            })
+        ));
+    }
+}
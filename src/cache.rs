@@ -0,0 +1,147 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+use tracing::debug;
+
+use crate::analysis::DogStatsDBatchStats;
+
+/// Bumped whenever the layout of the cached analysis changes
+const CACHE_FORMAT_VERSION: u32 = 9;
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+    #[error("Serde Error json")]
+    SerdeJSON(#[from] serde_json::Error),
+}
+
+/// `$XDG_CACHE_HOME/dogstatsd-utils`, falling back to `~/.cache/dogstatsd-utils`
+pub fn default_cache_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(base.join("dogstatsd-utils"))
+}
+
+/// Hashes the contents of every input in order along with 'options', which
+/// should describe every option that changes the result of the analysis.
+/// Inputs are identified by content only, so renaming or copying a capture
+/// still hits the cache.
+pub fn cache_key<P: AsRef<Path>>(inputs: &[P], options: &str) -> Result<String, CacheError> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&CACHE_FORMAT_VERSION.to_le_bytes());
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(options.as_bytes());
+    let mut buf = vec![0; 64 * 1024];
+    for input in inputs {
+        // delimit inputs so that their boundaries are part of the key
+        hasher.update(b"\0input\0");
+        let mut file = File::open(input)?;
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+fn entry_path(cache_dir: &Path, key: &str) -> PathBuf {
+    cache_dir.join(format!("{key}.json"))
+}
+
+/// Returns the cached analysis for 'key', any error reading it is treated as a miss
+pub fn load(cache_dir: &Path, key: &str) -> Option<DogStatsDBatchStats> {
+    let path = entry_path(cache_dir, key);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            debug!("No cached analysis at {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    match serde_json::from_reader(BufReader::new(file)) {
+        Ok(stats) => Some(stats),
+        Err(e) => {
+            debug!(
+                "Ignoring unreadable cached analysis {}: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+pub fn store(cache_dir: &Path, key: &str, stats: &DogStatsDBatchStats) -> Result<(), CacheError> {
+    fs::create_dir_all(cache_dir)?;
+    // write to a temporary file first so a concurrent reader never sees a partial entry
+    let tmp_path = cache_dir.join(format!("{key}.json.{}.tmp", std::process::id()));
+    let writer = BufWriter::new(File::create(&tmp_path)?);
+    serde_json::to_writer(writer, stats)?;
+    fs::rename(&tmp_path, entry_path(cache_dir, key))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::analyze_msgs, dogstatsdreader::DogStatsDReader};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "dogstatsd-utils-cache-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn key_depends_on_content_and_options() {
+        let dir = scratch_dir("key");
+        let a = dir.join("a.txt");
+        let b = dir.join("b.txt");
+        let c = dir.join("c.txt");
+        fs::write(&a, "my.metric:1|c\n").unwrap();
+        fs::write(&b, "my.metric:1|c\n").unwrap();
+        fs::write(&c, "my.metric:2|c\n").unwrap();
+
+        let key = cache_key(&[&a], "").unwrap();
+        assert_eq!(key, cache_key(&[&b], "").unwrap());
+        assert_ne!(key, cache_key(&[&c], "").unwrap());
+        assert_ne!(key, cache_key(&[&a], "archive_glob=*.zstd").unwrap());
+        assert_ne!(key, cache_key(&[&a, &b], "").unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn analysis_round_trips_through_the_cache() {
+        let dir = scratch_dir("round-trip");
+        assert!(load(&dir, "missing").is_none());
+
+        let mut reader =
+            DogStatsDReader::new("my.metric:1|c|#env:prod\n_sc|my.check|0\n".as_bytes()).unwrap();
+        let stats = analyze_msgs(&mut reader).unwrap();
+        store(&dir, "key", &stats).unwrap();
+
+        let cached = load(&dir, "key").unwrap();
+        assert_eq!(cached.num_msgs, 2);
+        assert_eq!(cached.num_contexts, stats.num_contexts);
+        assert_eq!(cached.unique_tags, stats.unique_tags);
+        assert_eq!(cached.kind, stats.kind);
+        assert_eq!(cached.name_length.count(), stats.name_length.count());
+
+        fs::write(entry_path(&dir, "corrupt"), "{not json").unwrap();
+        assert!(load(&dir, "corrupt").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
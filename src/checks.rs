@@ -0,0 +1,137 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::dogstatsdmsg::{DogStatsDMsg, ServiceCheckStatus};
+
+/// Orders statuses from healthy to unhealthy, a check that stopped reporting
+/// its state (Unknown) is considered healthier than one that is warning
+fn severity(status: ServiceCheckStatus) -> u8 {
+    match status {
+        ServiceCheckStatus::Ok => 0,
+        ServiceCheckStatus::Unknown => 1,
+        ServiceCheckStatus::Warning => 2,
+        ServiceCheckStatus::Critical => 3,
+    }
+}
+
+/// Everything seen for a single service check name
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckSummary {
+    pub name: String,
+    /// Status of the last msg for this check, in capture order
+    pub latest_status: ServiceCheckStatus,
+    pub worst_status: ServiceCheckStatus,
+    /// Number of msgs per status, indexed by the status value on the wire
+    pub status_counts: [u64; 4],
+    pub hosts: BTreeSet<String>,
+    /// Message of the last msg for this check that had one
+    pub last_message: Option<String>,
+}
+
+impl CheckSummary {
+    pub fn total(&self) -> u64 {
+        self.status_counts.iter().sum()
+    }
+
+    pub fn count(&self, status: ServiceCheckStatus) -> u64 {
+        self.status_counts[status as usize]
+    }
+}
+
+/// Builds a `CheckSummary` per service check name from a stream of msgs
+#[derive(Debug, Default)]
+pub struct CheckSummaries {
+    checks: HashMap<String, CheckSummary>,
+}
+
+impl CheckSummaries {
+    /// Msgs that are not service checks are ignored
+    pub fn record(&mut self, msg: &DogStatsDMsg) {
+        let DogStatsDMsg::ServiceCheck(sc) = msg else {
+            return;
+        };
+        let summary = self
+            .checks
+            .entry(sc.name.to_string())
+            .or_insert_with(|| CheckSummary {
+                name: sc.name.to_string(),
+                latest_status: sc.status,
+                worst_status: sc.status,
+                status_counts: [0; 4],
+                hosts: BTreeSet::new(),
+                last_message: None,
+            });
+        summary.latest_status = sc.status;
+        if severity(sc.status) > severity(summary.worst_status) {
+            summary.worst_status = sc.status;
+        }
+        summary.status_counts[sc.status as usize] += 1;
+        if let Some(host) = msg.host() {
+            if !summary.hosts.contains(host) {
+                summary.hosts.insert(host.to_string());
+            }
+        }
+        if let Some(message) = sc.message.filter(|m| !m.is_empty()) {
+            summary.last_message = Some(message.to_string());
+        }
+    }
+
+    /// Unhealthiest checks first, then by name
+    pub fn into_sorted(self) -> Vec<CheckSummary> {
+        let mut checks: Vec<CheckSummary> = self.checks.into_values().collect();
+        checks.sort_by(|a, b| {
+            severity(b.latest_status)
+                .cmp(&severity(a.latest_status))
+                .then(severity(b.worst_status).cmp(&severity(a.worst_status)))
+                .then(a.name.cmp(&b.name))
+        });
+        checks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summarize(msgs: &[&str]) -> Vec<CheckSummary> {
+        let mut summaries = CheckSummaries::default();
+        for msg in msgs {
+            summaries.record(&DogStatsDMsg::new(msg).unwrap());
+        }
+        summaries.into_sorted()
+    }
+
+    #[test]
+    fn summarizes_per_check() {
+        let checks = summarize(&[
+            "_sc|db.up|0|h:db-1",
+            "_sc|web.up|2|h:web-1|m:connection refused",
+            "_sc|db.up|1|h:db-2|m:slow",
+            "a:1|c",
+            "_sc|web.up|0|#host:web-2",
+            "_sc|db.up|0|h:db-1",
+        ]);
+        assert_eq!(checks.len(), 2);
+
+        // both are OK now, but web.up was critical at some point
+        let web = &checks[0];
+        assert_eq!(web.name, "web.up");
+        assert_eq!(web.latest_status, ServiceCheckStatus::Ok);
+        assert_eq!(web.worst_status, ServiceCheckStatus::Critical);
+        assert_eq!(web.status_counts, [1, 0, 1, 0]);
+        assert_eq!(web.hosts.iter().collect::<Vec<_>>(), vec!["web-1", "web-2"]);
+        assert_eq!(web.last_message.as_deref(), Some("connection refused"));
+
+        let db = &checks[1];
+        assert_eq!(db.worst_status, ServiceCheckStatus::Warning);
+        assert_eq!(db.count(ServiceCheckStatus::Ok), 2);
+        assert_eq!(db.total(), 3);
+        assert_eq!(db.last_message.as_deref(), Some("slow"));
+    }
+
+    #[test]
+    fn unknown_is_healthier_than_warning() {
+        let checks = summarize(&["_sc|a|3", "_sc|b|1", "_sc|c|0"]);
+        let names: Vec<&str> = checks.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "a", "c"]);
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    fmt::Display,
+    io::{self, BufRead, Write},
+    str::FromStr,
+};
+
+use lading_payload::dogstatsd::{self, KindWeights, MetricWeights, ValueConf};
+use rand::{rngs::SmallRng, SeedableRng};
+
+use crate::{
+    datagram::DatagramBatcher,
+    dogstatsdreader::{DogStatsDReader, DogStatsDReaderError},
+    dogstatsdreplayreader::dogstatsd::unix::UnixDogstatsdMsg,
+    replay::ReplayWriter,
+};
+
+/// Seed used when none is given, so corpora of the same profile and size are
+/// byte-for-byte identical wherever they are generated
+pub const DEFAULT_CORPUS_SEED: u64 = 34512423;
+
+/// Largest payload per replay record, the agent's default UDS buffer size
+const REPLAY_RECORD_SIZE: usize = 8192;
+
+/// Replay records are spaced this far apart, starting from REPLAY_START_NANOS
+const REPLAY_RECORD_INTERVAL_NANOS: i64 = 1_000_000;
+const REPLAY_START_NANOS: i64 = 1_700_000_000_000_000_000;
+
+/// Standardized shapes of traffic, each stresses a different part of the parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorpusProfile {
+    /// Few contexts with many long tags per msg
+    HeavyTags,
+    /// Hundreds of thousands of contexts with a handful of short tags
+    ManyContexts,
+    /// Mostly events, with some metrics and service checks
+    EventsHeavy,
+}
+
+impl CorpusProfile {
+    pub const ALL: [CorpusProfile; 3] = [
+        CorpusProfile::HeavyTags,
+        CorpusProfile::ManyContexts,
+        CorpusProfile::EventsHeavy,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CorpusProfile::HeavyTags => "heavy-tags",
+            CorpusProfile::ManyContexts => "many-contexts",
+            CorpusProfile::EventsHeavy => "events-heavy",
+        }
+    }
+
+    fn config(&self) -> dogstatsd::Config {
+        let base = dogstatsd::Config {
+            contexts: dogstatsd::ConfRange::Inclusive {
+                min: 500,
+                max: 10000,
+            },
+            service_check_names: dogstatsd::ConfRange::Inclusive { min: 5, max: 10 },
+            name_length: dogstatsd::ConfRange::Inclusive { min: 5, max: 10 },
+            tag_key_length: dogstatsd::ConfRange::Inclusive { min: 5, max: 10 },
+            tag_value_length: dogstatsd::ConfRange::Inclusive { min: 5, max: 10 },
+            tags_per_msg: dogstatsd::ConfRange::Inclusive { min: 1, max: 10 },
+            multivalue_count: dogstatsd::ConfRange::Inclusive { min: 1, max: 10 },
+            multivalue_pack_probability: 0.08,
+            sampling_range: dogstatsd::ConfRange::Inclusive { min: 0.1, max: 1.0 },
+            sampling_probability: 0.50,
+            kind_weights: KindWeights::default(),
+            metric_weights: MetricWeights::default(),
+            value: ValueConf::default(),
+            length_prefix_framed: false,
+        };
+        match self {
+            CorpusProfile::HeavyTags => dogstatsd::Config {
+                contexts: dogstatsd::ConfRange::Inclusive {
+                    min: 1000,
+                    max: 5000,
+                },
+                tag_key_length: dogstatsd::ConfRange::Inclusive { min: 10, max: 30 },
+                tag_value_length: dogstatsd::ConfRange::Inclusive { min: 20, max: 60 },
+                tags_per_msg: dogstatsd::ConfRange::Inclusive { min: 20, max: 50 },
+                ..base
+            },
+            CorpusProfile::ManyContexts => dogstatsd::Config {
+                contexts: dogstatsd::ConfRange::Inclusive {
+                    min: 100_000,
+                    max: 200_000,
+                },
+                ..base
+            },
+            CorpusProfile::EventsHeavy => dogstatsd::Config {
+                kind_weights: KindWeights::new(2, 6, 2),
+                ..base
+            },
+        }
+    }
+}
+
+impl FromStr for CorpusProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CorpusProfile::ALL
+            .into_iter()
+            .find(|profile| profile.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = CorpusProfile::ALL.iter().map(|p| p.name()).collect();
+                format!(
+                    "Unknown profile '{}', expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl Display for CorpusProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Deterministic stream of msgs for a `CorpusProfile`
+pub struct CorpusGenerator {
+    dd: dogstatsd::DogStatsD,
+    rng: SmallRng,
+}
+
+impl CorpusGenerator {
+    pub fn new(profile: CorpusProfile, seed: u64) -> Self {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let dd = dogstatsd::DogStatsD::new(profile.config(), &mut rng)
+            .expect("Corpus profiles are valid dogstatsd configs");
+        Self { dd, rng }
+    }
+
+    pub fn next_msg(&mut self) -> String {
+        self.dd
+            .generate(&mut self.rng)
+            .expect("Generating a msg never fails")
+            .to_string()
+    }
+
+    /// Writes newline separated msgs to 'out' until at least 'size' bytes
+    /// have been written, returns the number of msgs written
+    pub fn write_text(&mut self, size: u64, mut out: impl Write) -> io::Result<u64> {
+        let mut written = 0;
+        let mut num_msgs = 0;
+        while written < size {
+            let msg = self.next_msg();
+            writeln!(out, "{}", msg)?;
+            written += msg.len() as u64 + 1;
+            num_msgs += 1;
+        }
+        out.flush()?;
+        Ok(num_msgs)
+    }
+}
+
+/// Packs the newline separated msgs of 'text' into a replay file, as if each
+/// record was read from the socket 1ms after the previous one. Returns the
+/// number of records written.
+pub fn write_replay(text: impl BufRead, out: impl Write) -> io::Result<u64> {
+    write_replay_msgs(text.lines(), out)
+}
+
+/// Like `write_replay`, but packs the msgs of 'reader', so any input the
+/// reader understands can be turned into a replay file
+pub fn write_replay_from_reader(
+    reader: &mut DogStatsDReader,
+    out: impl Write,
+) -> Result<u64, DogStatsDReaderError> {
+    let mut failure = None;
+    let mut line = String::new();
+    let msgs = std::iter::from_fn(|| {
+        line.clear();
+        match reader.read_msg(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(line.trim_end().to_string())),
+            Err(e) => {
+                failure = Some(e);
+                None
+            }
+        }
+    });
+    let num_records = write_replay_msgs(msgs, out)?;
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(num_records),
+    }
+}
+
+fn write_replay_msgs(
+    msgs: impl Iterator<Item = io::Result<String>>,
+    out: impl Write,
+) -> io::Result<u64> {
+    let mut writer = ReplayWriter::new(out)?;
+    let mut batcher = DatagramBatcher::new(REPLAY_RECORD_SIZE);
+    let mut num_records: i64 = 0;
+    let mut emit = |payload: &[u8]| -> io::Result<()> {
+        writer.write_msg(&UnixDogstatsdMsg {
+            timestamp: REPLAY_START_NANOS + num_records * REPLAY_RECORD_INTERVAL_NANOS,
+            payload_size: payload.len() as i32,
+            payload: payload.to_vec(),
+            ..Default::default()
+        })?;
+        num_records += 1;
+        Ok(())
+    };
+    for msg in msgs {
+        batcher.push(msg?.as_bytes(), &mut emit)?;
+    }
+    batcher.finish(&mut emit)?;
+    writer.finish(&[])?;
+    Ok(num_records as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_parse_from_their_names() {
+        for profile in CorpusProfile::ALL {
+            assert_eq!(profile.name().parse::<CorpusProfile>(), Ok(profile));
+        }
+        assert!("light".parse::<CorpusProfile>().is_err());
+    }
+
+    #[test]
+    fn corpora_are_deterministic() {
+        let mut first = Vec::new();
+        let num_msgs = CorpusGenerator::new(CorpusProfile::HeavyTags, DEFAULT_CORPUS_SEED)
+            .write_text(64 * 1024, &mut first)
+            .unwrap();
+        assert!(first.len() >= 64 * 1024);
+
+        let mut second = Vec::new();
+        CorpusGenerator::new(CorpusProfile::HeavyTags, DEFAULT_CORPUS_SEED)
+            .write_text(64 * 1024, &mut second)
+            .unwrap();
+        assert_eq!(first, second);
+
+        let mut replay = Vec::new();
+        let num_records = write_replay(first.as_slice(), &mut replay).unwrap();
+        assert!(num_records > 1);
+
+        let mut reader = DogStatsDReader::new(replay.as_slice()).unwrap();
+        let mut line = String::new();
+        let mut replayed_msgs = 0;
+        while reader.read_msg(&mut line).unwrap() > 0 {
+            replayed_msgs += 1;
+            line.clear();
+        }
+        assert_eq!(replayed_msgs, num_msgs);
+    }
+
+    #[test]
+    fn replays_are_written_from_any_input() {
+        let text = "a.b:1|c\nc.d:2|g|#env:prod\n_sc|db.up|0\n";
+        let mut replay = Vec::new();
+        let mut reader = DogStatsDReader::new(text.as_bytes()).unwrap();
+        assert_eq!(
+            write_replay_from_reader(&mut reader, &mut replay).unwrap(),
+            1
+        );
+
+        let mut reader = DogStatsDReader::new(replay.as_slice()).unwrap();
+        let mut msgs = Vec::new();
+        let mut line = String::new();
+        while reader.read_msg(&mut line).unwrap() > 0 {
+            msgs.push(line.trim_end().to_string());
+            line.clear();
+        }
+        assert_eq!(msgs, ["a.b:1|c", "c.d:2|g|#env:prod", "_sc|db.up|0"]);
+    }
+}
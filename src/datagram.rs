@@ -0,0 +1,115 @@
+use std::io;
+
+use tracing::warn;
+
+/// Largest payload that fits a 1500 byte ethernet MTU with room for IP and UDP
+/// headers, this is the size the datadog clients default to for UDP
+pub const DEFAULT_MAX_DATAGRAM_SIZE: usize = 1432;
+
+/// Packs newline separated msgs into datagrams of at most 'max_size' bytes.
+/// A msg is never split across datagrams, a msg that is larger than
+/// 'max_size' on its own is sent alone with a warning, the agent will drop it.
+#[derive(Debug)]
+pub struct DatagramBatcher {
+    max_size: usize,
+    batch: Vec<u8>,
+    oversized_msgs: u64,
+}
+
+impl DatagramBatcher {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            batch: Vec::with_capacity(max_size),
+            oversized_msgs: 0,
+        }
+    }
+
+    /// Adds 'msg' to the current datagram, the current datagram is passed to
+    /// 'emit' first if 'msg' would push it past the size limit
+    pub fn push<F>(&mut self, msg: &[u8], mut emit: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        if msg.len() > self.max_size {
+            self.oversized_msgs += 1;
+            warn!(
+                "Msg of {} bytes is over the {} byte datagram limit, the agent will drop it",
+                msg.len(),
+                self.max_size
+            );
+            self.finish(&mut emit)?;
+            return emit(msg);
+        }
+        let separator = usize::from(!self.batch.is_empty());
+        if self.batch.len() + separator + msg.len() > self.max_size {
+            self.finish(&mut emit)?;
+        }
+        if !self.batch.is_empty() {
+            self.batch.push(b'\n');
+        }
+        self.batch.extend_from_slice(msg);
+        Ok(())
+    }
+
+    /// Passes the current datagram to 'emit', if it holds any msgs
+    pub fn finish<F>(&mut self, mut emit: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let res = emit(&self.batch);
+        self.batch.clear();
+        res
+    }
+
+    /// Number of msgs seen that were larger than the max datagram size
+    pub fn oversized_msgs(&self) -> u64 {
+        self.oversized_msgs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(max_size: usize, msgs: &[&str]) -> (Vec<String>, u64) {
+        let mut datagrams = Vec::new();
+        let mut emit = |d: &[u8]| {
+            datagrams.push(String::from_utf8(d.to_vec()).unwrap());
+            Ok(())
+        };
+        let mut batcher = DatagramBatcher::new(max_size);
+        for msg in msgs {
+            batcher.push(msg.as_bytes(), &mut emit).unwrap();
+        }
+        batcher.finish(&mut emit).unwrap();
+        (datagrams, batcher.oversized_msgs())
+    }
+
+    #[test]
+    fn msgs_are_never_split() {
+        // "a:1|c\nb:1|c" is exactly 11 bytes
+        let (datagrams, oversized) = batch(11, &["a:1|c", "b:1|c", "c:1|c"]);
+        assert_eq!(datagrams, vec!["a:1|c\nb:1|c", "c:1|c"]);
+        assert_eq!(oversized, 0);
+
+        let (datagrams, _) = batch(10, &["a:1|c", "b:1|c", "c:1|c"]);
+        assert_eq!(datagrams, vec!["a:1|c", "b:1|c", "c:1|c"]);
+    }
+
+    #[test]
+    fn oversized_msgs_are_sent_alone() {
+        let (datagrams, oversized) = batch(8, &["a:1|c", "my.long.metric:1|c", "b:1|c"]);
+        assert_eq!(datagrams, vec!["a:1|c", "my.long.metric:1|c", "b:1|c"]);
+        assert_eq!(oversized, 1);
+    }
+
+    #[test]
+    fn nothing_to_finish() {
+        let (datagrams, _) = batch(DEFAULT_MAX_DATAGRAM_SIZE, &[]);
+        assert!(datagrams.is_empty());
+    }
+}
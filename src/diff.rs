@@ -0,0 +1,298 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::analysis::DogStatsDBatchStats;
+
+/// Bumped on any incompatible change to the serialized `CaptureDiff`.
+/// Adding new fields is not considered incompatible.
+pub const DIFF_SCHEMA_VERSION: u32 = 1;
+
+/// Rates are msgs per second when both captures know their duration (replay
+/// files and pcaps), otherwise they are plain msg counts.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateUnit {
+    MsgsPerSecond,
+    Msgs,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CaptureSummary {
+    pub num_msgs: u32,
+    pub num_metric_names: usize,
+    pub duration_secs: Option<f64>,
+}
+
+/// Change in rate of a metric name present in both captures
+#[derive(Serialize, Debug, PartialEq)]
+pub struct NameDelta {
+    pub name: String,
+    pub baseline_rate: f64,
+    pub candidate_rate: f64,
+    /// candidate_rate - baseline_rate
+    pub delta: f64,
+    /// delta relative to baseline_rate, eg 0.5 for a 50% increase
+    pub relative_delta: f64,
+    /// Two sided p-value of both captures sharing the same underlying rate
+    pub p_value: f64,
+    /// Whether the relative change reached the significance threshold and is
+    /// unlikely to be noise, ie p_value is below the significance level
+    pub significant: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CaptureDiff {
+    pub schema_version: u32,
+    pub rate_unit: RateUnit,
+    pub significance_threshold: f64,
+    pub significance_level: f64,
+    pub baseline: CaptureSummary,
+    pub candidate: CaptureSummary,
+    /// Metric names only seen in the candidate, sorted
+    pub added: Vec<String>,
+    /// Metric names only seen in the baseline, sorted
+    pub removed: Vec<String>,
+    /// Every metric name seen in both captures, sorted by name
+    pub deltas: Vec<NameDelta>,
+}
+
+impl CaptureDiff {
+    /// True when names were added or removed, or any rate changed significantly
+    pub fn has_significant_changes(&self) -> bool {
+        !self.added.is_empty()
+            || !self.removed.is_empty()
+            || self.deltas.iter().any(|d| d.significant)
+    }
+}
+
+fn msgs_per_name(stats: &DogStatsDBatchStats) -> BTreeMap<&str, u32> {
+    stats
+        .metric_types_by_name
+        .iter()
+        .map(|(name, types)| (name.as_str(), types.values().sum()))
+        .collect()
+}
+
+fn duration_secs(stats: &DogStatsDBatchStats) -> Option<f64> {
+    stats
+        .reader_analytics
+        .as_ref()
+        .map(|analytics| analytics.duration().as_secs_f64())
+        .filter(|secs| *secs > 0.0)
+}
+
+/// Complementary error function, Abramowitz and Stegun 7.1.26.
+/// Absolute error is below 1.5e-7 which is plenty for flagging changes.
+fn erfc(x: f64) -> f64 {
+    if x < 0.0 {
+        return 2.0 - erfc(-x);
+    }
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    poly * (-x * x).exp()
+}
+
+/// Two sided p-value for two poisson counts observed over 'baseline_exposure'
+/// and 'candidate_exposure' having the same rate. Conditioned on the total,
+/// the candidate count is binomial with p = candidate_exposure / total_exposure,
+/// which is approximated with a normal distribution.
+fn poisson_rate_p_value(
+    baseline_count: u32,
+    baseline_exposure: f64,
+    candidate_count: u32,
+    candidate_exposure: f64,
+) -> f64 {
+    let n = (baseline_count + candidate_count) as f64;
+    let p = candidate_exposure / (baseline_exposure + candidate_exposure);
+    let variance = n * p * (1.0 - p);
+    if variance <= 0.0 {
+        return 1.0;
+    }
+    let z = (candidate_count as f64 - n * p) / variance.sqrt();
+    erfc(z.abs() / std::f64::consts::SQRT_2)
+}
+
+fn summarize(stats: &DogStatsDBatchStats) -> CaptureSummary {
+    CaptureSummary {
+        num_msgs: stats.num_msgs,
+        num_metric_names: stats.metric_types_by_name.len(),
+        duration_secs: duration_secs(stats),
+    }
+}
+
+/// Compares the metric names seen in two captures. A name's rate changed
+/// significantly when it moved by at least 'significance_threshold' relative
+/// to the baseline, eg 0.5 flags anything that grew or shrank by 50% or more,
+/// and a poisson rate comparison puts the chance of that change being noise
+/// below 'significance_level', eg 0.01.
+///
+/// Captures of different durations are compared by rate. When either duration
+/// is unknown both captures are assumed to cover the same window.
+pub fn diff_captures(
+    baseline: &DogStatsDBatchStats,
+    candidate: &DogStatsDBatchStats,
+    significance_threshold: f64,
+    significance_level: f64,
+) -> CaptureDiff {
+    let baseline_summary = summarize(baseline);
+    let candidate_summary = summarize(candidate);
+    let (rate_unit, baseline_secs, candidate_secs) = match (
+        baseline_summary.duration_secs,
+        candidate_summary.duration_secs,
+    ) {
+        (Some(b), Some(c)) => (RateUnit::MsgsPerSecond, b, c),
+        _ => (RateUnit::Msgs, 1.0, 1.0),
+    };
+
+    let baseline_names = msgs_per_name(baseline);
+    let candidate_names = msgs_per_name(candidate);
+    let all_names: BTreeSet<&str> = baseline_names
+        .keys()
+        .chain(candidate_names.keys())
+        .copied()
+        .collect();
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut deltas = Vec::new();
+    for name in all_names {
+        match (baseline_names.get(name), candidate_names.get(name)) {
+            (Some(b), Some(c)) => {
+                let baseline_rate = *b as f64 / baseline_secs;
+                let candidate_rate = *c as f64 / candidate_secs;
+                let delta = candidate_rate - baseline_rate;
+                // names are only recorded once seen, so baseline_rate is never 0
+                let relative_delta = delta / baseline_rate;
+                let p_value = poisson_rate_p_value(*b, baseline_secs, *c, candidate_secs);
+                deltas.push(NameDelta {
+                    name: name.to_string(),
+                    baseline_rate,
+                    candidate_rate,
+                    delta,
+                    relative_delta,
+                    p_value,
+                    significant: relative_delta.abs() >= significance_threshold
+                        && p_value < significance_level,
+                });
+            }
+            (None, Some(_)) => added.push(name.to_string()),
+            (Some(_), None) => removed.push(name.to_string()),
+            (None, None) => unreachable!("name came from one of the two maps"),
+        }
+    }
+
+    CaptureDiff {
+        schema_version: DIFF_SCHEMA_VERSION,
+        rate_unit,
+        significance_threshold,
+        significance_level,
+        baseline: baseline_summary,
+        candidate: candidate_summary,
+        added,
+        removed,
+        deltas,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::analyze_msgs, dogstatsdreader::DogStatsDReader};
+
+    fn analyze(payload: &[u8]) -> DogStatsDBatchStats {
+        let mut reader = DogStatsDReader::new(payload).unwrap();
+        analyze_msgs(&mut reader).unwrap()
+    }
+
+    #[test]
+    fn added_removed_and_changed_names() {
+        let baseline = analyze(
+            format!(
+                "{}{}gone:1|c\n",
+                "a:1|c\n".repeat(100),
+                "b:1|g\n".repeat(100)
+            )
+            .as_bytes(),
+        );
+        let candidate = analyze(
+            format!(
+                "{}{}new:1|d\n",
+                "a:1|c\n".repeat(105),
+                "b:1|g\n".repeat(200)
+            )
+            .as_bytes(),
+        );
+        let diff = diff_captures(&baseline, &candidate, 0.5, 0.01);
+
+        assert_eq!(diff.rate_unit, RateUnit::Msgs);
+        assert_eq!(diff.added, vec!["new"]);
+        assert_eq!(diff.removed, vec!["gone"]);
+        assert_eq!(diff.deltas.len(), 2);
+        assert_eq!(diff.deltas[0].name, "a");
+        assert!(!diff.deltas[0].significant);
+        assert_eq!(diff.deltas[1].name, "b");
+        assert_eq!(diff.deltas[1].delta, 100.0);
+        assert_eq!(diff.deltas[1].relative_delta, 1.0);
+        assert!(diff.deltas[1].p_value < 0.0001);
+        assert!(diff.deltas[1].significant);
+        assert!(diff.has_significant_changes());
+    }
+
+    #[test]
+    fn large_relative_change_on_few_msgs_is_noise() {
+        let diff = diff_captures(&analyze(b"a:1|c\n"), &analyze(b"a:1|c\na:1|c\n"), 0.5, 0.01);
+        assert_eq!(diff.deltas[0].relative_delta, 1.0);
+        assert!(diff.deltas[0].p_value > 0.5);
+        assert!(!diff.deltas[0].significant);
+    }
+
+    #[test]
+    fn p_values() {
+        // equal counts over equal windows are as likely as it gets
+        assert!((poisson_rate_p_value(50, 1.0, 50, 1.0) - 1.0).abs() < 1e-6);
+        // the same counts over unequal windows are not
+        assert!(poisson_rate_p_value(50, 1.0, 50, 2.0) < 0.01);
+        // z of 1.96 is the classic 5% two sided cutoff
+        assert!((erfc(1.959964 / std::f64::consts::SQRT_2) - 0.05).abs() < 1e-6);
+    }
+
+    #[test]
+    fn identical_captures_have_no_changes() {
+        let payload = b"a:1|c\nb:1|g|#env:prod\n";
+        let diff = diff_captures(&analyze(payload), &analyze(payload), 0.1, 0.01);
+        assert!(!diff.has_significant_changes());
+    }
+
+    #[test]
+    fn json_schema_is_stable() {
+        let diff = diff_captures(&analyze(b"a:1|c\n"), &analyze(b"a:1|c\na:1|c\n"), 0.5, 0.01);
+        let mut json = serde_json::to_value(&diff).unwrap();
+        let p_value = json["deltas"][0]["p_value"].take();
+        assert!(p_value.is_f64());
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "schema_version": 1,
+                "rate_unit": "msgs",
+                "significance_threshold": 0.5,
+                "significance_level": 0.01,
+                "baseline": {"num_msgs": 1, "num_metric_names": 1, "duration_secs": null},
+                "candidate": {"num_msgs": 2, "num_metric_names": 1, "duration_secs": null},
+                "added": [],
+                "removed": [],
+                "deltas": [{
+                    "name": "a",
+                    "baseline_rate": 1.0,
+                    "candidate_rate": 2.0,
+                    "delta": 1.0,
+                    "relative_delta": 1.0,
+                    "p_value": null,
+                    "significant": false
+                }]
+            })
+        );
+    }
+}
@@ -1,28 +1,255 @@
-use std::fmt::Display;
-
+use alloc::{
+    borrow::ToOwned,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::cell::OnceCell;
+use core::fmt::Display;
+use core::ops::Deref;
+
+use memchr::memchr;
+use serde::{Deserialize, Serialize};
 use smallvec::{smallvec, SmallVec};
-use thiserror::Error;
 
+#[cfg(feature = "full")]
 use lading_payload::dogstatsd::event::Alert as LadingAlert;
+#[cfg(feature = "full")]
+use lading_payload::dogstatsd::Member as LadingMember;
 
 const MAX_TAGS: usize = 50;
 
-#[derive(Error, Debug, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub enum DogStatsDMsgError {
-    #[error("Parsing Error for {kind}: '{reason}' Full msg: '{raw_msg}'")]
     ParseError {
         kind: DogStatsDMsgKind,
         reason: &'static str,
         raw_msg: String,
+        /// Byte offset into raw_msg of the field that failed
+        offset: usize,
+        /// The field that failed, empty when a required field is missing
+        field: String,
     },
+    InvalidUtf8(core::str::Utf8Error),
+}
+
+// Written out rather than derived with thiserror, which needs std, so the
+// parser builds without it
+impl Display for DogStatsDMsgError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::ParseError {
+                kind,
+                reason,
+                raw_msg,
+                offset,
+                field,
+            } => write!(
+                f,
+                "Parsing Error for {kind}: '{reason}' at byte {offset} ('{field}') Full msg: '{raw_msg}'"
+            ),
+            Self::InvalidUtf8(e) => write!(f, "Invalid UTF-8 in msg: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for DogStatsDMsgError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::ParseError { .. } => None,
+            Self::InvalidUtf8(e) => Some(e),
+        }
+    }
+}
+
+impl From<core::str::Utf8Error> for DogStatsDMsgError {
+    fn from(e: core::str::Utf8Error) -> Self {
+        Self::InvalidUtf8(e)
+    }
 }
 
 impl DogStatsDMsgError {
-    fn new_parse_error(kind: DogStatsDMsgKind, reason: &'static str, raw_msg: String) -> Self {
+    /// 'field' must be a slice of 'raw_msg' so that its offset can be found
+    fn new_parse_error(
+        kind: DogStatsDMsgKind,
+        reason: &'static str,
+        raw_msg: &str,
+        field: &str,
+    ) -> Self {
+        let offset = (field.as_ptr() as usize).wrapping_sub(raw_msg.as_ptr() as usize);
         Self::ParseError {
             kind,
             reason,
-            raw_msg,
+            raw_msg: raw_msg.to_owned(),
+            offset: if offset <= raw_msg.len() { offset } else { 0 },
+            field: field.to_owned(),
+        }
+    }
+}
+
+/// The empty slice at the end of 's', for required fields that are missing
+fn end_of(s: &str) -> &str {
+    &s[s.len()..]
+}
+
+/// Everything in 's' from 'idx' on, or `end_of` 's' if 'idx' is past the end
+fn rest_from(s: &str, idx: usize) -> &str {
+    s.get(idx..).unwrap_or(end_of(s))
+}
+
+/// Whether title and text lengths of 'title_len' and 'text_len' bytes line up
+/// with the '|' separators of the event body starting at 'body_start'
+fn event_lengths_line_up(msg: &str, body_start: usize, title_len: usize, text_len: usize) -> bool {
+    let title_end = body_start + title_len;
+    let text_end = title_end + 1 + text_len;
+    msg.is_char_boundary(title_end)
+        && msg.as_bytes().get(title_end) == Some(&b'|')
+        && msg.get(title_end + 1..text_end).is_some()
+        && (text_end == msg.len() || msg.as_bytes()[text_end] == b'|')
+}
+
+/// Byte length of the first 'chars' characters of 's', None if 's' is shorter
+fn chars_to_bytes(s: &str, chars: usize) -> Option<usize> {
+    s.char_indices()
+        .map(|(idx, _)| idx)
+        .chain(core::iter::once(s.len()))
+        .nth(chars)
+}
+
+/// Byte lengths of an event title and text whose lengths were sent as
+/// character counts, None if the counts don't line up with the msg either
+fn event_char_lengths_to_bytes(
+    msg: &str,
+    body_start: usize,
+    title_chars: usize,
+    text_chars: usize,
+) -> Option<(usize, usize)> {
+    let body = msg.get(body_start..)?;
+    let title_len = chars_to_bytes(body, title_chars)?;
+    let text_len = chars_to_bytes(body.get(title_len + 1..)?, text_chars)?;
+    event_lengths_line_up(msg, body_start, title_len, text_len).then_some((title_len, text_len))
+}
+
+/// The '|' separated fields of a msg, like `str::split('|')` but located
+/// with memchr
+struct PipeFields<'a> {
+    rest: Option<&'a str>,
+}
+
+impl<'a> PipeFields<'a> {
+    fn new(s: &'a str) -> Self {
+        Self { rest: Some(s) }
+    }
+}
+
+impl<'a> Iterator for PipeFields<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.rest?;
+        match memchr(b'|', rest.as_bytes()) {
+            Some(idx) => {
+                self.rest = Some(&rest[idx + 1..]);
+                Some(&rest[..idx])
+            }
+            None => {
+                self.rest = None;
+                Some(rest)
+            }
+        }
+    }
+}
+
+/// A problem with a single field that `DogStatsDMsg::new_lenient` worked around
+#[derive(Debug, Clone, PartialEq)]
+pub struct DogStatsDMsgWarning<'a> {
+    pub kind: DogStatsDMsgKind,
+    pub reason: &'static str,
+    /// The offending field as it appears in the msg
+    pub field: &'a str,
+}
+
+type Warnings<'w, 'a> = Option<&'w mut Vec<DogStatsDMsgWarning<'a>>>;
+
+/// Recoverable problems are warnings when parsing leniently and errors otherwise
+fn warn_or_err<'a>(
+    warnings: &mut Warnings<'_, 'a>,
+    kind: DogStatsDMsgKind,
+    reason: &'static str,
+    field: &'a str,
+    raw_msg: &str,
+) -> Result<(), DogStatsDMsgError> {
+    match warnings {
+        Some(warnings) => {
+            warnings.push(DogStatsDMsgWarning {
+                kind,
+                reason,
+                field,
+            });
+            Ok(())
+        }
+        None => Err(DogStatsDMsgError::new_parse_error(
+            kind, reason, raw_msg, field,
+        )),
+    }
+}
+
+/// Problems strict parsing has always let through, only reported when parsing leniently
+fn warn<'a>(
+    warnings: &mut Warnings<'_, 'a>,
+    kind: DogStatsDMsgKind,
+    reason: &'static str,
+    field: &'a str,
+) {
+    if let Some(warnings) = warnings {
+        warnings.push(DogStatsDMsgWarning {
+            kind,
+            reason,
+            field,
+        });
+    }
+}
+
+/// Rejects the malformed separators only `DogStatsDMsg::new_strict` checks
+/// for, 'last' is whether 'field' is the last field of the msg
+fn check_separator(
+    kind: DogStatsDMsgKind,
+    field: &str,
+    last: bool,
+    raw_msg: &str,
+) -> Result<(), DogStatsDMsgError> {
+    let reason = match field {
+        "" if last => "Trailing pipe found",
+        "" => "Empty field between two pipes found",
+        "#" => "Empty tag section found",
+        _ => return Ok(()),
+    };
+    Err(DogStatsDMsgError::new_parse_error(
+        kind, reason, raw_msg, field,
+    ))
+}
+
+/// Checks `DogStatsDMsg::new_lenient_with_options` makes on top of those of
+/// `new_lenient`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LenientOptions {
+    /// Warn about every tag that repeats an earlier tag of the same msg. Off
+    /// by default, as it splits the tags of every metric.
+    pub duplicate_tags: bool,
+}
+
+/// Warns about every tag that repeats an earlier tag of the same msg
+fn warn_duplicate_tags<'a>(
+    warnings: &mut Vec<DogStatsDMsgWarning<'a>>,
+    kind: DogStatsDMsgKind,
+    tags: &[&'a str],
+) {
+    for (idx, tag) in tags.iter().enumerate() {
+        if tags[..idx].contains(tag) {
+            warnings.push(DogStatsDMsgWarning {
+                kind,
+                reason: "Duplicate tag found",
+                field: tag,
+            });
         }
     }
 }
@@ -41,18 +268,22 @@ pub enum DogStatsDMsg<'a> {
 pub struct DogStatsDEventStr<'a> {
     pub title: &'a str,
     pub text: &'a str,
-    pub timestamp: Option<&'a str>,
+    pub timestamp: Option<u64>,
     pub hostname: Option<&'a str>,
     pub priority: Option<&'a str>, // Set to normal or low. Default normal.
     pub alert_type: EventAlert,
     pub aggregation_key: Option<&'a str>,
     pub source_type_name: Option<&'a str>,
+    /// Origin detection data sent by the client as `e:`
+    pub external_data: Option<&'a str>,
+    /// Cardinality hint sent by the client as `card:`
+    pub cardinality: Option<Cardinality>,
     pub tags: SmallVec<&'a str, MAX_TAGS>,
     pub raw_msg: &'a str,
 }
 
 // Status: An integer corresponding to the check status (OK = 0, WARNING = 1, CRITICAL = 2, UNKNOWN = 3).
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum ServiceCheckStatus {
     Ok = 0,
     Warning = 1,
@@ -60,7 +291,7 @@ pub enum ServiceCheckStatus {
     Unknown = 3,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum EventAlert {
     Error,
     Warning,
@@ -82,6 +313,7 @@ impl TryFrom<&str> for EventAlert {
     }
 }
 
+#[cfg(feature = "full")]
 impl From<LadingAlert> for EventAlert {
     fn from(a: LadingAlert) -> Self {
         match a {
@@ -93,6 +325,68 @@ impl From<LadingAlert> for EventAlert {
     }
 }
 
+#[cfg(feature = "full")]
+impl From<EventAlert> for LadingAlert {
+    fn from(a: EventAlert) -> Self {
+        match a {
+            EventAlert::Error => LadingAlert::Error,
+            EventAlert::Warning => LadingAlert::Warning,
+            EventAlert::Info => LadingAlert::Info,
+            EventAlert::Success => LadingAlert::Success,
+        }
+    }
+}
+
+impl EventAlert {
+    /// The alert type as it appears in an event's `t:` field
+    pub fn wire_str(&self) -> &'static str {
+        match self {
+            EventAlert::Error => "error",
+            EventAlert::Warning => "warning",
+            EventAlert::Info => "info",
+            EventAlert::Success => "success",
+        }
+    }
+}
+
+/// Tag cardinality a client asks the Agent to enrich a msg with, sent as `card:`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Cardinality {
+    Low,
+    Orchestrator,
+    High,
+}
+
+impl TryFrom<&str> for Cardinality {
+    type Error = ();
+
+    fn try_from(s: &str) -> Result<Self, ()> {
+        match s {
+            "low" => Ok(Cardinality::Low),
+            "orchestrator" => Ok(Cardinality::Orchestrator),
+            "high" => Ok(Cardinality::High),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Cardinality {
+    /// The cardinality as it appears in a `card:` field
+    pub fn wire_str(&self) -> &'static str {
+        match self {
+            Cardinality::Low => "low",
+            Cardinality::Orchestrator => "orchestrator",
+            Cardinality::High => "high",
+        }
+    }
+}
+
+impl Display for Cardinality {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.wire_str())
+    }
+}
+
 impl TryFrom<&str> for ServiceCheckStatus {
     type Error = ();
 
@@ -107,14 +401,29 @@ impl TryFrom<&str> for ServiceCheckStatus {
     }
 }
 
+impl Display for ServiceCheckStatus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ServiceCheckStatus::Ok => write!(f, "OK"),
+            ServiceCheckStatus::Warning => write!(f, "WARNING"),
+            ServiceCheckStatus::Critical => write!(f, "CRITICAL"),
+            ServiceCheckStatus::Unknown => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
 // _sc|<NAME>|<STATUS>|d:<TIMESTAMP>|h:<HOSTNAME>|#<TAG_KEY_1>:<TAG_VALUE_1>,<TAG_2>|m:<SERVICE_CHECK_MESSAGE>
 #[derive(Debug)]
 pub struct DogStatsDServiceCheckStr<'a> {
     pub name: &'a str,
     pub status: ServiceCheckStatus,
-    pub timestamp: Option<&'a str>,
+    pub timestamp: Option<u64>,
     pub hostname: Option<&'a str>,
     pub message: Option<&'a str>,
+    /// Origin detection data sent by the client as `e:`
+    pub external_data: Option<&'a str>,
+    /// Cardinality hint sent by the client as `card:`
+    pub cardinality: Option<Cardinality>,
     pub tags: SmallVec<&'a str, MAX_TAGS>,
     pub raw_msg: &'a str,
 }
@@ -122,16 +431,93 @@ pub struct DogStatsDServiceCheckStr<'a> {
 #[derive(Debug)]
 pub struct DogStatsDMetricStr<'a> {
     pub name: &'a str,
+    /// Numeric values, for sets this only holds the member if it is numeric
     pub values: SmallVec<f64, MAX_TAGS>,
-    pub sample_rate: Option<&'a str>,
-    pub timestamp: Option<&'a str>,
+    /// The raw member of a set metric, which may be any string
+    pub set_value: Option<&'a str>,
+    pub sample_rate: Option<f64>,
+    pub timestamp: Option<u64>,
     pub container_id: Option<&'a str>,
+    /// Origin detection data sent by the client as `e:`
+    pub external_data: Option<&'a str>,
+    /// Cardinality hint sent by the client as `card:`
+    pub cardinality: Option<Cardinality>,
     pub metric_type: DogStatsDMetricType,
-    pub tags: SmallVec<&'a str, MAX_TAGS>,
+    pub tags: MetricTags<'a>,
     pub raw_msg: &'a str,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+/// Tags of a metric, split out of the msg's `|#` section the first time they
+/// are read, so paths that only need the name, values or type never split them.
+/// Derefs to a slice of tags, use `to_mut` to change them.
+#[derive(Clone, Default)]
+pub struct MetricTags<'a> {
+    /// The `|#` section without the `#`, None when the msg has no tags
+    section: Option<&'a str>,
+    split: OnceCell<SmallVec<&'a str, MAX_TAGS>>,
+}
+
+impl<'a> MetricTags<'a> {
+    fn from_section(section: Option<&'a str>) -> Self {
+        Self {
+            section,
+            split: OnceCell::new(),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[&'a str] {
+        self.split.get_or_init(|| match self.section {
+            Some(section) => section.split(',').collect(),
+            None => SmallVec::new(),
+        })
+    }
+
+    /// The tags, split if they weren't yet, to add, remove or change some
+    pub fn to_mut(&mut self) -> &mut SmallVec<&'a str, MAX_TAGS> {
+        self.as_slice();
+        self.split.get_mut().expect("tags were just split")
+    }
+}
+
+impl<'a> Deref for MetricTags<'a> {
+    type Target = [&'a str];
+
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl<'s, 'a> IntoIterator for &'s MetricTags<'a> {
+    type Item = &'s &'a str;
+    type IntoIter = core::slice::Iter<'s, &'a str>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_slice().iter()
+    }
+}
+
+impl<'a> FromIterator<&'a str> for MetricTags<'a> {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(tags: I) -> Self {
+        Self {
+            section: None,
+            split: OnceCell::from(tags.into_iter().collect::<SmallVec<_, MAX_TAGS>>()),
+        }
+    }
+}
+
+impl PartialEq for MetricTags<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl core::fmt::Debug for MetricTags<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum DogStatsDMetricType {
     Count,
     Gauge,
@@ -141,7 +527,7 @@ pub enum DogStatsDMetricType {
     Distribution,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum DogStatsDMsgKind {
     Metric,
     ServiceCheck,
@@ -149,7 +535,7 @@ pub enum DogStatsDMsgKind {
 }
 
 impl Display for DogStatsDMsgKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             DogStatsDMsgKind::Metric => write!(f, "Metric"),
             DogStatsDMsgKind::ServiceCheck => write!(f, "ServiceCheck"),
@@ -172,8 +558,22 @@ impl DogStatsDMetricType {
     }
 }
 
+impl DogStatsDMetricType {
+    /// The type as it appears on the wire, eg `ms` for a Timer
+    pub fn wire_str(&self) -> &'static str {
+        match self {
+            DogStatsDMetricType::Count => "c",
+            DogStatsDMetricType::Gauge => "g",
+            DogStatsDMetricType::Histogram => "h",
+            DogStatsDMetricType::Timer => "ms",
+            DogStatsDMetricType::Set => "s",
+            DogStatsDMetricType::Distribution => "d",
+        }
+    }
+}
+
 impl Display for DogStatsDMetricType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             DogStatsDMetricType::Count => write!(f, "Count"),
             DogStatsDMetricType::Gauge => write!(f, "Gauge"),
@@ -186,67 +586,165 @@ impl Display for DogStatsDMetricType {
 }
 
 impl<'a> DogStatsDMsg<'a> {
-    pub fn kind(self) -> DogStatsDMsgKind {
+    pub fn kind(&self) -> DogStatsDMsgKind {
         match self {
             DogStatsDMsg::Event(_) => DogStatsDMsgKind::Event,
             DogStatsDMsg::ServiceCheck(_) => DogStatsDMsgKind::ServiceCheck,
             DogStatsDMsg::Metric(_) => DogStatsDMsgKind::Metric,
         }
     }
+
+    pub fn raw_msg(&self) -> &'a str {
+        match self {
+            DogStatsDMsg::Event(e) => e.raw_msg,
+            DogStatsDMsg::ServiceCheck(sc) => sc.raw_msg,
+            DogStatsDMsg::Metric(m) => m.raw_msg,
+        }
+    }
+
+    /// The host this msg is attributed to. Events and service checks use their
+    /// `h:` hostname field, all kinds fall back to a `host:` tag.
+    pub fn host(&self) -> Option<&'a str> {
+        let (hostname, tags) = match self {
+            DogStatsDMsg::Event(e) => (e.hostname, e.tags.as_slice()),
+            DogStatsDMsg::ServiceCheck(sc) => (sc.hostname, sc.tags.as_slice()),
+            DogStatsDMsg::Metric(m) => (None, m.tags.as_slice()),
+        };
+        hostname
+            .filter(|h| !h.is_empty())
+            .or_else(|| tags.iter().find_map(|t| t.strip_prefix("host:")))
+    }
+
+    /// The tags of the msg, of whichever kind it is
+    pub fn tags(&self) -> &[&'a str] {
+        match self {
+            DogStatsDMsg::Event(e) => e.tags.as_slice(),
+            DogStatsDMsg::ServiceCheck(sc) => sc.tags.as_slice(),
+            DogStatsDMsg::Metric(m) => m.tags.as_slice(),
+        }
+    }
+
+    /// The `card:` cardinality hint the client sent, if any
+    pub fn cardinality(&self) -> Option<Cardinality> {
+        match self {
+            DogStatsDMsg::Event(e) => e.cardinality,
+            DogStatsDMsg::ServiceCheck(sc) => sc.cardinality,
+            DogStatsDMsg::Metric(m) => m.cardinality,
+        }
+    }
     // _e{<TITLE_UTF8_LENGTH>,<TEXT_UTF8_LENGTH>}:<TITLE>|<TEXT>|d:<TIMESTAMP>|h:<HOSTNAME>|p:<PRIORITY>|t:<ALERT_TYPE>|k:<AGGREGATION_KEY>|s:<SOURCE_TYPE_NAME>|#<TAG_KEY_1>:<TAG_VALUE_1>,<TAG_2>
-    fn parse_event(str_msg: &'a str) -> Result<Self, DogStatsDMsgError> {
+    fn parse_event(
+        str_msg: &'a str,
+        mut warnings: Warnings<'_, 'a>,
+        strict: bool,
+    ) -> Result<Self, DogStatsDMsgError> {
         let orig_msg = str_msg;
         let str_msg = str_msg.trim_end();
         let start_lengths_idx = str_msg.find('{').ok_or(DogStatsDMsgError::new_parse_error(
             DogStatsDMsgKind::Event,
             "No opening brace found",
-            str_msg.to_owned(),
-        ))?;
-        let end_lengths_idx = str_msg.find('}').ok_or(DogStatsDMsgError::new_parse_error(
-            DogStatsDMsgKind::Event,
-            "No closing brace found",
-            str_msg.to_owned(),
+            str_msg,
+            end_of(str_msg),
         ))?;
-
-        let lengths = &str_msg[start_lengths_idx + 1..end_lengths_idx]
-            .split(',')
-            .collect::<Vec<&str>>();
-        let title_length: usize = lengths[0].parse().map_err(|_e| {
+        // only a brace after the opening one closes the lengths
+        let end_lengths_idx = str_msg[start_lengths_idx..]
+            .find('}')
+            .map(|idx| start_lengths_idx + idx)
+            .ok_or(DogStatsDMsgError::new_parse_error(
+                DogStatsDMsgKind::Event,
+                "No closing brace found",
+                str_msg,
+                end_of(str_msg),
+            ))?;
+
+        let lengths = &str_msg[start_lengths_idx + 1..end_lengths_idx];
+        let (title_length, text_length) =
+            lengths
+                .split_once(',')
+                .ok_or(DogStatsDMsgError::new_parse_error(
+                    DogStatsDMsgKind::Event,
+                    "No comma found between title and text lengths",
+                    str_msg,
+                    lengths,
+                ))?;
+        let title_length: usize = title_length.parse().map_err(|_e| {
             DogStatsDMsgError::new_parse_error(
                 DogStatsDMsgKind::Event,
                 "Invalid title length specified",
-                str_msg.to_owned(),
+                str_msg,
+                title_length,
             )
         })?;
 
-        let text_length: usize = lengths[1].parse().map_err(|_e| {
+        let text_length: usize = text_length.parse().map_err(|_e| {
             DogStatsDMsgError::new_parse_error(
                 DogStatsDMsgKind::Event,
                 "Invalid text length specified",
-                str_msg.to_owned(),
+                str_msg,
+                text_length,
             )
         })?;
 
         let title_start_idx = end_lengths_idx + 2;
+        // Lengths are in bytes, but some clients send character counts. Those
+        // only differ for non-ascii titles and texts, where byte lengths then
+        // don't line up with the separators but character counts do.
+        let char_lengths =
+            if event_lengths_line_up(str_msg, title_start_idx, title_length, text_length) {
+                None
+            } else {
+                event_char_lengths_to_bytes(str_msg, title_start_idx, title_length, text_length)
+            };
+        let (title_length, text_length) = match char_lengths {
+            Some(byte_lengths) => {
+                warn_or_err(
+                    &mut warnings,
+                    DogStatsDMsgKind::Event,
+                    "Title and text lengths are character counts, not byte lengths",
+                    &str_msg[start_lengths_idx..=end_lengths_idx],
+                    str_msg,
+                )?;
+                byte_lengths
+            }
+            None => (title_length, text_length),
+        };
         let title_end_idx = title_start_idx + title_length;
         let text_start_idx = title_end_idx + 1;
         let text_end_idx = text_start_idx + text_length;
 
+        if title_end_idx <= str_msg.len() && !str_msg.is_char_boundary(title_end_idx) {
+            return Err(DogStatsDMsgError::new_parse_error(
+                DogStatsDMsgKind::Event,
+                "Title length ends inside a multi-byte character",
+                str_msg,
+                rest_from(str_msg, title_start_idx),
+            ));
+        }
         let title = str_msg.get(title_start_idx..title_end_idx).ok_or(
             DogStatsDMsgError::new_parse_error(
                 DogStatsDMsgKind::Event,
                 "Title length specified is longer than msg length",
-                str_msg.to_owned(),
+                str_msg,
+                rest_from(str_msg, title_start_idx),
             ),
         )?;
 
+        if text_end_idx <= str_msg.len() && !str_msg.is_char_boundary(text_end_idx) {
+            return Err(DogStatsDMsgError::new_parse_error(
+                DogStatsDMsgKind::Event,
+                "Text length ends inside a multi-byte character",
+                str_msg,
+                rest_from(str_msg, text_start_idx),
+            ));
+        }
         let text =
             str_msg
                 .get(text_start_idx..text_end_idx)
                 .ok_or(DogStatsDMsgError::new_parse_error(
                     DogStatsDMsgKind::Event,
                     "Text length specified is longer than msg length",
-                    str_msg.to_owned(),
+                    str_msg,
+                    rest_from(str_msg, text_start_idx),
                 ))?;
 
         // Initialize optional fields
@@ -256,6 +754,8 @@ impl<'a> DogStatsDMsg<'a> {
         let mut alert_type = EventAlert::Info;
         let mut aggregation_key = None;
         let mut source_type_name = None;
+        let mut external_data = None;
+        let mut cardinality = None;
         let mut tags = smallvec![];
 
         let post_text_idx = end_lengths_idx + 2 + title_length + text_length + 1;
@@ -265,31 +765,71 @@ impl<'a> DogStatsDMsg<'a> {
                 return Err(DogStatsDMsgError::new_parse_error(
                     DogStatsDMsgKind::Event,
                     "data present after title and text, but did not start with a pipe",
-                    str_msg.to_owned(),
+                    str_msg,
+                    post_text_msg,
                 ));
             }
-            for part in post_text_msg[1..].split('|') {
-                match part.chars().next() {
-                    Some('d') => timestamp = Some(&part[2..]),
-                    Some('h') => hostname = Some(&part[2..]),
-                    Some('p') => priority = Some(&part[2..]),
-                    Some('t') => {
+            // fields are matched on their 'x:' prefix, so the value after it
+            // can always be sliced off
+            let mut parts = post_text_msg[1..].split('|').peekable();
+            while let Some(part) = parts.next() {
+                if strict {
+                    check_separator(
+                        DogStatsDMsgKind::Event,
+                        part,
+                        parts.peek().is_none(),
+                        str_msg,
+                    )?;
+                }
+                match part.as_bytes() {
+                    [b'd', b':', ..] => match part[2..].parse() {
+                        Ok(ts) => timestamp = Some(ts),
+                        Err(_) => warn_or_err(
+                            &mut warnings,
+                            DogStatsDMsgKind::Event,
+                            "Invalid timestamp found",
+                            part,
+                            str_msg,
+                        )?,
+                    },
+                    [b'h', b':', ..] => hostname = Some(&part[2..]),
+                    [b'p', b':', ..] => priority = Some(&part[2..]),
+                    [b't', b':', ..] => {
                         alert_type = match EventAlert::try_from(&part[2..]) {
                             Ok(parsed_alert_type) => parsed_alert_type,
-                            // consider logging a trace/info level saying "defaulting to alert type"?
-                            Err(_) => EventAlert::Info,
+                            Err(_) => {
+                                // strict parsing has always defaulted these
+                                warn(
+                                    &mut warnings,
+                                    DogStatsDMsgKind::Event,
+                                    "Invalid alert type, defaulting to info",
+                                    part,
+                                );
+                                EventAlert::Info
+                            }
                         }
                     }
-                    Some('k') => aggregation_key = Some(&part[2..]),
-                    Some('s') => source_type_name = Some(&part[2..]),
-                    Some('#') => tags.extend(part[1..].split(',')),
-                    _ => {
-                        return Err(DogStatsDMsgError::new_parse_error(
+                    [b'k', b':', ..] => aggregation_key = Some(&part[2..]),
+                    [b's', b':', ..] => source_type_name = Some(&part[2..]),
+                    [b'e', b':', ..] => external_data = Some(&part[2..]),
+                    [b'c', b'a', b'r', b'd', b':', ..] => match Cardinality::try_from(&part[5..]) {
+                        Ok(c) => cardinality = Some(c),
+                        Err(_) => warn_or_err(
+                            &mut warnings,
                             DogStatsDMsgKind::Event,
-                            "Unknown event field value found",
-                            str_msg.to_owned(),
-                        ));
-                    }
+                            "Invalid cardinality found",
+                            part,
+                            str_msg,
+                        )?,
+                    },
+                    [b'#', ..] => tags.extend(part[1..].split(',')),
+                    _ => warn_or_err(
+                        &mut warnings,
+                        DogStatsDMsgKind::Event,
+                        "Unknown event field value found",
+                        part,
+                        str_msg,
+                    )?,
                 }
             }
         }
@@ -303,219 +843,901 @@ impl<'a> DogStatsDMsg<'a> {
             source_type_name,
             aggregation_key,
             alert_type,
+            external_data,
+            cardinality,
             tags,
             raw_msg: orig_msg,
         }))
     }
 
-    fn parse_metric(str_msg: &'a str) -> Result<Self, DogStatsDMsgError> {
+    // <METRIC_NAME>:<VALUE>|<TYPE>|@<SAMPLE_RATE>|#<TAG_KEY_1>:<TAG_VALUE_1>,<TAG_2>|T<TIMESTAMP>|c:<CONTAINER_ID>|e:<EXTERNAL_DATA>|card:<CARDINALITY>
+    // Parsing dominates analysis, so the msg is scanned once and the optional
+    // fields are matched on their first byte as they are found
+    fn parse_metric(
+        str_msg: &'a str,
+        mut warnings: Warnings<'_, 'a>,
+        strict: bool,
+    ) -> Result<Self, DogStatsDMsgError> {
         let str_msg = str_msg.trim_end();
-        let parts: Vec<&str> = str_msg.split('|').collect();
-        match parts.first() {
-            Some(prepipe) => {
-                let prepipe_deref = *prepipe;
-                let name_and_values = match prepipe_deref.split_once(':') {
-                    Some(n_and_v) => n_and_v,
-                    None => {
-                        return Err(DogStatsDMsgError::new_parse_error(
+        let mut fields = PipeFields::new(str_msg);
+        let prepipe = fields.next().unwrap_or(str_msg);
+        let Some(colon) = memchr(b':', prepipe.as_bytes()) else {
+            return Err(DogStatsDMsgError::new_parse_error(
+                DogStatsDMsgKind::Metric,
+                "Name or value missing",
+                str_msg,
+                prepipe,
+            ));
+        };
+        let name = &prepipe[..colon];
+        let str_values = &prepipe[colon + 1..];
+
+        let metric_type = match fields.next() {
+            Some(s) if s.len() > 2 => {
+                return Err(DogStatsDMsgError::new_parse_error(
+                    DogStatsDMsgKind::Metric,
+                    "Too many chars for metric type",
+                    str_msg,
+                    s,
+                ));
+            }
+            Some(s) => match DogStatsDMetricType::from_str(s) {
+                Ok(t) => t,
+                Err(_) => {
+                    return Err(DogStatsDMsgError::new_parse_error(
+                        DogStatsDMsgKind::Metric,
+                        "Invalid metric type found.",
+                        str_msg,
+                        s,
+                    ))
+                }
+            },
+            None => {
+                return Err(DogStatsDMsgError::new_parse_error(
+                    DogStatsDMsgKind::Metric,
+                    "No metric type found",
+                    str_msg,
+                    end_of(str_msg),
+                ))
+            }
+        };
+
+        let mut values = smallvec![];
+        let mut set_value = None;
+        if metric_type == DogStatsDMetricType::Set {
+            // set members are arbitrary strings and can't be packed
+            set_value = Some(str_values);
+            if let Ok(v) = str_values.parse::<f64>() {
+                values.push(v);
+            }
+        } else {
+            for part in str_values.split(':') {
+                match part.parse::<f64>() {
+                    Ok(v) => values.push(v),
+                    Err(_) => warn_or_err(
+                        &mut warnings,
+                        DogStatsDMsgKind::Metric,
+                        "Invalid or no value found",
+                        part,
+                        str_msg,
+                    )?,
+                }
+            }
+            // a metric needs at least one value, even when lenient
+            if values.is_empty() {
+                return Err(DogStatsDMsgError::new_parse_error(
+                    DogStatsDMsgKind::Metric,
+                    "Invalid or no value found",
+                    str_msg,
+                    str_values,
+                ));
+            }
+        }
+
+        // Only the first of each optional field counts, repeats are ignored
+        let mut tags = None;
+        let mut timestamp = None;
+        let mut sample_rate = None;
+        let mut container_id = None;
+        let mut external_data = None;
+        let mut cardinality = None;
+        let mut seen_timestamp = false;
+        let mut seen_sample_rate = false;
+        let mut seen_cardinality = false;
+        let mut fields = fields.peekable();
+        while let Some(field) = fields.next() {
+            if strict {
+                check_separator(
+                    DogStatsDMsgKind::Metric,
+                    field,
+                    fields.peek().is_none(),
+                    str_msg,
+                )?;
+            }
+            match field.as_bytes() {
+                [b'#', ..] => {
+                    if tags.is_some() {
+                        warn(
+                            &mut warnings,
                             DogStatsDMsgKind::Metric,
-                            "Name or value missing",
-                            str_msg.to_owned(),
-                        ))
+                            "Repeated tags field ignored",
+                            field,
+                        );
+                        continue;
                     }
-                };
-                let name = name_and_values.0;
-                let str_values = name_and_values.1;
-                let mut values = smallvec![];
-                for part in str_values.split(':') {
-                    match part.parse::<f64>() {
-                        Ok(v) => {values.push(v);}
-                        Err(_) => {
-                            return Err(DogStatsDMsgError::new_parse_error(
-                                DogStatsDMsgKind::Metric,
-                                "Invalid or no value found",
-                                str_msg.to_owned(),
-                            ))
-                        }
+                    tags = Some(&field[1..]);
+                }
+                [b'T', ..] => {
+                    if seen_timestamp {
+                        warn(
+                            &mut warnings,
+                            DogStatsDMsgKind::Metric,
+                            "Repeated timestamp field ignored",
+                            field,
+                        );
+                        continue;
+                    }
+                    seen_timestamp = true;
+                    match field[1..].parse::<u64>() {
+                        Ok(ts) => timestamp = Some(ts),
+                        Err(_) => warn_or_err(
+                            &mut warnings,
+                            DogStatsDMsgKind::Metric,
+                            "Invalid timestamp found",
+                            field,
+                            str_msg,
+                        )?,
                     }
                 }
-
-                let metric_type: DogStatsDMetricType = match parts.get(1) {
-                    Some(s) => {
-                        if s.len() > 2 {
-                            return Err(DogStatsDMsgError::new_parse_error(
-                                DogStatsDMsgKind::Metric,
-                                "Too many chars for metric type",
-                                str_msg.to_owned(),
-                            ));
-                        }
-                        match DogStatsDMetricType::from_str(s) {
-                            Ok(t) => t,
-                            Err(_) => {
-                                return Err(DogStatsDMsgError::new_parse_error(
-                                    DogStatsDMsgKind::Metric,
-                                    "Invalid metric type found.",
-                                    str_msg.to_owned(),
-                                ))
-                            }
-                        }
+                [b'@', ..] => {
+                    if seen_sample_rate {
+                        warn(
+                            &mut warnings,
+                            DogStatsDMsgKind::Metric,
+                            "Repeated sample rate field ignored",
+                            field,
+                        );
+                        continue;
                     }
-                    None => {
-                        return Err(DogStatsDMsgError::new_parse_error(
+                    seen_sample_rate = true;
+                    match field[1..].parse::<f64>() {
+                        Ok(rate) => sample_rate = Some(rate),
+                        Err(_) => warn_or_err(
+                            &mut warnings,
                             DogStatsDMsgKind::Metric,
-                            "No metric type found",
-                            str_msg.to_owned(),
-                        ))
+                            "Invalid sample rate found",
+                            field,
+                            str_msg,
+                        )?,
                     }
-                };
+                }
+                [b'c', b':', ..] => {
+                    if container_id.is_some() {
+                        warn(
+                            &mut warnings,
+                            DogStatsDMsgKind::Metric,
+                            "Repeated container id field ignored",
+                            field,
+                        );
+                        continue;
+                    }
+                    container_id = Some(&field[2..]);
+                }
+                [b'e', b':', ..] => {
+                    if external_data.is_some() {
+                        warn(
+                            &mut warnings,
+                            DogStatsDMsgKind::Metric,
+                            "Repeated external data field ignored",
+                            field,
+                        );
+                        continue;
+                    }
+                    external_data = Some(&field[2..]);
+                }
+                [b'c', b'a', b'r', b'd', b':', ..] => {
+                    if seen_cardinality {
+                        warn(
+                            &mut warnings,
+                            DogStatsDMsgKind::Metric,
+                            "Repeated cardinality field ignored",
+                            field,
+                        );
+                        continue;
+                    }
+                    seen_cardinality = true;
+                    match Cardinality::try_from(&field[5..]) {
+                        Ok(c) => cardinality = Some(c),
+                        Err(_) => warn_or_err(
+                            &mut warnings,
+                            DogStatsDMsgKind::Metric,
+                            "Invalid cardinality found",
+                            field,
+                            str_msg,
+                        )?,
+                    }
+                }
+                // strict parsing ignores fields it doesn't know about
+                _ => warn(
+                    &mut warnings,
+                    DogStatsDMsgKind::Metric,
+                    "Unknown metric field value found",
+                    field,
+                ),
+            }
+        }
+
+        let tags = MetricTags::from_section(tags);
+
+        Ok(DogStatsDMsg::Metric(DogStatsDMetricStr {
+            raw_msg: str_msg,
+            name,
+            values,
+            set_value,
+            container_id,
+            external_data,
+            cardinality,
+            timestamp,
+            sample_rate,
+            tags,
+            metric_type,
+        }))
+    }
+
+    // _sc|<NAME>|<STATUS>|d:<TIMESTAMP>|h:<HOSTNAME>|#<TAG_KEY_1>:<TAG_VALUE_1>,<TAG_2>|m:<SERVICE_CHECK_MESSAGE>
+    // Status: An integer corresponding to the check status (OK = 0, WARNING = 1, CRITICAL = 2, UNKNOWN = 3).
+    fn parse_servicecheck(
+        str_msg: &'a str,
+        mut warnings: Warnings<'_, 'a>,
+        strict: bool,
+    ) -> Result<Self, DogStatsDMsgError> {
+        let raw_msg = str_msg;
+        let str_msg = str_msg.trim_end();
+        let mut fields = str_msg.split('|');
+        // consume prefix
+        match fields.next() {
+            Some(pre) => {
+                if pre != "_sc" {
+                    return Err(DogStatsDMsgError::new_parse_error(
+                        DogStatsDMsgKind::ServiceCheck,
+                        "Unexpected prefix found for service check",
+                        raw_msg,
+                        pre,
+                    ));
+                }
+            }
+            None => {
+                return Err(DogStatsDMsgError::new_parse_error(
+                    DogStatsDMsgKind::ServiceCheck,
+                    "Not enough fields in msg",
+                    raw_msg,
+                    end_of(raw_msg),
+                ))
+            }
+        }
+        let name = match fields.next() {
+            Some(name) => name,
+            None => {
+                return Err(DogStatsDMsgError::new_parse_error(
+                    DogStatsDMsgKind::ServiceCheck,
+                    "Not enough fields, couldn't find name",
+                    raw_msg,
+                    end_of(raw_msg),
+                ))
+            }
+        };
+
+        let status = match fields.next() {
+            Some(status) => match ServiceCheckStatus::try_from(status) {
+                Ok(s) => s,
+                Err(_) => {
+                    return Err(DogStatsDMsgError::new_parse_error(
+                        DogStatsDMsgKind::ServiceCheck,
+                        "Invalid status found.",
+                        raw_msg,
+                        status,
+                    ))
+                }
+            },
+            None => {
+                return Err(DogStatsDMsgError::new_parse_error(
+                    DogStatsDMsgKind::ServiceCheck,
+                    "Not enough fields, couldn't find status",
+                    raw_msg,
+                    end_of(raw_msg),
+                ))
+            }
+        };
+
+        let mut timestamp = None;
+        let mut hostname = None;
+        let mut message = None;
+        let mut external_data = None;
+        let mut cardinality = None;
+        let mut tags = smallvec![];
+        if strict {
+            // the status follows the name, so it is never the last field
+            check_separator(DogStatsDMsgKind::ServiceCheck, name, false, str_msg)?;
+        }
+        // fields are matched on their 'x:' prefix, as for events
+        let mut fields = fields.peekable();
+        while let Some(field) = fields.next() {
+            if strict {
+                check_separator(
+                    DogStatsDMsgKind::ServiceCheck,
+                    field,
+                    fields.peek().is_none(),
+                    str_msg,
+                )?;
+            }
+            match field.as_bytes() {
+                [b'd', b':', ..] => match field[2..].parse() {
+                    Ok(ts) => timestamp = Some(ts),
+                    Err(_) => warn_or_err(
+                        &mut warnings,
+                        DogStatsDMsgKind::ServiceCheck,
+                        "Invalid timestamp found",
+                        field,
+                        raw_msg,
+                    )?,
+                },
+                [b'h', b':', ..] => hostname = Some(&field[2..]),
+                [b'm', b':', ..] => message = Some(&field[2..]),
+                [b'e', b':', ..] => external_data = Some(&field[2..]),
+                [b'c', b'a', b'r', b'd', b':', ..] => match Cardinality::try_from(&field[5..]) {
+                    Ok(c) => cardinality = Some(c),
+                    Err(_) => warn_or_err(
+                        &mut warnings,
+                        DogStatsDMsgKind::ServiceCheck,
+                        "Invalid cardinality found",
+                        field,
+                        raw_msg,
+                    )?,
+                },
+                [b'#', ..] => tags.extend(field[1..].split(',')),
+                _ => warn_or_err(
+                    &mut warnings,
+                    DogStatsDMsgKind::ServiceCheck,
+                    "Unknown servicecheck field value found",
+                    field,
+                    raw_msg,
+                )?,
+            }
+        }
+
+        Ok(DogStatsDMsg::ServiceCheck(DogStatsDServiceCheckStr {
+            raw_msg,
+            name,
+            tags,
+            status,
+            timestamp,
+            hostname,
+            message,
+            external_data,
+            cardinality,
+        }))
+    }
+
+    /// Parses a msg straight out of a packet payload without copying it.
+    /// Every field borrows from the payload as a `&str`, so the bytes are
+    /// validated as UTF-8 in place, which is the only work this adds to `new`.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, DogStatsDMsgError> {
+        Self::new(core::str::from_utf8(bytes)?)
+    }
+
+    pub fn new(str_msg: &'a str) -> Result<Self, DogStatsDMsgError> {
+        Self::parse(str_msg, None, false)
+    }
+
+    /// Like `new`, but problems confined to a single optional field (an
+    /// unparseable timestamp or sample rate, an unknown field, an invalid value
+    /// among several) are returned as warnings and the field is skipped.
+    /// Msgs that are unusable as a whole, eg without a name or type, are still errors.
+    pub fn new_lenient(
+        str_msg: &'a str,
+    ) -> Result<(Self, Vec<DogStatsDMsgWarning<'a>>), DogStatsDMsgError> {
+        Self::new_lenient_with_options(str_msg, LenientOptions::default())
+    }
+
+    /// Like `new_lenient`, with the extra checks of 'options'
+    pub fn new_lenient_with_options(
+        str_msg: &'a str,
+        options: LenientOptions,
+    ) -> Result<(Self, Vec<DogStatsDMsgWarning<'a>>), DogStatsDMsgError> {
+        let mut warnings = Vec::new();
+        let msg = Self::parse(str_msg, Some(&mut warnings), false)?;
+        if options.duplicate_tags {
+            warn_duplicate_tags(&mut warnings, msg.kind(), msg.tags());
+        }
+        Ok((msg, warnings))
+    }
+
+    /// Like `new`, but also rejects malformed separators that `new` tolerates:
+    /// an empty field between two pipes, a trailing pipe and an empty `|#` tag
+    /// section, each with its own reason. These are usually client bugs.
+    pub fn new_strict(str_msg: &'a str) -> Result<Self, DogStatsDMsgError> {
+        // separators are checked as each field is reached, so an empty field
+        // is reported as such rather than as an unknown field
+        Self::parse(str_msg, None, true)
+    }
+
+    /// 'strict' also rejects the malformed separators of `new_strict`
+    fn parse(
+        str_msg: &'a str,
+        warnings: Warnings<'_, 'a>,
+        strict: bool,
+    ) -> Result<Self, DogStatsDMsgError> {
+        if str_msg.starts_with("_e") {
+            return Self::parse_event(str_msg, warnings, strict);
+        }
+        if str_msg.starts_with("_sc") {
+            return Self::parse_servicecheck(str_msg, warnings, strict);
+        }
+        Self::parse_metric(str_msg, warnings, strict)
+    }
+}
+
+// For callers that already know the kind of their msgs, eg a pipeline that
+// has filtered out everything but metrics. These skip the prefix dispatch of
+// `DogStatsDMsg::new` and don't check that the msg is of the given kind, an
+// event passed to `parse_metric` is parsed (or rejected) as a metric.
+
+/// Like `DogStatsDMsg::new`, for a msg known to be a metric
+///
+/// ```
+/// use dogstatsd_utils::dogstatsdmsg::parse_metric;
+///
+/// assert_eq!(parse_metric("my.metric:1|c").unwrap().name, "my.metric");
+/// assert!(parse_metric("my.metric").is_err());
+/// assert!(parse_metric("my.metric:1|").is_err());
+/// assert!(parse_metric("my.metric:x|c").is_err());
+/// ```
+pub fn parse_metric(str_msg: &str) -> Result<DogStatsDMetricStr<'_>, DogStatsDMsgError> {
+    match DogStatsDMsg::parse_metric(str_msg, None, false)? {
+        DogStatsDMsg::Metric(metric) => Ok(metric),
+        _ => unreachable!("metric parser returned another kind"),
+    }
+}
+
+/// Like `DogStatsDMsg::new`, for a msg known to be an event
+///
+/// ```
+/// use dogstatsd_utils::dogstatsdmsg::parse_event;
+///
+/// assert_eq!(parse_event("_e{5,4}:title|text").unwrap().title, "title");
+/// assert!(parse_event("_e{5}:a|b").is_err());
+/// assert!(parse_event("_e}{").is_err());
+/// assert!(parse_event("_e{9,9}:a|b").is_err());
+/// assert!(parse_event("_e{1,1}:a|b|h").is_err());
+/// ```
+pub fn parse_event(str_msg: &str) -> Result<DogStatsDEventStr<'_>, DogStatsDMsgError> {
+    match DogStatsDMsg::parse_event(str_msg, None, false)? {
+        DogStatsDMsg::Event(event) => Ok(event),
+        _ => unreachable!("event parser returned another kind"),
+    }
+}
+
+/// Like `DogStatsDMsg::new`, for a msg known to be a service check
+///
+/// ```
+/// use dogstatsd_utils::dogstatsdmsg::parse_servicecheck;
+///
+/// assert_eq!(parse_servicecheck("_sc|my.check|0").unwrap().name, "my.check");
+/// assert!(parse_servicecheck("_sc|my.check").is_err());
+/// assert!(parse_servicecheck("_sc|my.check|9").is_err());
+/// assert!(parse_servicecheck("_sc|my.check|0|d").is_err());
+/// ```
+pub fn parse_servicecheck(
+    str_msg: &str,
+) -> Result<DogStatsDServiceCheckStr<'_>, DogStatsDMsgError> {
+    match DogStatsDMsg::parse_servicecheck(str_msg, None, false)? {
+        DogStatsDMsg::ServiceCheck(service_check) => Ok(service_check),
+        _ => unreachable!("service check parser returned another kind"),
+    }
+}
+
+/// Owned counterpart of `DogStatsDMsg`, does not borrow from the raw msg so it
+/// can be stored or sent across threads.
+/// Serialized with a "kind" field naming the variant, eg `{"kind":"Metric",...}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum DogStatsDMsgOwned {
+    Metric(DogStatsDMetricOwned),
+    Event(DogStatsDEventOwned),
+    ServiceCheck(DogStatsDServiceCheckOwned),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DogStatsDMetricOwned {
+    pub name: String,
+    pub values: Vec<f64>,
+    pub set_value: Option<String>,
+    pub sample_rate: Option<f64>,
+    pub timestamp: Option<u64>,
+    pub container_id: Option<String>,
+    pub external_data: Option<String>,
+    pub cardinality: Option<Cardinality>,
+    pub metric_type: DogStatsDMetricType,
+    pub tags: Vec<String>,
+    pub raw_msg: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DogStatsDEventOwned {
+    pub title: String,
+    pub text: String,
+    pub timestamp: Option<u64>,
+    pub hostname: Option<String>,
+    pub priority: Option<String>,
+    pub alert_type: EventAlert,
+    pub aggregation_key: Option<String>,
+    pub source_type_name: Option<String>,
+    pub external_data: Option<String>,
+    pub cardinality: Option<Cardinality>,
+    pub tags: Vec<String>,
+    pub raw_msg: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DogStatsDServiceCheckOwned {
+    pub name: String,
+    pub status: ServiceCheckStatus,
+    pub timestamp: Option<u64>,
+    pub hostname: Option<String>,
+    pub message: Option<String>,
+    pub external_data: Option<String>,
+    pub cardinality: Option<Cardinality>,
+    pub tags: Vec<String>,
+    pub raw_msg: String,
+}
+
+fn owned_opt(s: Option<&str>) -> Option<String> {
+    s.map(str::to_owned)
+}
+
+fn owned_tags(tags: &[&str]) -> Vec<String> {
+    tags.iter().map(|t| t.to_string()).collect()
+}
+
+impl From<&DogStatsDMetricStr<'_>> for DogStatsDMetricOwned {
+    fn from(m: &DogStatsDMetricStr<'_>) -> Self {
+        Self {
+            name: m.name.to_owned(),
+            values: m.values.to_vec(),
+            set_value: owned_opt(m.set_value),
+            sample_rate: m.sample_rate,
+            timestamp: m.timestamp,
+            container_id: owned_opt(m.container_id),
+            external_data: owned_opt(m.external_data),
+            cardinality: m.cardinality,
+            metric_type: m.metric_type,
+            tags: owned_tags(&m.tags),
+            raw_msg: m.raw_msg.to_owned(),
+        }
+    }
+}
+
+impl From<&DogStatsDEventStr<'_>> for DogStatsDEventOwned {
+    fn from(e: &DogStatsDEventStr<'_>) -> Self {
+        Self {
+            title: e.title.to_owned(),
+            text: e.text.to_owned(),
+            timestamp: e.timestamp,
+            hostname: owned_opt(e.hostname),
+            priority: owned_opt(e.priority),
+            alert_type: e.alert_type,
+            aggregation_key: owned_opt(e.aggregation_key),
+            source_type_name: owned_opt(e.source_type_name),
+            external_data: owned_opt(e.external_data),
+            cardinality: e.cardinality,
+            tags: owned_tags(&e.tags),
+            raw_msg: e.raw_msg.to_owned(),
+        }
+    }
+}
+
+impl From<&DogStatsDServiceCheckStr<'_>> for DogStatsDServiceCheckOwned {
+    fn from(sc: &DogStatsDServiceCheckStr<'_>) -> Self {
+        Self {
+            name: sc.name.to_owned(),
+            status: sc.status,
+            timestamp: sc.timestamp,
+            hostname: owned_opt(sc.hostname),
+            message: owned_opt(sc.message),
+            external_data: owned_opt(sc.external_data),
+            cardinality: sc.cardinality,
+            tags: owned_tags(&sc.tags),
+            raw_msg: sc.raw_msg.to_owned(),
+        }
+    }
+}
+
+impl From<&DogStatsDMsg<'_>> for DogStatsDMsgOwned {
+    fn from(msg: &DogStatsDMsg<'_>) -> Self {
+        match msg {
+            DogStatsDMsg::Metric(m) => DogStatsDMsgOwned::Metric(m.into()),
+            DogStatsDMsg::Event(e) => DogStatsDMsgOwned::Event(e.into()),
+            DogStatsDMsg::ServiceCheck(sc) => DogStatsDMsgOwned::ServiceCheck(sc.into()),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl TryFrom<&LadingMember<'_>> for DogStatsDMsgOwned {
+    type Error = DogStatsDMsgError;
+
+    /// Members are converted through the wire format lading sends them in,
+    /// so they come out exactly as a capture of lading's traffic would parse
+    fn try_from(member: &LadingMember<'_>) -> Result<Self, Self::Error> {
+        let wire = member.to_string();
+        Ok((&DogStatsDMsg::new(&wire)?).into())
+    }
+}
+
+/// Implements `TryFrom<&LadingMember>` for the owned type of a single msg
+/// kind, members of another kind are an error
+#[cfg(feature = "full")]
+macro_rules! try_from_lading_member {
+    ($owned:ty, $variant:ident, $reason:literal) => {
+        impl TryFrom<&LadingMember<'_>> for $owned {
+            type Error = DogStatsDMsgError;
+
+            fn try_from(member: &LadingMember<'_>) -> Result<Self, Self::Error> {
+                match DogStatsDMsgOwned::try_from(member)? {
+                    DogStatsDMsgOwned::$variant(owned) => Ok(owned),
+                    other => Err(DogStatsDMsgError::new_parse_error(
+                        other.kind(),
+                        $reason,
+                        other.raw_msg(),
+                        end_of(other.raw_msg()),
+                    )),
+                }
+            }
+        }
+    };
+}
+
+#[cfg(feature = "full")]
+try_from_lading_member!(
+    DogStatsDMetricOwned,
+    Metric,
+    "Lading member is not a metric"
+);
+#[cfg(feature = "full")]
+try_from_lading_member!(DogStatsDEventOwned, Event, "Lading member is not an event");
+#[cfg(feature = "full")]
+try_from_lading_member!(
+    DogStatsDServiceCheckOwned,
+    ServiceCheck,
+    "Lading member is not a service check"
+);
+
+impl DogStatsDMsgOwned {
+    pub fn kind(&self) -> DogStatsDMsgKind {
+        match self {
+            DogStatsDMsgOwned::Event(_) => DogStatsDMsgKind::Event,
+            DogStatsDMsgOwned::ServiceCheck(_) => DogStatsDMsgKind::ServiceCheck,
+            DogStatsDMsgOwned::Metric(_) => DogStatsDMsgKind::Metric,
+        }
+    }
+
+    pub fn raw_msg(&self) -> &str {
+        match self {
+            DogStatsDMsgOwned::Event(e) => &e.raw_msg,
+            DogStatsDMsgOwned::ServiceCheck(sc) => &sc.raw_msg,
+            DogStatsDMsgOwned::Metric(m) => &m.raw_msg,
+        }
+    }
+}
+
+// The wire format is rebuilt from the parsed fields rather than echoing
+// raw_msg, so any changes made to a parsed msg are reflected in the output.
+// Optional fields are emitted in the order documented above each struct.
+
+fn write_tags(f: &mut core::fmt::Formatter<'_>, tags: &[&str]) -> core::fmt::Result {
+    if tags.is_empty() {
+        return Ok(());
+    }
+    write!(f, "|#{}", tags.join(","))
+}
+
+impl Display for DogStatsDMetricStr<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.name)?;
+        match self.set_value {
+            Some(set_value) => write!(f, ":{}", set_value)?,
+            None => {
+                for value in &self.values {
+                    write!(f, ":{}", value)?;
+                }
+            }
+        }
+        write!(f, "|{}", self.metric_type.wire_str())?;
+        if let Some(sample_rate) = self.sample_rate {
+            write!(f, "|@{}", sample_rate)?;
+        }
+        write_tags(f, &self.tags)?;
+        if let Some(container_id) = self.container_id {
+            write!(f, "|c:{}", container_id)?;
+        }
+        if let Some(external_data) = self.external_data {
+            write!(f, "|e:{}", external_data)?;
+        }
+        if let Some(cardinality) = self.cardinality {
+            write!(f, "|card:{}", cardinality)?;
+        }
+        if let Some(timestamp) = self.timestamp {
+            write!(f, "|T{}", timestamp)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for DogStatsDEventStr<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "_e{{{},{}}}:{}|{}",
+            self.title.len(),
+            self.text.len(),
+            self.title,
+            self.text
+        )?;
+        if let Some(timestamp) = self.timestamp {
+            write!(f, "|d:{}", timestamp)?;
+        }
+        if let Some(hostname) = self.hostname {
+            write!(f, "|h:{}", hostname)?;
+        }
+        if let Some(priority) = self.priority {
+            write!(f, "|p:{}", priority)?;
+        }
+        // Info is the default when no alert type is given
+        if self.alert_type != EventAlert::Info {
+            write!(f, "|t:{}", self.alert_type.wire_str())?;
+        }
+        if let Some(aggregation_key) = self.aggregation_key {
+            write!(f, "|k:{}", aggregation_key)?;
+        }
+        if let Some(source_type_name) = self.source_type_name {
+            write!(f, "|s:{}", source_type_name)?;
+        }
+        write_tags(f, &self.tags)?;
+        if let Some(external_data) = self.external_data {
+            write!(f, "|e:{}", external_data)?;
+        }
+        if let Some(cardinality) = self.cardinality {
+            write!(f, "|card:{}", cardinality)?;
+        }
+        Ok(())
+    }
+}
 
-                let tags: SmallVec<&'a str, MAX_TAGS> =
-                    match parts.iter().find(|part| part.starts_with('#')) {
-                        Some(tags) => tags[1..].split(',').collect(),
-                        None => smallvec![],
-                    };
-
-                let timestamp = parts
-                    .iter()
-                    .find(|part| part.starts_with('T'))
-                    .map(|p| p.get(1..).unwrap());
-                let sample_rate = parts
-                    .iter()
-                    .find(|part| part.starts_with('@'))
-                    .map(|p| p.get(1..).unwrap());
-                let container_id = parts
-                    .iter()
-                    .find(|part| part.starts_with("c:"))
-                    .map(|p| p.get(2..).unwrap());
-
-                Ok(DogStatsDMsg::Metric(DogStatsDMetricStr {
-                    raw_msg: str_msg,
-                    name,
-                    values,
-                    container_id,
-                    timestamp,
-                    sample_rate,
-                    tags,
-                    metric_type,
-                }))
-            }
-            None => Err(DogStatsDMsgError::new_parse_error(
-                DogStatsDMsgKind::Metric,
-                "Unknown error",
-                str_msg.to_owned(),
-            )),
+impl Display for DogStatsDServiceCheckStr<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "_sc|{}|{}", self.name, self.status as u8)?;
+        if let Some(timestamp) = self.timestamp {
+            write!(f, "|d:{}", timestamp)?;
+        }
+        if let Some(hostname) = self.hostname {
+            write!(f, "|h:{}", hostname)?;
+        }
+        write_tags(f, &self.tags)?;
+        if let Some(external_data) = self.external_data {
+            write!(f, "|e:{}", external_data)?;
         }
+        if let Some(cardinality) = self.cardinality {
+            write!(f, "|card:{}", cardinality)?;
+        }
+        // The message must be the last field
+        if let Some(message) = self.message {
+            write!(f, "|m:{}", message)?;
+        }
+        Ok(())
     }
+}
 
-    // _sc|<NAME>|<STATUS>|d:<TIMESTAMP>|h:<HOSTNAME>|#<TAG_KEY_1>:<TAG_VALUE_1>,<TAG_2>|m:<SERVICE_CHECK_MESSAGE>
-    // Status: An integer corresponding to the check status (OK = 0, WARNING = 1, CRITICAL = 2, UNKNOWN = 3).
-    fn parse_servicecheck(str_msg: &'a str) -> Result<Self, DogStatsDMsgError> {
-        let raw_msg = str_msg;
-        let str_msg = str_msg.trim_end();
-        let mut fields = str_msg.split('|');
-        // consume prefix
-        match fields.next() {
-            Some(pre) => {
-                if pre != "_sc" {
-                    return Err(DogStatsDMsgError::ParseError {
-                        kind: DogStatsDMsgKind::ServiceCheck,
-                        reason: "Unexpected prefix found for service check",
-                        raw_msg: raw_msg.to_owned(),
-                    });
-                }
+impl Display for DogStatsDMsg<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DogStatsDMsg::Metric(m) => m.fmt(f),
+            DogStatsDMsg::Event(e) => e.fmt(f),
+            DogStatsDMsg::ServiceCheck(sc) => sc.fmt(f),
+        }
+    }
+}
+
+impl DogStatsDMsg<'_> {
+    /// Serializes this msg back into a single dogstatsd line, without a
+    /// trailing newline.
+    pub fn to_wire(&self) -> String {
+        self.to_string()
+    }
+
+    /// The wire format of this msg with tags sorted, numbers printed in their
+    /// shortest form (`1.50` and `1.5` are both `1.5`) and a sample rate of 1
+    /// dropped, so msgs the agent would treat the same compare equal.
+    pub fn canonicalize(&self) -> CanonicalMsg {
+        let canonical = match self {
+            DogStatsDMsg::Metric(m) => DogStatsDMetricStr {
+                // -0 and 0 are the same value
+                values: m.values.iter().map(|v| v + 0.0).collect(),
+                sample_rate: m.sample_rate.filter(|rate| *rate != 1.0),
+                tags: sorted_tags(&m.tags).into_iter().collect(),
+                ..*m
             }
-            None => {
-                return Err(DogStatsDMsgError::ParseError {
-                    kind: DogStatsDMsgKind::ServiceCheck,
-                    reason: "Not enough fields in msg",
-                    raw_msg: raw_msg.to_owned(),
-                })
+            .to_string(),
+            DogStatsDMsg::Event(e) => DogStatsDEventStr {
+                tags: sorted_tags(&e.tags),
+                ..*e
             }
-        }
-        let name = match fields.next() {
-            Some(name) => name,
-            None => {
-                return Err(DogStatsDMsgError::new_parse_error(
-                    DogStatsDMsgKind::ServiceCheck,
-                    "Not enough fields, couldn't find name",
-                    raw_msg.to_owned(),
-                ))
+            .to_string(),
+            DogStatsDMsg::ServiceCheck(sc) => DogStatsDServiceCheckStr {
+                tags: sorted_tags(&sc.tags),
+                ..*sc
             }
+            .to_string(),
         };
+        CanonicalMsg(canonical)
+    }
+}
 
-        let status = match fields.next() {
-            Some(status) => match ServiceCheckStatus::try_from(status) {
-                Ok(s) => s,
-                Err(_) => {
-                    return Err(DogStatsDMsgError::new_parse_error(
-                        DogStatsDMsgKind::ServiceCheck,
-                        "Invalid status found.",
-                        raw_msg.to_owned(),
-                    ))
-                }
-            },
-            None => {
-                return Err(DogStatsDMsgError::new_parse_error(
-                    DogStatsDMsgKind::ServiceCheck,
-                    "Not enough fields, couldn't find status",
-                    raw_msg.to_owned(),
-                ))
-            }
-        };
+fn sorted_tags<'a>(tags: &[&'a str]) -> SmallVec<&'a str, MAX_TAGS> {
+    let mut sorted: SmallVec<&'a str, MAX_TAGS> = tags.iter().copied().collect();
+    sorted.sort_unstable();
+    sorted
+}
 
-        let mut timestamp = None;
-        let mut hostname = None;
-        let mut message = None;
-        let mut tags = smallvec![];
-        for field in fields {
-            match field.chars().next() {
-                Some('d') => timestamp = Some(&field[2..]),
-                Some('h') => hostname = Some(&field[2..]),
-                Some('m') => message = Some(&field[2..]),
-                Some('#') => tags.extend(field[1..].split(',')),
-                _ => {
-                    return Err(DogStatsDMsgError::new_parse_error(
-                        DogStatsDMsgKind::ServiceCheck,
-                        "Unknown servicecheck field value found",
-                        raw_msg.to_owned(),
-                    ));
-                }
-            }
-        }
+/// Stable string form of a msg from `DogStatsDMsg::canonicalize`, msgs that
+/// only differ in tag order or number formatting are equal and hash the same
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CanonicalMsg(String);
 
-        Ok(DogStatsDMsg::ServiceCheck(DogStatsDServiceCheckStr {
-            raw_msg,
-            name,
-            tags,
-            status,
-            timestamp,
-            hostname,
-            message,
-        }))
+impl CanonicalMsg {
+    pub fn as_str(&self) -> &str {
+        &self.0
     }
+}
 
-    pub fn new(str_msg: &'a str) -> Result<Self, DogStatsDMsgError> {
-        if str_msg.starts_with("_e") {
-            return Self::parse_event(str_msg);
-        }
-        if str_msg.starts_with("_sc") {
-            return Self::parse_servicecheck(str_msg);
-        }
-        Self::parse_metric(str_msg)
+impl Display for CanonicalMsg {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// FNV-1a offset basis and prime, unlike `RandomState` FNV is seeded the same
+/// in every process so keys can be compared across tools and runs
+const CONTEXT_KEY_SEED: u64 = 0xcbf2_9ce4_8422_2325;
+const CONTEXT_KEY_PRIME: u64 = 0x0100_0000_01b3;
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(CONTEXT_KEY_PRIME);
     }
+    hash
 }
 
-// TODO implement debug once I figure out the syntax using lifetimes
-/*
-impl Debug for DogStatsDMsg {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Full dogstatsd msg: {}", self)
+/// Identifies the context of a metric, ie its name and set of tags. Tag order
+/// and repeated tags don't change the key. This is how `analyze_msgs` counts
+/// contexts.
+pub fn context_key(name: &str, tags: &[&str]) -> u64 {
+    let mut tags = sorted_tags(tags);
+    tags.dedup();
+    let mut hash = fnv1a(CONTEXT_KEY_SEED, name.as_bytes());
+    for tag in tags {
+        // NUL can't appear in a msg, so it can't be confused with tag contents
+        hash = fnv1a(hash, &[0]);
+        hash = fnv1a(hash, tag.as_bytes());
     }
-} */
+    hash
+}
 
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "full")]
     use lading_payload::dogstatsd::{self};
+    #[cfg(feature = "full")]
     use rand::{rngs::SmallRng, SeedableRng};
 
     use super::*;
@@ -534,8 +1756,10 @@ mod tests {
                         let Some((expected_error_kind, expected_error_message)) = $expected_error else {
                             panic!("Got an error but did not expect one {}", e);
                         };
-                        let expected_error = DogStatsDMsgError::new_parse_error(expected_error_kind, expected_error_message, $input.to_owned());
-                        assert_eq!(e, expected_error);
+                        let DogStatsDMsgError::ParseError { kind, reason, raw_msg, .. } = e else {
+                            panic!("Expected a parse error, got {}", e);
+                        };
+                        assert_eq!((kind, reason, raw_msg.as_str()), (expected_error_kind, expected_error_message, $input));
                         return;
                     },
                 };
@@ -548,7 +1772,7 @@ mod tests {
                 assert_eq!(msg.values, expected_values);
                 assert_eq!(msg.metric_type, $expected_type);
                 let expected_tags: SmallVec<&str, MAX_TAGS> = $expected_tags;
-                assert_eq!(msg.tags, expected_tags);
+                assert_eq!(msg.tags.as_slice(), expected_tags.as_slice());
                 assert_eq!(msg.sample_rate, $expected_sample_rate);
                 assert_eq!(msg.timestamp, $expected_timestamp);
                 assert_eq!(msg.container_id, $expected_container_id);
@@ -571,8 +1795,10 @@ mod tests {
                             let Some((expected_error_kind, expected_error_message)) = $expected_error else {
                                 panic!("Got an error but did not expect one {}", e);
                             };
-                            let expected_error = DogStatsDMsgError::new_parse_error(expected_error_kind, expected_error_message, $input.to_owned());
-                            assert_eq!(e, expected_error);
+                            let DogStatsDMsgError::ParseError { kind, reason, raw_msg, .. } = e else {
+                                panic!("Expected a parse error, got {}", e);
+                            };
+                            assert_eq!((kind, reason, raw_msg.as_str()), (expected_error_kind, expected_error_message, $input));
                             return;
                         }
                         None => panic!("Unexpected error: {}", e),
@@ -718,8 +1944,8 @@ mod tests {
         smallvec![1.0],
         DogStatsDMetricType::Count,
         smallvec!["tag1:value1", "tag2"],
-        Some("0.5"),
-        Some("1234567890"),
+        Some(0.5),
+        Some(1234567890),
         Some("container123"),
         NO_ERR
     );
@@ -731,12 +1957,38 @@ mod tests {
         smallvec![1.0],
         DogStatsDMetricType::Count,
         smallvec!["tag1:value1", "tag2"],
-        Some("0.5"),
-        Some("1234567890"),
+        Some(0.5),
+        Some(1234567890),
         Some("container123"),
         NO_ERR
     );
 
+    metric_test!(
+        metric_with_invalid_timestamp,
+        "metric.name:1|c|Tyesterday",
+        "metric.name",
+        smallvec![1.0],
+        DogStatsDMetricType::Count,
+        smallvec![],
+        None,
+        None,
+        None,
+        Some((DogStatsDMsgKind::Metric, "Invalid timestamp found"))
+    );
+
+    metric_test!(
+        metric_with_invalid_sample_rate,
+        "metric.name:1|c|@half",
+        "metric.name",
+        smallvec![1.0],
+        DogStatsDMetricType::Count,
+        smallvec![],
+        None,
+        None,
+        None,
+        Some((DogStatsDMsgKind::Metric, "Invalid sample rate found"))
+    );
+
     metric_test!(
         metric_with_multiple_tags,
         "metric.name:1|c|#tag1:value1,tag2,tag3:another",
@@ -859,7 +2111,7 @@ mod tests {
         "_e{2,4}:ab|cdef|d:160|h:myhost|p:high|t:error|#env:prod,onfire:true\n",
         "ab",
         "cdef",
-        Some("160"),
+        Some(160),
         Some("myhost"),
         Some("high"),
         EventAlert::Error,
@@ -897,6 +2149,158 @@ mod tests {
     }
 
     #[test]
+    fn host_from_hostname_or_tag() {
+        let msg = DogStatsDMsg::new("my.metric:1|g|#env:prod,host:web-1").unwrap();
+        assert_eq!(msg.host(), Some("web-1"));
+
+        let msg = DogStatsDMsg::new("my.metric:1|g|#env:prod").unwrap();
+        assert_eq!(msg.host(), None);
+
+        let msg = DogStatsDMsg::new("_sc|my.check|0|h:web-2|#host:other").unwrap();
+        assert_eq!(msg.host(), Some("web-2"));
+
+        let msg = DogStatsDMsg::new("_e{1,1}:a|b|#host:web-3").unwrap();
+        assert_eq!(msg.host(), Some("web-3"));
+    }
+
+    #[test]
+    #[cfg(feature = "full")]
+    fn owned_msgs_serde_round_trip() {
+        let raw = "my.metric:1:2|d|@0.5|#env:prod|c:abc\n_sc|my.check|1|h:web|m:uh oh\n_e{1,2}:a|bc|t:error";
+        for line in raw.lines() {
+            let owned: DogStatsDMsgOwned = (&DogStatsDMsg::new(line).unwrap()).into();
+            let json = serde_json::to_string(&owned).unwrap();
+            assert_eq!(serde_json::from_str::<DogStatsDMsgOwned>(&json).unwrap(), owned);
+        }
+
+        let owned: DogStatsDMsgOwned = (&DogStatsDMsg::new("_sc|my.check|2").unwrap()).into();
+        let json = serde_json::to_value(&owned).unwrap();
+        assert_eq!(json["kind"], "ServiceCheck");
+        assert_eq!(json["name"], "my.check");
+        assert_eq!(json["status"], "Critical");
+    }
+
+    #[test]
+    fn owned_msgs_outlive_the_raw_msg() {
+        let owned: Vec<DogStatsDMsgOwned> = {
+            let raw = String::from(
+                "my.metric:1:2|d|@0.5|#env:prod,host:a|c:abc\n_sc|my.check|1|h:web|m:uh oh\n_e{1,2}:a|bc|t:error",
+            );
+            raw.lines()
+                .map(|line| (&DogStatsDMsg::new(line).unwrap()).into())
+                .collect()
+        };
+
+        let handle = std::thread::spawn(move || owned);
+        let owned = handle.join().unwrap();
+
+        let DogStatsDMsgOwned::Metric(ref metric) = owned[0] else {
+            panic!("expected metric");
+        };
+        assert_eq!(metric.name, "my.metric");
+        assert_eq!(metric.values, vec![1.0, 2.0]);
+        assert_eq!(metric.metric_type, DogStatsDMetricType::Distribution);
+        assert_eq!(metric.sample_rate, Some(0.5));
+        assert_eq!(metric.container_id.as_deref(), Some("abc"));
+        assert_eq!(metric.tags, vec!["env:prod", "host:a"]);
+
+        let DogStatsDMsgOwned::ServiceCheck(ref sc) = owned[1] else {
+            panic!("expected service check");
+        };
+        assert_eq!(sc.status, ServiceCheckStatus::Warning);
+        assert_eq!(sc.hostname.as_deref(), Some("web"));
+        assert_eq!(sc.message.as_deref(), Some("uh oh"));
+
+        let DogStatsDMsgOwned::Event(ref event) = owned[2] else {
+            panic!("expected event");
+        };
+        assert_eq!(event.title, "a");
+        assert_eq!(event.text, "bc");
+        assert_eq!(event.alert_type, EventAlert::Error);
+        assert_eq!(owned[2].kind(), DogStatsDMsgKind::Event);
+        assert_eq!(owned[2].raw_msg(), "_e{1,2}:a|bc|t:error");
+    }
+
+    #[test]
+    fn to_wire_round_trips_canonical_msgs() {
+        for raw in [
+            "my.metric:1|c",
+            "my.metric:1.5:2:3|d|@0.5|#env:prod,host:a|c:abc|T1656581400",
+            "my.timer:250|ms|#env:prod",
+            "my.metric:1|g|#env:prod|c:abc|e:it-false,cn-app,pu-1234",
+            "_e{5,4}:title|text",
+            "_e{5,4}:title|text|d:1656581400|h:web|p:low|t:error|k:agg|s:src|#env:prod",
+            "_sc|my.check|0",
+            "_sc|my.check|2|d:1656581400|h:web|#env:prod|m:it broke",
+        ] {
+            let msg = DogStatsDMsg::new(raw).unwrap();
+            assert_eq!(msg.to_wire(), raw);
+        }
+    }
+
+    #[test]
+    fn to_wire_reflects_modified_fields() {
+        let msg = DogStatsDMsg::new("my.metric:1|g|#env:prod\n").unwrap();
+        let DogStatsDMsg::Metric(mut metric) = msg else {
+            panic!("expected metric");
+        };
+        metric.tags.to_mut().retain(|t| !t.starts_with("env:"));
+        metric.tags.to_mut().push("team:core");
+        metric.values[0] = 2.5;
+        assert_eq!(metric.to_string(), "my.metric:2.5|g|#team:core");
+
+        let msg = DogStatsDMsg::new("_e{5,4}:title|text|t:info").unwrap();
+        let DogStatsDMsg::Event(mut event) = msg else {
+            panic!("expected event");
+        };
+        event.title = "longer title";
+        assert_eq!(event.to_string(), "_e{12,4}:longer title|text");
+    }
+
+    #[test]
+    fn canonical_form_ignores_tag_order_and_number_formatting() {
+        let canonical = |raw| DogStatsDMsg::new(raw).unwrap().canonicalize();
+
+        let a = canonical("my.metric:1.50:-0|d|@1|#b,env:prod,a");
+        assert_eq!(a.as_str(), "my.metric:1.5:0|d|#a,b,env:prod");
+        assert_eq!(a, canonical("my.metric:1.5:0|d|#env:prod,a,b"));
+        assert_ne!(a, canonical("my.metric:1.5:0|d|#env:prod,a"));
+        assert_ne!(a, canonical("my.metric:1.5:0|d|@0.5|#env:prod,a,b"));
+
+        assert_eq!(
+            canonical("_e{5,4}:title|text|#b,a"),
+            canonical("_e{5,4}:title|text|#a,b")
+        );
+        assert_eq!(
+            canonical("_sc|my.check|0|#b,a|m:ok").to_string(),
+            "_sc|my.check|0|#a,b|m:ok"
+        );
+
+        let unique: std::collections::HashSet<CanonicalMsg> = [
+            "my.metric:1|c|#a,b",
+            "my.metric:1.0|c|#b,a",
+            "my.metric:1|c|@1|#a,b",
+            "my.metric:2|c|#a,b",
+        ]
+        .into_iter()
+        .map(canonical)
+        .collect();
+        assert_eq!(unique.len(), 2);
+    }
+
+    #[test]
+    fn context_keys_ignore_tag_order() {
+        let key = context_key("my.metric", &["env:prod", "a", "b"]);
+        assert_eq!(key, context_key("my.metric", &["b", "env:prod", "a", "a"]));
+        assert_ne!(key, context_key("my.metric", &["env:prod", "a"]));
+        assert_ne!(key, context_key("my.other", &["env:prod", "a", "b"]));
+        assert_ne!(context_key("a", &["b"]), context_key("ab", &[]));
+        // Keys are stable across processes
+        assert_eq!(context_key("", &[]), 0xcbf2_9ce4_8422_2325);
+    }
+
+    #[test]
+    #[cfg(feature = "full")]
     fn lading_test() {
         let mut rng = SmallRng::seed_from_u64(34512423); // todo use random seed
         let config = dogstatsd::Config::default();
@@ -920,8 +2324,7 @@ mod tests {
                         // todo: Implement to/from
                         // assert_eq!(e_parsed.priority, ld_event.priority);
 
-                        // todo: Represent timestamp as Option<u32>
-                        // assert_eq!(e_parsed.timestamp, ld_event.timestamp);
+                        assert_eq!(e_parsed.timestamp, ld_event.timestamp.map(u64::from));
                         if let Some(ld_alert_type) = ld_event.alert_type {
                             let ld_alert_as_alert: EventAlert = ld_alert_type.into();
                             assert_eq!(ld_alert_as_alert, e_parsed.alert_type);
@@ -938,8 +2341,10 @@ mod tests {
                             assert_eq!(sc_parsed.name, ld_sc.name);
                             assert_eq!(sc_parsed.hostname, ld_sc.hostname);
                             assert_eq!(sc_parsed.message, ld_sc.message);
-                            // todo: Represent our timestamp as option<u32>
-                            // assert_eq!(sc_parsed.timestamp, ld_sc.timestamp_second);
+                            assert_eq!(
+                                sc_parsed.timestamp,
+                                ld_sc.timestamp_second.map(u64::from)
+                            );
 
                             // todo: implement into/from
                             // assert_eq!(sc_parsed.status, sc.status);
@@ -1011,6 +2416,82 @@ mod tests {
         }
     }
 
+    #[test]
+    #[cfg(feature = "full")]
+    fn lading_members_convert() {
+        let mut rng = SmallRng::seed_from_u64(34512423);
+        let dd = dogstatsd::DogStatsD::new(dogstatsd::Config::default(), &mut rng)
+            .expect("Failed to create dogstatsd generator");
+
+        for _ in 0..10_000 {
+            let member = dd.generate(&mut rng).unwrap();
+            let owned = DogStatsDMsgOwned::try_from(&member).unwrap();
+            match (member, &owned) {
+                (dogstatsd::Member::Event(e), DogStatsDMsgOwned::Event(owned)) => {
+                    assert_eq!(owned.title, e.title);
+                    let alert = e.alert_type.map(EventAlert::from);
+                    assert_eq!(owned.alert_type, alert.unwrap_or(EventAlert::Info));
+                }
+                (dogstatsd::Member::ServiceCheck(sc), DogStatsDMsgOwned::ServiceCheck(owned)) => {
+                    assert_eq!(owned.name, sc.name);
+                }
+                (dogstatsd::Member::Metric(_), DogStatsDMsgOwned::Metric(_)) => {}
+                _ => panic!("{} converted to the wrong kind", owned.raw_msg()),
+            }
+        }
+
+        for alert in [
+            EventAlert::Error,
+            EventAlert::Warning,
+            EventAlert::Info,
+            EventAlert::Success,
+        ] {
+            assert_eq!(EventAlert::from(LadingAlert::from(alert)), alert);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "full")]
+    fn lading_members_round_trip_by_kind() {
+        let mut rng = SmallRng::seed_from_u64(90210);
+        let dd = dogstatsd::DogStatsD::new(dogstatsd::Config::default(), &mut rng)
+            .expect("Failed to create dogstatsd generator");
+
+        // a converted member is written back out and parses to the same msg
+        let round_trip = |owned: DogStatsDMsgOwned| {
+            let msg = DogStatsDMsg::new(owned.raw_msg()).unwrap();
+            let rewritten = msg.to_string();
+            let mut again = DogStatsDMsgOwned::from(&DogStatsDMsg::new(&rewritten).unwrap());
+            match (&owned, &mut again) {
+                (DogStatsDMsgOwned::Metric(a), DogStatsDMsgOwned::Metric(b)) => {
+                    b.raw_msg = a.raw_msg.clone()
+                }
+                (DogStatsDMsgOwned::Event(a), DogStatsDMsgOwned::Event(b)) => {
+                    b.raw_msg = a.raw_msg.clone()
+                }
+                (DogStatsDMsgOwned::ServiceCheck(a), DogStatsDMsgOwned::ServiceCheck(b)) => {
+                    b.raw_msg = a.raw_msg.clone()
+                }
+                _ => panic!("{} changed kind when rewritten", rewritten),
+            }
+            assert_eq!(again, owned);
+        };
+
+        for _ in 0..10_000 {
+            let member = dd.generate(&mut rng).unwrap();
+            let metric = DogStatsDMetricOwned::try_from(&member);
+            let event = DogStatsDEventOwned::try_from(&member);
+            let service_check = DogStatsDServiceCheckOwned::try_from(&member);
+            // exactly one of the kinds converts
+            match (metric, event, service_check) {
+                (Ok(m), Err(_), Err(_)) => round_trip(DogStatsDMsgOwned::Metric(m)),
+                (Err(_), Ok(e), Err(_)) => round_trip(DogStatsDMsgOwned::Event(e)),
+                (Err(_), Err(_), Ok(sc)) => round_trip(DogStatsDMsgOwned::ServiceCheck(sc)),
+                _ => panic!("{} converted to more or less than one kind", member),
+            }
+        }
+    }
+
     #[test]
     fn basic_service_checks() {
         // _sc|<NAME>|<STATUS>|d:<TIMESTAMP>|h:<HOSTNAME>|#<TAG_KEY_1>:<TAG_VALUE_1>,<TAG_2>|m:<SERVICE_CHECK_MESSAGE>
@@ -1021,12 +2502,496 @@ mod tests {
             Err(e) => panic!("Unexpected error {}", e),
         };
         assert_eq!(msg.hostname, Some("myhost"));
-        assert_eq!(msg.timestamp, Some("160"));
+        assert_eq!(msg.timestamp, Some(160));
         assert_eq!(msg.message, Some("mymessage"));
         assert_eq!(msg.name, "ab");
         assert_eq!(msg.status, ServiceCheckStatus::Critical);
     }
 
+    #[test]
+    fn from_bytes() {
+        let payload: &[u8] = b"my.metric:1|c|#env:prod\n_sc|my.check|0";
+        let mut lines = payload.split(|b| *b == b'\n');
+        let Ok(DogStatsDMsg::Metric(metric)) = DogStatsDMsg::from_bytes(lines.next().unwrap())
+        else {
+            panic!("expected metric");
+        };
+        assert_eq!(metric.name, "my.metric");
+        assert_eq!(metric.tags.as_slice(), &["env:prod"]);
+        assert_eq!(
+            DogStatsDMsg::from_bytes(lines.next().unwrap()).unwrap().kind(),
+            DogStatsDMsgKind::ServiceCheck
+        );
+
+        let invalid: &[u8] = b"my.metric:1|c|#env:\xff";
+        assert!(matches!(
+            DogStatsDMsg::from_bytes(invalid),
+            Err(DogStatsDMsgError::InvalidUtf8(e)) if e.valid_up_to() == 19
+        ));
+    }
+
+    #[test]
+    fn set_with_string_value() {
+        let raw = "users.uniques:user:1234|s|#env:prod";
+        let Ok(DogStatsDMsg::Metric(metric)) = DogStatsDMsg::new(raw) else {
+            panic!("expected metric");
+        };
+        assert_eq!(metric.metric_type, DogStatsDMetricType::Set);
+        assert_eq!(metric.set_value, Some("user:1234"));
+        assert!(metric.values.is_empty());
+        assert_eq!(metric.to_string(), raw);
+
+        let Ok(DogStatsDMsg::Metric(metric)) = DogStatsDMsg::new("users.uniques:42|s") else {
+            panic!("expected metric");
+        };
+        assert_eq!(metric.set_value, Some("42"));
+        assert_eq!(metric.values.as_slice(), &[42.0]);
+
+        // only sets may carry string values
+        assert!(DogStatsDMsg::new("my.gauge:abc|g").is_err());
+    }
+
+    #[test]
+    fn external_data_field() {
+        let ext = "it-false,cn-nginx,pu-9d5f3a4b";
+        let raw = format!("my.metric:1|c|#env:prod|c:abc|e:{ext}");
+        let Ok(DogStatsDMsg::Metric(metric)) = DogStatsDMsg::new(&raw) else {
+            panic!("expected metric");
+        };
+        assert_eq!(metric.external_data, Some(ext));
+        assert_eq!(metric.container_id, Some("abc"));
+        assert_eq!(metric.tags.as_slice(), &["env:prod"]);
+
+        let raw = format!("_e{{5,4}}:title|text|#env:prod|e:{ext}");
+        let Ok(DogStatsDMsg::Event(event)) = DogStatsDMsg::new(&raw) else {
+            panic!("expected event");
+        };
+        assert_eq!(event.external_data, Some(ext));
+        assert_eq!(event.to_string(), raw);
+
+        let raw = format!("_sc|my.check|0|e:{ext}|m:all good");
+        let Ok(DogStatsDMsg::ServiceCheck(sc)) = DogStatsDMsg::new(&raw) else {
+            panic!("expected service check");
+        };
+        assert_eq!(sc.external_data, Some(ext));
+        assert_eq!(sc.message, Some("all good"));
+        assert_eq!(sc.to_string(), raw);
+
+        // fields that only start with 'e' are unknown fields
+        for (raw, reason) in [
+            ("_e{5,4}:title|text|e", "Unknown event field value found"),
+            ("_e{5,4}:title|text|ex", "Unknown event field value found"),
+            ("_sc|my.check|0|e", "Unknown servicecheck field value found"),
+            (
+                "_sc|my.check|0|ex",
+                "Unknown servicecheck field value found",
+            ),
+        ] {
+            let Err(DogStatsDMsgError::ParseError { reason: r, .. }) = DogStatsDMsg::new(raw)
+            else {
+                panic!("expected a parse error for {}", raw);
+            };
+            assert_eq!(r, reason);
+            let (msg, warnings) = DogStatsDMsg::new_lenient(raw).unwrap();
+            let external_data = match msg {
+                DogStatsDMsg::Event(e) => e.external_data,
+                DogStatsDMsg::ServiceCheck(sc) => sc.external_data,
+                DogStatsDMsg::Metric(_) => panic!("expected event or service check"),
+            };
+            assert_eq!(external_data, None);
+            assert_eq!(warnings.len(), 1, "{}", raw);
+            assert_eq!(warnings[0].reason, reason);
+        }
+    }
+
+    #[test]
+    fn strict_parsing_rejects_malformed_separators() {
+        let reason = |msg| match DogStatsDMsg::new_strict(msg) {
+            Err(DogStatsDMsgError::ParseError { reason, field, .. }) => Some((reason, field)),
+            _ => None,
+        };
+        for msg in [
+            "my.metric:1|c|#env:prod",
+            "_e{9,3}:a|b title|c|d|#env:prod",
+            "_sc|my.check|0|#env:prod|m:ok",
+        ] {
+            assert!(DogStatsDMsg::new_strict(msg).is_ok(), "{}", msg);
+        }
+
+        // the lenient default parser accepts these
+        for msg in ["my.metric:1|c||#env:prod", "my.metric:1|c|", "my.metric:1|c|#"] {
+            assert!(DogStatsDMsg::new(msg).is_ok(), "{}", msg);
+        }
+        assert_eq!(
+            reason("my.metric:1|c||#env:prod"),
+            Some(("Empty field between two pipes found", String::new()))
+        );
+        assert_eq!(
+            reason("my.metric:1|c|#env:prod|\n"),
+            Some(("Trailing pipe found", String::new()))
+        );
+        assert_eq!(
+            reason("my.metric:1|c|#|T123"),
+            Some(("Empty tag section found", "#".to_string()))
+        );
+        assert_eq!(
+            reason("_e{5,4}:title|text|"),
+            Some(("Trailing pipe found", String::new()))
+        );
+        assert_eq!(
+            reason("_sc|my.check|0|#"),
+            Some(("Empty tag section found", "#".to_string()))
+        );
+        assert_eq!(
+            reason("_sc||0"),
+            Some(("Empty field between two pipes found", String::new()))
+        );
+
+        // anything `new` rejects is rejected too, without parsing twice
+        assert_eq!(
+            reason("my.metric:1|c|@half"),
+            Some(("Invalid sample rate found", "@half".to_string()))
+        );
+        assert_eq!(
+            reason("_e{5,4}:title|text|z"),
+            Some(("Unknown event field value found", "z".to_string()))
+        );
+        // and fields `new` lets through still are
+        assert!(DogStatsDMsg::new_strict("my.metric:1|c|x:unknown").is_ok());
+    }
+
+    #[test]
+    fn duplicate_tags_and_repeated_fields() {
+        let raw = "my.metric:1|c|@0.5|#a,b,a|T1|@1|c:x|T2|c:y|#c|e:1|e:2|card:low|card:high";
+        let duplicate_tags = LenientOptions {
+            duplicate_tags: true,
+        };
+        // duplicate tags are only looked for when asked
+        let (_, warnings) = DogStatsDMsg::new_lenient(raw).unwrap();
+        assert!(warnings.iter().all(|w| w.reason != "Duplicate tag found"));
+
+        let (msg, warnings) = DogStatsDMsg::new_lenient_with_options(raw, duplicate_tags).unwrap();
+        let DogStatsDMsg::Metric(ref metric) = msg else {
+            panic!("expected metric");
+        };
+        // the first of each field is used, as when parsing strictly
+        assert_eq!(metric.sample_rate, Some(0.5));
+        assert_eq!(metric.timestamp, Some(1));
+        assert_eq!(metric.container_id, Some("x"));
+        assert_eq!(metric.external_data, Some("1"));
+        assert_eq!(metric.cardinality, Some(Cardinality::Low));
+        assert_eq!(metric.tags.as_slice(), &["a", "b", "a"]);
+        let warnings: Vec<(&str, &str)> = warnings.iter().map(|w| (w.reason, w.field)).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                ("Repeated sample rate field ignored", "@1"),
+                ("Repeated timestamp field ignored", "T2"),
+                ("Repeated container id field ignored", "c:y"),
+                ("Repeated tags field ignored", "#c"),
+                ("Repeated external data field ignored", "e:2"),
+                ("Repeated cardinality field ignored", "card:high"),
+                ("Duplicate tag found", "a"),
+            ]
+        );
+        assert!(DogStatsDMsg::new(raw).is_ok());
+
+        let (_, warnings) =
+            DogStatsDMsg::new_lenient_with_options("_sc|check|0|#env:a,env:a", duplicate_tags)
+                .unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, DogStatsDMsgKind::ServiceCheck);
+        let (_, warnings) =
+            DogStatsDMsg::new_lenient_with_options("_e{1,1}:a|b|#x|#x", duplicate_tags).unwrap();
+        assert_eq!(warnings[0].reason, "Duplicate tag found");
+    }
+
+    #[test]
+    fn cardinality_field() {
+        let raw = "my.metric:1|c|#env:prod|c:abc|card:orchestrator";
+        let Ok(DogStatsDMsg::Metric(metric)) = DogStatsDMsg::new(raw) else {
+            panic!("expected metric");
+        };
+        assert_eq!(metric.cardinality, Some(Cardinality::Orchestrator));
+        assert_eq!(metric.container_id, Some("abc"));
+        assert_eq!(metric.to_string(), raw);
+
+        let raw = "_e{5,4}:title|text|#env:prod|card:high";
+        let msg = DogStatsDMsg::new(raw).unwrap();
+        assert_eq!(msg.cardinality(), Some(Cardinality::High));
+        assert_eq!(msg.to_string(), raw);
+
+        let raw = "_sc|my.check|0|card:low|m:all good";
+        let msg = DogStatsDMsg::new(raw).unwrap();
+        assert_eq!(msg.cardinality(), Some(Cardinality::Low));
+        assert_eq!(msg.to_string(), raw);
+
+        // an unknown cardinality is only skipped when lenient
+        assert!(DogStatsDMsg::new("my.metric:1|c|card:extreme").is_err());
+        let (msg, warnings) = DogStatsDMsg::new_lenient("my.metric:1|c|card:extreme").unwrap();
+        assert_eq!(msg.cardinality(), None);
+        assert_eq!(warnings[0].reason, "Invalid cardinality found");
+        assert_eq!(warnings[0].field, "card:extreme");
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_field() {
+        let offset_and_field = |msg: &str| match DogStatsDMsg::new(msg).unwrap_err() {
+            DogStatsDMsgError::ParseError { offset, field, .. } => (offset, field),
+            e => panic!("Expected a parse error, got {}", e),
+        };
+        assert_eq!(offset_and_field("my.metric:1:x:3|d"), (12, "x".to_owned()));
+        assert_eq!(offset_and_field("my.metric:1|zz"), (12, "zz".to_owned()));
+        assert_eq!(offset_and_field("my.metric:1|c|@x"), (14, "@x".to_owned()));
+        // missing fields point at the end of the msg
+        assert_eq!(offset_and_field("my.metric:1"), (11, "".to_owned()));
+        assert_eq!(offset_and_field("_sc|ab"), (6, "".to_owned()));
+        assert_eq!(offset_and_field("_sc|ab|7"), (7, "7".to_owned()));
+        assert_eq!(offset_and_field("_e{x,1}:a|b"), (3, "x".to_owned()));
+        assert_eq!(offset_and_field("_e{1,5}:a|b"), (10, "b".to_owned()));
+    }
+
+    #[test]
+    fn metric_tags_are_split_on_first_read() {
+        let metric = parse_metric("my.metric:1|c|#env:prod,team:a").unwrap();
+        assert!(metric.tags.split.get().is_none());
+        assert_eq!(metric.tags.len(), 2);
+        assert!(metric.tags.split.get().is_some());
+
+        // reporting duplicates needs them split up front
+        let (msg, _) = DogStatsDMsg::new_lenient("my.metric:1|c|#env:prod").unwrap();
+        let DogStatsDMsg::Metric(metric) = msg else {
+            panic!("expected metric");
+        };
+        assert!(metric.tags.split.get().is_some());
+
+        let no_tags = parse_metric("my.metric:1|c").unwrap();
+        assert!(no_tags.tags.is_empty());
+        let empty_section = parse_metric("my.metric:1|c|#").unwrap();
+        assert_eq!(empty_section.tags.as_slice(), [""]);
+    }
+
+    #[test]
+    fn kind_specific_parsers() {
+        let metric = parse_metric("my.metric:1|c|#env:prod").unwrap();
+        assert_eq!(metric.name, "my.metric");
+        assert_eq!(metric.tags.as_slice(), ["env:prod"]);
+        let event = parse_event("_e{5,4}:title|text|#env:prod").unwrap();
+        assert_eq!((event.title, event.text), ("title", "text"));
+        let service_check = parse_servicecheck("_sc|db.up|2").unwrap();
+        assert_eq!(service_check.status, ServiceCheckStatus::Critical);
+
+        // each gives the same result as the dispatching parser
+        for msg in ["my.metric:1:2|d|@0.5", "my.metric|c", "my.metric:1|x"] {
+            assert_eq!(
+                parse_metric(msg).map(|m| DogStatsDMsgOwned::from(&DogStatsDMsg::Metric(m))),
+                DogStatsDMsg::new(msg).map(|m| DogStatsDMsgOwned::from(&m))
+            );
+        }
+        // the kind is not checked, a msg of another kind is rejected as malformed
+        assert!(parse_metric("_sc|db.up|2").is_err());
+        assert!(parse_event("my.metric:1|c").is_err());
+    }
+
+    #[test]
+    fn parse_error_display() {
+        let e = DogStatsDMsg::new("my.metric:1|c|@x").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Parsing Error for Metric: 'Invalid sample rate found' at byte 14 ('@x') Full msg: 'my.metric:1|c|@x'"
+        );
+    }
+
+    #[test]
+    fn invalid_timestamps_are_errors() {
+        assert_eq!(
+            DogStatsDMsg::new("_sc|ab|2|d:soon").unwrap_err(),
+            DogStatsDMsgError::ParseError {
+                kind: DogStatsDMsgKind::ServiceCheck,
+                reason: "Invalid timestamp found",
+                raw_msg: "_sc|ab|2|d:soon".to_owned(),
+                offset: 9,
+                field: "d:soon".to_owned(),
+            }
+        );
+        assert_eq!(
+            DogStatsDMsg::new("_e{1,1}:a|b|d:-1").unwrap_err(),
+            DogStatsDMsgError::ParseError {
+                kind: DogStatsDMsgKind::Event,
+                reason: "Invalid timestamp found",
+                raw_msg: "_e{1,1}:a|b|d:-1".to_owned(),
+                offset: 12,
+                field: "d:-1".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn lenient_metric_skips_bad_fields() {
+        let raw = "my.metric:1:oops:3|d|@half|T123|x:unknown|#env:prod";
+        assert!(DogStatsDMsg::new(raw).is_err());
+
+        let (msg, warnings) = DogStatsDMsg::new_lenient(raw).unwrap();
+        let DogStatsDMsg::Metric(metric) = msg else {
+            panic!("Expected a metric");
+        };
+        assert_eq!(metric.values.as_slice(), &[1.0, 3.0]);
+        assert_eq!(metric.sample_rate, None);
+        assert_eq!(metric.timestamp, Some(123));
+        assert_eq!(metric.tags.as_slice(), &["env:prod"]);
+        let fields: Vec<(&str, &str)> = warnings.iter().map(|w| (w.reason, w.field)).collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("Invalid or no value found", "oops"),
+                ("Invalid sample rate found", "@half"),
+                ("Unknown metric field value found", "x:unknown"),
+            ]
+        );
+    }
+
+    #[test]
+    fn metric_fields_are_matched_after_the_type() {
+        // names that look like optional fields are still names
+        let DogStatsDMsg::Metric(metric) = DogStatsDMsg::new("Total.reqs:1|c|T123").unwrap() else {
+            panic!("Expected a metric");
+        };
+        assert_eq!(metric.name, "Total.reqs");
+        assert_eq!(metric.timestamp, Some(123));
+
+        // the first of a repeated field wins
+        let DogStatsDMsg::Metric(metric) =
+            DogStatsDMsg::new("a:1|c|@0.5|#env:prod|@0.1|#env:dev|c:abc|c:def").unwrap()
+        else {
+            panic!("Expected a metric");
+        };
+        assert_eq!(metric.sample_rate, Some(0.5));
+        assert_eq!(metric.tags.as_slice(), &["env:prod"]);
+        assert_eq!(metric.container_id, Some("abc"));
+    }
+
+    #[test]
+    fn lenient_events_and_service_checks() {
+        let (msg, warnings) =
+            DogStatsDMsg::new_lenient("_e{1,1}:a|b|d:soon|t:bad|z:what|#env:prod").unwrap();
+        let DogStatsDMsg::Event(event) = msg else {
+            panic!("Expected an event");
+        };
+        assert_eq!(event.timestamp, None);
+        assert_eq!(event.alert_type, EventAlert::Info);
+        assert_eq!(event.tags.as_slice(), &["env:prod"]);
+        let fields: Vec<&str> = warnings.iter().map(|w| w.field).collect();
+        assert_eq!(fields, vec!["d:soon", "t:bad", "z:what"]);
+
+        let (msg, warnings) = DogStatsDMsg::new_lenient("_sc|ab|2|d:soon|h:web-1").unwrap();
+        let DogStatsDMsg::ServiceCheck(sc) = msg else {
+            panic!("Expected a service check");
+        };
+        assert_eq!(sc.hostname, Some("web-1"));
+        assert_eq!(
+            warnings,
+            vec![DogStatsDMsgWarning {
+                kind: DogStatsDMsgKind::ServiceCheck,
+                reason: "Invalid timestamp found",
+                field: "d:soon",
+            }]
+        );
+
+        // clean msgs have no warnings, unusable msgs are still errors
+        assert!(DogStatsDMsg::new_lenient("a:1|c").unwrap().1.is_empty());
+        assert!(DogStatsDMsg::new_lenient("a:oops|c").is_err());
+        assert!(DogStatsDMsg::new_lenient("a:1|zz").is_err());
+    }
+
+    #[test]
+    fn event_lengths_in_bytes_or_chars() {
+        let DogStatsDMsg::Event(event) = DogStatsDMsg::new("_e{6,4}:héllo|wör").unwrap() else {
+            panic!("Expected an event");
+        };
+        assert_eq!((event.title, event.text), ("héllo", "wör"));
+
+        // character counts are an error unless parsing leniently
+        let reason = |msg| match DogStatsDMsg::new(msg).unwrap_err() {
+            DogStatsDMsgError::ParseError { reason, .. } => reason,
+            e => panic!("Expected a parse error, got {}", e),
+        };
+        assert_eq!(
+            reason("_e{5,3}:héllo|wör|#env:prod"),
+            "Title and text lengths are character counts, not byte lengths"
+        );
+        let (msg, warnings) = DogStatsDMsg::new_lenient("_e{5,3}:héllo|wör|#env:prod").unwrap();
+        let DogStatsDMsg::Event(event) = msg else {
+            panic!("Expected an event");
+        };
+        assert_eq!((event.title, event.text), ("héllo", "wör"));
+        assert_eq!(event.tags.as_slice(), &["env:prod"]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].field, "{5,3}");
+
+        // lengths that fit neither are never sliced mid-character
+        assert_eq!(
+            reason("_e{1,1}:éa|b"),
+            "Title length ends inside a multi-byte character"
+        );
+        assert_eq!(
+            reason("_e{1,1}:a|éb"),
+            "Text length ends inside a multi-byte character"
+        );
+    }
+
+    #[test]
+    fn malformed_events_are_errors_not_panics() {
+        let reason = |msg| match DogStatsDMsg::new(msg) {
+            Err(DogStatsDMsgError::ParseError { reason, .. }) => reason,
+            other => panic!("Expected a parse error for {}, got {:?}", msg, other),
+        };
+        assert_eq!(
+            reason("_e{5}:a|b"),
+            "No comma found between title and text lengths"
+        );
+        assert_eq!(reason("_e}{"), "No closing brace found");
+        assert_eq!(reason("_e{1,1}:a|b|h"), "Unknown event field value found");
+        assert_eq!(reason("_e{1,1}:a|b|dé"), "Unknown event field value found");
+
+        // every short msg made of the pieces of an event parses without
+        // panicking, strictly or leniently
+        const PIECES: &[&str] = &["_e", "{", "}", ",", ":", "|", "1", "a", "d", "h", "#", "é"];
+        let mut msgs = vec![String::from("_e")];
+        for _ in 0..5 {
+            msgs = msgs
+                .iter()
+                .flat_map(|msg| PIECES.iter().map(move |piece| format!("{msg}{piece}")))
+                .collect();
+            for msg in &msgs {
+                let _ = DogStatsDMsg::new_strict(msg);
+                let _ = DogStatsDMsg::new_lenient(msg);
+            }
+        }
+
+        // as does every truncation of a complete event
+        let event =
+            "_e{5,4}:title|text|d:1|h:web|p:low|t:error|k:key|s:src|e:ext|card:low|#env:prod";
+        for end in 0..=event.len() {
+            let _ = DogStatsDMsg::new_strict(&event[..end]);
+            let _ = DogStatsDMsg::new_lenient(&event[..end]);
+        }
+    }
+
+    #[test]
+    fn service_check_fields_without_values_are_unknown() {
+        let sc = "_sc|my.check|0|d:1|h:web|e:ext|card:low|#env:prod|m:all good";
+        for end in 0..=sc.len() {
+            let _ = parse_servicecheck(&sc[..end]);
+            let _ = DogStatsDMsg::new_lenient(&sc[..end]);
+        }
+        for field in ["d", "h", "m", "e", "dé"] {
+            let raw = format!("_sc|my.check|0|{field}");
+            assert!(parse_servicecheck(&raw).is_err(), "{}", raw);
+            let (_, warnings) = DogStatsDMsg::new_lenient(&raw).unwrap();
+            assert_eq!(warnings[0].reason, "Unknown servicecheck field value found");
+        }
+    }
+
     #[test]
     fn invalid_statsd_msg() {
         let mut found_expected_error = false;
@@ -1,18 +1,42 @@
-use std::io::BufRead;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::io::BufReader;
 use std::io::Read;
-use std::time::Duration;
+use std::io::{Seek, SeekFrom};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 use sketches_ddsketch::DDSketch;
 use thiserror::Error;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::{
-    dogstatsdreplayreader::{DogStatsDReplayReader, DogStatsDReplayReaderError},
+    addrfilter::AddrFilter,
+    archive::{ArchiveError, MatchingEntries},
+    dogstatsdmsg::{DogStatsDMsg, DogStatsDMsgError},
+    dogstatsdreplayreader::{
+        dogstatsd::unix::TaggerState, DogStatsDReplayReader, DogStatsDReplayReaderError,
+        ReplayRecordMeta,
+    },
+    filter::MsgFilter,
+    framedogstatsdreader::{
+        is_length_prefix_framed, FramedDogStatsDReader, FramedDogStatsDReaderError,
+    },
+    gzip::is_gzip,
+    lz4::is_lz4,
     pcapdogstatsdreader::{PcapDogStatsDReader, PcapDogStatsDReaderError},
-    replay::ReplayReaderError,
+    pcapreader::PCAP_HEADER_LEN,
+    replay::{ReplayReader, ReplayReaderError, UnixCredentials},
+    snappy::is_snappy,
+    unixdatagramdogstatsdreader::{UnixDatagramDogStatsDReader, UnixDatagramDogStatsDReaderError},
+    unixstreamdogstatsdreader::{UnixStreamDogStatsDReader, UnixStreamDogStatsDReaderError},
     utf8dogstatsdreader::Utf8DogStatsDReader,
+    xz::is_xz,
     zstd::is_zstd,
 };
 
@@ -22,17 +46,28 @@ pub enum DogStatsDReaderError {
     Replay(#[from] DogStatsDReplayReaderError),
     #[error("PCAP")]
     Pcap(#[from] PcapDogStatsDReaderError),
+    #[error("Length prefix framed")]
+    Framed(#[from] FramedDogStatsDReaderError),
+    #[error("Unix datagram socket")]
+    UnixDatagram(#[from] UnixDatagramDogStatsDReaderError),
+    #[error("Unix stream socket")]
+    UnixStream(#[from] UnixStreamDogStatsDReaderError),
     #[error("IO Error")]
     Io(#[from] std::io::Error),
+    #[error("Archive: {0}")]
+    Archive(#[from] ArchiveError),
     #[error("Unsupported Operation: {0}")]
     UnsupportedOperation(String),
+    #[error("Could not parse msg")]
+    Parse(#[from] DogStatsDMsgError),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Transport {
     Udp,
     UnixDatagram,
-    // UnixStream, not supported yet
+    /// Length prefixed frames over a unix stream connection
+    UnixStream,
 }
 
 impl std::fmt::Display for Transport {
@@ -40,11 +75,27 @@ impl std::fmt::Display for Transport {
         match self {
             Transport::Udp => write!(f, "UDP"),
             Transport::UnixDatagram => write!(f, "Unix Datagram"),
+            Transport::UnixStream => write!(f, "Unix Stream"),
         }
     }
 }
 
-#[derive(Clone)]
+/// Silences shorter than this are never considered suspicious
+const MIN_SUSPICIOUS_GAP: Duration = Duration::from_secs(2);
+/// A silence is suspicious when it is this many times longer than the average
+/// inter-packet interval seen before it
+const SUSPICIOUS_GAP_FACTOR: u32 = 10;
+/// Individual gaps are only kept for the first few occurrences
+const MAX_RECORDED_GAPS: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Gap {
+    /// Timestamp of the last packet before the silence, nanoseconds since epoch
+    pub start: Duration,
+    pub length: Duration,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Analytics {
     pub total_packets: u64,
     pub total_bytes: u64,
@@ -56,6 +107,172 @@ pub struct Analytics {
     pub latest_timestamp: Duration,
     /// Original transport type of the stream
     pub transport_type: Transport,
+    /// Timestamp of the most recently read packet, used for ordering checks
+    previous_timestamp: Duration,
+    /// Packets whose timestamp is earlier than the packet read before them
+    pub out_of_order_packets: u64,
+    /// Packets captured with less than their full length, eg with a small
+    /// snaplen. Msgs cut off in them are dropped.
+    pub truncated_packets: u64,
+    /// Longest silence between two consecutive packets
+    pub largest_gap: Duration,
+    /// Number of silences that stand out from the surrounding traffic
+    pub num_suspicious_gaps: u64,
+    /// The first `MAX_RECORDED_GAPS` suspicious silences
+    pub suspicious_gaps: Vec<Gap>,
+    /// Present when the input was compressed
+    pub compression: Option<CompressionAnalytics>,
+    /// Msgs per sending uid, only known when unix socket credentials were captured
+    pub msgs_per_uid: HashMap<u32, u64>,
+    /// Msgs per sending pid, only known when unix socket credentials were captured
+    pub msgs_per_pid: HashMap<i32, u64>,
+    /// Msgs read from packets that carried no credentials
+    pub msgs_without_credentials: u64,
+}
+
+/// Byte counts and time spent decoding a compressed input
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CompressionAnalytics {
+    pub codec: Cow<'static, str>,
+    pub compressed_bytes: u64,
+    pub decompressed_bytes: u64,
+    /// Wall time spent inside the decoder, this includes reading the
+    /// compressed bytes from the underlying source
+    pub decompression_time: Duration,
+}
+
+/// Codec of the compression of merged analytics whose inputs weren't all
+/// compressed with the same codec
+pub const MIXED_CODECS: &str = "mixed";
+
+/// Adds the compression of merged analytics or stats to 'compression'
+pub(crate) fn merge_compression(
+    compression: &mut Option<CompressionAnalytics>,
+    other: &Option<CompressionAnalytics>,
+) {
+    let Some(other) = other else {
+        return;
+    };
+    match compression {
+        Some(compression) => {
+            // The bytes still add up, no single codec accounts for them
+            if compression.codec != other.codec {
+                compression.codec = Cow::Borrowed(MIXED_CODECS);
+            }
+            compression.compressed_bytes += other.compressed_bytes;
+            compression.decompressed_bytes += other.decompressed_bytes;
+            compression.decompression_time += other.decompression_time;
+        }
+        None => *compression = Some(other.clone()),
+    }
+}
+
+impl CompressionAnalytics {
+    /// Decompressed bytes per compressed byte, 0 if nothing was read
+    pub fn compression_ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            return 0.0;
+        }
+        self.decompressed_bytes as f64 / self.compressed_bytes as f64
+    }
+}
+
+/// Shared between the reader wrappers on both sides of a decoder so that
+/// compression analytics can be reported while the input is being read
+pub struct DecompressionCounters {
+    codec: &'static str,
+    compressed_bytes: AtomicU64,
+    decompressed_bytes: AtomicU64,
+    decompression_nanos: AtomicU64,
+}
+
+impl DecompressionCounters {
+    fn new(codec: &'static str) -> Self {
+        Self {
+            codec,
+            compressed_bytes: AtomicU64::new(0),
+            decompressed_bytes: AtomicU64::new(0),
+            decompression_nanos: AtomicU64::new(0),
+        }
+    }
+
+    pub fn snapshot(&self) -> CompressionAnalytics {
+        CompressionAnalytics {
+            codec: Cow::Borrowed(self.codec),
+            compressed_bytes: self.compressed_bytes.load(Ordering::Relaxed),
+            decompressed_bytes: self.decompressed_bytes.load(Ordering::Relaxed),
+            decompression_time: Duration::from_nanos(
+                self.decompression_nanos.load(Ordering::Relaxed),
+            ),
+        }
+    }
+}
+
+/// Counts the compressed bytes handed to a decoder
+struct CompressedSide<R> {
+    inner: R,
+    counters: Arc<DecompressionCounters>,
+}
+
+impl<R: Read> Read for CompressedSide<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.counters
+            .compressed_bytes
+            .fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// Counts and times the decompressed bytes coming out of a decoder
+struct DecompressedSide<R> {
+    inner: R,
+    counters: Arc<DecompressionCounters>,
+}
+
+impl<R: Read> Read for DecompressedSide<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let start = Instant::now();
+        let n = self.inner.read(buf)?;
+        self.counters
+            .decompression_nanos
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        self.counters
+            .decompressed_bytes
+            .fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// How often a followed file is checked for new data at its end
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Reads a file that is still being written, like `tail -f`. At the end of
+/// the file it waits for more data instead of ending, until none was
+/// appended for 'idle_timeout', if there is one.
+struct Following<R> {
+    inner: R,
+    idle_timeout: Option<Duration>,
+    poll_interval: Duration,
+}
+
+impl<R: Read> Read for Following<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let idle_since = Instant::now();
+        loop {
+            let n = self.inner.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            if self
+                .idle_timeout
+                .is_some_and(|idle_timeout| idle_since.elapsed() >= idle_timeout)
+            {
+                return Ok(0);
+            }
+            std::thread::sleep(self.poll_interval);
+        }
+    }
 }
 
 impl Analytics {
@@ -68,11 +285,135 @@ impl Analytics {
             earliest_timestamp: Duration::ZERO,
             latest_timestamp: Duration::ZERO,
             transport_type,
+            previous_timestamp: Duration::ZERO,
+            out_of_order_packets: 0,
+            truncated_packets: 0,
+            largest_gap: Duration::ZERO,
+            num_suspicious_gaps: 0,
+            suspicious_gaps: Vec::new(),
+            compression: None,
+            msgs_per_uid: HashMap::new(),
+            msgs_per_pid: HashMap::new(),
+            msgs_without_credentials: 0,
         }
     }
 
+    /// Accounts for a single packet read at 'timestamp' carrying 'bytes' bytes
+    pub fn record_packet(&mut self, timestamp: Duration, bytes: u64) {
+        if self.total_packets == 0 {
+            self.earliest_timestamp = timestamp;
+            self.latest_timestamp = timestamp;
+        } else {
+            if timestamp < self.previous_timestamp {
+                self.out_of_order_packets += 1;
+            }
+            self.earliest_timestamp = self.earliest_timestamp.min(timestamp);
+            if timestamp > self.latest_timestamp {
+                let gap = timestamp - self.latest_timestamp;
+                // At least one interval is needed as a baseline for what "steady" means
+                let is_suspicious = self.total_packets >= 2 && gap >= MIN_SUSPICIOUS_GAP && {
+                    let average_interval =
+                        self.duration().as_nanos() / u128::from(self.total_packets - 1);
+                    // Packets that were all captured at once have no steady
+                    // rate to compare against
+                    average_interval > 0
+                        && gap.as_nanos() >= average_interval * u128::from(SUSPICIOUS_GAP_FACTOR)
+                };
+                if is_suspicious {
+                    self.num_suspicious_gaps += 1;
+                    if self.suspicious_gaps.len() < MAX_RECORDED_GAPS {
+                        self.suspicious_gaps.push(Gap {
+                            start: self.latest_timestamp,
+                            length: gap,
+                        });
+                    }
+                }
+                self.largest_gap = self.largest_gap.max(gap);
+                self.latest_timestamp = timestamp;
+            }
+        }
+        self.previous_timestamp = timestamp;
+        self.total_packets += 1;
+        self.total_bytes += bytes;
+    }
+
+    /// Attributes a single msg to the process that sent it
+    pub fn record_credentials(&mut self, credentials: Option<UnixCredentials>) {
+        match credentials {
+            Some(credentials) => {
+                *self.msgs_per_uid.entry(credentials.uid).or_insert(0) += 1;
+                *self.msgs_per_pid.entry(credentials.pid).or_insert(0) += 1;
+            }
+            None => self.msgs_without_credentials += 1,
+        }
+    }
+
+    /// Adds in the analytics of a stream that was read after this one, so
+    /// several inputs read one after the other are reported as one. Gaps
+    /// between the streams are not counted, and the compression of inputs
+    /// that used different codecs is reported as MIXED_CODECS.
+    pub fn merge(&mut self, other: &Analytics) {
+        if other.total_packets > 0 {
+            if self.total_packets == 0 {
+                self.earliest_timestamp = other.earliest_timestamp;
+                self.latest_timestamp = other.latest_timestamp;
+            } else {
+                self.earliest_timestamp = self.earliest_timestamp.min(other.earliest_timestamp);
+                self.latest_timestamp = self.latest_timestamp.max(other.latest_timestamp);
+            }
+            self.previous_timestamp = other.previous_timestamp;
+        }
+        self.total_packets += other.total_packets;
+        self.total_bytes += other.total_bytes;
+        self.total_messages += other.total_messages;
+        if let Err(e) = self.message_length.merge(&other.message_length) {
+            warn!("Could not merge msg lengths: {e:?}");
+        }
+        self.out_of_order_packets += other.out_of_order_packets;
+        self.truncated_packets += other.truncated_packets;
+        self.largest_gap = self.largest_gap.max(other.largest_gap);
+        self.num_suspicious_gaps += other.num_suspicious_gaps;
+        let room = MAX_RECORDED_GAPS.saturating_sub(self.suspicious_gaps.len());
+        self.suspicious_gaps
+            .extend(other.suspicious_gaps.iter().take(room).cloned());
+        merge_compression(&mut self.compression, &other.compression);
+        for (uid, msgs) in &other.msgs_per_uid {
+            *self.msgs_per_uid.entry(*uid).or_insert(0) += msgs;
+        }
+        for (pid, msgs) in &other.msgs_per_pid {
+            *self.msgs_per_pid.entry(*pid).or_insert(0) += msgs;
+        }
+        self.msgs_without_credentials += other.msgs_without_credentials;
+    }
+
+    /// Human readable warnings about the capture itself, empty when the
+    /// capture looks complete
+    pub fn capture_health_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.out_of_order_packets > 0 {
+            warnings.push(format!(
+                "{} packet(s) had a timestamp earlier than the packet before them",
+                self.out_of_order_packets
+            ));
+        }
+        if self.truncated_packets > 0 {
+            warnings.push(format!(
+                "{} packet(s) were captured cut short, eg by a small snaplen, msgs cut off in them were dropped",
+                self.truncated_packets
+            ));
+        }
+        if self.num_suspicious_gaps > 0 {
+            warnings.push(format!(
+                "{} suspicious gap(s) in otherwise steady traffic, largest was {:?}",
+                self.num_suspicious_gaps, self.largest_gap
+            ));
+        }
+        warnings
+    }
+
     pub fn duration(&self) -> Duration {
-        self.latest_timestamp - self.earliest_timestamp
+        self.latest_timestamp
+            .saturating_sub(self.earliest_timestamp)
     }
     pub fn average_bytes_per_second(&self) -> f64 {
         if self.duration().as_secs() == 0 {
@@ -92,15 +433,11 @@ impl Analytics {
             byte_unit::Byte::from_unit(20.0, byte_unit::ByteUnit::MB).unwrap();
         let throttle = lading_throttle::Config::Stable;
         match self.transport_type {
-            Transport::Udp => lading::generator::Inner::Udp(lading::generator::udp::Config {
-                seed,
-                addr: "fill_me_in".to_string(),
+            Transport::Udp => lading::generator::Inner::Udp(lading_udp_config(
+                "fill_me_in".to_string(),
                 variant,
                 bytes_per_second,
-                maximum_prebuild_cache_size_bytes,
-                block_sizes: None,
-                throttle,
-            }),
+            )),
             Transport::UnixDatagram => {
                 lading::generator::Inner::UnixDatagram(lading::generator::unix_datagram::Config {
                     seed,
@@ -114,6 +451,124 @@ impl Analytics {
                     parallel_connections: 1,
                 })
             }
+            Transport::UnixStream => {
+                lading::generator::Inner::UnixStream(lading::generator::unix_stream::Config {
+                    seed,
+                    path: "fill_me_in".into(),
+                    variant,
+                    bytes_per_second,
+                    maximum_prebuild_cache_size_bytes,
+                    block_sizes: None,
+                    throttle,
+                    block_cache_method: lading_payload::block::default_cache_method(),
+                    parallel_connections: 1,
+                })
+            }
+        }
+    }
+}
+
+/// A lading udp generator sending 'variant' to 'addr' at 'bytes_per_second'
+pub fn lading_udp_config(
+    addr: String,
+    variant: lading_payload::Config,
+    bytes_per_second: byte_unit::Byte,
+) -> lading::generator::udp::Config {
+    // todo better default seed
+    let seed: [u8; 32] = [12; 32];
+    lading::generator::udp::Config {
+        seed,
+        addr,
+        variant,
+        bytes_per_second,
+        maximum_prebuild_cache_size_bytes: byte_unit::Byte::from_unit(
+            20.0,
+            byte_unit::ByteUnit::MB,
+        )
+        .unwrap(),
+        block_sizes: None,
+        throttle: lading_throttle::Config::Stable,
+    }
+}
+
+/// Largest msg read by default, or packet for replay and pcap inputs. Far
+/// above what clients send (8KB for UDS by default), it only guards against
+/// corrupt input being read into memory.
+pub const DEFAULT_MAX_MSG_SIZE: usize = 1024 * 1024;
+
+/// A packet as it was received, before it is split into msgs. What is known
+/// about where it came from depends on the input.
+#[derive(Clone, Debug)]
+pub struct DogStatsDPacket {
+    /// Unix stream payloads are a single frame without its length prefix,
+    /// except in replay files, which keep the prefixes
+    pub payload: Vec<u8>,
+    /// When the packet was captured or received
+    pub timestamp: Option<Duration>,
+    pub transport: Transport,
+    /// The process that sent the packet, for unix sockets on linux and
+    /// replay files captured with credentials
+    pub credentials: Option<UnixCredentials>,
+    /// The address that sent the packet, for pcaps of UDP traffic
+    pub source_addr: Option<SocketAddr>,
+}
+
+/// Puts 'msg' in front of whatever 's' already holds. An empty 's', the
+/// common case, takes over the allocation of 'msg' instead of copying it.
+pub(crate) fn prepend_msg(s: &mut String, msg: String) {
+    if s.is_empty() {
+        *s = msg;
+    } else {
+        s.insert_str(0, &msg);
+    }
+}
+
+/// Order the files of a directory are read in
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DirOrder {
+    /// Lexical order of the file names, eg for numbered or dated captures
+    #[default]
+    Name,
+    /// Least recently modified first, eg for rotated captures
+    Modified,
+}
+
+/// The files directly in 'dir' in 'order', leaving out subdirectories and
+/// hidden files
+pub fn dir_files(dir: impl AsRef<Path>, order: DirOrder) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !path.is_file() || entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let modified = match order {
+            DirOrder::Name => None,
+            DirOrder::Modified => Some(std::fs::metadata(&path)?.modified()?),
+        };
+        files.push((modified, path));
+    }
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Options that apply to every input type
+#[derive(Debug, Clone, PartialEq)]
+pub struct DogStatsDReaderOptions {
+    /// Msgs (or packets, for replay and pcap inputs) larger than this are an
+    /// error rather than being truncated
+    pub max_msg_size: usize,
+    /// Decode replay files of an unsupported (newer) version as if they were
+    /// this version, on a best effort basis
+    pub force_replay_version: Option<u8>,
+}
+
+impl Default for DogStatsDReaderOptions {
+    fn default() -> Self {
+        Self {
+            max_msg_size: DEFAULT_MAX_MSG_SIZE,
+            force_replay_version: None,
         }
     }
 }
@@ -122,17 +577,210 @@ pub enum DogStatsDReader<'a> {
     Replay(DogStatsDReplayReader<'a>),
     Utf8(Utf8DogStatsDReader<'a>),
     Pcap(PcapDogStatsDReader<'a>),
-    Multi(Vec<DogStatsDReader<'a>>),
+    Framed(FramedDogStatsDReader<'a>),
+    /// Live msgs received on a unix datagram socket
+    UnixDatagram(UnixDatagramDogStatsDReader),
+    /// Live msgs received over unix stream connections
+    UnixStream(UnixStreamDogStatsDReader),
+    /// Readers read one after the other, along with the analytics of the
+    /// ones that were already read to the end and the archive entries that
+    /// are opened once the readers run out
+    Multi(
+        Vec<DogStatsDReader<'a>>,
+        FinishedReaders,
+        Option<Box<PendingEntries<'a>>>,
+    ),
+    /// Any of the above, read through a decoder
+    Decompressed(Box<DogStatsDReader<'a>>, Arc<DecompressionCounters>),
+    /// Any of the above, only returning the msgs that match the filter
+    Filtered(Box<DogStatsDReader<'a>>, MsgFilter),
+    /// Any of the above, with leading msgs skipped and the msgs after that
+    /// limited
+    Sliced(Box<DogStatsDReader<'a>>, MsgSlice),
+}
+
+/// Analytics of the readers of a `DogStatsDReader::Multi` that were read to
+/// the end. Compression is kept apart, text inputs have no other analytics.
+#[derive(Default)]
+pub struct FinishedReaders {
+    analytics: Option<Analytics>,
+    compression: Option<CompressionAnalytics>,
+}
+
+/// Archive entries of a `DogStatsDReader::Multi` that weren't opened yet.
+/// Entries can only be read in archive order, so each is opened once the
+/// one before it was read to the end, with the same time range and address
+/// filter as the readers before it.
+pub struct PendingEntries<'a> {
+    entries: MatchingEntries<'a>,
+    options: DogStatsDReaderOptions,
+    time_range: Option<(Duration, Duration)>,
+    addr_filter: Option<AddrFilter>,
+}
+
+impl<'a> PendingEntries<'a> {
+    fn open_next(&mut self) -> Result<Option<DogStatsDReader<'a>>, DogStatsDReaderError> {
+        let Some((path, entry)) = self.entries.next_entry()? else {
+            return Ok(None);
+        };
+        info!("Reading archive entry {}", path.display());
+        let mut reader = DogStatsDReader::new_with_options(entry, &self.options)?;
+        if let Some((start, end)) = self.time_range {
+            reader = reader.with_time_range(start, end)?;
+        }
+        if let Some(filter) = &self.addr_filter {
+            reader = reader.with_addr_filter(filter)?;
+        }
+        Ok(Some(reader))
+    }
+}
+
+/// Which msgs of a `DogStatsDReader::Sliced` are still to be returned
+#[derive(Debug, Clone, Copy)]
+pub struct MsgSlice {
+    /// Msgs still to be skipped before any is returned
+    pub skip: u64,
+    /// Msgs that may still be returned after that, no limit when None
+    pub limit: Option<u64>,
+}
+
+/// Where a reader got to in its input, to resume reading the same input from
+/// there later on, eg to carry on with a long analysis that was interrupted
+/// or to only read what was added to a growing capture since
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReaderCheckpoint {
+    /// Format of the input, resuming an input of another format is an error
+    pub input_type: InputType,
+    /// Msgs read so far, skipped ones included
+    pub msgs_read: u64,
+    /// Packets (replay records for replay inputs) whose msgs were read, not
+    /// counting the one that was only partly read
+    pub packet_index: u64,
+    /// Where the packet at 'packet_index' starts in the input. Only known for
+    /// uncompressed replay and classic pcap inputs, as the state of a decoder
+    /// can't be saved. Inputs without it are read past 'msgs_read' msgs.
+    pub byte_offset: Option<u64>,
+    /// Msgs of the packet at 'packet_index' that were read already
+    pub msgs_into_packet: u64,
 }
 
-enum InputType {
+/// Format of an input, as detected from its first bytes once any
+/// compression is removed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputType {
     Replay,
     Pcap,
+    Framed,
     Utf8,
 }
 
+impl std::fmt::Display for InputType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputType::Replay => write!(f, "dogstatsd-replay"),
+            InputType::Pcap => write!(f, "pcap"),
+            InputType::Framed => write!(f, "length prefix framed"),
+            InputType::Utf8 => write!(f, "utf-8 text"),
+        }
+    }
+}
+
+/// What an input was detected as, see `DogStatsDReader::input_type`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DetectedInput {
+    pub format: InputType,
+    /// Codecs the input was compressed with, outermost first, empty when
+    /// it wasn't compressed
+    pub compression: Vec<Cow<'static, str>>,
+}
+
+impl std::fmt::Display for DetectedInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for codec in &self.compression {
+            write!(f, "{} compressed ", codec)?;
+        }
+        write!(f, "{}", self.format)
+    }
+}
+
+/// Number of bytes needed to detect the type of an input
+pub(crate) const HEADER_LEN: usize = 8;
+
+/// Reads until HEADER_LEN bytes are available or the stream ends, whichever
+/// comes first. A single read is not enough as pipes can return fewer bytes.
+/// Returns the bytes read and a reader that starts from the beginning of the
+/// stream, ie the header bytes are replayed before the rest of the stream.
+fn peek_header<'a>(
+    mut reader: Box<dyn Read + 'a>,
+) -> Result<(Vec<u8>, BufReader<Box<dyn Read + 'a>>), std::io::Error> {
+    let mut header = vec![0; HEADER_LEN];
+    let mut filled = 0;
+    while filled < HEADER_LEN {
+        match reader.read(&mut header[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    header.truncate(filled);
+    let replayed: Box<dyn Read + 'a> = Box::new(std::io::Cursor::new(header.clone()).chain(reader));
+    Ok((header, BufReader::new(replayed)))
+}
+
+/// Upper bound on nested compression layers, guards against pathological input
+const MAX_COMPRESSION_LAYERS: usize = 4;
+
+/// Compression formats that are removed before detecting the input type
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Codec {
+    Zstd,
+    Gzip,
+    Lz4,
+    Xz,
+    Snappy,
+}
+
+impl Codec {
+    pub(crate) fn detect(header: &[u8]) -> Option<Self> {
+        if header.len() >= 4 && is_zstd(&header[0..4]) {
+            Some(Codec::Zstd)
+        } else if is_gzip(header) {
+            Some(Codec::Gzip)
+        } else if is_lz4(header) {
+            Some(Codec::Lz4)
+        } else if is_xz(header) {
+            Some(Codec::Xz)
+        } else if is_snappy(header) {
+            Some(Codec::Snappy)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Codec::Zstd => "zstd",
+            Codec::Gzip => "gzip",
+            Codec::Lz4 => "lz4",
+            Codec::Xz => "xz",
+            Codec::Snappy => "snappy",
+        }
+    }
+
+    fn decoder<'a>(self, compressed: impl Read + 'a) -> Result<Box<dyn Read + 'a>, std::io::Error> {
+        Ok(match self {
+            Codec::Zstd => Box::new(zstd::Decoder::new(compressed)?),
+            Codec::Gzip => Box::new(flate2::read::GzDecoder::new(compressed)),
+            Codec::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(compressed)),
+            Codec::Xz => Box::new(xz2::read::XzDecoder::new(compressed)),
+            Codec::Snappy => Box::new(snap::read::FrameDecoder::new(compressed)),
+        })
+    }
+}
+
 /// Does not consume from header
-fn input_type_of(header: Bytes) -> InputType {
+pub(crate) fn input_type_of(header: Bytes, force_replay_version: Option<u8>) -> InputType {
     // I need to decide and unify if file type detection
     // should be done by
     // - looking at a fixed-length byte slice from beginning of stream (current approach)
@@ -146,9 +794,16 @@ fn input_type_of(header: Bytes) -> InputType {
         Ok(()) => return InputType::Replay,
         Err(e) => match e {
             ReplayReaderError::NotAReplayFile => debug!("Not a replay file."),
-            ReplayReaderError::UnsupportedReplayVersion(v) => {
-                debug!("Replay header detected, but unsupported version found: {v:x}.")
-            }
+            ReplayReaderError::UnsupportedReplayVersion(v) => match force_replay_version {
+                Some(forced) => {
+                    debug!("Replay header with unsupported version {v} found, forcing version {forced}.");
+                    return InputType::Replay;
+                }
+                None => warn!(
+                    "Replay header detected, but unsupported version {v} found. Force one of the supported versions {:?} to decode it anyway.",
+                    ReplayReader::supported_versions()
+                ),
+            },
             _ => {
                 error!("Unexpected error while checking for replay file: {e:?}");
             }
@@ -162,6 +817,10 @@ fn input_type_of(header: Bytes) -> InputType {
         }
     }
 
+    if is_length_prefix_framed(&header) {
+        return InputType::Framed;
+    }
+
     // fallback to text, its probably utf8
 
     InputType::Utf8
@@ -170,111 +829,923 @@ fn input_type_of(header: Bytes) -> InputType {
 impl<'a> DogStatsDReader<'a> {
     /// 'buf' should point either to the beginning of a utf-8 encoded stream of
     /// DogStatsD messages, or to the beginning of a DogStatsD Replay/Capture file
-    /// Either sequence can be optionally zstd encoded, it will be automatically
-    /// decoded if needed.
+    /// or pcap. Any of these can be optionally zstd or gzip encoded, possibly
+    /// several times over, it will be automatically decoded if needed.
     pub fn new(byte_reader: impl Read + 'a) -> Result<Self, DogStatsDReaderError> {
-        let mut buf_reader: BufReader<Box<dyn Read + 'a>> = BufReader::new(Box::new(byte_reader));
-        // fill_buf allows for a peek-like operation
-        // 'consume' is intentionally never consumed here so that the reader
-        // passed to each reader implementation is always at the beginning of
-        // the stream
-        let mut start_buf = buf_reader.fill_buf()?;
-        if start_buf.len() < 8 {
-            error!("Input stream is too short to be a valid DogStatsD stream");
+        Self::new_with_options(byte_reader, &DogStatsDReaderOptions::default())
+    }
+
+    /// Like `new`, but reads with the given 'options' instead of the defaults
+    pub fn new_with_options(
+        byte_reader: impl Read + 'a,
+        options: &DogStatsDReaderOptions,
+    ) -> Result<Self, DogStatsDReaderError> {
+        // Input may arrive in small pieces (eg, a pipe on stdin), so keep
+        // reading until the full header is available or the stream ends.
+        let (mut header, mut buf_reader) = peek_header(Box::new(byte_reader))?;
+        if header.is_empty() {
+            error!("Input stream is empty");
             return Err(DogStatsDReaderError::Io(std::io::Error::new(
                 std::io::ErrorKind::UnexpectedEof,
-                "Input stream is too short to be a valid DogStatsD stream",
+                "Input stream is empty",
             )));
         }
-        let mut header_bytes = &start_buf[0..8];
-        if is_zstd(&header_bytes[0..4]) {
-            info!("Detected zstd compression.");
-            // consume original buffer to completion
-            let zstd_decoder = zstd::Decoder::new(buf_reader).unwrap();
-            buf_reader = BufReader::new(Box::new(zstd_decoder));
-            start_buf = buf_reader.fill_buf()?;
-            if start_buf.len() < 8 {
-                error!("Decompressed input stream is too short to be a valid DogStatsD stream");
+        // Compression can be nested, eg gzip(zstd(replay)), so keep unwrapping
+        // and re-detecting until something other than a compressed stream is found
+        let mut layers: Vec<Arc<DecompressionCounters>> = Vec::new();
+        while let Some(codec) = Codec::detect(&header) {
+            if layers.len() == MAX_COMPRESSION_LAYERS {
+                return Err(DogStatsDReaderError::UnsupportedOperation(format!(
+                    "More than {MAX_COMPRESSION_LAYERS} nested compression layers"
+                )));
+            }
+            debug!("Detected {} compression.", codec.name());
+            let counters = Arc::new(DecompressionCounters::new(codec.name()));
+            let decoder = codec.decoder(CompressedSide {
+                inner: buf_reader,
+                counters: counters.clone(),
+            })?;
+            (header, buf_reader) = peek_header(Box::new(DecompressedSide {
+                inner: decoder,
+                counters: counters.clone(),
+            }))?;
+            layers.push(counters);
+            if header.is_empty() {
+                error!("Decompressed input stream is empty");
                 return Err(DogStatsDReaderError::Io(std::io::Error::new(
                     std::io::ErrorKind::UnexpectedEof,
-                    "Decompressed input stream is too short to be a valid DogStatsD stream",
+                    "Decompressed input stream is empty",
                 )));
             }
-            header_bytes = &start_buf[0..8];
         }
 
-        match input_type_of(Bytes::copy_from_slice(header_bytes)) {
-            InputType::Pcap => {
-                info!("Treating input as pcap");
-                match PcapDogStatsDReader::new(buf_reader) {
-                    Ok(reader) => Ok(Self::Pcap(reader)),
-                    Err(e) => {
-                        panic!("Pcap Reader couldn't be created: {e:?}");
+        // Every binary format has a longer header, so a shorter stream can
+        // only be a msg or two, eg 'a:1|c'
+        let format = if header.len() < HEADER_LEN {
+            InputType::Utf8
+        } else {
+            input_type_of(Bytes::from(header), options.force_replay_version)
+        };
+        info!(
+            "Treating input as {}",
+            DetectedInput {
+                format,
+                compression: layers.iter().map(|c| Cow::Borrowed(c.codec)).collect(),
+            }
+        );
+        let reader = Self::from_input_type(format, buf_reader, options)?;
+
+        // Outermost compression layer ends up as the outermost reader
+        Ok(layers.into_iter().rev().fold(reader, |reader, counters| {
+            Self::Decompressed(Box::new(reader), counters)
+        }))
+    }
+
+    /// Reads 'buf_reader', which starts with an uncompressed header that was
+    /// detected as 'format'
+    fn from_input_type(
+        format: InputType,
+        buf_reader: impl std::io::BufRead + 'a,
+        options: &DogStatsDReaderOptions,
+    ) -> Result<Self, DogStatsDReaderError> {
+        Ok(match format {
+            InputType::Pcap => Self::Pcap(
+                PcapDogStatsDReader::new(buf_reader)?.with_max_msg_size(options.max_msg_size),
+            ),
+            InputType::Replay => {
+                let reader = match options.force_replay_version {
+                    Some(version) => {
+                        DogStatsDReplayReader::new_forcing_version(buf_reader, version)
+                    }
+                    None => DogStatsDReplayReader::new(buf_reader),
+                };
+                Self::Replay(reader?.with_max_msg_size(options.max_msg_size))
+            }
+            InputType::Framed => Self::Framed(
+                FramedDogStatsDReader::new(buf_reader).with_max_msg_size(options.max_msg_size),
+            ),
+            InputType::Utf8 => Self::Utf8(
+                Utf8DogStatsDReader::new(buf_reader).with_max_msg_size(options.max_msg_size),
+            ),
+        })
+    }
+
+    /// Memory maps the file at 'path' and reads msgs straight out of the
+    /// mapping, skipping the copy into a read buffer. Meant for large
+    /// uncompressed captures that are analyzed repeatedly, compressed ones are
+    /// decoded as usual with the decoder reading from the mapping.
+    ///
+    /// The file must not be truncated while it is read, reading a page past
+    /// the new end of the file faults the process.
+    pub fn from_path_mmap(path: impl AsRef<Path>) -> Result<Self, DogStatsDReaderError> {
+        Self::from_path_mmap_with_options(path, &DogStatsDReaderOptions::default())
+    }
+
+    pub fn from_path_mmap_with_options(
+        path: impl AsRef<Path>,
+        options: &DogStatsDReaderOptions,
+    ) -> Result<Self, DogStatsDReaderError> {
+        let file = std::fs::File::open(path)?;
+        // Safety: the mapping is only ever read, see the doc comment of
+        // from_path_mmap about truncating the file while it is mapped
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        #[cfg(unix)]
+        {
+            if let Err(e) = map.advise(memmap2::Advice::Sequential) {
+                debug!("Could not advise sequential access: {e}");
+            }
+        }
+        if map.len() < HEADER_LEN || Codec::detect(&map[..HEADER_LEN]).is_some() {
+            return Self::new_with_options(std::io::Cursor::new(map), options);
+        }
+        let format = input_type_of(
+            Bytes::copy_from_slice(&map[..HEADER_LEN]),
+            options.force_replay_version,
+        );
+        info!(
+            "Treating input as memory mapped {}",
+            DetectedInput {
+                format,
+                compression: Vec::new(),
+            }
+        );
+        Self::from_input_type(format, std::io::Cursor::new(map), options)
+    }
+
+    /// Reads the file at 'path' from where 'checkpoint' of an earlier reader
+    /// of it got to, eg to carry on with an analysis that was interrupted, or
+    /// to only read the packets added to a growing capture since. The analytics
+    /// only cover the msgs read after the checkpoint.
+    ///
+    /// Uncompressed replay and classic pcap files are read from the byte
+    /// offset of the checkpoint. Other inputs are read from their start, with
+    /// the msgs before the checkpoint skipped, and those count towards the
+    /// analytics. Apply the same time range and filters as the reader that
+    /// was checkpointed, but not its `skip_msgs`, the checkpoint is past those.
+    /// Fragmented packets and TCP streams that weren't complete at the
+    /// checkpoint are lost, their msgs are not read.
+    pub fn resume_from_path(
+        path: impl AsRef<Path>,
+        checkpoint: &ReaderCheckpoint,
+    ) -> Result<Self, DogStatsDReaderError> {
+        Self::resume_from_path_with_options(path, checkpoint, &DogStatsDReaderOptions::default())
+    }
+
+    pub fn resume_from_path_with_options(
+        path: impl AsRef<Path>,
+        checkpoint: &ReaderCheckpoint,
+        options: &DogStatsDReaderOptions,
+    ) -> Result<Self, DogStatsDReaderError> {
+        let mismatch = |found: InputType| {
+            DogStatsDReaderError::UnsupportedOperation(format!(
+                "Checkpoint of a {} input can't resume a {} input",
+                checkpoint.input_type, found
+            ))
+        };
+        let Some(byte_offset) = checkpoint.byte_offset else {
+            let reader = Self::new_with_options(std::fs::File::open(path)?, options)?;
+            let format = reader.input_type().map(|detected| detected.format);
+            if format != Some(checkpoint.input_type) {
+                return Err(mismatch(format.unwrap_or(InputType::Utf8)));
+            }
+            return Ok(reader.skip_msgs(checkpoint.msgs_read));
+        };
+
+        let header_len = match checkpoint.input_type {
+            InputType::Replay => HEADER_LEN,
+            InputType::Pcap => PCAP_HEADER_LEN,
+            format => {
+                return Err(DogStatsDReaderError::UnsupportedOperation(format!(
+                    "{format} input has no byte offsets to resume from"
+                )))
+            }
+        };
+        let mut file = std::fs::File::open(path)?;
+        let mut header = vec![0; header_len];
+        file.read_exact(&mut header)?;
+        if Codec::detect(&header).is_some() {
+            return Err(DogStatsDReaderError::UnsupportedOperation(String::from(
+                "Compressed input can't be resumed from a byte offset",
+            )));
+        }
+        let format = input_type_of(
+            Bytes::copy_from_slice(&header[..HEADER_LEN]),
+            options.force_replay_version,
+        );
+        if format != checkpoint.input_type {
+            return Err(mismatch(format));
+        }
+        file.seek(SeekFrom::Start(byte_offset))?;
+        let buf_reader = BufReader::new(std::io::Cursor::new(header).chain(file));
+        let mut reader = Self::from_input_type(format, buf_reader, options)?;
+        match reader {
+            Self::Replay(ref mut r) => r.resume_at(checkpoint),
+            Self::Pcap(ref mut r) => r.resume_at(checkpoint),
+            _ => unreachable!("Only replay and pcap inputs have byte offsets"),
+        }
+        Ok(if checkpoint.msgs_into_packet > 0 {
+            reader.skip_msgs(checkpoint.msgs_into_packet)
+        } else {
+            reader
+        })
+    }
+
+    /// Where reading got to, to resume reading the same input from there with
+    /// `resume_from_path`. Only replay and pcap inputs can be resumed, as
+    /// only they number their packets, other inputs are an
+    /// `UnsupportedOperation`. Msgs of `read_packet` aren't counted, a
+    /// checkpoint of a reader read that way resumes the wrong msgs.
+    pub fn checkpoint(&self) -> Result<ReaderCheckpoint, DogStatsDReaderError> {
+        match self {
+            Self::Replay(r) => Ok(r.checkpoint()),
+            Self::Pcap(r) => Ok(r.checkpoint()),
+            Self::Decompressed(r, _) => Ok(ReaderCheckpoint {
+                byte_offset: None,
+                ..r.checkpoint()?
+            }),
+            Self::Filtered(r, _) | Self::Sliced(r, _) => r.checkpoint(),
+            r => Err(DogStatsDReaderError::UnsupportedOperation(format!(
+                "{} input can't be resumed from a checkpoint",
+                r.input_description()
+            ))),
+        }
+    }
+
+    /// Reads every one of 'readers' to the end, one after the other
+    pub fn from_readers(readers: Vec<DogStatsDReader<'a>>) -> Self {
+        Self::Multi(readers, FinishedReaders::default(), None)
+    }
+
+    /// Only returns the msgs that match 'filter' from now on, filtering here
+    /// keeps every tool consistent about what a filter matches
+    pub fn with_filter(self, filter: MsgFilter) -> Self {
+        Self::Filtered(Box::new(self), filter)
+    }
+
+    /// Skips the first 'n' msgs, eg to page through a huge capture. Replay
+    /// and pcap inputs skip whole packets without copying their msgs out.
+    /// Skipped msgs still count towards the analytics.
+    pub fn skip_msgs(self, n: u64) -> Self {
+        match self {
+            Self::Sliced(r, slice) => Self::Sliced(
+                r,
+                MsgSlice {
+                    skip: slice.skip + n,
+                    limit: slice.limit.map(|limit| limit.saturating_sub(n)),
+                },
+            ),
+            r => Self::Sliced(
+                Box::new(r),
+                MsgSlice {
+                    skip: n,
+                    limit: None,
+                },
+            ),
+        }
+    }
+
+    /// Stops after 'n' msgs (after the ones skipped by `skip_msgs`), eg to
+    /// sample the start of a huge capture
+    pub fn limit_msgs(self, n: u64) -> Self {
+        match self {
+            Self::Sliced(r, slice) => Self::Sliced(
+                r,
+                MsgSlice {
+                    skip: slice.skip,
+                    limit: Some(slice.limit.map_or(n, |limit| limit.min(n))),
+                },
+            ),
+            r => Self::Sliced(
+                Box::new(r),
+                MsgSlice {
+                    skip: 0,
+                    limit: Some(n),
+                },
+            ),
+        }
+    }
+
+    /// Only reads packets captured at or after 'start' and before 'end', both
+    /// since the unix epoch, eg the minutes around an incident out of a long
+    /// capture. Only replay and pcap inputs record capture times, other
+    /// inputs are an `UnsupportedOperation`.
+    pub fn with_time_range(
+        self,
+        start: Duration,
+        end: Duration,
+    ) -> Result<Self, DogStatsDReaderError> {
+        match self {
+            Self::Replay(r) => Ok(Self::Replay(r.with_time_range(start, end))),
+            Self::Pcap(r) => Ok(Self::Pcap(r.with_time_range(start, end))),
+            Self::Decompressed(r, counters) => Ok(Self::Decompressed(
+                Box::new(r.with_time_range(start, end)?),
+                counters,
+            )),
+            Self::Filtered(r, filter) => Ok(Self::Filtered(
+                Box::new(r.with_time_range(start, end)?),
+                filter,
+            )),
+            Self::Sliced(r, slice) => Ok(Self::Sliced(
+                Box::new(r.with_time_range(start, end)?),
+                slice,
+            )),
+            Self::Multi(readers, finished, mut pending) => {
+                if let Some(pending) = pending.as_mut() {
+                    pending.time_range = Some((start, end));
+                }
+                Ok(Self::Multi(
+                    readers
+                        .into_iter()
+                        .map(|r| r.with_time_range(start, end))
+                        .collect::<Result<_, _>>()?,
+                    finished,
+                    pending,
+                ))
+            }
+            r => Err(DogStatsDReaderError::UnsupportedOperation(format!(
+                "{} input has no capture times to select a time range by",
+                r.input_description()
+            ))),
+        }
+    }
+
+    /// Only reads packets sent from and to the addresses of 'filter', eg one
+    /// client's traffic out of the capture of a shared host. Only pcap
+    /// inputs record addresses, other inputs are an `UnsupportedOperation`.
+    pub fn with_addr_filter(self, filter: &AddrFilter) -> Result<Self, DogStatsDReaderError> {
+        match self {
+            Self::Pcap(r) => Ok(Self::Pcap(r.with_addr_filter(filter.clone()))),
+            Self::Decompressed(r, counters) => Ok(Self::Decompressed(
+                Box::new(r.with_addr_filter(filter)?),
+                counters,
+            )),
+            Self::Filtered(r, msg_filter) => Ok(Self::Filtered(
+                Box::new(r.with_addr_filter(filter)?),
+                msg_filter,
+            )),
+            Self::Sliced(r, slice) => {
+                Ok(Self::Sliced(Box::new(r.with_addr_filter(filter)?), slice))
+            }
+            Self::Multi(readers, finished, mut pending) => {
+                if let Some(pending) = pending.as_mut() {
+                    pending.addr_filter = Some(filter.clone());
+                }
+                Ok(Self::Multi(
+                    readers
+                        .into_iter()
+                        .map(|r| r.with_addr_filter(filter))
+                        .collect::<Result<_, _>>()?,
+                    finished,
+                    pending,
+                ))
+            }
+            r => Err(DogStatsDReaderError::UnsupportedOperation(format!(
+                "{} input has no packet addresses to filter by",
+                r.input_description()
+            ))),
+        }
+    }
+
+    /// Reads the files at 'paths' one after the other as a single stream,
+    /// eg rotated captures. Every file is detected independently, so they may
+    /// be of different types and compressions.
+    pub fn from_paths<P: AsRef<Path>>(paths: &[P]) -> Result<Self, DogStatsDReaderError> {
+        Self::from_paths_with_options(paths, &DogStatsDReaderOptions::default())
+    }
+
+    pub fn from_paths_with_options<P: AsRef<Path>>(
+        paths: &[P],
+        options: &DogStatsDReaderOptions,
+    ) -> Result<Self, DogStatsDReaderError> {
+        let mut readers = Vec::new();
+        for path in paths {
+            let file = std::fs::File::open(path)?;
+            readers.push(DogStatsDReader::new_with_options(file, options)?);
+        }
+        Ok(Self::from_readers(readers))
+    }
+
+    /// Reads every file directly in 'dir' as a single stream, eg the captures
+    /// of an agent flare. Files are detected independently, see `dir_files`
+    /// for which files are read and `from_paths` for how.
+    pub fn from_dir(dir: impl AsRef<Path>, order: DirOrder) -> Result<Self, DogStatsDReaderError> {
+        Self::from_dir_with_options(dir, order, &DogStatsDReaderOptions::default())
+    }
+
+    pub fn from_dir_with_options(
+        dir: impl AsRef<Path>,
+        order: DirOrder,
+        options: &DogStatsDReaderOptions,
+    ) -> Result<Self, DogStatsDReaderError> {
+        Self::from_paths_with_options(&dir_files(dir, order)?, options)
+    }
+
+    /// 'archive_reader' should point to the beginning of a tar archive, optionally
+    /// gzip or zstd compressed. Every file entry whose path matches 'entry_glob'
+    /// is detected independently and read in archive order as a single stream.
+    /// Entries are read straight out of the archive, one at a time.
+    pub fn from_archive(
+        archive_reader: impl Read + 'a,
+        entry_glob: &str,
+    ) -> Result<Self, DogStatsDReaderError> {
+        Self::from_archive_with_options(
+            archive_reader,
+            entry_glob,
+            &DogStatsDReaderOptions::default(),
+        )
+    }
+
+    pub fn from_archive_with_options(
+        archive_reader: impl Read + 'a,
+        entry_glob: &str,
+        options: &DogStatsDReaderOptions,
+    ) -> Result<Self, DogStatsDReaderError> {
+        let mut pending = PendingEntries {
+            entries: crate::archive::matching_entries(archive_reader, entry_glob)?,
+            options: options.clone(),
+            time_range: None,
+            addr_filter: None,
+        };
+        // The first entry is opened right away, so an archive without a
+        // matching entry or with an undetectable one fails here
+        let readers = pending.open_next()?.into_iter().collect();
+        Ok(Self::Multi(
+            readers,
+            FinishedReaders::default(),
+            Some(Box::new(pending)),
+        ))
+    }
+
+    /// Binds a unix datagram socket at 'path' and reads msgs as clients send
+    /// them. Reading ends once no packet arrived for 'idle_timeout', without
+    /// one it never ends.
+    pub fn from_unix_datagram(
+        path: impl AsRef<std::path::Path>,
+        idle_timeout: Option<Duration>,
+    ) -> Result<Self, DogStatsDReaderError> {
+        Self::from_unix_datagram_with_options(
+            path,
+            idle_timeout,
+            &DogStatsDReaderOptions::default(),
+        )
+    }
+
+    pub fn from_unix_datagram_with_options(
+        path: impl AsRef<std::path::Path>,
+        idle_timeout: Option<Duration>,
+        options: &DogStatsDReaderOptions,
+    ) -> Result<Self, DogStatsDReaderError> {
+        let mut reader =
+            UnixDatagramDogStatsDReader::bind(path)?.with_max_msg_size(options.max_msg_size);
+        if let Some(idle_timeout) = idle_timeout {
+            reader = reader.with_idle_timeout(idle_timeout)?;
+        }
+        Ok(Self::UnixDatagram(reader))
+    }
+
+    /// Reads the file at 'path' while it is still being written, eg a capture
+    /// the agent is taking, like `tail -f`. At the end of the file reading
+    /// waits for it to grow. Reading ends once the file didn't grow for
+    /// 'idle_timeout', without one it never ends.
+    pub fn follow(
+        path: impl AsRef<Path>,
+        idle_timeout: Option<Duration>,
+    ) -> Result<Self, DogStatsDReaderError> {
+        Self::follow_with_options(path, idle_timeout, &DogStatsDReaderOptions::default())
+    }
+
+    pub fn follow_with_options(
+        path: impl AsRef<Path>,
+        idle_timeout: Option<Duration>,
+        options: &DogStatsDReaderOptions,
+    ) -> Result<Self, DogStatsDReaderError> {
+        Self::new_with_options(
+            Following {
+                inner: std::fs::File::open(path)?,
+                idle_timeout,
+                poll_interval: FOLLOW_POLL_INTERVAL,
+            },
+            options,
+        )
+    }
+
+    /// Binds a unix stream socket at 'path' and reads the length prefixed
+    /// msgs of every client that connects. Reading ends once no msg arrived
+    /// for 'idle_timeout', without one it never ends.
+    pub fn from_unix_stream(
+        path: impl AsRef<std::path::Path>,
+        idle_timeout: Option<Duration>,
+    ) -> Result<Self, DogStatsDReaderError> {
+        Self::from_unix_stream_with_options(path, idle_timeout, &DogStatsDReaderOptions::default())
+    }
+
+    pub fn from_unix_stream_with_options(
+        path: impl AsRef<std::path::Path>,
+        idle_timeout: Option<Duration>,
+        options: &DogStatsDReaderOptions,
+    ) -> Result<Self, DogStatsDReaderError> {
+        let mut reader =
+            UnixStreamDogStatsDReader::bind_with_max_msg_size(path, options.max_msg_size)?;
+        if let Some(idle_timeout) = idle_timeout {
+            reader = reader.with_idle_timeout(idle_timeout);
+        }
+        Ok(Self::UnixStream(reader))
+    }
+
+    /// read_msg populates the given String with a dogstatsd message
+    /// and returns the number of messages read (currently always 1)
+    pub fn read_msg(&mut self, s: &mut String) -> Result<usize, DogStatsDReaderError> {
+        match self {
+            Self::Utf8(r) => Ok(r.read_msg(s)?),
+            Self::Replay(r) => Ok(r.read_msg(s)?),
+            Self::Pcap(r) => Ok(r.read_msg(s)?),
+            Self::Framed(r) => Ok(r.read_msg(s)?),
+            Self::UnixDatagram(r) => Ok(r.read_msg(s)?),
+            Self::UnixStream(r) => Ok(r.read_msg(s)?),
+            Self::Decompressed(r, _) => r.read_msg(s),
+            Self::Filtered(r, filter) => {
+                while let Some(msg) = r.read_msg_ref()? {
+                    if filter.matches_raw(msg) {
+                        prepend_msg(s, msg.to_string());
+                        return Ok(1);
+                    }
+                }
+                Ok(0)
+            }
+            Self::Sliced(r, slice) => {
+                if !Self::skip_to_slice(r, slice)? {
+                    return Ok(0);
+                }
+                let num_read = r.read_msg(s)?;
+                if let Some(limit) = slice.limit.as_mut() {
+                    *limit -= num_read as u64;
+                }
+                Ok(num_read)
+            }
+            Self::Multi(readers, finished, pending) => {
+                if let Some(first_reader) = readers.first_mut() {
+                    let num_read = first_reader.read_msg(s)?;
+                    if num_read == 0 {
+                        // remove the first reader from the list
+                        Self::finish_first_reader(readers, finished, pending)?;
+                        // if there are more readers, recursively call read_msg
+                        if !readers.is_empty() {
+                            self.read_msg(s)
+                        } else {
+                            Ok(0)
+                        }
+                    } else {
+                        Ok(num_read)
+                    }
+                } else {
+                    Ok(0)
+                }
+            }
+        }
+    }
+
+    /// Like `read_msg`, but the msg stays in the reader, the returned slice
+    /// is valid until the next read. Saves copying every msg out in loops
+    /// that only look at each msg once.
+    pub fn read_msg_ref(&mut self) -> Result<Option<&str>, DogStatsDReaderError> {
+        match self {
+            Self::Utf8(r) => Ok(r.read_msg_ref()?),
+            Self::Replay(r) => Ok(r.read_msg_ref()?),
+            Self::Pcap(r) => Ok(r.read_msg_ref()?),
+            Self::Framed(r) => Ok(r.read_msg_ref()?),
+            Self::UnixDatagram(r) => Ok(r.read_msg_ref()?),
+            Self::UnixStream(r) => Ok(r.read_msg_ref()?),
+            Self::Decompressed(r, _) => r.read_msg_ref(),
+            Self::Filtered(r, filter) => {
+                loop {
+                    match r.read_msg_ref()? {
+                        None => return Ok(None),
+                        Some(msg) if filter.matches_raw(msg) => break,
+                        Some(_) => {}
+                    }
+                }
+                Ok(Some(r.current_msg()))
+            }
+            Self::Sliced(r, slice) => {
+                if !Self::skip_to_slice(r, slice)? {
+                    return Ok(None);
+                }
+                let msg = r.read_msg_ref()?;
+                if let (Some(limit), Some(_)) = (slice.limit.as_mut(), &msg) {
+                    *limit -= 1;
+                }
+                Ok(msg)
+            }
+            Self::Multi(readers, finished, pending) => {
+                while let Some(first_reader) = readers.first_mut() {
+                    if first_reader.read_msg_ref()?.is_some() {
+                        break;
+                    }
+                    Self::finish_first_reader(readers, finished, pending)?;
+                }
+                // Looked up again, the msg can't be held across the removal
+                Ok(readers.first().map(|r| r.current_msg()))
+            }
+        }
+    }
+
+    /// Reads the next packet whole, for tools that look at how msgs are
+    /// batched into packets. Msgs still left from a packet that was started
+    /// with `read_msg` are skipped. Utf-8 text has no packet boundaries and
+    /// is an `UnsupportedOperation`.
+    pub fn read_packet(&mut self) -> Result<Option<DogStatsDPacket>, DogStatsDReaderError> {
+        match self {
+            Self::Utf8(_) => Err(DogStatsDReaderError::UnsupportedOperation(
+                "utf-8 text input has no packet boundaries".to_string(),
+            )),
+            Self::Replay(r) => Ok(r.read_packet()?),
+            Self::Pcap(r) => Ok(r.read_packet()?),
+            Self::Framed(r) => Ok(r.read_packet()?),
+            Self::UnixDatagram(r) => Ok(r.read_packet()?),
+            Self::UnixStream(r) => Ok(r.read_packet()),
+            Self::Decompressed(r, _) => r.read_packet(),
+            Self::Filtered(..) => Err(DogStatsDReaderError::UnsupportedOperation(
+                "filters select msgs, not packets".to_string(),
+            )),
+            Self::Sliced(..) => Err(DogStatsDReaderError::UnsupportedOperation(
+                "msgs are skipped and limited, not packets".to_string(),
+            )),
+            Self::Multi(readers, finished, pending) => {
+                while let Some(first_reader) = readers.first_mut() {
+                    if let Some(packet) = first_reader.read_packet()? {
+                        return Ok(Some(packet));
+                    }
+                    Self::finish_first_reader(readers, finished, pending)?;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// The msg returned by the last `read_msg_ref`
+    fn current_msg(&self) -> &str {
+        match self {
+            Self::Utf8(r) => r.current_msg(),
+            Self::Replay(r) => r.current_msg(),
+            Self::Pcap(r) => r.current_msg(),
+            Self::Framed(r) => r.current_msg(),
+            Self::UnixDatagram(r) => r.current_msg(),
+            Self::UnixStream(r) => r.current_msg(),
+            Self::Decompressed(r, _) | Self::Filtered(r, _) | Self::Sliced(r, _) => r.current_msg(),
+            Self::Multi(readers, ..) => readers.first().map_or("", |r| r.current_msg()),
+        }
+    }
+
+    /// Skips the msgs 'slice' still has to skip, then tells whether it allows
+    /// reading another msg
+    fn skip_to_slice(
+        reader: &mut DogStatsDReader<'a>,
+        slice: &mut MsgSlice,
+    ) -> Result<bool, DogStatsDReaderError> {
+        if slice.skip > 0 {
+            slice.skip -= reader.discard_msgs(slice.skip)?;
+            if slice.skip > 0 {
+                // The input ended first
+                return Ok(false);
+            }
+        }
+        Ok(slice.limit != Some(0))
+    }
+
+    /// Reads past up to 'n' msgs and returns how many there were, whole
+    /// packets at a time where the reader supports it
+    fn discard_msgs(&mut self, n: u64) -> Result<u64, DogStatsDReaderError> {
+        match self {
+            Self::Replay(r) => Ok(r.discard_msgs(n)?),
+            Self::Pcap(r) => Ok(r.discard_msgs(n)?),
+            Self::Decompressed(r, _) => r.discard_msgs(n),
+            Self::Multi(readers, finished, pending) => {
+                let mut discarded = 0;
+                while let Some(first_reader) = readers.first_mut() {
+                    discarded += first_reader.discard_msgs(n - discarded)?;
+                    if discarded == n {
+                        break;
+                    }
+                    Self::finish_first_reader(readers, finished, pending)?;
+                }
+                Ok(discarded)
+            }
+            _ => {
+                let mut discarded = 0;
+                while discarded < n && self.read_msg_ref()?.is_some() {
+                    discarded += 1;
+                }
+                Ok(discarded)
+            }
+        }
+    }
+
+    /// Reads the next msg into 'line' and parses it, for consumers that only
+    /// want parsed msgs. 'line' is cleared first and the msg borrows from it.
+    /// Returns None at the end of the input. A msg that doesn't parse is a
+    /// `Parse` error, reading can go on with the next msg after it.
+    pub fn read_parsed<'b>(
+        &mut self,
+        line: &'b mut String,
+    ) -> Result<Option<DogStatsDMsg<'b>>, DogStatsDReaderError> {
+        line.clear();
+        if self.read_msg(line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(DogStatsDMsg::new(line)?))
+    }
+
+    /// Drops the first of 'readers' once it is read to the end, its analytics
+    /// are kept in 'finished' so they still count towards the total. The next
+    /// 'pending' archive entry is opened once no reader is left.
+    fn finish_first_reader(
+        readers: &mut Vec<DogStatsDReader<'a>>,
+        finished: &mut FinishedReaders,
+        pending: &mut Option<Box<PendingEntries<'a>>>,
+    ) -> Result<(), DogStatsDReaderError> {
+        let reader = readers.remove(0);
+        if let Some(analytics) = reader.get_analytics()? {
+            match finished.analytics.as_mut() {
+                Some(finished) => finished.merge(&analytics),
+                None => finished.analytics = Some(analytics),
+            }
+        }
+        merge_compression(&mut finished.compression, &reader.compression_analytics());
+        if readers.is_empty() {
+            if let Some(entries) = pending.as_mut() {
+                match entries.open_next()? {
+                    Some(reader) => readers.push(reader),
+                    None => *pending = None,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a snapshot of the analytics of everything read so far, it can
+    /// be taken at any point while reading, eg to report progress.
+    /// Only supported for readers that deal with packets
+    pub fn get_analytics(&self) -> Result<Option<Analytics>, DogStatsDReaderError> {
+        match self {
+            Self::Utf8(_r) | Self::Framed(_r) => Ok(None),
+            Self::Replay(r) => Ok(Some(r.get_analytics()?)),
+            Self::Pcap(r) => Ok(Some(r.get_analytics()?)),
+            Self::UnixDatagram(r) => Ok(Some(r.get_analytics()?)),
+            Self::UnixStream(r) => Ok(Some(r.get_analytics()?)),
+            Self::Multi(readers, finished, _) => {
+                let current = match readers.first() {
+                    Some(reader) => reader.get_analytics()?,
+                    None => None,
+                };
+                Ok(match (finished.analytics.clone(), current) {
+                    (Some(mut analytics), Some(current)) => {
+                        analytics.merge(&current);
+                        Some(analytics)
                     }
+                    (finished, current) => finished.or(current),
                 }
+                .map(|mut analytics| {
+                    // Compressed text inputs count too
+                    analytics.compression = self.compression_analytics();
+                    analytics
+                }))
             }
-            InputType::Replay => {
-                info!("Treating input as dogstatsd-replay");
-                match DogStatsDReplayReader::new(buf_reader) {
-                    Ok(reader) => Ok(Self::Replay(reader)),
-                    Err(e) => {
-                        panic!("Replay reader couldn't be created: {e:?}");
-                    }
-                }
+            Self::Filtered(r, _) | Self::Sliced(r, _) => r.get_analytics(),
+            Self::Decompressed(r, counters) => Ok(r.get_analytics()?.map(|mut analytics| {
+                analytics.compression = Some(counters.snapshot());
+                analytics
+            })),
+        }
+    }
+
+    /// Credentials of the process that sent the most recently read msg.
+    /// Only available for replay files captured with credentials and for
+    /// unix sockets on linux.
+    pub fn last_msg_credentials(&self) -> Option<UnixCredentials> {
+        match self {
+            Self::Replay(r) => r.last_msg_credentials(),
+            Self::UnixDatagram(r) => r.last_msg_credentials(),
+            Self::UnixStream(r) => r.last_msg_credentials(),
+            Self::Decompressed(r, _) | Self::Filtered(r, _) | Self::Sliced(r, _) => {
+                r.last_msg_credentials()
             }
-            InputType::Utf8 => {
-                info!("Treating input as utf8");
-                Ok(Self::Utf8(Utf8DogStatsDReader::new(buf_reader)))
+            Self::Multi(readers, ..) => readers.first().and_then(|r| r.last_msg_credentials()),
+            Self::Utf8(_) | Self::Pcap(_) | Self::Framed(_) => None,
+        }
+    }
+
+    /// Pid, capture time and ancillary data recorded along with the packet of
+    /// the most recently read msg. Only available for replay files.
+    pub fn last_msg_record(&self) -> Option<&ReplayRecordMeta> {
+        match self {
+            Self::Replay(r) => r.last_msg_record(),
+            Self::Decompressed(r, _) | Self::Filtered(r, _) | Self::Sliced(r, _) => {
+                r.last_msg_record()
             }
+            Self::Multi(readers, ..) => readers.first().and_then(|r| r.last_msg_record()),
+            _ => None,
         }
     }
 
-    pub fn from_paths(paths: Vec<String>) -> Result<Self, DogStatsDReaderError> {
-        let mut readers = Vec::new();
-        for path in paths {
-            let file = std::fs::File::open(path)?;
-            readers.push(DogStatsDReader::new(file)?);
+    /// The tagger state of a replay file, ie the tags the agent held for
+    /// every entity when the capture was taken. Only available once every msg
+    /// was read, None for other inputs and for several inputs.
+    pub fn tagger_state(&mut self) -> Result<Option<TaggerState>, DogStatsDReaderError> {
+        match self {
+            Self::Replay(r) => Ok(r.tagger_state()?),
+            Self::Decompressed(r, _) | Self::Filtered(r, _) | Self::Sliced(r, _) => {
+                r.tagger_state()
+            }
+            _ => Ok(None),
         }
-        Ok(Self::Multi(readers))
     }
 
-    /// read_msg populates the given String with a dogstatsd message
-    /// and returns the number of messages read (currently always 1)
-    pub fn read_msg(&mut self, s: &mut String) -> Result<usize, DogStatsDReaderError> {
+    /// When the packet of the most recently read msg was captured. Only
+    /// available for replay, pcap and unix socket inputs.
+    pub fn last_msg_timestamp(&self) -> Option<Duration> {
         match self {
-            Self::Utf8(r) => Ok(r.read_msg(s)?),
-            Self::Replay(r) => Ok(r.read_msg(s)?),
-            Self::Pcap(r) => Ok(r.read_msg(s)?),
-            Self::Multi(readers) => {
-                if let Some(first_reader) = readers.first_mut() {
-                    let num_read = first_reader.read_msg(s)?;
-                    if num_read == 0 {
-                        // remove the first reader from the list
-                        readers.remove(0);
-                        // if there are more readers, recursively call read_msg
-                        if !readers.is_empty() {
-                            self.read_msg(s)
-                        } else {
-                            Ok(0)
-                        }
-                    } else {
-                        Ok(num_read)
-                    }
-                } else {
-                    Ok(0)
-                }
+            Self::Replay(r) => r.last_msg_timestamp(),
+            Self::Pcap(r) => r.last_msg_timestamp(),
+            Self::UnixDatagram(r) => r.last_msg_timestamp(),
+            Self::UnixStream(r) => r.last_msg_timestamp(),
+            Self::Decompressed(r, _) | Self::Filtered(r, _) | Self::Sliced(r, _) => {
+                r.last_msg_timestamp()
             }
+            Self::Multi(readers, ..) => readers.first().and_then(|r| r.last_msg_timestamp()),
+            Self::Utf8(_) | Self::Framed(_) => None,
         }
     }
 
-    /// Returns a snapshot of the current analytics from the underlying reader
-    /// Only supported for readers that deal with packets
-    pub fn get_analytics(&mut self) -> Result<Option<Analytics>, DogStatsDReaderError> {
+    /// What the input was detected as. None for live sockets, which aren't
+    /// detected, and for several inputs that weren't all detected the same.
+    /// Inputs of a `Multi` reader are forgotten once read, so ask before reading.
+    /// Archive entries that weren't opened yet aren't considered.
+    pub fn input_type(&self) -> Option<DetectedInput> {
+        let format = match self {
+            Self::Utf8(_) => InputType::Utf8,
+            Self::Replay(_) => InputType::Replay,
+            Self::Pcap(_) => InputType::Pcap,
+            Self::Framed(_) => InputType::Framed,
+            Self::UnixDatagram(_) | Self::UnixStream(_) => return None,
+            Self::Decompressed(r, counters) => {
+                let mut detected = r.input_type()?;
+                detected
+                    .compression
+                    .insert(0, Cow::Borrowed(counters.codec));
+                return Some(detected);
+            }
+            Self::Filtered(r, _) | Self::Sliced(r, _) => return r.input_type(),
+            Self::Multi(readers, ..) => {
+                let (first, rest) = readers.split_first()?;
+                let detected = first.input_type()?;
+                return rest
+                    .iter()
+                    .all(|r| r.input_type().as_ref() == Some(&detected))
+                    .then_some(detected);
+            }
+        };
+        Some(DetectedInput {
+            format,
+            compression: Vec::new(),
+        })
+    }
+
+    /// What the input was detected as, eg 'zstd compressed dogstatsd-replay'
+    pub fn input_description(&self) -> String {
         match self {
-            Self::Utf8(_r) => Ok(None),
-            Self::Replay(r) => Ok(Some(r.get_analytics()?)),
-            Self::Pcap(r) => Ok(Some(r.get_analytics()?)),
-            Self::Multi(_readers) => Ok(None),
+            Self::Utf8(_) => InputType::Utf8.to_string(),
+            Self::Replay(_) => InputType::Replay.to_string(),
+            Self::Pcap(_) => InputType::Pcap.to_string(),
+            Self::Framed(_) => InputType::Framed.to_string(),
+            Self::UnixDatagram(r) => format!("unix datagram socket {}", r.path().display()),
+            Self::UnixStream(r) => format!("unix stream socket {}", r.path().display()),
+            Self::Decompressed(r, counters) => {
+                format!("{} compressed {}", counters.codec, r.input_description())
+            }
+            Self::Filtered(r, _) => format!("filtered {}", r.input_description()),
+            Self::Sliced(r, _) => r.input_description(),
+            Self::Multi(readers, ..) => readers
+                .iter()
+                .map(|r| r.input_description())
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+
+    /// Returns a snapshot of the compression analytics when the input was
+    /// compressed. Available for every input type, including utf8.
+    pub fn compression_analytics(&self) -> Option<CompressionAnalytics> {
+        match self {
+            Self::Decompressed(_r, counters) => Some(counters.snapshot()),
+            Self::Filtered(r, _) | Self::Sliced(r, _) => r.compression_analytics(),
+            Self::Multi(readers, finished, _) => {
+                let mut compression = finished.compression.clone();
+                if let Some(reader) = readers.first() {
+                    merge_compression(&mut compression, &reader.compression_analytics());
+                }
+                compression
+            }
+            _ => None,
         }
     }
 }
@@ -357,6 +1828,312 @@ mod tests {
         assert_eq!(reader.read_msg(&mut s).unwrap(), 0);
     }
 
+    #[test]
+    fn corrupt_pcap_is_an_error() {
+        // The magic bytes are there, the rest of the pcap header is not
+        let truncated = &PCAP_SLL2_SINGLE_UDP_PACKET[..12];
+        assert!(matches!(
+            DogStatsDReader::new(truncated),
+            Err(DogStatsDReaderError::Pcap(_))
+        ));
+    }
+
+    #[test]
+    fn paths_are_chained_and_detected_independently() {
+        let dir =
+            std::env::temp_dir().join(format!("dogstatsd-utils-paths-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = [dir.join("capture.1.gz"), dir.join("capture.2")];
+        std::fs::write(&paths[0], gzip(b"my.metric:1|g\n")).unwrap();
+        std::fs::write(&paths[1], TWO_MSGS_ONE_LINE_EACH).unwrap();
+
+        let mut reader = DogStatsDReader::from_paths(&paths).unwrap();
+        assert_eq!(
+            reader.input_description(),
+            "gzip compressed utf-8 text, dogstatsd-replay"
+        );
+        let msgs = read_all(&mut reader);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(msgs[0], "my.metric:1|g");
+    }
+
+    #[test]
+    fn mmapped_paths_are_detected_like_any_input() {
+        let dir = std::env::temp_dir().join(format!("dogstatsd-utils-mmap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let replay = dir.join("capture");
+        let compressed = dir.join("capture.gz");
+        std::fs::write(&replay, TWO_MSGS_ONE_LINE_EACH).unwrap();
+        std::fs::write(&compressed, gzip(b"my.metric:1|g\n")).unwrap();
+
+        let mut reader = DogStatsDReader::from_path_mmap(&replay).unwrap();
+        assert_eq!(reader.input_description(), "dogstatsd-replay");
+        assert_eq!(read_all(&mut reader).len(), 2);
+        assert_eq!(reader.get_analytics().unwrap().unwrap().total_messages, 2);
+
+        let mut reader = DogStatsDReader::from_path_mmap(&compressed).unwrap();
+        let msgs = read_all(&mut reader);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(reader.input_description(), "gzip compressed utf-8 text");
+        assert_eq!(msgs, ["my.metric:1|g"]);
+    }
+
+    #[test]
+    fn input_type_is_detected() {
+        let compressed = gzip(&zstd::encode_all(TWO_MSGS_ONE_LINE_EACH, 0).unwrap());
+        let reader = DogStatsDReader::new(&compressed[..]).unwrap().limit_msgs(1);
+        let detected = reader.input_type().unwrap();
+        assert_eq!(detected.format, InputType::Replay);
+        assert_eq!(detected.compression, ["gzip", "zstd"]);
+        assert_eq!(detected.to_string(), reader.input_description());
+
+        let text = DogStatsDReader::new(&b"my.metric:1|g\n"[..]).unwrap();
+        let detected = text.input_type().unwrap();
+        assert_eq!(detected.format, InputType::Utf8);
+        assert!(detected.compression.is_empty());
+
+        // shorter than any binary header
+        let mut short = DogStatsDReader::new(&b"a:1|c"[..]).unwrap();
+        assert_eq!(short.input_type().unwrap().format, InputType::Utf8);
+        let mut s = String::new();
+        assert_eq!(short.read_msg(&mut s).unwrap(), 1);
+        assert_eq!(s, "a:1|c");
+        let compressed = gzip(b"a:1|c");
+        let mut short = DogStatsDReader::new(&compressed[..]).unwrap();
+        assert_eq!(short.input_type().unwrap().format, InputType::Utf8);
+        s.clear();
+        assert_eq!(short.read_msg(&mut s).unwrap(), 1);
+        assert_eq!(s, "a:1|c");
+        assert!(DogStatsDReader::new(&b""[..]).is_err());
+
+        // several inputs only have a type when they all agree
+        let same = DogStatsDReader::from_readers(vec![
+            DogStatsDReader::new(&b"a:1|c\n"[..]).unwrap(),
+            DogStatsDReader::new(&b"b:1|c\n"[..]).unwrap(),
+        ]);
+        assert_eq!(same.input_type().unwrap().format, InputType::Utf8);
+        let mixed = DogStatsDReader::from_readers(vec![
+            DogStatsDReader::new(&b"a:1|c\n"[..]).unwrap(),
+            DogStatsDReader::new(TWO_MSGS_ONE_LINE_EACH).unwrap(),
+        ]);
+        assert!(mixed.input_type().is_none());
+    }
+
+    #[test]
+    fn dirs_are_read_file_by_file() {
+        let dir = std::env::temp_dir().join(format!("dogstatsd-utils-dir-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("b.txt"), "b:1|c\n").unwrap();
+        std::fs::write(dir.join("a.gz"), gzip(b"a:1|c\n")).unwrap();
+        std::fs::write(dir.join(".hidden"), "hidden:1|c\n").unwrap();
+        std::fs::write(dir.join("nested").join("c.txt"), "c:1|c\n").unwrap();
+
+        let files = dir_files(&dir, DirOrder::Name).unwrap();
+        let mut reader = DogStatsDReader::from_dir(&dir, DirOrder::Name).unwrap();
+        let msgs = read_all(&mut reader);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, [dir.join("a.gz"), dir.join("b.txt")]);
+        assert_eq!(msgs, ["a:1|c", "b:1|c"]);
+    }
+
+    #[test]
+    fn readers_resume_from_their_checkpoint() {
+        let dir =
+            std::env::temp_dir().join(format!("dogstatsd-utils-resume-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let replay = dir.join("capture");
+        let compressed = dir.join("capture.gz");
+        std::fs::write(&replay, TWO_MSGS_ONE_LINE_EACH).unwrap();
+        std::fs::write(&compressed, gzip(TWO_MSGS_ONE_LINE_EACH)).unwrap();
+
+        let mut reader = DogStatsDReader::from_path_mmap(&replay).unwrap();
+        let first = reader.read_msg_ref().unwrap().unwrap().to_string();
+        let checkpoint = reader.checkpoint().unwrap();
+        assert_eq!(checkpoint.input_type, InputType::Replay);
+        assert_eq!(checkpoint.msgs_read, 1);
+        assert_eq!(checkpoint.packet_index, 1);
+        assert!(checkpoint.byte_offset.is_some());
+        let rest = read_all(&mut reader);
+
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        let checkpoint: ReaderCheckpoint = serde_json::from_str(&json).unwrap();
+        let mut resumed = DogStatsDReader::resume_from_path(&replay, &checkpoint).unwrap();
+        assert_eq!(read_all(&mut resumed), rest);
+        assert_eq!(resumed.get_analytics().unwrap().unwrap().total_messages, 1);
+        assert_eq!(resumed.checkpoint().unwrap().msgs_read, 2);
+
+        // compressed inputs skip the msgs before the checkpoint instead
+        let mut reader = DogStatsDReader::from_path_mmap(&compressed).unwrap();
+        reader.read_msg_ref().unwrap();
+        let checkpoint = reader.checkpoint().unwrap();
+        assert_eq!(checkpoint.byte_offset, None);
+        let mut resumed = DogStatsDReader::resume_from_path(&compressed, &checkpoint).unwrap();
+        let resumed_rest = read_all(&mut resumed);
+
+        let text = dir.join("capture.txt");
+        std::fs::write(&text, format!("{first}\n")).unwrap();
+        let utf8 = DogStatsDReader::from_path_mmap(&text).unwrap();
+        assert!(utf8.checkpoint().is_err());
+        assert!(DogStatsDReader::resume_from_path(&text, &checkpoint).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(resumed_rest, rest);
+    }
+
+    #[test]
+    fn msgs_can_be_skipped_and_limited() {
+        let payload = b"a:1|c\nb:1|c\nc:1|c\nd:1|c\n";
+        let mut reader = DogStatsDReader::new(&payload[..])
+            .unwrap()
+            .skip_msgs(1)
+            .limit_msgs(2);
+        assert_eq!(read_all(&mut reader), ["b:1|c", "c:1|c"]);
+
+        // Skipped msgs of packet inputs still count
+        let mut reader = DogStatsDReader::new(TWO_MSGS_ONE_LINE_EACH)
+            .unwrap()
+            .skip_msgs(1);
+        assert_eq!(read_all(&mut reader).len(), 1);
+        assert_eq!(reader.get_analytics().unwrap().unwrap().total_messages, 2);
+    }
+
+    #[test]
+    fn time_range_needs_capture_times() {
+        // The second of the two records was captured at 1692823178.27s
+        let mut reader = DogStatsDReader::new(TWO_MSGS_ONE_LINE_EACH)
+            .unwrap()
+            .with_time_range(Duration::from_secs(1692823178), Duration::MAX)
+            .unwrap();
+        assert_eq!(read_all(&mut reader).len(), 1);
+
+        assert!(matches!(
+            DogStatsDReader::new(&b"my.metric:1|g"[..])
+                .unwrap()
+                .with_time_range(Duration::ZERO, Duration::MAX),
+            Err(DogStatsDReaderError::UnsupportedOperation(_))
+        ));
+    }
+
+    #[test]
+    fn addr_filter_needs_packet_addresses() {
+        for input in [TWO_MSGS_ONE_LINE_EACH, &b"my.metric:1|g"[..]] {
+            assert!(matches!(
+                DogStatsDReader::new(input)
+                    .unwrap()
+                    .with_addr_filter(&AddrFilter::default()),
+                Err(DogStatsDReaderError::UnsupportedOperation(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn replay_packets_are_read_whole() {
+        let mut reader = DogStatsDReader::new(TWO_MSGS_ONE_LINE_EACH).unwrap();
+        let mut num_packets = 0;
+        while let Some(packet) = reader.read_packet().unwrap() {
+            assert!(packet
+                .payload
+                .starts_with(b"statsd.example.time.micros:2.39283|d"));
+            assert!(matches!(packet.transport, Transport::UnixDatagram));
+            assert!(packet.timestamp.is_some());
+            num_packets += 1;
+        }
+        assert_eq!(num_packets, 2);
+
+        let mut reader = DogStatsDReader::new(&b"my.metric:1|g"[..]).unwrap();
+        assert!(matches!(
+            reader.read_packet(),
+            Err(DogStatsDReaderError::UnsupportedOperation(_))
+        ));
+    }
+
+    #[test]
+    fn analytics_can_be_taken_while_reading() {
+        let mut reader = DogStatsDReader::from_readers(vec![
+            DogStatsDReader::new(TWO_MSGS_ONE_LINE_EACH).unwrap(),
+            DogStatsDReader::new(TWO_MSGS_ONE_LINE_EACH).unwrap(),
+        ]);
+        let mut s = String::new();
+        reader.read_msg(&mut s).unwrap();
+        let analytics = reader.get_analytics().unwrap().unwrap();
+        assert_eq!(analytics.total_packets, 1);
+        assert_eq!(analytics.total_messages, 1);
+
+        for _ in 0..3 {
+            s.clear();
+            assert_eq!(reader.read_msg(&mut s).unwrap(), 1);
+        }
+        let analytics = reader.get_analytics().unwrap().unwrap();
+        assert_eq!(analytics.total_packets, 4);
+        assert_eq!(analytics.total_messages, 4);
+
+        // Readers that were read to the end still count
+        s.clear();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 0);
+        let analytics = reader.get_analytics().unwrap().unwrap();
+        assert_eq!(analytics.total_packets, 4);
+        assert_eq!(analytics.total_messages, 4);
+    }
+
+    #[test]
+    fn read_msg_ref_reads_across_readers() {
+        let mut reader = DogStatsDReader::from_readers(vec![
+            DogStatsDReader::new(&b"my.metric:1|g\nmy.metric:2|g\n"[..]).unwrap(),
+            DogStatsDReader::new(&b"my.metric:3|g"[..]).unwrap(),
+        ]);
+        let mut msgs = Vec::new();
+        while let Some(msg) = reader.read_msg_ref().unwrap() {
+            msgs.push(msg.to_string());
+        }
+        assert_eq!(msgs, ["my.metric:1|g", "my.metric:2|g", "my.metric:3|g"]);
+    }
+
+    #[test]
+    fn read_parsed_skips_past_unparseable_msgs() {
+        let payload = b"my.metric:1|g\nnot a msg\n_sc|my.check|0\n";
+        let mut reader = DogStatsDReader::new(&payload[..])
+            .expect("could create dogstatsd reader from static bytes");
+        let mut line = String::new();
+
+        match reader.read_parsed(&mut line).unwrap() {
+            Some(DogStatsDMsg::Metric(metric)) => assert_eq!(metric.name, "my.metric"),
+            _ => panic!("expected a metric"),
+        }
+        assert!(matches!(
+            reader.read_parsed(&mut line),
+            Err(DogStatsDReaderError::Parse(_))
+        ));
+        assert!(matches!(
+            reader.read_parsed(&mut line).unwrap(),
+            Some(DogStatsDMsg::ServiceCheck(_))
+        ));
+        assert!(reader.read_parsed(&mut line).unwrap().is_none());
+    }
+
+    #[test]
+    fn filtered_reader_only_returns_matches() {
+        let payload = b"my.metric:1|g|#env:prod\nother.metric:2|g|#env:prod\nmy.metric:3|g\n";
+        let filter = MsgFilter {
+            name: Some(regex::Regex::new("^my\\.").unwrap()),
+            tags: vec!["env".to_string()],
+            ..Default::default()
+        };
+        let reader = || {
+            DogStatsDReader::new(&payload[..])
+                .unwrap()
+                .with_filter(filter.clone())
+        };
+        assert_eq!(read_all(&mut reader()), ["my.metric:1|g|#env:prod"]);
+
+        let mut reader = reader();
+        assert_eq!(
+            reader.read_msg_ref().unwrap(),
+            Some("my.metric:1|g|#env:prod")
+        );
+        assert_eq!(reader.read_msg_ref().unwrap(), None);
+        assert_eq!(reader.input_description(), "filtered utf-8 text");
+    }
+
     #[test]
     fn utf8_multi_msg() {
         // Given 2 msgs
@@ -450,6 +2227,14 @@ mod tests {
 
         // then no more
         assert_eq!(reader.read_msg(&mut s).unwrap(), 0);
+
+        let compression = reader
+            .compression_analytics()
+            .expect("zstd input has compression analytics");
+        assert_eq!(compression.codec, "zstd");
+        assert_eq!(compression.compressed_bytes, payload.len() as u64);
+        assert_eq!(compression.decompressed_bytes, 13);
+        assert!(compression.compression_ratio() < 1.0);
     }
 
     #[test]
@@ -530,6 +2315,445 @@ mod tests {
         assert_eq!("statsd.example.time.micros:2.39283|d|@1.000000|#environment:dev|c:2a25f7fc8fbf573d62053d7263dd2d440c07b6ab4d2b107e50b0d4df1f2ee15f", s);
         let res = replay.read_msg(&mut s).unwrap();
         assert_eq!(res, 0);
+        assert!(replay.compression_analytics().is_none());
+    }
+
+    #[test]
+    fn archive_entries_are_read_in_order() {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, contents) in [
+            ("capture-1.txt", "my.metric:1|g\n"),
+            ("notes.md", "ignore me"),
+            ("capture-2.txt", "my.metric:2|g\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        let archive = builder.into_inner().unwrap();
+
+        let mut reader = DogStatsDReader::from_archive(&archive[..], "capture-*")
+            .expect("could create dogstatsd reader from archive");
+        let mut s = String::new();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 1);
+        assert_eq!(s.as_str(), "my.metric:1|g");
+        s.clear();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 1);
+        assert_eq!(s.as_str(), "my.metric:2|g");
+        s.clear();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 0);
+    }
+
+    #[test]
+    fn analytics_detects_suspicious_gap() {
+        let mut analytics = Analytics::new(Transport::Udp);
+        for i in 0..10 {
+            analytics.record_packet(Duration::from_millis(100 * i), 10);
+        }
+        // 10s of silence after steady 100ms intervals
+        analytics.record_packet(Duration::from_millis(10_900), 10);
+        analytics.record_packet(Duration::from_millis(11_000), 10);
+
+        assert_eq!(analytics.total_packets, 12);
+        assert_eq!(analytics.total_bytes, 120);
+        assert_eq!(analytics.num_suspicious_gaps, 1);
+        assert_eq!(
+            analytics.suspicious_gaps,
+            vec![Gap {
+                start: Duration::from_millis(900),
+                length: Duration::from_secs(10),
+            }]
+        );
+        assert_eq!(analytics.largest_gap, Duration::from_secs(10));
+        assert_eq!(analytics.duration(), Duration::from_secs(11));
+        assert_eq!(analytics.capture_health_warnings().len(), 1);
+    }
+
+    #[test]
+    fn analytics_burst_at_one_timestamp_has_no_gaps() {
+        let mut analytics = Analytics::new(Transport::Udp);
+        for _ in 0..5 {
+            analytics.record_packet(Duration::from_secs(1), 10);
+        }
+        analytics.record_packet(Duration::from_secs(10), 10);
+        assert_eq!(analytics.num_suspicious_gaps, 0);
+        assert_eq!(analytics.largest_gap, Duration::from_secs(9));
+        assert!(analytics.capture_health_warnings().is_empty());
+    }
+
+    #[test]
+    fn analytics_steady_slow_traffic_has_no_gaps() {
+        let mut analytics = Analytics::new(Transport::Udp);
+        for i in 0..10 {
+            analytics.record_packet(Duration::from_secs(5 * i), 10);
+        }
+        assert_eq!(analytics.num_suspicious_gaps, 0);
+        assert_eq!(analytics.largest_gap, Duration::from_secs(5));
+        assert!(analytics.capture_health_warnings().is_empty());
+    }
+
+    #[test]
+    fn analytics_counts_out_of_order_packets() {
+        let mut analytics = Analytics::new(Transport::UnixDatagram);
+        analytics.record_packet(Duration::from_secs(10), 1);
+        analytics.record_packet(Duration::from_secs(12), 1);
+        analytics.record_packet(Duration::from_secs(9), 1);
+        analytics.record_packet(Duration::from_secs(13), 1);
+
+        assert_eq!(analytics.out_of_order_packets, 1);
+        assert_eq!(analytics.earliest_timestamp, Duration::from_secs(9));
+        assert_eq!(analytics.latest_timestamp, Duration::from_secs(13));
+        assert_eq!(analytics.capture_health_warnings().len(), 1);
+    }
+
+    #[test]
+    fn merged_analytics_of_different_codecs_are_mixed() {
+        let compression = |codec, compressed_bytes, decompressed_bytes| CompressionAnalytics {
+            codec: Cow::Borrowed(codec),
+            compressed_bytes,
+            decompressed_bytes,
+            decompression_time: Duration::from_millis(10),
+        };
+        let mut analytics = Analytics::new(Transport::Udp);
+        analytics.compression = Some(compression("zstd", 10, 100));
+        let mut other = Analytics::new(Transport::Udp);
+        other.compression = Some(compression("gzip", 20, 50));
+
+        analytics.merge(&other);
+        assert_eq!(
+            analytics.compression,
+            Some(CompressionAnalytics {
+                codec: Cow::Borrowed(MIXED_CODECS),
+                compressed_bytes: 30,
+                decompressed_bytes: 150,
+                decompression_time: Duration::from_millis(20),
+            })
+        );
+    }
+
+    /// Yields a single byte per read, like a slow pipe
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((first, rest)), Some(out)) => {
+                    *out = *first;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    /// Ends after every chunk, an empty chunk is a read at the end of the
+    /// input, like a file that is still being written
+    struct Growing(std::collections::VecDeque<&'static [u8]>);
+
+    impl Read for Growing {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some(chunk) = self.0.pop_front() else {
+                return Ok(0);
+            };
+            let n = chunk.len().min(buf.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            if n < chunk.len() {
+                self.0.push_front(&chunk[n..]);
+            }
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn followed_input_is_read_as_it_grows() {
+        let growing = Growing([&b"a:1|c\n"[..], b"", b"b:1|", b"", b"c\n"].into());
+        let mut reader = DogStatsDReader::new(Following {
+            inner: growing,
+            idle_timeout: Some(Duration::from_millis(50)),
+            poll_interval: Duration::from_millis(1),
+        })
+        .unwrap();
+        assert_eq!(read_all(&mut reader), ["a:1|c", "b:1|c"]);
+    }
+
+    #[test]
+    fn replay_detected_from_trickled_input() {
+        let mut replay = DogStatsDReader::new(Trickle(TWO_MSGS_ONE_LINE_EACH))
+            .expect("could create dogstatsd reader from trickled bytes");
+        assert!(matches!(replay, DogStatsDReader::Replay(_)));
+        let mut s = String::new();
+        assert_eq!(replay.read_msg(&mut s).unwrap(), 1);
+        s.clear();
+        assert_eq!(replay.read_msg(&mut s).unwrap(), 1);
+        s.clear();
+        assert_eq!(replay.read_msg(&mut s).unwrap(), 0);
+    }
+
+    #[test]
+    fn zstd_pcap_detected_from_trickled_input() {
+        let compressed = zstd::encode_all(PCAP_SLL2_SINGLE_UDP_PACKET, 0).unwrap();
+        let mut reader = DogStatsDReader::new(Trickle(&compressed))
+            .expect("could create dogstatsd reader from trickled bytes");
+        let DogStatsDReader::Decompressed(ref inner, _) = reader else {
+            panic!("expected zstd input to be detected");
+        };
+        assert!(matches!(**inner, DogStatsDReader::Pcap(_)));
+
+        let mut s = String::new();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 1);
+        assert_eq!("abc.my.fav.metric:1|c|#host:foo", s);
+        s.clear();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 0);
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn read_all(reader: &mut DogStatsDReader) -> Vec<String> {
+        let mut msgs = Vec::new();
+        let mut s = String::new();
+        while reader.read_msg(&mut s).unwrap() > 0 {
+            msgs.push(s.clone());
+            s.clear();
+        }
+        msgs
+    }
+
+    #[test]
+    fn zstd_replay() {
+        let compressed = zstd::encode_all(TWO_MSGS_ONE_LINE_EACH, 0).unwrap();
+        let mut reader = DogStatsDReader::new(&compressed[..]).unwrap();
+        assert_eq!(read_all(&mut reader).len(), 2);
+        assert_eq!(reader.compression_analytics().unwrap().codec, "zstd");
+    }
+
+    #[test]
+    fn gzip_utf8() {
+        let compressed = gzip(b"my.metric:1|g\nmy.metric:2|g\n");
+        let mut reader = DogStatsDReader::new(&compressed[..]).unwrap();
+        assert_eq!(
+            read_all(&mut reader),
+            vec!["my.metric:1|g", "my.metric:2|g"]
+        );
+        assert_eq!(reader.compression_analytics().unwrap().codec, "gzip");
+    }
+
+    #[test]
+    fn gzip_replay() {
+        let compressed = gzip(TWO_MSGS_ONE_LINE_EACH);
+        let mut reader = DogStatsDReader::new(&compressed[..]).unwrap();
+        assert_eq!(
+            reader.input_description(),
+            "gzip compressed dogstatsd-replay"
+        );
+        assert_eq!(read_all(&mut reader).len(), 2);
+    }
+
+    #[test]
+    fn gzip_pcap() {
+        let compressed = gzip(PCAP_SLL2_SINGLE_UDP_PACKET);
+        let mut reader = DogStatsDReader::new(&compressed[..]).unwrap();
+        assert_eq!(
+            read_all(&mut reader),
+            vec!["abc.my.fav.metric:1|c|#host:foo"]
+        );
+    }
+
+    fn lz4(bytes: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn lz4_replay() {
+        let compressed = lz4(TWO_MSGS_ONE_LINE_EACH);
+        let mut reader = DogStatsDReader::new(&compressed[..]).unwrap();
+        assert_eq!(read_all(&mut reader).len(), 2);
+        assert_eq!(reader.compression_analytics().unwrap().codec, "lz4");
+    }
+
+    #[test]
+    fn lz4_utf8() {
+        let compressed = lz4(b"my.metric:1|g\nmy.metric:2|g\n");
+        let mut reader = DogStatsDReader::new(&compressed[..]).unwrap();
+        assert_eq!(
+            read_all(&mut reader),
+            vec!["my.metric:1|g", "my.metric:2|g"]
+        );
+    }
+
+    fn xz(bytes: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn xz_replay() {
+        let compressed = xz(TWO_MSGS_ONE_LINE_EACH);
+        let mut reader = DogStatsDReader::new(&compressed[..]).unwrap();
+        assert_eq!(read_all(&mut reader).len(), 2);
+        assert_eq!(reader.compression_analytics().unwrap().codec, "xz");
+    }
+
+    fn snappy(bytes: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+        encoder.write_all(bytes).unwrap();
+        encoder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn snappy_replay() {
+        let compressed = snappy(TWO_MSGS_ONE_LINE_EACH);
+        let mut reader = DogStatsDReader::new(&compressed[..]).unwrap();
+        assert_eq!(read_all(&mut reader).len(), 2);
+        assert_eq!(reader.compression_analytics().unwrap().codec, "snappy");
+    }
+
+    #[test]
+    fn gzip_zstd_utf8() {
+        let compressed = gzip(&zstd::encode_all(&b"my.metric:1|g\n"[..], 0).unwrap());
+        let mut reader = DogStatsDReader::new(&compressed[..]).unwrap();
+        assert_eq!(
+            reader.input_description(),
+            "gzip compressed zstd compressed utf-8 text"
+        );
+        assert_eq!(read_all(&mut reader), vec!["my.metric:1|g"]);
+
+        // analytics describe the outermost layer
+        let compression = reader.compression_analytics().unwrap();
+        assert_eq!(compression.codec, "gzip");
+        assert_eq!(compression.compressed_bytes, compressed.len() as u64);
+    }
+
+    #[test]
+    fn compression_of_several_inputs_adds_up() {
+        let gzipped = gzip(b"my.metric:1|g\n");
+        let zstd = zstd::encode_all(&b"my.metric:2|g\n"[..], 0).unwrap();
+        let mut reader = DogStatsDReader::from_readers(vec![
+            DogStatsDReader::new(&gzipped[..]).unwrap(),
+            DogStatsDReader::new(&zstd[..]).unwrap(),
+        ]);
+        assert_eq!(read_all(&mut reader).len(), 2);
+        let compression = reader.compression_analytics().unwrap();
+        assert_eq!(compression.codec, MIXED_CODECS);
+        assert_eq!(
+            compression.compressed_bytes,
+            (gzipped.len() + zstd.len()) as u64
+        );
+        assert_eq!(compression.decompressed_bytes, 28);
+
+        // and along with the analytics of packet inputs
+        let replay = gzip(TWO_MSGS_ONE_LINE_EACH);
+        let mut reader = DogStatsDReader::from_readers(vec![
+            DogStatsDReader::new(&replay[..]).unwrap(),
+            DogStatsDReader::new(&gzipped[..]).unwrap(),
+        ]);
+        assert_eq!(read_all(&mut reader).len(), 3);
+        let compression = reader.get_analytics().unwrap().unwrap().compression;
+        assert_eq!(
+            compression.unwrap().compressed_bytes,
+            (replay.len() + gzipped.len()) as u64
+        );
+    }
+
+    #[test]
+    fn zstd_length_prefix_framed() {
+        let mut framed = Vec::new();
+        for payload in ["my.metric:1|g", "my.metric:2|g\nmy.metric:3|g"] {
+            framed.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            framed.extend_from_slice(payload.as_bytes());
+        }
+        let compressed = zstd::encode_all(&framed[..], 0).unwrap();
+        let mut reader = DogStatsDReader::new(&compressed[..]).unwrap();
+        assert_eq!(
+            reader.input_description(),
+            "zstd compressed length prefix framed"
+        );
+        assert_eq!(
+            read_all(&mut reader),
+            vec!["my.metric:1|g", "my.metric:2|g", "my.metric:3|g"]
+        );
+    }
+
+    #[test]
+    fn too_many_compression_layers() {
+        let mut compressed = b"my.metric:1|g\n".to_vec();
+        for _ in 0..=MAX_COMPRESSION_LAYERS {
+            compressed = gzip(&compressed);
+        }
+        assert!(matches!(
+            DogStatsDReader::new(&compressed[..]),
+            Err(DogStatsDReaderError::UnsupportedOperation(_))
+        ));
+    }
+
+    #[test]
+    fn newer_replay_versions_can_be_forced() {
+        let mut v4 = TWO_MSGS_ONE_LINE_EACH.to_vec();
+        v4[4] = 0xF4;
+
+        let options = DogStatsDReaderOptions {
+            force_replay_version: Some(3),
+            ..Default::default()
+        };
+        let mut reader = DogStatsDReader::new_with_options(&v4[..], &options).unwrap();
+        assert!(matches!(reader, DogStatsDReader::Replay(_)));
+        let mut s = String::new();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 1);
+        assert!(s.starts_with("statsd.example.time.micros:2.39283|d"));
+
+        // only supported versions can be forced
+        let options = DogStatsDReaderOptions {
+            force_replay_version: Some(4),
+            ..Default::default()
+        };
+        assert!(matches!(
+            DogStatsDReader::new_with_options(&v4[..], &options),
+            Err(DogStatsDReaderError::Replay(
+                DogStatsDReplayReaderError::UnsupportedReplayVersion(4)
+            ))
+        ));
+    }
+
+    #[test]
+    fn msgs_over_max_msg_size_are_errors() {
+        let options = DogStatsDReaderOptions {
+            max_msg_size: 64,
+            ..Default::default()
+        };
+        let mut s = String::new();
+
+        let payload = format!("my.metric:1|g\n{}:1|g\n", "a".repeat(64));
+        let mut reader = DogStatsDReader::new_with_options(payload.as_bytes(), &options).unwrap();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 1);
+        s.clear();
+        assert!(matches!(
+            reader.read_msg(&mut s),
+            Err(DogStatsDReaderError::Io(_))
+        ));
+
+        // each packet of this replay is over 64 bytes
+        let mut reader =
+            DogStatsDReader::new_with_options(TWO_MSGS_ONE_LINE_EACH, &options).unwrap();
+        assert!(matches!(
+            reader.read_msg(&mut s),
+            Err(DogStatsDReaderError::Replay(
+                DogStatsDReplayReaderError::MsgTooLarge { max: 64, .. }
+            ))
+        ));
     }
 
     #[test]
@@ -1,10 +1,11 @@
-use std::{collections::VecDeque, io::BufRead, str::Utf8Error, time::Duration};
+use std::{collections::VecDeque, io::BufRead, ops::Range, str::Utf8Error, time::Duration};
 use thiserror::Error;
 use tracing::warn;
 
 use crate::{
     dogstatsdreader,
-    replay::{ReplayReader, ReplayReaderError},
+    framedogstatsdreader::split_frames,
+    replay::{msg_credentials, ReplayReader, ReplayReaderError, UnixCredentials},
 };
 
 pub mod dogstatsd {
@@ -13,7 +14,7 @@ pub mod dogstatsd {
     }
 }
 
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug)]
 pub enum DogStatsDReplayReaderError {
     #[error("No dogstatsd replay marker found")]
     NotAReplayFile,
@@ -21,81 +22,326 @@ pub enum DogStatsDReplayReaderError {
     UnsupportedReplayVersion(u8),
     #[error("Invalid UTF-8 sequence found in payload of msg")]
     InvalidUtf8Sequence(Utf8Error),
+    #[error("Packet of {size} bytes is larger than the max msg size of {max} bytes")]
+    MsgTooLarge { size: usize, max: usize },
+    #[error("Could not read replay file: {0}")]
+    ReplayReader(ReplayReaderError),
+    /// 'offset' is within the decompressed file for compressed inputs
+    #[error("Replay file ends mid-record at byte {offset}, after {msgs_read} msgs")]
+    Truncated { offset: u64, msgs_read: u64 },
+}
+
+/// Splits the payload of a record into its msgs, along with whether it was
+/// read from a unix stream connection. Those payloads keep their length prefixes.
+pub(crate) fn payload_lines(payload: &[u8]) -> Result<(Vec<&str>, bool), Utf8Error> {
+    let (frames, is_stream) = match split_frames(payload) {
+        Some(frames) => (frames, true),
+        None => (vec![payload], false),
+    };
+    let mut lines = Vec::new();
+    for frame in frames {
+        lines.extend(std::str::from_utf8(frame)?.lines());
+    }
+    Ok((lines, is_stream))
+}
+
+/// What was recorded along with the packet a msg was read from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayRecordMeta {
+    /// When the packet was captured, since the unix epoch
+    pub timestamp: Duration,
+    /// Pid of the sender as recorded by the agent, 0 when it wasn't known
+    pub pid: i32,
+    /// Credentials of the sender, when the ancillary data holds them
+    pub credentials: Option<UnixCredentials>,
+    /// Raw ancillary (socket control message) data of the packet
+    pub ancillary: Vec<u8>,
 }
 
 pub struct DogStatsDReplayReader<'a> {
     replay_msg_reader: ReplayReader<'a>,
     current_messages: VecDeque<String>,
+    /// The msg handed out by `read_msg_ref`
+    current_msg: String,
+    /// Metadata of the packet `current_messages` were read from
+    current_record: Option<ReplayRecordMeta>,
+    /// Only records captured within this range are read
+    time_range: Option<Range<Duration>>,
     analytics: dogstatsdreader::Analytics,
+    /// Msgs read or discarded so far
+    msgs_read: u64,
+    /// Records that msgs were read from so far
+    packets_read: u64,
+    /// Offset of the record `current_messages` were read from
+    packet_offset: u64,
+    /// Msgs the record `current_messages` were read from was split into
+    packet_msgs: u64,
 }
 
 impl<'a> DogStatsDReplayReader<'a> {
     pub fn get_analytics(&self) -> Result<dogstatsdreader::Analytics, DogStatsDReplayReaderError> {
         Ok(self.analytics.clone())
     }
+
+    /// Credentials of the process that sent the most recently read msg, if
+    /// they were captured
+    pub fn last_msg_credentials(&self) -> Option<UnixCredentials> {
+        self.current_record.as_ref().and_then(|r| r.credentials)
+    }
+
+    /// When the packet of the most recently read msg was captured
+    pub fn last_msg_timestamp(&self) -> Option<Duration> {
+        self.current_record.as_ref().map(|r| r.timestamp)
+    }
+
+    /// Everything recorded along with the packet of the most recently read
+    /// msg, eg to tell clients apart by pid
+    pub fn last_msg_record(&self) -> Option<&ReplayRecordMeta> {
+        self.current_record.as_ref()
+    }
+
+    /// Like `read_msg_ref`, along with the metadata of the packet the msg
+    /// was read from. Both are valid until the next read.
+    pub fn read_msg_with_record(
+        &mut self,
+    ) -> Result<Option<(&str, &ReplayRecordMeta)>, DogStatsDReplayReaderError> {
+        if self.read_msg_ref()?.is_none() {
+            return Ok(None);
+        }
+        Ok(self
+            .current_record
+            .as_ref()
+            .map(|record| (self.current_msg.as_str(), record)))
+    }
+
+    /// The tagger state stored after the records, see
+    /// `ReplayReader::tagger_state`. Only available once every msg was read.
+    pub fn tagger_state(
+        &mut self,
+    ) -> Result<Option<dogstatsd::unix::TaggerState>, DogStatsDReplayReaderError> {
+        self.replay_msg_reader
+            .tagger_state()
+            .map_err(DogStatsDReplayReaderError::ReplayReader)
+    }
+
     pub fn read_msg(&mut self, s: &mut String) -> Result<usize, DogStatsDReplayReaderError> {
         if let Some(line) = self.current_messages.pop_front() {
-            s.insert_str(0, &line);
-            self.analytics.total_messages += 1;
-            self.analytics.message_length.add(line.len() as f64);
+            self.record_msg(line.len());
+            dogstatsdreader::prepend_msg(s, line);
             return Ok(1);
         }
 
-        match self.replay_msg_reader.read_msg() {
-            Ok(Some(msg)) => {
-                let timestamp = match self.replay_msg_reader.version {
-                    crate::replay::CaptureFileVersion::V3 => {
-                        Duration::from_nanos(msg.timestamp as u64)
-                    }
-                    crate::replay::CaptureFileVersion::V2 => {
-                        Duration::from_secs(msg.timestamp as u64)
-                    }
-                    _ => {
-                        panic!("Unexpected version in DogStatsDReplayReader::read_msg");
-                    }
-                };
-                if self.analytics.earliest_timestamp.is_zero() {
-                    self.analytics.earliest_timestamp = timestamp;
-                } else {
-                    self.analytics.latest_timestamp = timestamp;
+        let Some(msg) = self.next_record()? else {
+            return Ok(0);
+        };
+        let (lines, is_stream) =
+            payload_lines(&msg.payload).map_err(DogStatsDReplayReaderError::InvalidUtf8Sequence)?;
+        if is_stream {
+            self.analytics.transport_type = dogstatsdreader::Transport::UnixStream;
+        }
+        self.current_messages
+            .extend(lines.into_iter().map(String::from));
+        self.packet_msgs = self.current_messages.len() as u64;
+        if self.current_messages.is_empty() {
+            // Read operation was successful, read 0 msgs
+            return Ok(0);
+        }
+
+        self.read_msg(s)
+    }
+
+    /// Reads past up to 'n' msgs and returns how many there were. Packets
+    /// that are skipped entirely are never split into owned msgs.
+    pub fn discard_msgs(&mut self, n: u64) -> Result<u64, DogStatsDReplayReaderError> {
+        let mut discarded = 0;
+        while discarded < n {
+            if let Some(line) = self.current_messages.pop_front() {
+                self.record_msg(line.len());
+                discarded += 1;
+                continue;
+            }
+            let Some(msg) = self.next_record()? else {
+                break;
+            };
+            let (lines, is_stream) = payload_lines(&msg.payload)
+                .map_err(DogStatsDReplayReaderError::InvalidUtf8Sequence)?;
+            if is_stream {
+                self.analytics.transport_type = dogstatsdreader::Transport::UnixStream;
+            }
+            if lines.len() as u64 <= n - discarded {
+                for line in &lines {
+                    self.record_msg(line.len());
                 }
-                self.analytics.total_packets += 1;
-                self.analytics.total_bytes += msg.payload.len() as u64;
-                match std::str::from_utf8(&msg.payload) {
-                    Ok(v) => {
-                        if v.is_empty() {
-                            // Read operation was successful, read 0 msgs
-                            return Ok(0);
-                        }
+                discarded += lines.len() as u64;
+            } else {
+                self.packet_msgs = lines.len() as u64;
+                self.current_messages
+                    .extend(lines.into_iter().map(String::from));
+            }
+        }
+        Ok(discarded)
+    }
 
-                        for line in v.lines() {
-                            self.current_messages.push_back(String::from(line));
-                        }
+    /// Accounts for a msg that was read or discarded
+    fn record_msg(&mut self, len: usize) {
+        self.msgs_read += 1;
+        self.analytics.total_messages += 1;
+        self.analytics.message_length.add(len as f64);
+        self.analytics
+            .record_credentials(self.last_msg_credentials());
+    }
+
+    /// Reads the next packet whole instead of msg by msg. Msgs still left
+    /// from a packet that was started with `read_msg` are skipped.
+    pub fn read_packet(
+        &mut self,
+    ) -> Result<Option<dogstatsdreader::DogStatsDPacket>, DogStatsDReplayReaderError> {
+        self.current_messages.clear();
+        let Some(msg) = self.next_record()? else {
+            return Ok(None);
+        };
+        // Records of unix stream connections keep their length prefixes
+        let transport = if split_frames(&msg.payload).is_some() {
+            self.analytics.transport_type = dogstatsdreader::Transport::UnixStream;
+            dogstatsdreader::Transport::UnixStream
+        } else {
+            dogstatsdreader::Transport::UnixDatagram
+        };
+        Ok(Some(dogstatsdreader::DogStatsDPacket {
+            payload: msg.payload,
+            timestamp: self.last_msg_timestamp(),
+            transport,
+            credentials: self.last_msg_credentials(),
+            source_addr: None,
+        }))
+    }
 
-                        self.read_msg(s)
+    /// Reads the next record within the time range and accounts for it in
+    /// the analytics
+    fn next_record(
+        &mut self,
+    ) -> Result<Option<dogstatsd::unix::UnixDogstatsdMsg>, DogStatsDReplayReaderError> {
+        loop {
+            let offset = self.replay_msg_reader.offset();
+            match self.replay_msg_reader.read_msg() {
+                Ok(Some(mut msg)) => {
+                    let timestamp = self
+                        .replay_msg_reader
+                        .version()
+                        .record_timestamp(msg.timestamp);
+                    if let Some(ref time_range) = self.time_range {
+                        if !time_range.contains(&timestamp) {
+                            continue;
+                        }
                     }
-                    Err(e) => Err(DogStatsDReplayReaderError::InvalidUtf8Sequence(e)),
+                    self.analytics
+                        .record_packet(timestamp, msg.payload.len() as u64);
+                    let credentials = msg_credentials(&msg);
+                    let mut ancillary = std::mem::take(&mut msg.ancillary);
+                    ancillary.truncate(usize::try_from(msg.ancillary_size).unwrap_or(0));
+                    self.current_record = Some(ReplayRecordMeta {
+                        timestamp,
+                        pid: msg.pid,
+                        credentials,
+                        ancillary,
+                    });
+                    self.packets_read += 1;
+                    self.packet_offset = offset;
+                    return Ok(Some(msg));
                 }
+                Ok(None) => return Ok(None), // Read was validly issued, just nothing to be read.
+                Err(ReplayReaderError::MsgTooLarge { size, max }) => {
+                    return Err(DogStatsDReplayReaderError::MsgTooLarge { size, max })
+                }
+                Err(ReplayReaderError::Truncated { offset, .. }) => {
+                    let msgs_read = self.analytics.total_messages;
+                    warn!("Replay file ends mid-record at byte {offset}, likely a truncated file. File is incomplete and processing is done after {msgs_read} msgs.");
+                    return Err(DogStatsDReplayReaderError::Truncated { offset, msgs_read });
+                }
+                Err(e) => return Err(DogStatsDReplayReaderError::ReplayReader(e)),
             }
-            Ok(None) => Ok(0), // Read was validly issued, just nothing to be read.
-            Err(ReplayReaderError::UnexpectedEof) => {
-                warn!("Encountered unexpected Eof, likely a truncated file. File is incomplete and processing is done.");
-                Ok(0)
-            }
-            Err(e) => {
-                panic!("Unexpected error from ReplayReader::read_msg: {:?}", e);
-            }
         }
     }
 
+    /// Like `read_msg`, but the msg stays in the reader, the returned slice
+    /// is valid until the next read. Saves copying every msg out.
+    pub fn read_msg_ref(&mut self) -> Result<Option<&str>, DogStatsDReplayReaderError> {
+        let mut msg = std::mem::take(&mut self.current_msg);
+        msg.clear();
+        let num_read = self.read_msg(&mut msg);
+        self.current_msg = msg;
+        Ok((num_read? > 0).then_some(self.current_msg.as_str()))
+    }
+
+    /// The msg returned by the last `read_msg_ref`
+    pub(crate) fn current_msg(&self) -> &str {
+        &self.current_msg
+    }
+
+    /// Where reading got to, see `DogStatsDReader::checkpoint`
+    pub fn checkpoint(&self) -> dogstatsdreader::ReaderCheckpoint {
+        let msgs_left = self.current_messages.len() as u64;
+        let (packet_index, byte_offset, msgs_into_packet) = if msgs_left == 0 {
+            (self.packets_read, self.replay_msg_reader.offset(), 0)
+        } else {
+            (
+                self.packets_read - 1,
+                self.packet_offset,
+                self.packet_msgs - msgs_left,
+            )
+        };
+        dogstatsdreader::ReaderCheckpoint {
+            input_type: dogstatsdreader::InputType::Replay,
+            msgs_read: self.msgs_read,
+            packet_index,
+            byte_offset: Some(byte_offset),
+            msgs_into_packet,
+        }
+    }
+
+    /// For a reader of a file that was opened at the byte offset of
+    /// 'checkpoint', rather than at its first record
+    pub(crate) fn resume_at(&mut self, checkpoint: &dogstatsdreader::ReaderCheckpoint) {
+        if let Some(offset) = checkpoint.byte_offset {
+            self.replay_msg_reader
+                .resume_at(offset, checkpoint.packet_index);
+        }
+        self.packets_read = checkpoint.packet_index;
+        self.msgs_read = checkpoint
+            .msgs_read
+            .saturating_sub(checkpoint.msgs_into_packet);
+    }
+
     pub fn new(buf: impl BufRead + 'a) -> Result<Self, DogStatsDReplayReaderError> {
-        match ReplayReader::new(buf) {
+        Self::from_replay_reader(ReplayReader::new(buf))
+    }
+
+    /// Like `new`, but files of an unsupported version are decoded as
+    /// 'version', see `ReplayReader::new_forcing_version`
+    pub fn new_forcing_version(
+        buf: impl BufRead + 'a,
+        version: u8,
+    ) -> Result<Self, DogStatsDReplayReaderError> {
+        Self::from_replay_reader(ReplayReader::new_forcing_version(buf, version))
+    }
+
+    fn from_replay_reader(
+        reader: Result<ReplayReader<'a>, ReplayReaderError>,
+    ) -> Result<Self, DogStatsDReplayReaderError> {
+        match reader {
             Ok(reader) => Ok(DogStatsDReplayReader {
                 replay_msg_reader: reader,
                 current_messages: VecDeque::new(),
+                current_msg: String::new(),
+                current_record: None,
+                time_range: None,
                 analytics: dogstatsdreader::Analytics::new(
                     dogstatsdreader::Transport::UnixDatagram,
                 ),
+                msgs_read: 0,
+                packets_read: 0,
+                packet_offset: 0,
+                packet_msgs: 0,
             }),
             Err(e) => match e {
                 ReplayReaderError::NotAReplayFile => {
@@ -104,12 +350,24 @@ impl<'a> DogStatsDReplayReader<'a> {
                 ReplayReaderError::UnsupportedReplayVersion(e) => {
                     Err(DogStatsDReplayReaderError::UnsupportedReplayVersion(e))
                 }
-                _ => {
-                    panic!("Unexpected error from ReplayReader::new: {:?}", e);
-                }
+                e => Err(DogStatsDReplayReaderError::ReplayReader(e)),
             },
         }
     }
+
+    /// Packets larger than 'max_msg_size' are an error, see `ReplayReader::with_max_msg_size`
+    pub fn with_max_msg_size(mut self, max_msg_size: usize) -> Self {
+        self.replay_msg_reader = self.replay_msg_reader.with_max_msg_size(max_msg_size);
+        self
+    }
+
+    /// Only reads records captured at or after 'start' and before 'end', both
+    /// since the unix epoch. Records outside of it are skipped and left out
+    /// of the analytics.
+    pub fn with_time_range(mut self, start: Duration, end: Duration) -> Self {
+        self.time_range = Some(start..end);
+        self
+    }
 }
 
 #[cfg(test)]
@@ -232,4 +490,180 @@ mod tests {
         let res = replay.read_msg(&mut s).unwrap();
         assert_eq!(res, 0);
     }
+
+    #[test]
+    fn truncated_files_are_an_error_after_the_complete_records() {
+        // The second record starts at byte 159 and is cut short
+        let mut replay = DogStatsDReplayReader::new(&TWO_MSGS_ONE_LINE_EACH[..200]).unwrap();
+        let mut s = String::new();
+        assert_eq!(replay.read_msg(&mut s).unwrap(), 1);
+        assert!(matches!(
+            replay.read_msg(&mut s),
+            Err(DogStatsDReplayReaderError::Truncated {
+                offset: 159,
+                msgs_read: 1
+            })
+        ));
+        assert_eq!(replay.read_msg(&mut s).unwrap(), 0);
+        assert_eq!(replay.tagger_state().unwrap(), None);
+
+        // Cut right after a record, without the record separator
+        let cut = TWO_MSGS_ONE_LINE_EACH.len() - 8;
+        let mut replay = DogStatsDReplayReader::new(&TWO_MSGS_ONE_LINE_EACH[..cut]).unwrap();
+        assert_eq!(replay.read_msg(&mut s).unwrap(), 1);
+        assert_eq!(replay.read_msg(&mut s).unwrap(), 1);
+        assert!(matches!(
+            replay.read_msg(&mut s),
+            Err(DogStatsDReplayReaderError::Truncated { msgs_read: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn credentials_are_attributed_to_msgs() {
+        // SCM_CREDENTIALS cmsg for pid 4242, uid 1000, gid 100
+        const CREDENTIALS_CMSG: &[u8] = &[
+            0x1c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00,
+            0x00, 0x00, 0x92, 0x10, 0x00, 0x00, 0xe8, 0x03, 0x00, 0x00, 0x64, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut writer = crate::replay::ReplayWriter::new(Vec::new()).unwrap();
+        writer
+            .write_msg(&dogstatsd::unix::UnixDogstatsdMsg {
+                timestamp: 1,
+                payload: b"a:1|c\nb:1|c\n".to_vec(),
+                ancillary: CREDENTIALS_CMSG.to_vec(),
+                ancillary_size: CREDENTIALS_CMSG.len() as i32,
+                ..Default::default()
+            })
+            .unwrap();
+        writer
+            .write_msg(&dogstatsd::unix::UnixDogstatsdMsg {
+                timestamp: 2,
+                payload: b"c:1|c\n".to_vec(),
+                ..Default::default()
+            })
+            .unwrap();
+        let replay_file = writer.finish(&[]).unwrap();
+
+        let mut replay = DogStatsDReplayReader::new(replay_file.as_slice()).unwrap();
+        let expected = UnixCredentials {
+            pid: 4242,
+            uid: 1000,
+            gid: 100,
+        };
+        let mut s = String::new();
+        for _ in 0..2 {
+            s.clear();
+            assert_eq!(replay.read_msg(&mut s).unwrap(), 1);
+            assert_eq!(replay.last_msg_credentials(), Some(expected));
+        }
+        s.clear();
+        assert_eq!(replay.read_msg(&mut s).unwrap(), 1);
+        assert_eq!(replay.last_msg_credentials(), None);
+        assert_eq!(
+            replay.last_msg_record().unwrap().timestamp,
+            Duration::from_nanos(2)
+        );
+
+        let analytics = replay.get_analytics().unwrap();
+        assert_eq!(analytics.msgs_per_uid.get(&1000), Some(&2));
+        assert_eq!(analytics.msgs_per_pid.get(&4242), Some(&2));
+        assert_eq!(analytics.msgs_without_credentials, 1);
+    }
+
+    #[test]
+    fn msgs_are_read_with_their_record() {
+        let mut writer = crate::replay::ReplayWriter::new(Vec::new()).unwrap();
+        for (pid, payload) in [(10, "a:1|c\nb:1|c\n"), (20, "c:1|c\n")] {
+            writer
+                .write_msg(&dogstatsd::unix::UnixDogstatsdMsg {
+                    timestamp: pid as i64,
+                    payload: payload.as_bytes().to_vec(),
+                    pid,
+                    ancillary: vec![1, 2, 3, 0, 0],
+                    ancillary_size: 3,
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let replay_file = writer.finish(&[]).unwrap();
+
+        let mut replay = DogStatsDReplayReader::new(replay_file.as_slice()).unwrap();
+        let mut msgs = Vec::new();
+        while let Some((msg, record)) = replay.read_msg_with_record().unwrap() {
+            assert_eq!(record.timestamp, Duration::from_nanos(record.pid as u64));
+            assert_eq!(record.ancillary, [1, 2, 3]);
+            assert_eq!(record.credentials, None);
+            msgs.push((msg.to_string(), record.pid));
+        }
+        assert_eq!(
+            msgs,
+            [
+                ("a:1|c".to_string(), 10),
+                ("b:1|c".to_string(), 10),
+                ("c:1|c".to_string(), 20)
+            ]
+        );
+    }
+
+    #[test]
+    fn unix_stream_payloads_are_unframed() {
+        let mut payload = Vec::new();
+        for frame in ["a:1|c", "b:1|c\nc:1|c\n"] {
+            payload.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            payload.extend_from_slice(frame.as_bytes());
+        }
+        let mut writer = crate::replay::ReplayWriter::new(Vec::new()).unwrap();
+        writer
+            .write_msg(&dogstatsd::unix::UnixDogstatsdMsg {
+                timestamp: 1,
+                payload,
+                ..Default::default()
+            })
+            .unwrap();
+        let replay_file = writer.finish(&[]).unwrap();
+
+        let mut replay = DogStatsDReplayReader::new(replay_file.as_slice()).unwrap();
+        let mut msgs = Vec::new();
+        let mut s = String::new();
+        while replay.read_msg(&mut s).unwrap() > 0 {
+            msgs.push(s.clone());
+            s.clear();
+        }
+        assert_eq!(msgs, ["a:1|c", "b:1|c", "c:1|c"]);
+        assert!(matches!(
+            replay.get_analytics().unwrap().transport_type,
+            dogstatsdreader::Transport::UnixStream
+        ));
+    }
+
+    #[test]
+    fn records_outside_of_time_range_are_skipped() {
+        // The two records were captured at 1692823177.48s and 1692823178.27s
+        let mut replay = DogStatsDReplayReader::new(TWO_MSGS_ONE_LINE_EACH)
+            .unwrap()
+            .with_time_range(Duration::from_secs(1692823178), Duration::MAX);
+        let mut s = String::new();
+        assert_eq!(replay.read_msg(&mut s).unwrap(), 1);
+        assert_eq!(
+            replay.last_msg_timestamp(),
+            Some(Duration::from_nanos(1692823178271749279))
+        );
+        s.clear();
+        assert_eq!(replay.read_msg(&mut s).unwrap(), 0);
+        assert_eq!(replay.get_analytics().unwrap().total_packets, 1);
+    }
+
+    #[test]
+    fn discarding_can_stop_within_a_packet() {
+        let mut replay = DogStatsDReplayReader::new(ONE_MSG_THREE_LINES).unwrap();
+        assert_eq!(replay.discard_msgs(2).unwrap(), 2);
+        let mut s = String::new();
+        assert_eq!(replay.read_msg(&mut s).unwrap(), 1);
+        assert_eq!("statsd.other.metric:7|c|@1.000000|#environment:dev", s);
+
+        let mut replay = DogStatsDReplayReader::new(TWO_MSGS_ONE_LINE_EACH).unwrap();
+        assert_eq!(replay.discard_msgs(5).unwrap(), 2);
+        assert_eq!(replay.get_analytics().unwrap().total_messages, 2);
+    }
 }
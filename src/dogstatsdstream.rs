@@ -0,0 +1,117 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use thiserror::Error;
+
+use crate::{
+    dogstatsdmsg::{DogStatsDMsg, DogStatsDMsgError, DogStatsDMsgOwned},
+    dogstatsdreader::{DogStatsDReader, DogStatsDReaderError},
+};
+
+#[derive(Error, Debug)]
+pub enum DogStatsDStreamError {
+    #[error("Could not read dogstatsd from provided source")]
+    Reader(#[from] DogStatsDReaderError),
+    #[error("Could not parse msg")]
+    Parse(#[from] DogStatsDMsgError),
+}
+
+/// The msgs of a `DogStatsDReader` as a `Stream`, so captures can be plugged
+/// into async pipelines. A msg that doesn't parse is an error item and the
+/// stream goes on, a read error is the last item.
+///
+/// Msgs are read on the polling task, which suits captures on disk. Live
+/// inputs that may have to wait for msgs are better read with
+/// `AsyncDogStatsDReader`.
+pub struct DogStatsDStream<'a> {
+    reader: DogStatsDReader<'a>,
+    line: String,
+    done: bool,
+}
+
+impl<'a> DogStatsDStream<'a> {
+    pub fn new(reader: DogStatsDReader<'a>) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            done: false,
+        }
+    }
+
+    /// Gives the reader back, eg for its analytics once the stream ended
+    pub fn into_inner(self) -> DogStatsDReader<'a> {
+        self.reader
+    }
+}
+
+impl Stream for DogStatsDStream<'_> {
+    type Item = Result<DogStatsDMsgOwned, DogStatsDStreamError>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        this.line.clear();
+        match this.reader.read_msg(&mut this.line) {
+            Ok(0) => {
+                this.done = true;
+                Poll::Ready(None)
+            }
+            Ok(_) => Poll::Ready(Some(
+                DogStatsDMsg::new(&this.line)
+                    .map(|msg| DogStatsDMsgOwned::from(&msg))
+                    .map_err(DogStatsDStreamError::from),
+            )),
+            Err(e) => {
+                this.done = true;
+                Poll::Ready(Some(Err(e.into())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        task::{Wake, Waker},
+    };
+
+    use super::*;
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// The stream never returns Pending, so polling it until it ends is enough
+    fn collect(
+        mut stream: DogStatsDStream,
+    ) -> Vec<Result<DogStatsDMsgOwned, DogStatsDStreamError>> {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mut items = Vec::new();
+        while let Poll::Ready(Some(item)) = Pin::new(&mut stream).poll_next(&mut cx) {
+            items.push(item);
+        }
+        items
+    }
+
+    #[test]
+    fn streams_owned_msgs() {
+        let input: &[u8] = b"my.metric:1|g\nnot a msg\n_sc|my.check|0\n";
+        let items = collect(DogStatsDStream::new(DogStatsDReader::new(input).unwrap()));
+        assert_eq!(items.len(), 3);
+        assert!(matches!(
+            &items[0],
+            Ok(DogStatsDMsgOwned::Metric(metric)) if metric.name == "my.metric"
+        ));
+        assert!(matches!(items[1], Err(DogStatsDStreamError::Parse(_))));
+        assert!(matches!(items[2], Ok(DogStatsDMsgOwned::ServiceCheck(_))));
+    }
+}
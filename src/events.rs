@@ -0,0 +1,173 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use chrono::{TimeZone, Utc};
+use regex::Regex;
+
+use crate::dogstatsdmsg::{DogStatsDEventStr, EventAlert};
+
+/// Priority of an event, events without a `p:` field are normal priority
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventPriority {
+    Normal,
+    Low,
+}
+
+impl EventPriority {
+    pub fn of(event: &DogStatsDEventStr) -> Self {
+        match event.priority {
+            Some("low") => EventPriority::Low,
+            _ => EventPriority::Normal,
+        }
+    }
+}
+
+/// Selects events, every filter that is set must match
+#[derive(Debug, Default)]
+pub struct EventFilter {
+    pub title: Option<Regex>,
+    pub text: Option<Regex>,
+    pub priority: Option<EventPriority>,
+}
+
+impl EventFilter {
+    /// 'text' is matched against the unescaped text, so patterns can span lines
+    pub fn matches(&self, event: &DogStatsDEventStr) -> bool {
+        if let Some(ref title) = self.title {
+            if !title.is_match(event.title) {
+                return false;
+            }
+        }
+        if let Some(ref text) = self.text {
+            if !text.is_match(&unescape_text(event.text)) {
+                return false;
+            }
+        }
+        match self.priority {
+            Some(priority) => EventPriority::of(event) == priority,
+            None => true,
+        }
+    }
+}
+
+/// Newlines in event text are sent as a literal `\n` on the wire
+pub fn unescape_text(text: &str) -> Cow<str> {
+    if text.contains("\\n") {
+        Cow::Owned(text.replace("\\n", "\n"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Renders an event for humans: a header line with the resolved timestamp,
+/// alert type and title, the remaining fields, then the unescaped text
+/// indented below. Ends with a blank line so consecutive events stay apart.
+pub fn render_event(event: &DogStatsDEventStr) -> String {
+    let timestamp = match event
+        .timestamp
+        .and_then(|ts| Utc.timestamp_opt(ts as i64, 0).single())
+    {
+        Some(datetime) => datetime.to_rfc3339(),
+        None => "no timestamp".to_string(),
+    };
+    let alert = match event.alert_type {
+        EventAlert::Error => "ERROR",
+        EventAlert::Warning => "WARNING",
+        EventAlert::Info => "INFO",
+        EventAlert::Success => "SUCCESS",
+    };
+    let priority = match EventPriority::of(event) {
+        EventPriority::Normal => "normal",
+        EventPriority::Low => "low",
+    };
+
+    let mut out = String::new();
+    // writing to a String can't fail
+    let _ = writeln!(out, "[{}] {} {}", timestamp, alert, event.title);
+    let _ = write!(out, "  priority: {}", priority);
+    if let Some(hostname) = event.hostname {
+        let _ = write!(out, "  host: {}", hostname);
+    }
+    if let Some(source_type_name) = event.source_type_name {
+        let _ = write!(out, "  source: {}", source_type_name);
+    }
+    if let Some(aggregation_key) = event.aggregation_key {
+        let _ = write!(out, "  aggregation_key: {}", aggregation_key);
+    }
+    out.push('\n');
+    if !event.tags.is_empty() {
+        let _ = writeln!(out, "  tags: {}", event.tags.join(", "));
+    }
+    for line in unescape_text(event.text).lines() {
+        let _ = writeln!(out, "    {}", line);
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dogstatsdmsg::DogStatsDMsg;
+
+    fn event(msg: &str) -> DogStatsDEventStr {
+        match DogStatsDMsg::new(msg).unwrap() {
+            DogStatsDMsg::Event(e) => e,
+            _ => panic!("not an event"),
+        }
+    }
+
+    #[test]
+    fn renders_unescaped_text_and_timestamp() {
+        let e = event(concat!(
+            "_e{12,26}:deploy done!|rolled out\\nv1.2.3 to prod",
+            "|d:1700000000|h:web-1|t:success|#env:prod,team:a"
+        ));
+        assert_eq!(
+            render_event(&e),
+            "[2023-11-14T22:13:20+00:00] SUCCESS deploy done!
+  priority: normal  host: web-1
+  tags: env:prod, team:a
+    rolled out
+    v1.2.3 to prod
+
+"
+        );
+
+        let e = event("_e{5,4}:title|text|p:low");
+        assert_eq!(
+            render_event(&e),
+            "[no timestamp] INFO title\n  priority: low\n    text\n\n"
+        );
+    }
+
+    #[test]
+    fn filters() {
+        let deploy = event("_e{6,17}:deploy|step 1\\nstep 2 ok|p:low");
+        let outage = event("_e{6,4}:outage|oops|t:error");
+
+        let all = EventFilter::default();
+        assert!(all.matches(&deploy) && all.matches(&outage));
+
+        let by_title = EventFilter {
+            title: Some(Regex::new("^out").unwrap()),
+            ..Default::default()
+        };
+        assert!(!by_title.matches(&deploy));
+        assert!(by_title.matches(&outage));
+
+        let by_text = EventFilter {
+            text: Some(Regex::new("(?m)^step 2").unwrap()),
+            ..Default::default()
+        };
+        assert!(by_text.matches(&deploy));
+        assert!(!by_text.matches(&outage));
+
+        let by_priority = EventFilter {
+            priority: Some(EventPriority::Normal),
+            ..Default::default()
+        };
+        assert!(!by_priority.matches(&deploy));
+        assert!(by_priority.matches(&outage));
+    }
+}
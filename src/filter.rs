@@ -0,0 +1,96 @@
+use regex::Regex;
+
+use crate::dogstatsdmsg::{DogStatsDMsg, DogStatsDMsgKind};
+
+/// Selects msgs, every filter that is set must match. Applied by
+/// `DogStatsDReader::with_filter` so every tool filters the same way.
+#[derive(Debug, Default, Clone)]
+pub struct MsgFilter {
+    /// Matched against metric and service check names and event titles
+    pub name: Option<Regex>,
+    /// Tags the msg must carry, all of them. `key:value` only matches that
+    /// exact tag, a bare `key` matches the tag `key` and any `key:` tag.
+    pub tags: Vec<String>,
+    /// Kinds of msgs to keep, every kind when empty
+    pub kinds: Vec<DogStatsDMsgKind>,
+}
+
+impl MsgFilter {
+    /// True when no filter is set and every msg matches
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.tags.is_empty() && self.kinds.is_empty()
+    }
+
+    pub fn matches(&self, msg: &DogStatsDMsg) -> bool {
+        if !self.kinds.is_empty() && !self.kinds.contains(&msg.kind()) {
+            return false;
+        }
+        let (name, tags) = match msg {
+            DogStatsDMsg::Metric(m) => (m.name, &m.tags[..]),
+            DogStatsDMsg::Event(e) => (e.title, &e.tags[..]),
+            DogStatsDMsg::ServiceCheck(sc) => (sc.name, &sc.tags[..]),
+        };
+        if let Some(ref pattern) = self.name {
+            if !pattern.is_match(name) {
+                return false;
+            }
+        }
+        self.tags
+            .iter()
+            .all(|wanted| tags.iter().any(|tag| tag_matches(wanted, tag)))
+    }
+
+    /// Like `matches` for a msg that wasn't parsed yet. A msg that doesn't
+    /// parse can't be told apart and only passes an empty filter.
+    pub fn matches_raw(&self, msg: &str) -> bool {
+        self.is_empty() || DogStatsDMsg::new(msg).is_ok_and(|msg| self.matches(&msg))
+    }
+}
+
+fn tag_matches(wanted: &str, tag: &str) -> bool {
+    if wanted.contains(':') {
+        return tag == wanted;
+    }
+    match tag.split_once(':') {
+        Some((key, _)) => key == wanted,
+        None => tag == wanted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_filter_that_is_set_must_match() {
+        let metric = "web.requests:1|c|#env:prod,team:a,canary";
+        let event = "_e{6,4}:deploy|done|#env:prod";
+        let check = "_sc|web.up|0|#env:staging";
+
+        let all = MsgFilter::default();
+        assert!([metric, event, check, "not a msg"]
+            .iter()
+            .all(|msg| all.matches_raw(msg)));
+
+        let web = MsgFilter {
+            name: Some(Regex::new("^web\\.").unwrap()),
+            ..Default::default()
+        };
+        assert!(web.matches_raw(metric) && web.matches_raw(check));
+        assert!(!web.matches_raw(event) && !web.matches_raw("not a msg"));
+
+        let prod = MsgFilter {
+            tags: vec!["env:prod".to_string(), "canary".to_string()],
+            ..Default::default()
+        };
+        assert!(prod.matches_raw(metric));
+        assert!(!prod.matches_raw(event) && !prod.matches_raw(check));
+
+        let team_events = MsgFilter {
+            tags: vec!["team".to_string()],
+            kinds: vec![DogStatsDMsgKind::Event],
+            ..Default::default()
+        };
+        assert!(!team_events.matches_raw(metric) && !team_events.matches_raw(event));
+    }
+}
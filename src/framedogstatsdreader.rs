@@ -0,0 +1,283 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, ErrorKind, Read},
+    str::Utf8Error,
+};
+
+use thiserror::Error;
+use tracing::warn;
+
+use crate::dogstatsdreader::{DogStatsDPacket, Transport, DEFAULT_MAX_MSG_SIZE};
+
+/// Every frame starts with its length as a little-endian u32
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum FramedDogStatsDReaderError {
+    #[error("Invalid UTF-8 sequence found in payload of frame")]
+    InvalidUtf8Sequence(Utf8Error),
+    #[error("Frame of {size} bytes is larger than the max msg size of {max} bytes")]
+    MsgTooLarge { size: usize, max: usize },
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+}
+
+/// Whether 'header' is the start of a length prefix framed stream, as written
+/// by lading's `length_prefix_framed` option. The length of a sane frame has
+/// a NUL in its top byte, which text never does, and is followed by utf-8
+/// text, spaces and newlines included. 'header' may end in the middle of a
+/// character.
+pub fn is_length_prefix_framed(header: &[u8]) -> bool {
+    let Some(prefix) = header.get(..LENGTH_PREFIX_SIZE) else {
+        return false;
+    };
+    if prefix[LENGTH_PREFIX_SIZE - 1] != 0 {
+        return false;
+    }
+    let len = u32::from_le_bytes(prefix.try_into().expect("prefix is 4 bytes")) as usize;
+    if len == 0 || len > DEFAULT_MAX_MSG_SIZE {
+        return false;
+    }
+    let payload = &header[LENGTH_PREFIX_SIZE..header.len().min(LENGTH_PREFIX_SIZE + len)];
+    let text = match std::str::from_utf8(payload) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&payload[..e.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return false,
+    };
+    !text.chars().any(|c| c.is_control() && !c.is_whitespace())
+}
+
+/// Splits 'payload' into the payloads of its frames when it holds nothing but
+/// whole length prefixed frames, as recorded for unix stream connections. The
+/// first four bytes of text read as a length far beyond the payload itself.
+pub fn split_frames(payload: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut frames = Vec::new();
+    let mut rest = payload;
+    while !rest.is_empty() {
+        let prefix = rest.get(..LENGTH_PREFIX_SIZE)?;
+        let len = u32::from_le_bytes(prefix.try_into().expect("prefix is 4 bytes")) as usize;
+        if len == 0 {
+            return None;
+        }
+        frames.push(rest.get(LENGTH_PREFIX_SIZE..LENGTH_PREFIX_SIZE + len)?);
+        rest = &rest[LENGTH_PREFIX_SIZE + len..];
+    }
+    (!frames.is_empty()).then_some(frames)
+}
+
+/// Reads msgs from a stream of length prefixed frames, each frame holds one
+/// or more newline separated msgs
+pub struct FramedDogStatsDReader<'a> {
+    reader: Box<dyn BufRead + 'a>,
+    current_messages: VecDeque<String>,
+    /// The msg handed out by `read_msg_ref`
+    current_msg: String,
+    frame: Vec<u8>,
+    max_msg_size: usize,
+}
+
+impl<'a> FramedDogStatsDReader<'a> {
+    pub fn new(reader: impl BufRead + 'a) -> Self {
+        FramedDogStatsDReader {
+            reader: Box::new(reader),
+            current_messages: VecDeque::new(),
+            current_msg: String::new(),
+            frame: Vec::new(),
+            max_msg_size: DEFAULT_MAX_MSG_SIZE,
+        }
+    }
+
+    /// Frames longer than 'max_msg_size' are an error rather than being read
+    /// into memory
+    pub fn with_max_msg_size(mut self, max_msg_size: usize) -> Self {
+        self.max_msg_size = max_msg_size;
+        self
+    }
+
+    /// Reads the next frame into `self.frame`, false at the end of the stream
+    fn read_frame(&mut self) -> Result<bool, FramedDogStatsDReaderError> {
+        // The stream can only end cleanly between frames
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let mut prefix = [0; LENGTH_PREFIX_SIZE];
+        if !self.read_exact_or_truncated(&mut prefix)? {
+            return Ok(false);
+        }
+        let len = u32::from_le_bytes(prefix) as usize;
+        if len > self.max_msg_size {
+            return Err(FramedDogStatsDReaderError::MsgTooLarge {
+                size: len,
+                max: self.max_msg_size,
+            });
+        }
+        let mut frame = std::mem::take(&mut self.frame);
+        frame.resize(len, 0);
+        let complete = self.read_exact_or_truncated(&mut frame)?;
+        self.frame = frame;
+        Ok(complete)
+    }
+
+    fn read_exact_or_truncated(&mut self, buf: &mut [u8]) -> Result<bool, io::Error> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                warn!("Encountered unexpected Eof, likely a truncated stream. Stream is incomplete and processing is done.");
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn read_msg(&mut self, s: &mut String) -> Result<usize, FramedDogStatsDReaderError> {
+        loop {
+            if let Some(line) = self.current_messages.pop_front() {
+                if s.is_empty() {
+                    *s = line;
+                } else {
+                    s.push_str(&line);
+                }
+                return Ok(1);
+            }
+            if !self.read_frame()? {
+                return Ok(0);
+            }
+            let payload = std::str::from_utf8(&self.frame)
+                .map_err(FramedDogStatsDReaderError::InvalidUtf8Sequence)?;
+            self.current_messages.extend(
+                payload
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(String::from),
+            );
+        }
+    }
+
+    /// Reads the next frame whole instead of msg by msg, without its length
+    /// prefix. Msgs still left from a frame that was started with `read_msg`
+    /// are skipped.
+    pub fn read_packet(&mut self) -> Result<Option<DogStatsDPacket>, FramedDogStatsDReaderError> {
+        self.current_messages.clear();
+        if !self.read_frame()? {
+            return Ok(None);
+        }
+        Ok(Some(DogStatsDPacket {
+            payload: std::mem::take(&mut self.frame),
+            timestamp: None,
+            transport: Transport::UnixStream,
+            credentials: None,
+            source_addr: None,
+        }))
+    }
+
+    /// Like `read_msg`, but the msg stays in the reader, the returned slice
+    /// is valid until the next read. Saves copying every msg out.
+    pub fn read_msg_ref(&mut self) -> Result<Option<&str>, FramedDogStatsDReaderError> {
+        let mut msg = std::mem::take(&mut self.current_msg);
+        msg.clear();
+        let num_read = self.read_msg(&mut msg);
+        self.current_msg = msg;
+        Ok((num_read? > 0).then_some(self.current_msg.as_str()))
+    }
+
+    /// The msg returned by the last `read_msg_ref`
+    pub(crate) fn current_msg(&self) -> &str {
+        &self.current_msg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framed(payloads: &[&str]) -> Vec<u8> {
+        let mut stream = Vec::new();
+        for payload in payloads {
+            stream.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            stream.extend_from_slice(payload.as_bytes());
+        }
+        stream
+    }
+
+    fn read_all(reader: &mut FramedDogStatsDReader) -> Vec<String> {
+        let mut msgs = Vec::new();
+        let mut line = String::new();
+        while reader.read_msg(&mut line).unwrap() > 0 {
+            msgs.push(line.clone());
+            line.clear();
+        }
+        msgs
+    }
+
+    #[test]
+    fn frames_are_detected() {
+        assert!(is_length_prefix_framed(&framed(&["my.metric:1|c"])));
+        assert!(!is_length_prefix_framed(b"my.metric:1|c\n"));
+        assert!(!is_length_prefix_framed(b"\0\0\0\0a:1|c"));
+        // names may hold spaces, the detection header ends right after one
+        assert!(is_length_prefix_framed(&framed(&["a b:1|c"])[..8]));
+        assert!(is_length_prefix_framed(&framed(&["a:1|c\n"])));
+        // a length whose top byte isn't NUL is text
+        assert!(!is_length_prefix_framed(b"\x05\0\0\x01a:1|c"));
+        assert!(!is_length_prefix_framed(b"\x05\0\0\0\x01:1|c"));
+        // tags may hold any unicode, even cut off by the detection header
+        let unicode = framed(&["a:1|c|#café:crème"]);
+        assert!(is_length_prefix_framed(&unicode));
+        assert!(is_length_prefix_framed(&unicode[..LENGTH_PREFIX_SIZE + 11]));
+        assert!(!is_length_prefix_framed(b"\x05\0\0\0a:\xff|c"));
+    }
+
+    #[test]
+    fn payloads_are_split_into_frames() {
+        let payload = framed(&["a:1|c", "b:2|g\nc:3|g\n"]);
+        assert_eq!(
+            split_frames(&payload),
+            Some(vec![b"a:1|c".as_slice(), b"b:2|g\nc:3|g\n".as_slice()])
+        );
+        assert_eq!(split_frames(b"a:1|c\nb:2|g"), None);
+        assert_eq!(split_frames(&payload[..payload.len() - 1]), None);
+        assert_eq!(split_frames(b""), None);
+    }
+
+    #[test]
+    fn frames_hold_one_or_more_msgs() {
+        let stream = framed(&["my.metric:1|c", "a:1|g\nb:2|g\n", "_sc|my.check|0"]);
+        let mut reader = FramedDogStatsDReader::new(stream.as_slice());
+        assert_eq!(
+            read_all(&mut reader),
+            vec!["my.metric:1|c", "a:1|g", "b:2|g", "_sc|my.check|0"]
+        );
+    }
+
+    #[test]
+    fn frames_are_read_whole_as_packets() {
+        let stream = framed(&["a:1|g\nb:2|g", "_sc|my.check|0"]);
+        let mut reader = FramedDogStatsDReader::new(stream.as_slice());
+        let mut s = String::new();
+        reader.read_msg(&mut s).unwrap();
+        assert_eq!(s, "a:1|g");
+
+        // The rest of the first frame is skipped
+        let packet = reader.read_packet().unwrap().unwrap();
+        assert_eq!(packet.payload, b"_sc|my.check|0");
+        assert!(matches!(packet.transport, Transport::UnixStream));
+        assert!(reader.read_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_and_oversized_frames() {
+        let mut stream = framed(&["my.metric:1|c", "my.metric:2|c"]);
+        stream.truncate(stream.len() - 3);
+        let mut reader = FramedDogStatsDReader::new(stream.as_slice());
+        assert_eq!(read_all(&mut reader), vec!["my.metric:1|c"]);
+
+        let stream = framed(&["my.metric:1|c"]);
+        let mut reader = FramedDogStatsDReader::new(stream.as_slice()).with_max_msg_size(8);
+        assert!(matches!(
+            reader.read_msg(&mut String::new()),
+            Err(FramedDogStatsDReaderError::MsgTooLarge { size: 13, max: 8 })
+        ));
+    }
+}
@@ -0,0 +1,34 @@
+// https://www.rfc-editor.org/rfc/rfc1952#page-6
+// ID1, ID2 followed by CM (8 = deflate, the only method in use)
+const GZIP_MAGIC_BYTES: [u8; 3] = [0x1F, 0x8B, 0x08];
+
+pub fn is_gzip(header: &[u8]) -> bool {
+    header.len() >= GZIP_MAGIC_BYTES.len() && header[0..3] == GZIP_MAGIC_BYTES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // echo -n "hello" | gzip -n | xxd -i
+    const HELLO_GZIP_BYTES: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x07, 0x00, 0x86, 0xa6, 0x10, 0x36, 0x05, 0x00, 0x00, 0x00,
+    ];
+
+    const HELLO_BYTES: &[u8] = &[0x68, 0x65, 0x6c, 0x6c, 0x6f];
+
+    #[test]
+    fn is_gzip_compressed_data_is_detected() {
+        assert!(is_gzip(HELLO_GZIP_BYTES));
+    }
+
+    #[test]
+    fn is_gzip_ascii_data_is_not_detected() {
+        assert!(!is_gzip(HELLO_BYTES));
+    }
+
+    #[test]
+    fn is_gzip_short_data_is_not_detected() {
+        assert!(!is_gzip(&[0x1f]));
+    }
+}
@@ -0,0 +1,75 @@
+use std::{collections::HashMap, rc::Rc};
+
+/// Stable id of a string interned by a `DogStatsDInterner`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Deduplicates metric names and tags so each distinct string is stored once,
+/// no matter how many msgs it appears in. Ids are handed out in the order
+/// strings are first seen and never change.
+#[derive(Debug, Default)]
+pub struct DogStatsDInterner {
+    ids: HashMap<Rc<str>, Symbol>,
+    strings: Vec<Rc<str>>,
+}
+
+impl DogStatsDInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Id of 's', allocating only the first time 's' is seen
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        if let Some(id) = self.ids.get(s) {
+            return *id;
+        }
+        let id =
+            Symbol(u32::try_from(self.strings.len()).expect("More than u32::MAX interned strings"));
+        let s: Rc<str> = Rc::from(s);
+        self.strings.push(Rc::clone(&s));
+        self.ids.insert(s, id);
+        id
+    }
+
+    /// Id of 's' if it has been interned
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.ids.get(s).copied()
+    }
+
+    pub fn resolve(&self, id: Symbol) -> &str {
+        &self.strings[id.index()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_stable() {
+        let mut interner = DogStatsDInterner::new();
+        let env = interner.intern("env:prod");
+        let name = interner.intern("my.metric");
+        assert_ne!(env, name);
+        assert_eq!(interner.intern("env:prod"), env);
+        assert_eq!(interner.get("my.metric"), Some(name));
+        assert_eq!(interner.get("env:staging"), None);
+        assert_eq!(interner.resolve(env), "env:prod");
+        assert_eq!(interner.resolve(name), "my.metric");
+        assert_eq!(interner.len(), 2);
+    }
+}
@@ -1,17 +1,103 @@
+//! Without the `std` feature, which the default `full` feature turns on, only
+//! `dogstatsdmsg`, the msg parser, is built, as `no_std` with `alloc`.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "full")]
 use tracing::level_filters::LevelFilter;
+#[cfg(feature = "full")]
 use tracing_subscriber::{fmt, layer::SubscriberExt as _, util::SubscriberInitExt as _, EnvFilter};
 
+#[cfg(feature = "full")]
+pub mod addrfilter;
+#[cfg(feature = "full")]
 pub mod analysis;
+#[cfg(feature = "full")]
+pub mod analyzers;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary_msgs;
+#[cfg(feature = "full")]
+pub mod archive;
+#[cfg(feature = "full")]
+pub mod asyncdogstatsdreader;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "full")]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod checks;
+#[cfg(feature = "full")]
+pub mod corpus;
+#[cfg(feature = "full")]
+pub mod datagram;
+#[cfg(feature = "full")]
+pub mod diff;
 pub mod dogstatsdmsg;
+#[cfg(feature = "full")]
 pub mod dogstatsdreader;
+#[cfg(feature = "full")]
 pub mod dogstatsdreplayreader;
+#[cfg(feature = "stream")]
+pub mod dogstatsdstream;
+#[cfg(feature = "full")]
+pub mod events;
+#[cfg(feature = "full")]
+pub mod filter;
+#[cfg(feature = "full")]
+pub mod framedogstatsdreader;
+#[cfg(feature = "full")]
+pub mod gzip;
+#[cfg(feature = "full")]
+pub mod hll;
+#[cfg(feature = "full")]
+pub mod interner;
+#[cfg(feature = "full")]
+pub mod lz4;
+#[cfg(feature = "full")]
+pub mod paralleldogstatsdreader;
+#[cfg(feature = "full")]
+pub mod plan;
+#[cfg(feature = "full")]
+pub mod profiling;
+#[cfg(feature = "full")]
 pub mod rate;
+#[cfg(feature = "full")]
 pub mod replay;
+#[cfg(feature = "full")]
+pub mod report;
+#[cfg(feature = "full")]
+pub mod show;
+#[cfg(feature = "std")]
+pub mod sizes;
+#[cfg(feature = "full")]
+pub mod snappy;
+#[cfg(feature = "std")]
+pub mod suspicious;
+#[cfg(feature = "full")]
+pub mod tcpstream;
+#[cfg(feature = "std")]
+pub mod transform;
+#[cfg(feature = "full")]
+pub mod unixdatagramdogstatsdreader;
+#[cfg(feature = "full")]
+pub mod unixstreamdogstatsdreader;
+#[cfg(feature = "full")]
 pub mod utf8dogstatsdreader;
+#[cfg(feature = "std")]
+pub mod validation;
+#[cfg(feature = "full")]
+pub mod visitor;
+#[cfg(feature = "full")]
+pub mod xz;
+#[cfg(feature = "full")]
 pub mod zstd;
+#[cfg(feature = "full")]
 pub mod pcapreader;
+#[cfg(feature = "full")]
 pub mod pcapdogstatsdreader;
 
+#[cfg(feature = "full")]
 pub fn init_logging() {
     let env_filter = EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
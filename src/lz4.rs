@@ -0,0 +1,34 @@
+// https://github.com/lz4/lz4/blob/dev/doc/lz4_Frame_format.md#general-structure-of-lz4-frame-format
+// 0x184D2204 as a little endian u32
+const LZ4_MAGIC_BYTES: [u8; 4] = [0x04, 0x22, 0x4D, 0x18];
+
+pub fn is_lz4(header: &[u8]) -> bool {
+    header.len() >= LZ4_MAGIC_BYTES.len() && header[0..4] == LZ4_MAGIC_BYTES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // echo -n "hello" | lz4 | xxd -i
+    const HELLO_LZ4_BYTES: &[u8] = &[
+        0x04, 0x22, 0x4d, 0x18, 0x64, 0x40, 0xa7, 0x05, 0x00, 0x00, 0x80, 0x68, 0x65, 0x6c, 0x6c,
+        0x6f, 0x00, 0x00, 0x00, 0x00, 0xf9, 0x77, 0x00, 0xfb,
+    ];
+
+    const HELLO_BYTES: &[u8] = &[0x68, 0x65, 0x6c, 0x6c, 0x6f];
+
+    #[test]
+    fn is_lz4_compressed_data_is_detected() {
+        assert!(is_lz4(HELLO_LZ4_BYTES));
+    }
+
+    #[test]
+    fn is_lz4_ascii_data_is_not_detected() {
+        assert!(!is_lz4(HELLO_BYTES));
+    }
+
+    #[test]
+    fn is_lz4_short_data_is_not_detected() {
+        assert!(!is_lz4(&[0x04, 0x22]));
+    }
+}
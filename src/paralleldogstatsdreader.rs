@@ -0,0 +1,262 @@
+use std::{
+    panic,
+    path::Path,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+use thiserror::Error;
+
+use crate::{
+    dogstatsdmsg::{DogStatsDMsg, DogStatsDMsgError, DogStatsDMsgOwned},
+    dogstatsdreader::{Analytics, DogStatsDReader, DogStatsDReaderError, DogStatsDReaderOptions},
+};
+
+/// Msgs are handed to the workers this many at a time, so that sending them
+/// between threads costs little next to parsing them
+const BATCH_SIZE: usize = 1024;
+/// Batches that may queue up in front of and behind every worker, bounds
+/// memory when parsing can't keep up with reading or the consumer with parsing
+const QUEUED_BATCHES: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum ParallelDogStatsDReaderError {
+    #[error("Could not read dogstatsd from provided source")]
+    Reader(#[from] DogStatsDReaderError),
+    #[error("Could not parse msg")]
+    Parse(#[from] DogStatsDMsgError),
+}
+
+type ParsedMsg = Result<DogStatsDMsgOwned, DogStatsDMsgError>;
+
+enum Batch<T> {
+    Msgs(Vec<T>),
+    /// Reading failed, nothing follows
+    Failed(DogStatsDReaderError),
+    /// The input was read to the end, with the final analytics of the reader
+    End(Option<Analytics>),
+}
+
+/// Reads a `DogStatsDReader` on one thread, which includes decompressing and
+/// decoding packets, while a pool of workers parses the msgs into owned msgs.
+/// Msgs come out in the order they were read. Like `DogStatsDStream`, a msg
+/// that doesn't parse is an error item and reading goes on, a read error is
+/// the last item.
+///
+/// Batches are handed to the workers in turn and collected from them in the
+/// same turn, which keeps the msgs in order without any reordering buffer.
+pub struct ParallelDogStatsDReader {
+    /// Parsed batches of every worker, in the order batches were handed out
+    parsed: Vec<Receiver<Batch<ParsedMsg>>>,
+    /// Worker the next batch comes from
+    next_worker: usize,
+    current: std::vec::IntoIter<ParsedMsg>,
+    analytics: Option<Analytics>,
+    done: bool,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ParallelDogStatsDReader {
+    /// 'open' creates the reader on the reading thread, as a `DogStatsDReader`
+    /// can't be moved between threads. An error creating it is the only item.
+    /// 'workers' threads parse msgs, at least one is started, eg
+    /// `std::thread::available_parallelism()` of them.
+    pub fn new<F>(open: F, workers: usize) -> Self
+    where
+        F: FnOnce() -> Result<DogStatsDReader<'static>, DogStatsDReaderError> + Send + 'static,
+    {
+        let mut threads = Vec::new();
+        let mut unparsed = Vec::new();
+        let mut parsed = Vec::new();
+        for _ in 0..workers.max(1) {
+            let (unparsed_sender, unparsed_receiver) = mpsc::sync_channel(QUEUED_BATCHES);
+            let (parsed_sender, parsed_receiver) = mpsc::sync_channel(QUEUED_BATCHES);
+            threads.push(thread::spawn(move || {
+                parse_batches(unparsed_receiver, parsed_sender)
+            }));
+            unparsed.push(unparsed_sender);
+            parsed.push(parsed_receiver);
+        }
+        threads.push(thread::spawn(move || match open() {
+            Ok(reader) => read_batches(reader, &unparsed),
+            Err(e) => {
+                let _ = unparsed[0].send(Batch::Failed(e));
+            }
+        }));
+        Self {
+            parsed,
+            next_worker: 0,
+            current: Vec::new().into_iter(),
+            analytics: None,
+            done: false,
+            threads,
+        }
+    }
+
+    /// Reads the file at 'path', detected like any input of `DogStatsDReader::new`
+    pub fn from_path(path: impl AsRef<Path>, workers: usize) -> Self {
+        Self::from_path_with_options(path, workers, &DogStatsDReaderOptions::default())
+    }
+
+    pub fn from_path_with_options(
+        path: impl AsRef<Path>,
+        workers: usize,
+        options: &DogStatsDReaderOptions,
+    ) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let options = options.clone();
+        Self::new(
+            move || DogStatsDReader::new_with_options(std::fs::File::open(path)?, &options),
+            workers,
+        )
+    }
+
+    /// The final analytics of the reader, only known once every msg was read.
+    /// Only available for readers that deal with packets.
+    pub fn get_analytics(&self) -> Option<&Analytics> {
+        self.analytics.as_ref()
+    }
+
+    /// A thread only hangs up early when it panicked, the panic is passed on
+    /// to the consumer rather than ending the msgs early
+    fn propagate_panic(&mut self) {
+        for thread in self.threads.drain(..) {
+            if let Err(payload) = thread.join() {
+                panic::resume_unwind(payload);
+            }
+        }
+    }
+}
+
+impl Iterator for ParallelDogStatsDReader {
+    type Item = Result<DogStatsDMsgOwned, ParallelDogStatsDReaderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(msg) = self.current.next() {
+                return Some(msg.map_err(ParallelDogStatsDReaderError::from));
+            }
+            if self.done {
+                return None;
+            }
+            let batch = self.parsed[self.next_worker].recv();
+            self.next_worker = (self.next_worker + 1) % self.parsed.len();
+            match batch {
+                Ok(Batch::Msgs(msgs)) => self.current = msgs.into_iter(),
+                Ok(Batch::End(analytics)) => {
+                    self.done = true;
+                    self.analytics = analytics;
+                }
+                Ok(Batch::Failed(e)) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+                Err(_) => {
+                    self.done = true;
+                    self.propagate_panic();
+                }
+            }
+        }
+    }
+}
+
+/// Hands out batches of the msgs of 'reader' to 'workers' in turn. Returns
+/// early once the consumer is gone, as the workers hang up along with it.
+fn read_batches(mut reader: DogStatsDReader, workers: &[SyncSender<Batch<String>>]) {
+    let mut next_worker = 0;
+    let mut send = |batch| {
+        let sent = workers[next_worker].send(batch).is_ok();
+        next_worker = (next_worker + 1) % workers.len();
+        sent
+    };
+    let mut msgs = Vec::with_capacity(BATCH_SIZE);
+    let last = loop {
+        match reader.read_msg_ref() {
+            Ok(Some(msg)) => {
+                msgs.push(msg.to_string());
+                if msgs.len() == BATCH_SIZE {
+                    let batch = std::mem::replace(&mut msgs, Vec::with_capacity(BATCH_SIZE));
+                    if !send(Batch::Msgs(batch)) {
+                        return;
+                    }
+                }
+            }
+            Ok(None) => {
+                break reader
+                    .get_analytics()
+                    .map_or_else(Batch::Failed, Batch::End)
+            }
+            Err(e) => break Batch::Failed(e),
+        }
+    };
+    if !msgs.is_empty() && !send(Batch::Msgs(msgs)) {
+        return;
+    }
+    send(last);
+}
+
+fn parse_batches(unparsed: Receiver<Batch<String>>, parsed: SyncSender<Batch<ParsedMsg>>) {
+    for batch in unparsed {
+        let batch = match batch {
+            Batch::Msgs(msgs) => Batch::Msgs(
+                msgs.iter()
+                    .map(|msg| DogStatsDMsg::new(msg).map(|msg| DogStatsDMsgOwned::from(&msg)))
+                    .collect(),
+            ),
+            Batch::Failed(e) => Batch::Failed(e),
+            Batch::End(analytics) => Batch::End(analytics),
+        };
+        if parsed.send(batch).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msgs_are_parsed_in_order() {
+        let mut input = String::new();
+        for i in 0..BATCH_SIZE * 3 + 7 {
+            input.push_str(&format!("my.metric.{i}:1|c\n"));
+            if i == BATCH_SIZE + 1 {
+                input.push_str("not a msg\n");
+            }
+        }
+        let items: Vec<_> = ParallelDogStatsDReader::new(
+            move || DogStatsDReader::new(std::io::Cursor::new(input.into_bytes())),
+            3,
+        )
+        .collect();
+
+        assert_eq!(items.len(), BATCH_SIZE * 3 + 8);
+        assert!(matches!(
+            items[BATCH_SIZE + 2],
+            Err(ParallelDogStatsDReaderError::Parse(_))
+        ));
+        let names: Vec<_> = items
+            .into_iter()
+            .filter_map(|item| match item {
+                Ok(DogStatsDMsgOwned::Metric(metric)) => Some(metric.name),
+                _ => None,
+            })
+            .collect();
+        let expected: Vec<_> = (0..BATCH_SIZE * 3 + 7)
+            .map(|i| format!("my.metric.{i}"))
+            .collect();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn an_input_that_cant_be_read_is_the_only_item() {
+        let mut reader = ParallelDogStatsDReader::new(|| DogStatsDReader::new(&b"a:1"[..]), 2);
+        assert!(matches!(
+            reader.next(),
+            Some(Err(ParallelDogStatsDReaderError::Reader(_)))
+        ));
+        assert!(reader.next().is_none());
+        assert!(reader.get_analytics().is_none());
+    }
+}
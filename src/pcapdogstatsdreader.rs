@@ -1,11 +1,13 @@
-use std::{collections::VecDeque, io::BufRead, str::Utf8Error};
+use std::{collections::VecDeque, io::BufRead, ops::Range, str::Utf8Error, time::Duration};
 use thiserror::Error;
 
 use tracing::{debug, error, warn};
 
 use crate::{
+    addrfilter::AddrFilter,
     dogstatsdreader,
-    pcapreader::{PcapReader, PcapReaderError},
+    pcapreader::{FragmentReassembler, PcapReader, PcapReaderError, TransportPayload, UdpDatagram},
+    tcpstream::TcpStreams,
 };
 
 #[derive(Error, Debug)]
@@ -14,12 +16,35 @@ pub enum PcapDogStatsDReaderError {
     PcapReader(PcapReaderError),
     #[error("Invalid UTF-8 sequence found in packet")]
     InvalidUtf8Sequence(Utf8Error),
+    #[error("Packet of {size} bytes is larger than the max msg size of {max} bytes")]
+    MsgTooLarge { size: usize, max: usize },
 }
 
 pub struct PcapDogStatsDReader<'a> {
     pcap_reader: PcapReader<'a>,
     current_messages: VecDeque<String>,
+    /// The msg handed out by `read_msg_ref`
+    current_msg: String,
     analytics: dogstatsdreader::Analytics,
+    max_msg_size: usize,
+    /// Capture time of the packet `current_messages` were read from
+    current_timestamp: Option<Duration>,
+    /// Only packets captured within this range are read
+    time_range: Option<Range<Duration>>,
+    /// Only packets sent from and to these addresses are read
+    addr_filter: AddrFilter,
+    /// Fragments of IP packets that weren't all captured yet
+    fragments: FragmentReassembler,
+    /// TCP streams, for dogstatsd sent over TCP
+    streams: TcpStreams,
+    /// Msgs read or discarded so far
+    msgs_read: u64,
+    /// UDP packets (and TCP segments) that msgs were read from so far
+    packets_read: u64,
+    /// Offset of the packet `current_messages` were read from
+    packet_offset: Option<u64>,
+    /// Msgs the packet `current_messages` were read from was split into
+    packet_msgs: u64,
 }
 
 impl<'a> PcapDogStatsDReader<'a> {
@@ -28,71 +53,292 @@ impl<'a> PcapDogStatsDReader<'a> {
             Ok(reader) => Ok(PcapDogStatsDReader {
                 pcap_reader: reader,
                 current_messages: VecDeque::new(),
+                current_msg: String::new(),
                 analytics: dogstatsdreader::Analytics::new(dogstatsdreader::Transport::Udp),
+                max_msg_size: dogstatsdreader::DEFAULT_MAX_MSG_SIZE,
+                current_timestamp: None,
+                time_range: None,
+                addr_filter: AddrFilter::default(),
+                fragments: FragmentReassembler::default(),
+                streams: TcpStreams::new(),
+                msgs_read: 0,
+                packets_read: 0,
+                packet_offset: None,
+                packet_msgs: 0,
             }),
             Err(e) => Err(PcapDogStatsDReaderError::PcapReader(e)),
         }
     }
 
+    /// UDP payloads larger than 'max_msg_size' are an error rather than being read
+    pub fn with_max_msg_size(mut self, max_msg_size: usize) -> Self {
+        self.max_msg_size = max_msg_size;
+        self
+    }
+
+    /// Fragmented IP packets whose fragments weren't all captured within
+    /// 'timeout' are dropped, defaults to `DEFAULT_FRAGMENT_TIMEOUT`
+    pub fn with_fragment_timeout(mut self, timeout: Duration) -> Self {
+        self.fragments = FragmentReassembler::new(timeout);
+        self
+    }
+
+    /// Only reads packets captured at or after 'start' and before 'end', both
+    /// since the unix epoch. Packets outside of it are skipped and left out
+    /// of the analytics.
+    pub fn with_time_range(mut self, start: Duration, end: Duration) -> Self {
+        self.time_range = Some(start..end);
+        self
+    }
+
+    /// Only reads packets sent from and to the addresses of 'filter'.
+    /// Packets it doesn't match are skipped and left out of the analytics,
+    /// like packets outside of the time range.
+    pub fn with_addr_filter(mut self, filter: AddrFilter) -> Self {
+        self.addr_filter = filter;
+        self
+    }
+
     pub fn get_analytics(&self) -> Result<dogstatsdreader::Analytics, PcapDogStatsDReaderError> {
         Ok(self.analytics.clone())
     }
 
+    /// When the packet of the most recently read msg was captured
+    pub fn last_msg_timestamp(&self) -> Option<Duration> {
+        self.current_timestamp
+    }
+
     pub fn read_msg(&mut self, s: &mut String) -> Result<usize, PcapDogStatsDReaderError> {
         if let Some(line) = self.current_messages.pop_front() {
-            s.insert_str(0, &line);
-            self.analytics.total_messages += 1;
-            self.analytics.message_length.add(line.len() as f64);
+            self.record_msg(line.len());
+            dogstatsdreader::prepend_msg(s, line);
             return Ok(1);
         }
-        let header = self.pcap_reader.header;
-
-        match self.pcap_reader.read_packet() {
-            Ok(Some(packet)) => {
-                if self.analytics.earliest_timestamp.is_zero() {
-                    self.analytics.earliest_timestamp = packet.timestamp;
-                } else {
-                    self.analytics.latest_timestamp = packet.timestamp;
+        let Some(datagram) = self.next_datagram()? else {
+            return Ok(0);
+        };
+        match std::str::from_utf8(&datagram.payload) {
+            Ok(v) => {
+                if v.is_empty() {
+                    // Read operation was successful, read 0 msgs
+                    return Ok(0);
                 }
-                self.analytics.total_packets += 1;
-
-                self.analytics.total_bytes += packet.data.len() as u64;
-                match crate::pcapreader::get_udp_payload_from_packet(packet, header) {
-                    Ok(Some(udp_payload)) => {
-                        debug!("Got a UDP Payload of length {}", udp_payload.len());
-                        match std::str::from_utf8(&udp_payload) {
-                            Ok(v) => {
-                                if v.is_empty() {
-                                    // Read operation was successful, read 0 msgs
-                                    return Ok(0);
-                                }
-
-                                for line in v.lines() {
-                                    self.current_messages.push_back(String::from(line));
-                                }
-
-                                self.read_msg(s)
-                            }
-                            Err(e) => Err(PcapDogStatsDReaderError::InvalidUtf8Sequence(e)),
+
+                for line in v.lines() {
+                    self.current_messages.push_back(String::from(line));
+                }
+                self.packet_msgs = self.current_messages.len() as u64;
+
+                self.read_msg(s)
+            }
+            Err(e) => Err(PcapDogStatsDReaderError::InvalidUtf8Sequence(e)),
+        }
+    }
+
+    /// Reads past up to 'n' msgs and returns how many there were. Packets
+    /// that are skipped entirely are never split into owned msgs.
+    pub fn discard_msgs(&mut self, n: u64) -> Result<u64, PcapDogStatsDReaderError> {
+        let mut discarded = 0;
+        while discarded < n {
+            if let Some(line) = self.current_messages.pop_front() {
+                self.record_msg(line.len());
+                discarded += 1;
+                continue;
+            }
+            let Some(datagram) = self.next_datagram()? else {
+                break;
+            };
+            let payload = std::str::from_utf8(&datagram.payload)
+                .map_err(PcapDogStatsDReaderError::InvalidUtf8Sequence)?;
+            let num_msgs = payload.lines().count() as u64;
+            if num_msgs <= n - discarded {
+                for line in payload.lines() {
+                    self.record_msg(line.len());
+                }
+                discarded += num_msgs;
+            } else {
+                self.current_messages
+                    .extend(payload.lines().map(String::from));
+                self.packet_msgs = num_msgs;
+            }
+        }
+        Ok(discarded)
+    }
+
+    /// Accounts for a msg that was read or discarded
+    fn record_msg(&mut self, len: usize) {
+        self.msgs_read += 1;
+        self.analytics.total_messages += 1;
+        self.analytics.message_length.add(len as f64);
+    }
+
+    /// Reads the next UDP payload whole instead of msg by msg. Msgs still
+    /// left from a packet that was started with `read_msg` are skipped.
+    pub fn read_packet(
+        &mut self,
+    ) -> Result<Option<dogstatsdreader::DogStatsDPacket>, PcapDogStatsDReaderError> {
+        self.current_messages.clear();
+        Ok(self
+            .next_datagram()?
+            .map(|datagram| dogstatsdreader::DogStatsDPacket {
+                payload: datagram.payload.to_vec(),
+                timestamp: self.current_timestamp,
+                transport: dogstatsdreader::Transport::Udp,
+                credentials: None,
+                source_addr: datagram.source,
+            }))
+    }
+
+    /// Reads up to the next UDP packet, accounting for every captured packet
+    /// in the analytics. Packets that aren't UDP or that are outside of the
+    /// time range or the address filter are skipped. The msgs of TCP streams are read as if every
+    /// segment that completes msgs was a UDP packet of them.
+    fn next_datagram(&mut self) -> Result<Option<UdpDatagram>, PcapDogStatsDReaderError> {
+        loop {
+            let offset = self.pcap_reader.offset();
+            match self.pcap_reader.read_link_packet() {
+                Ok(Some((packet, datalink))) => {
+                    if let Some(ref time_range) = self.time_range {
+                        if !time_range.contains(&packet.timestamp) {
+                            continue;
+                        }
+                    }
+                    let (timestamp, len) = (packet.timestamp, packet.data.len() as u64);
+                    // Captured with less than the whole packet, eg by a small snaplen
+                    let truncated = packet.orig_len as u64 > len;
+                    let payload = match self.fragments.get_payload(packet, datalink) {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            error!("Error while trying to read a packet: {e}");
+                            return Err(PcapDogStatsDReaderError::PcapReader(e));
+                        }
+                    };
+                    // Packets without known addresses, eg fragments of a
+                    // packet that isn't complete yet, don't match
+                    if !self.addr_filter.is_empty() {
+                        let (source, destination) = match payload {
+                            Some(ref payload) => payload.addrs(),
+                            None => (None, None),
+                        };
+                        if !self.addr_filter.matches(
+                            source.map(|addr| addr.ip()),
+                            destination.map(|addr| addr.ip()),
+                        ) {
+                            debug!("Skipping packet from {:?} to {:?}", source, destination);
+                            continue;
                         }
                     }
-                    Ok(None) => {
-                        debug!("Skipping non-udp packet");
-                        self.read_msg(s)
+                    self.analytics.record_packet(timestamp, len);
+                    self.current_timestamp = Some(timestamp);
+                    if truncated {
+                        self.analytics.truncated_packets += 1;
                     }
-                    Err(e) => {
-                        error!("Error while trying to read a packet: {e}");
-                        Err(PcapDogStatsDReaderError::PcapReader(e))
+                    let datagram = match payload {
+                        Some(TransportPayload::Udp(mut datagram)) if truncated => {
+                            // Only the msgs before the one that was cut off are whole
+                            let complete = datagram
+                                .payload
+                                .iter()
+                                .rposition(|b| *b == b'\n')
+                                .unwrap_or(0);
+                            debug!(
+                                "Packet was truncated, dropping the last {} bytes of its payload",
+                                datagram.payload.len() - complete
+                            );
+                            datagram.payload.truncate(complete);
+                            (!datagram.payload.is_empty()).then_some(datagram)
+                        }
+                        Some(TransportPayload::Udp(datagram)) => Some(datagram),
+                        Some(TransportPayload::Tcp(_)) if truncated => {
+                            // The stream picks up again after the missing data
+                            debug!("Skipping truncated TCP segment");
+                            None
+                        }
+                        Some(TransportPayload::Tcp(segment)) => self
+                            .streams
+                            .add_segment(&segment)
+                            .map(|payload| UdpDatagram {
+                                source: Some(segment.source),
+                                destination: Some(segment.destination),
+                                payload,
+                            }),
+                        None => None,
+                    };
+                    match datagram {
+                        Some(datagram) => {
+                            debug!("Got a UDP Payload of length {}", datagram.payload.len());
+                            if datagram.payload.len() > self.max_msg_size {
+                                return Err(PcapDogStatsDReaderError::MsgTooLarge {
+                                    size: datagram.payload.len(),
+                                    max: self.max_msg_size,
+                                });
+                            }
+                            self.packets_read += 1;
+                            self.packet_offset = offset;
+                            return Ok(Some(datagram));
+                        }
+                        None => {
+                            debug!("Skipping packet without msgs, or a fragment of a packet that isn't complete yet");
+                        }
                     }
                 }
+                Ok(None) => return Ok(None), // Read was validly issued, just nothing to be read.
+                Err(e) => {
+                    warn!("Error while trying to read a packet: {e}");
+                    return Err(PcapDogStatsDReaderError::PcapReader(e));
+                }
             }
-            Ok(None) => Ok(0), // Read was validly issued, just nothing to be read.
-            Err(e) => {
-                warn!("Error while trying to read a packet: {e}");
-                Err(PcapDogStatsDReaderError::PcapReader(e))
-            }
         }
     }
+
+    /// Like `read_msg`, but the msg stays in the reader, the returned slice
+    /// is valid until the next read. Saves copying every msg out.
+    pub fn read_msg_ref(&mut self) -> Result<Option<&str>, PcapDogStatsDReaderError> {
+        let mut msg = std::mem::take(&mut self.current_msg);
+        msg.clear();
+        let num_read = self.read_msg(&mut msg);
+        self.current_msg = msg;
+        Ok((num_read? > 0).then_some(self.current_msg.as_str()))
+    }
+
+    /// The msg returned by the last `read_msg_ref`
+    pub(crate) fn current_msg(&self) -> &str {
+        &self.current_msg
+    }
+
+    /// Where reading got to, see `DogStatsDReader::checkpoint`. Fragments
+    /// and TCP streams that weren't complete yet aren't part of it.
+    pub fn checkpoint(&self) -> dogstatsdreader::ReaderCheckpoint {
+        let msgs_left = self.current_messages.len() as u64;
+        let (packet_index, byte_offset, msgs_into_packet) = if msgs_left == 0 {
+            (self.packets_read, self.pcap_reader.offset(), 0)
+        } else {
+            (
+                self.packets_read - 1,
+                self.packet_offset,
+                self.packet_msgs - msgs_left,
+            )
+        };
+        dogstatsdreader::ReaderCheckpoint {
+            input_type: dogstatsdreader::InputType::Pcap,
+            msgs_read: self.msgs_read,
+            packet_index,
+            byte_offset,
+            msgs_into_packet,
+        }
+    }
+
+    /// For a reader of a file that was opened at the byte offset of
+    /// 'checkpoint', rather than at its first packet
+    pub(crate) fn resume_at(&mut self, checkpoint: &dogstatsdreader::ReaderCheckpoint) {
+        if let Some(offset) = checkpoint.byte_offset {
+            self.pcap_reader.resume_at(offset);
+        }
+        self.packets_read = checkpoint.packet_index;
+        self.msgs_read = checkpoint
+            .msgs_read
+            .saturating_sub(checkpoint.msgs_into_packet);
+    }
 }
 
 #[cfg(test)]
@@ -124,4 +370,117 @@ mod test {
         let res = reader.read_msg(&mut s).unwrap();
         assert_eq!(res, 0);
     }
+    #[test]
+    fn packets_keep_their_source() {
+        let mut reader = PcapDogStatsDReader::new(PCAP_SINGLE_MESSAGE).unwrap();
+
+        let packet = reader.read_packet().unwrap().unwrap();
+        assert_eq!(packet.payload, b"abc.my.fav.metric:1|c|#host:foo");
+        assert_eq!(packet.source_addr, Some("127.0.0.1:36225".parse().unwrap()));
+        assert!(packet.timestamp.is_some());
+
+        assert!(reader.read_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn packets_outside_of_time_range_are_skipped() {
+        let timestamp = PcapDogStatsDReader::new(PCAP_SINGLE_MESSAGE)
+            .unwrap()
+            .read_packet()
+            .unwrap()
+            .and_then(|packet| packet.timestamp)
+            .unwrap();
+
+        let mut reader = PcapDogStatsDReader::new(PCAP_SINGLE_MESSAGE)
+            .unwrap()
+            .with_time_range(timestamp, timestamp + Duration::from_secs(1));
+        assert!(reader.read_packet().unwrap().is_some());
+
+        let mut reader = PcapDogStatsDReader::new(PCAP_SINGLE_MESSAGE)
+            .unwrap()
+            .with_time_range(Duration::ZERO, timestamp);
+        assert!(reader.read_packet().unwrap().is_none());
+        assert_eq!(reader.get_analytics().unwrap().total_packets, 0);
+    }
+
+    #[test]
+    fn msgs_cut_off_by_the_snaplen_are_dropped() {
+        let mut pcap = PCAP_SINGLE_MESSAGE.to_vec();
+        // the payload starts after the sll2, ipv4 and udp headers of the
+        // packet, make it two msgs and cut off the second one
+        let payload = 24 + 16 + 20 + 20 + 8;
+        pcap[payload + 21] = b'\n';
+        pcap.truncate(pcap.len() - 3);
+        let captured_len = (pcap.len() - 24 - 16) as u32;
+        pcap[32..36].copy_from_slice(&captured_len.to_le_bytes());
+
+        let mut reader = PcapDogStatsDReader::new(&pcap[..]).unwrap();
+        let mut s = String::new();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 1);
+        assert_eq!(s, "abc.my.fav.metric:1|c");
+        s.clear();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 0);
+
+        let analytics = reader.get_analytics().unwrap();
+        assert_eq!(analytics.truncated_packets, 1);
+        assert_eq!(analytics.total_messages, 1);
+        assert_eq!(analytics.capture_health_warnings().len(), 1);
+    }
+
+    #[test]
+    fn packets_cut_off_in_their_headers_are_counted_as_truncated() {
+        let mut pcap = PCAP_SINGLE_MESSAGE.to_vec();
+        // the sll2 header and half of the ipv4 header
+        let captured_len = 20 + 10;
+        pcap.truncate(24 + 16 + captured_len);
+        pcap[32..36].copy_from_slice(&(captured_len as u32).to_le_bytes());
+
+        let mut reader = PcapDogStatsDReader::new(&pcap[..]).unwrap();
+        let mut s = String::new();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 0);
+
+        let analytics = reader.get_analytics().unwrap();
+        assert_eq!(analytics.total_packets, 1);
+        assert_eq!(analytics.truncated_packets, 1);
+        assert_eq!(analytics.total_messages, 0);
+    }
+
+    #[test]
+    fn nanosecond_timestamps_are_kept() {
+        let mut pcap = PCAP_SINGLE_MESSAGE.to_vec();
+        // nanosecond magic, and 123456789ns into the second of the packet
+        pcap[..4].copy_from_slice(&[0x4d, 0x3c, 0xb2, 0xa1]);
+        pcap[28..32].copy_from_slice(&123456789u32.to_le_bytes());
+
+        let mut reader = PcapDogStatsDReader::new(&pcap[..]).unwrap();
+        let mut s = String::new();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 1);
+        let timestamp = reader.last_msg_timestamp().unwrap();
+        assert_eq!(timestamp.subsec_nanos(), 123456789);
+        let analytics = reader.get_analytics().unwrap();
+        assert_eq!(analytics.earliest_timestamp, timestamp);
+        assert_eq!(analytics.latest_timestamp, timestamp);
+    }
+
+    #[test]
+    fn packets_outside_of_addr_filter_are_skipped() {
+        // sent from and to 127.0.0.1
+        let filter = |sources: &[&str], destinations: &[&str]| AddrFilter {
+            sources: sources.iter().map(|s| s.parse().unwrap()).collect(),
+            destinations: destinations.iter().map(|s| s.parse().unwrap()).collect(),
+        };
+
+        let mut reader = PcapDogStatsDReader::new(PCAP_SINGLE_MESSAGE)
+            .unwrap()
+            .with_addr_filter(filter(&["10.0.0.1", "127.0.0.0/8"], &["127.0.0.1"]));
+        assert!(reader.read_packet().unwrap().is_some());
+
+        for filter in [filter(&["10.0.0.1"], &[]), filter(&[], &["::1"])] {
+            let mut reader = PcapDogStatsDReader::new(PCAP_SINGLE_MESSAGE)
+                .unwrap()
+                .with_addr_filter(filter);
+            assert!(reader.read_packet().unwrap().is_none());
+            assert_eq!(reader.get_analytics().unwrap().total_packets, 0);
+        }
+    }
 }
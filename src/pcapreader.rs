@@ -1,8 +1,16 @@
 
 
 
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::io::BufRead;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
 use bytes::{Buf, Bytes};
 use pcap_file::pcap::PcapPacket;
+use pcap_file::pcapng::{Block, PcapNgReader};
+use pcap_file::DataLink;
 use pcap_file::{pcap::PcapHeader, PcapError};
 use pnet::packet::Packet;
 use thiserror::Error;
@@ -18,11 +26,36 @@ use tracing::{debug, error, info};
 // https://wiki.wireshark.org/Development/LibpcapFileFormat
 const PCAP_HEADER: &[u8] = &[0xa1, 0xb2, 0xc3, 0xd4];
 const PCAP_HEADER_SWAPPED: &[u8] = &[0xd4, 0xc3, 0xb2, 0xa1];
+// Captures with nanosecond rather than microsecond timestamps write
+// 0xa1b23c4d instead, eg 'tcpdump --time-stamp-precision=nano'
+const PCAP_NS_HEADER: &[u8] = &[0xa1, 0xb2, 0x3c, 0x4d];
+const PCAP_NS_HEADER_SWAPPED: &[u8] = &[0x4d, 0x3c, 0xb2, 0xa1];
+// pcapng files start with a section header block, whose block type reads
+// the same in either byte order
+// https://www.ietf.org/archive/id/draft-tuexen-opsawg-pcapng-05.html
+const PCAPNG_HEADER: &[u8] = &[0x0a, 0x0d, 0x0d, 0x0a];
+/// Classic pcap files start with a header of this many bytes, and every
+/// packet with a record header of PCAP_RECORD_HEADER_LEN bytes
+pub const PCAP_HEADER_LEN: usize = 24;
+const PCAP_RECORD_HEADER_LEN: u64 = 16;
+
+enum Capture<'a> {
+    Pcap(pcap_file::pcap::PcapReader<Box<dyn BufRead + 'a>>),
+    PcapNg {
+        reader: PcapNgReader<Box<dyn BufRead + 'a>>,
+        /// Link type of every interface of the current section, by interface id
+        interfaces: Vec<DataLink>,
+    },
+}
 
 pub struct PcapReader<'a>
 {
-    reader: pcap_file::pcap::PcapReader<Box<dyn std::io::BufRead + 'a>>,
-    pub header: pcap_file::pcap::PcapHeader,
+    capture: Capture<'a>,
+    /// Header of classic pcap files. pcapng files have none, the link type
+    /// is given per interface instead.
+    pub header: Option<pcap_file::pcap::PcapHeader>,
+    /// Offset of the next packet of classic pcap files, None for pcapng
+    offset: Option<u64>,
 }
 
 #[derive(Error, Debug)]
@@ -33,16 +66,26 @@ pub enum PcapReaderError {
     Pcap(#[from] PcapError),
     #[error("Unsupported datalink type: {0:?}")]
     UnsupportedDatalinkType(pcap_file::DataLink),
+    #[error("Packet of interface {0}, which no interface description block describes")]
+    UnknownInterface(u32),
 }
 
-// Advances header 4 bytes
+// Advances header 4 bytes. Both pcap and pcapng files are pcaps.
 pub fn is_pcap(mut header: Bytes) -> Result<(), PcapReaderError> {
     assert!(header.len() >= 4);
 
     let first_four = header.slice(0..4);
     header.advance(4);
     // pcap_file has a more comprehensive check, but requires at least 24 bytes
-    if first_four != PCAP_HEADER && first_four != PCAP_HEADER_SWAPPED {
+    if ![
+        PCAP_HEADER,
+        PCAP_HEADER_SWAPPED,
+        PCAP_NS_HEADER,
+        PCAP_NS_HEADER_SWAPPED,
+        PCAPNG_HEADER,
+    ]
+    .contains(&&first_four[..])
+    {
         return Err(PcapReaderError::BadHeader(format!(
             "first four: {first_four:#?}"
         )));
@@ -57,94 +100,584 @@ pub fn get_udp_payload_from_packet(
     packet: PcapPacket,
     header: PcapHeader,
 ) -> Result<Option<Bytes>, PcapReaderError> {
+    Ok(get_udp_datagram_from_packet(packet, header)?.map(|datagram| datagram.payload))
+}
+
+fn is_supported_datalink(datalink: DataLink) -> bool {
+    matches!(
+        datalink,
+        DataLink::ETHERNET
+            | DataLink::LINUX_SLL2
+            | DataLink::NULL
+            | DataLink::LOOP
+            | DataLink::RAW
+            | DataLink::IPV4
+            | DataLink::IPV6
+    )
+}
+
+/// The payload of a UDP packet along with who sent it and to whom
+#[derive(Debug, Clone)]
+pub struct UdpDatagram {
+    /// None when the packet couldn't be decoded down to its UDP header
+    pub source: Option<SocketAddr>,
+    /// None when the packet couldn't be decoded down to its UDP header
+    pub destination: Option<SocketAddr>,
+    pub payload: Bytes,
+}
+
+/// A segment of a TCP stream, see `TcpStreams` to read the stream it is part of
+#[derive(Debug, Clone)]
+pub struct TcpSegment {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    pub sequence: u32,
+    /// Opens the stream, its sequence number comes before the first byte
+    pub syn: bool,
+    /// Closes the stream after this segment's payload
+    pub fin: bool,
+    /// Aborts the stream
+    pub rst: bool,
+    pub payload: Bytes,
+}
+
+/// What an IP packet carries, as far as it is decoded
+#[derive(Debug, Clone)]
+pub enum TransportPayload {
+    Udp(UdpDatagram),
+    Tcp(TcpSegment),
+}
+
+impl TransportPayload {
+    /// Who the payload was sent from and to, when known
+    pub fn addrs(&self) -> (Option<SocketAddr>, Option<SocketAddr>) {
+        match self {
+            Self::Udp(datagram) => (datagram.source, datagram.destination),
+            Self::Tcp(segment) => (Some(segment.source), Some(segment.destination)),
+        }
+    }
+}
+
+/// Like `get_udp_payload_from_packet`, but keeps the address the packet was
+/// sent from
+pub fn get_udp_datagram_from_packet(
+    packet: PcapPacket,
+    header: PcapHeader,
+) -> Result<Option<UdpDatagram>, PcapReaderError> {
+    get_udp_datagram_from_link_packet(packet, header.datalink)
+}
+
+/// Like `get_udp_datagram_from_packet`, for a packet whose data starts with
+/// a 'datalink' header, as read by `PcapReader::read_link_packet`. A single
+/// fragment of a fragmented IP packet has no UDP packet to read, see
+/// `FragmentReassembler` to put them back together.
+pub fn get_udp_datagram_from_link_packet(
+    packet: PcapPacket,
+    datalink: DataLink,
+) -> Result<Option<UdpDatagram>, PcapReaderError> {
+    FragmentReassembler::default().get_udp_datagram(packet, datalink)
+}
+
+/// How long the fragments of an IP packet wait for the rest of them, like
+/// the 30s linux waits by default (net.ipv4.ipfrag_time)
+pub const DEFAULT_FRAGMENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fragments belong to the same IP packet when they share its source,
+/// destination, protocol and id. IPv6 fragments have no protocol in the key,
+/// the fragments of one packet may name different next headers.
+type FragmentKey = (IpAddr, IpAddr, Option<u8>, u32);
+
+struct PendingPacket {
+    /// Capture time of the first fragment that arrived
+    first_seen: Duration,
+    /// Data of every fragment that arrived, by its offset in the payload
+    fragments: BTreeMap<usize, Vec<u8>>,
+    /// Known once the last fragment arrived
+    total_len: Option<usize>,
+}
+
+/// Decodes UDP packets like `get_udp_datagram_from_link_packet`, keeping the
+/// fragments of IP packets until all of them were captured. Large dogstatsd
+/// payloads are fragmented, each fragment alone is only part of the payload.
+///
+/// Packets whose fragments don't all arrive within the timeout, in capture
+/// time, are dropped.
+pub struct FragmentReassembler {
+    pending: HashMap<FragmentKey, PendingPacket>,
+    timeout: Duration,
+}
+
+impl Default for FragmentReassembler {
+    fn default() -> Self {
+        Self::new(DEFAULT_FRAGMENT_TIMEOUT)
+    }
+}
+
+impl FragmentReassembler {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: HashMap::new(),
+            timeout,
+        }
+    }
+
+    /// IP packets that some fragments were captured of, waiting for the rest
+    pub fn pending_packets(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The UDP packet of 'packet', whose data starts with a 'datalink'
+    /// header. None for packets that aren't UDP, and for fragments until the
+    /// last missing one of their packet is given.
+    pub fn get_udp_datagram(
+        &mut self,
+        packet: PcapPacket,
+        datalink: DataLink,
+    ) -> Result<Option<UdpDatagram>, PcapReaderError> {
+        match self.get_payload(packet, datalink)? {
+            Some(TransportPayload::Udp(datagram)) => Ok(Some(datagram)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Like `get_udp_datagram`, TCP segments included
+    pub fn get_payload(
+        &mut self,
+        packet: PcapPacket,
+        datalink: DataLink,
+    ) -> Result<Option<TransportPayload>, PcapReaderError> {
+        get_payload_from_link(packet, datalink, self)
+    }
+
+    /// Keeps the fragment at 'offset' of the payload of packet 'key'.
+    /// Returns the whole payload once the fragments cover it without gaps.
+    fn add_fragment(
+        &mut self,
+        key: FragmentKey,
+        offset: usize,
+        more_fragments: bool,
+        data: &[u8],
+        timestamp: Duration,
+    ) -> Option<Vec<u8>> {
+        self.expire(timestamp);
+        let pending = self.pending.entry(key).or_insert_with(|| PendingPacket {
+            first_seen: timestamp,
+            fragments: BTreeMap::new(),
+            total_len: None,
+        });
+        if !more_fragments {
+            pending.total_len = Some(offset + data.len());
+        }
+        pending.fragments.insert(offset, data.to_vec());
+
+        let total_len = pending.total_len?;
+        let mut covered = 0;
+        for (&offset, data) in &pending.fragments {
+            if offset > covered {
+                debug!("Waiting on more fragments of packet {:?}", key);
+                return None;
+            }
+            covered = covered.max(offset + data.len());
+        }
+        if covered < total_len {
+            debug!("Waiting on more fragments of packet {:?}", key);
+            return None;
+        }
+
+        let pending = self.pending.remove(&key)?;
+        debug!(
+            "Reassembled {} bytes from {} fragments of packet {:?}",
+            total_len,
+            pending.fragments.len(),
+            key
+        );
+        let mut payload = vec![0; total_len];
+        for (offset, data) in pending.fragments {
+            let end = (offset + data.len()).min(total_len);
+            if offset < end {
+                payload[offset..end].copy_from_slice(&data[..end - offset]);
+            }
+        }
+        Some(payload)
+    }
+
+    /// Drops packets whose first fragment was captured more than the
+    /// timeout before 'now'
+    fn expire(&mut self, now: Duration) {
+        let timeout = self.timeout;
+        self.pending.retain(|key, pending| {
+            let expired = now.saturating_sub(pending.first_seen) > timeout;
+            if expired {
+                debug!(
+                    "Dropping packet {:?}, not all of its fragments arrived within {:?}",
+                    key, timeout
+                );
+            }
+            !expired
+        });
+    }
+}
+
+fn get_payload_from_link(
+    packet: PcapPacket,
+    datalink: DataLink,
+    fragments: &mut FragmentReassembler,
+) -> Result<Option<TransportPayload>, PcapReaderError> {
+    let timestamp = packet.timestamp;
+    // Fragments of a packet that was cut off would leave holes in the
+    // reassembled payload
+    let truncated = packet.orig_len as usize > packet.data.len();
     let data = packet.data;
     // data will be interpreted according to the datalink type
-    // specified in the pcap header
+    // specified in the pcap header or the interface it was captured on
 
     debug!(
         "Attempting to read UDP packet out of raw PCAP packet (len: {})",
         data.len()
     );
 
-    match header.datalink {
+    match datalink {
         pcap_file::DataLink::ETHERNET => {
-            let ethernet_packet = pnet::packet::ethernet::EthernetPacket::new(&data)
-                .expect("pcap header claimed ethernet packet, but parsing failed");
+            let Some(ethernet_packet) = pnet::packet::ethernet::EthernetPacket::new(&data) else {
+                error!("Failed to parse ethernet header of length {}", data.len());
+                return Ok(None);
+            };
             debug!("Ethernet packet: {:?}", ethernet_packet);
-            match ethernet_packet.get_ethertype() {
+            let (ethertype, payload) =
+                strip_vlan_tags(ethernet_packet.get_ethertype(), ethernet_packet.payload());
+            match ethertype {
                 pnet::packet::ethernet::EtherTypes::Ipv4 => {
-                    let ipv4_packet =
-                        pnet::packet::ipv4::Ipv4Packet::new(ethernet_packet.payload())
-                            .expect("Header said ipv4, but parsing failed");
+                    let Some(ipv4_packet) = pnet::packet::ipv4::Ipv4Packet::new(payload) else {
+                        error!("Failed to parse IPv4 packet");
+                        return Ok(None);
+                    };
                     debug!("IPv4 packet: {:?}", ipv4_packet);
-                    return get_udp_payload_from_ipv4(ipv4_packet);
+                    get_payload_from_ipv4(ipv4_packet, fragments, timestamp, truncated)
+                }
+                pnet::packet::ethernet::EtherTypes::Ipv6 => {
+                    let Some(ipv6_packet) = pnet::packet::ipv6::Ipv6Packet::new(payload) else {
+                        error!("Failed to parse IPv6 packet");
+                        return Ok(None);
+                    };
+                    debug!("IPv6 packet: {:?}", ipv6_packet);
+                    get_payload_from_ipv6(ipv6_packet, fragments, timestamp, truncated)
                 }
                 _ => {
-                    // todo - ipv6
                     error!(
                         "Unsupported protocol found in ethernet packet: {}",
-                        ethernet_packet.get_ethertype()
+                        ethertype
                     );
+                    Ok(None)
                 }
             }
-            todo!()
         }
         pcap_file::DataLink::LINUX_SLL2 => {
-            let sllv2_packet = pnet::packet::sll2::SLL2Packet::new(&data)
-                .expect("Pcap header claimed sll2 packets, but parsing failed.");
+            let Some(sllv2_packet) = pnet::packet::sll2::SLL2Packet::new(&data) else {
+                error!("Failed to parse SLLv2 header of length {}", data.len());
+                return Ok(None);
+            };
             debug!(
                 "SLLv2 packet: {:?} with protocol type: {}",
                 sllv2_packet,
                 sllv2_packet.get_protocol_type()
             );
-            match sllv2_packet.get_protocol_type() {
+            let (protocol_type, payload) =
+                strip_vlan_tags(sllv2_packet.get_protocol_type(), sllv2_packet.payload());
+            match protocol_type {
                 pnet::packet::ethernet::EtherTypes::Ipv4 => {
-                    let ipv4_packet = pnet::packet::ipv4::Ipv4Packet::new(sllv2_packet.payload())
-                        .expect("Header said ipv4, but parsing failed");
+                    let Some(ipv4_packet) = pnet::packet::ipv4::Ipv4Packet::new(payload) else {
+                        error!("Failed to parse IPv4 packet");
+                        return Ok(None);
+                    };
                     debug!("IPv4 packet: {:?}", ipv4_packet);
-                    return get_udp_payload_from_ipv4(ipv4_packet);
+                    get_payload_from_ipv4(ipv4_packet, fragments, timestamp, truncated)
+                }
+                pnet::packet::ethernet::EtherTypes::Ipv6 => {
+                    let Some(ipv6_packet) = pnet::packet::ipv6::Ipv6Packet::new(payload) else {
+                        error!("Failed to parse IPv6 packet");
+                        return Ok(None);
+                    };
+                    debug!("IPv6 packet: {:?}", ipv6_packet);
+                    get_payload_from_ipv6(ipv6_packet, fragments, timestamp, truncated)
                 }
                 _ => {
-                    // todo - ipv6
                     error!(
                         "Unsupported protocol found in SLLv2 packet: {}",
-                        sllv2_packet.get_protocol_type()
+                        protocol_type
                     );
+                    Ok(None)
                 }
             }
         }
+        pcap_file::DataLink::NULL | pcap_file::DataLink::LOOP => {
+            // The address family of the packet comes first, in the byte order
+            // of the capturing host for NULL and in network byte order for
+            // LOOP. Its values for IPv6 differ between BSDs, the IP version
+            // of the packet is what counts.
+            let Some(packet) = data.get(4..) else {
+                error!("Failed to parse loopback header of length {}", data.len());
+                return Ok(None);
+            };
+            debug!("Loopback packet with family header {:?}", &data[..4]);
+            get_payload_from_ip(packet, fragments, timestamp, truncated)
+        }
+        pcap_file::DataLink::RAW | pcap_file::DataLink::IPV4 | pcap_file::DataLink::IPV6 => {
+            get_payload_from_ip(&data, fragments, timestamp, truncated)
+        }
         _ => {
             unreachable!("Unsupported datalink type found, this should have been caught during construction.");
         }
     }
+}
 
-    Ok(Some(Bytes::copy_from_slice(&data)))
+/// A packet without a link layer header, either IPv4 or IPv6 as told by its
+/// version
+fn get_payload_from_ip(
+    packet: &[u8],
+    fragments: &mut FragmentReassembler,
+    timestamp: Duration,
+    truncated: bool,
+) -> Result<Option<TransportPayload>, PcapReaderError> {
+    match packet.first().map(|first| first >> 4) {
+        Some(4) => match pnet::packet::ipv4::Ipv4Packet::new(packet) {
+            Some(ipv4_packet) => {
+                debug!("IPv4 packet: {:?}", ipv4_packet);
+                get_payload_from_ipv4(ipv4_packet, fragments, timestamp, truncated)
+            }
+            None => {
+                error!("Failed to parse IPv4 packet");
+                Ok(None)
+            }
+        },
+        Some(6) => match pnet::packet::ipv6::Ipv6Packet::new(packet) {
+            Some(ipv6_packet) => {
+                debug!("IPv6 packet: {:?}", ipv6_packet);
+                get_payload_from_ipv6(ipv6_packet, fragments, timestamp, truncated)
+            }
+            None => {
+                error!("Failed to parse IPv6 packet");
+                Ok(None)
+            }
+        },
+        version => {
+            error!("Unsupported IP version found in raw packet: {:?}", version);
+            Ok(None)
+        }
+    }
 }
 
-fn get_udp_payload_from_ipv4(
+/// 802.1Q VLAN tags, along with the outer tags of QinQ, sit between the link
+/// layer header and the packet. Returns the ethertype and payload under them.
+fn strip_vlan_tags(
+    mut ethertype: pnet::packet::ethernet::EtherType,
+    mut payload: &[u8],
+) -> (pnet::packet::ethernet::EtherType, &[u8]) {
+    use pnet::packet::ethernet::EtherTypes;
+
+    while matches!(
+        ethertype,
+        EtherTypes::Vlan | EtherTypes::PBridge | EtherTypes::QinQ
+    ) {
+        let Some(tag) = pnet::packet::vlan::VlanPacket::new(payload) else {
+            break;
+        };
+        debug!("VLAN tag: {:?}", tag);
+        ethertype = tag.get_ethertype();
+        // tag control information, then the ethertype of what follows
+        payload = &payload[4..];
+    }
+    (ethertype, payload)
+}
+
+fn get_payload_from_ipv4(
     ipv4: pnet::packet::ipv4::Ipv4Packet,
-) -> Result<Option<Bytes>, PcapReaderError> {
-    match ipv4.get_next_level_protocol() {
-        pnet::packet::ip::IpNextHeaderProtocols::Udp => {
-            let udp_packet = pnet::packet::udp::UdpPacket::new(ipv4.payload());
-            debug!("UDP packet: {:?}", udp_packet);
-            match udp_packet {
-                Some(udp_packet) => {
-                    return Ok(Some(Bytes::copy_from_slice(udp_packet.payload())));
-                }
-                None => {
-                    error!("Failed to parse UDP packet from IPv4 packet");
+    fragments: &mut FragmentReassembler,
+    timestamp: Duration,
+    truncated: bool,
+) -> Result<Option<TransportPayload>, PcapReaderError> {
+    let more_fragments = ipv4.get_flags() & pnet::packet::ipv4::Ipv4Flags::MoreFragments != 0;
+    let reassembled;
+    let payload = if more_fragments || ipv4.get_fragment_offset() != 0 {
+        let key = (
+            IpAddr::V4(ipv4.get_source()),
+            IpAddr::V4(ipv4.get_destination()),
+            Some(ipv4.get_next_level_protocol().0),
+            u32::from(ipv4.get_identification()),
+        );
+        if truncated {
+            debug!("Skipping truncated IPv4 fragment");
+            return Ok(None);
+        }
+        // The offset is given in 8 byte units
+        let offset = 8 * ipv4.get_fragment_offset() as usize;
+        match fragments.add_fragment(key, offset, more_fragments, ipv4.payload(), timestamp) {
+            Some(payload) => {
+                reassembled = payload;
+                &reassembled[..]
+            }
+            None => return Ok(None),
+        }
+    } else {
+        ipv4.payload()
+    };
+
+    Ok(get_payload_from_transport(
+        ipv4.get_next_level_protocol(),
+        (
+            IpAddr::V4(ipv4.get_source()),
+            IpAddr::V4(ipv4.get_destination()),
+        ),
+        payload,
+    ))
+}
+
+fn get_payload_from_ipv6(
+    ipv6: pnet::packet::ipv6::Ipv6Packet,
+    fragments: &mut FragmentReassembler,
+    timestamp: Duration,
+    truncated: bool,
+) -> Result<Option<TransportPayload>, PcapReaderError> {
+    get_payload_from_ipv6_headers(
+        (ipv6.get_source(), ipv6.get_destination()),
+        ipv6.get_next_header(),
+        ipv6.payload(),
+        fragments,
+        timestamp,
+        truncated,
+    )
+}
+
+/// Follows the extension headers of an IPv6 packet sent from and to 'addrs'
+/// down to its UDP packet, starting with 'next_header' at the start of
+/// 'payload'. Fragmented payloads are reassembled first.
+fn get_payload_from_ipv6_headers(
+    addrs: (Ipv6Addr, Ipv6Addr),
+    mut next_header: pnet::packet::ip::IpNextHeaderProtocol,
+    mut payload: &[u8],
+    fragments: &mut FragmentReassembler,
+    timestamp: Duration,
+    truncated: bool,
+) -> Result<Option<TransportPayload>, PcapReaderError> {
+    use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+
+    while !matches!(
+        next_header,
+        IpNextHeaderProtocols::Udp | IpNextHeaderProtocols::Tcp
+    ) {
+        let header_len = match next_header {
+            // Header length in 8 byte units, not counting the first 8 bytes
+            IpNextHeaderProtocols::Hopopt
+            | IpNextHeaderProtocols::Ipv6Route
+            | IpNextHeaderProtocols::Ipv6Opts => payload.get(1).map(|len| 8 + 8 * *len as usize),
+            IpNextHeaderProtocols::Ipv6Frag => {
+                let Some(header) = payload.get(..8) else {
+                    error!("Failed to parse fragment header from IPv6 packet");
+                    return Ok(None);
+                };
+                // The offset is given in 8 byte units, followed by 2 reserved
+                // bits and the more fragments flag
+                let offset_and_flags = u16::from_be_bytes([header[2], header[3]]);
+                let id = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+                if truncated {
+                    debug!("Skipping truncated IPv6 fragment");
+                    return Ok(None);
                 }
+                let key = (IpAddr::V6(addrs.0), IpAddr::V6(addrs.1), None, id);
+                let Some(reassembled) = fragments.add_fragment(
+                    key,
+                    8 * (offset_and_flags >> 3) as usize,
+                    offset_and_flags & 1 != 0,
+                    &payload[8..],
+                    timestamp,
+                ) else {
+                    return Ok(None);
+                };
+                return get_payload_from_ipv6_headers(
+                    addrs,
+                    IpNextHeaderProtocol::new(header[0]),
+                    &reassembled,
+                    fragments,
+                    timestamp,
+                    truncated,
+                );
+            }
+            // Header length in 4 byte units, not counting the first 8 bytes
+            IpNextHeaderProtocols::Ah => payload.get(1).map(|len| 4 * (*len as usize + 2)),
+            _ => {
+                error!(
+                    "Unsupported protocol found in IPv6 packet: {:?}",
+                    next_header
+                );
+                return Ok(None);
+            }
+        };
+        match header_len.filter(|len| *len <= payload.len()) {
+            Some(len) => {
+                next_header = IpNextHeaderProtocol::new(payload[0]);
+                payload = &payload[len..];
+            }
+            None => {
+                error!(
+                    "Failed to parse {:?} extension header from IPv6 packet",
+                    next_header
+                );
+                return Ok(None);
             }
         }
+    }
+
+    Ok(get_payload_from_transport(
+        next_header,
+        (IpAddr::V6(addrs.0), IpAddr::V6(addrs.1)),
+        payload,
+    ))
+}
+
+/// The UDP datagram or TCP segment in the 'payload' of an IP packet of
+/// 'protocol', sent from and to 'addrs'
+fn get_payload_from_transport(
+    protocol: pnet::packet::ip::IpNextHeaderProtocol,
+    addrs: (IpAddr, IpAddr),
+    payload: &[u8],
+) -> Option<TransportPayload> {
+    use pnet::packet::ip::IpNextHeaderProtocols;
+    use pnet::packet::tcp::TcpFlags;
+
+    match protocol {
+        IpNextHeaderProtocols::Udp => {
+            let Some(udp_packet) = pnet::packet::udp::UdpPacket::new(payload) else {
+                error!("Failed to parse UDP packet from IP packet");
+                return None;
+            };
+            debug!("UDP packet: {:?}", udp_packet);
+            Some(TransportPayload::Udp(UdpDatagram {
+                source: Some(SocketAddr::new(addrs.0, udp_packet.get_source())),
+                destination: Some(SocketAddr::new(addrs.1, udp_packet.get_destination())),
+                payload: Bytes::copy_from_slice(udp_packet.payload()),
+            }))
+        }
+        IpNextHeaderProtocols::Tcp => {
+            let Some(tcp_packet) = pnet::packet::tcp::TcpPacket::new(payload) else {
+                error!("Failed to parse TCP packet from IP packet");
+                return None;
+            };
+            debug!("TCP packet: {:?}", tcp_packet);
+            let flags = tcp_packet.get_flags();
+            Some(TransportPayload::Tcp(TcpSegment {
+                source: SocketAddr::new(addrs.0, tcp_packet.get_source()),
+                destination: SocketAddr::new(addrs.1, tcp_packet.get_destination()),
+                sequence: tcp_packet.get_sequence(),
+                syn: flags & TcpFlags::SYN != 0,
+                fin: flags & TcpFlags::FIN != 0,
+                rst: flags & TcpFlags::RST != 0,
+                payload: Bytes::copy_from_slice(tcp_packet.payload()),
+            }))
+        }
         _ => {
-            error!(
-                "Unsupported protocol found in IPv4 packet: {:?}",
-                ipv4.get_next_level_protocol()
-            );
+            error!("Unsupported protocol found in IP packet: {:?}", protocol);
+            None
         }
     }
-    Ok(None)
 }
 
 
@@ -156,15 +689,108 @@ impl<'a> PcapReader<'a>
     /// # Errors
     /// - This function will return an error if the pcap data is malformed
     pub fn read_packet(&mut self) -> Result<Option<PcapPacket>, PcapReaderError> {
-        match self.reader.next_packet() {
-            Some(Ok(packet)) => Ok(Some(packet)),
-            Some(Err(e)) => Err(PcapReaderError::Pcap(e)),
-            None => Ok(None),
+        Ok(self.read_link_packet()?.map(|(packet, _)| packet))
+    }
+
+    /// Like `read_packet`, along with the link type the packet data starts
+    /// with. That is the same for every packet of a pcap file, but a pcapng
+    /// file may have captured on several interfaces of different link types.
+    ///
+    /// # Errors
+    /// - Packets of a pcapng interface whose link type isn't supported are
+    ///   an error, like pcap files of such a link type
+    pub fn read_link_packet(&mut self) -> Result<Option<(PcapPacket, DataLink)>, PcapReaderError> {
+        match self.capture {
+            Capture::Pcap(ref mut reader) => {
+                let datalink = reader.header().datalink;
+                match reader.next_packet() {
+                    Some(Ok(packet)) => {
+                        if let Some(ref mut offset) = self.offset {
+                            *offset += PCAP_RECORD_HEADER_LEN + packet.data.len() as u64;
+                        }
+                        Ok(Some((packet, datalink)))
+                    }
+                    Some(Err(e)) => Err(PcapReaderError::Pcap(e)),
+                    None => Ok(None),
+                }
+            }
+            Capture::PcapNg {
+                ref mut reader,
+                ref mut interfaces,
+            } => loop {
+                // Packet data is copied out of the block, which borrows the reader
+                let (interface_id, timestamp, orig_len, data) = match reader.next_block() {
+                    Some(Ok(Block::SectionHeader(_))) => {
+                        // Interface ids start over in every section
+                        interfaces.clear();
+                        continue;
+                    }
+                    Some(Ok(Block::InterfaceDescription(interface))) => {
+                        debug!(
+                            "Interface {} has datalink {:?}",
+                            interfaces.len(),
+                            interface.linktype
+                        );
+                        interfaces.push(interface.linktype);
+                        continue;
+                    }
+                    Some(Ok(Block::EnhancedPacket(packet))) => (
+                        packet.interface_id,
+                        packet.timestamp,
+                        packet.original_len,
+                        packet.data.into_owned(),
+                    ),
+                    // Simple packets have no timestamp and are always
+                    // captured on the first interface
+                    Some(Ok(Block::SimplePacket(packet))) => (
+                        0,
+                        Duration::ZERO,
+                        packet.original_len,
+                        packet.data.into_owned(),
+                    ),
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(PcapReaderError::Pcap(e)),
+                    None => return Ok(None),
+                };
+                let Some(&datalink) = interfaces.get(interface_id as usize) else {
+                    return Err(PcapReaderError::UnknownInterface(interface_id));
+                };
+                if !is_supported_datalink(datalink) {
+                    error!(
+                        "Unsupported datalink type of interface {interface_id} in pcapng file: {datalink:?}"
+                    );
+                    return Err(PcapReaderError::UnsupportedDatalinkType(datalink));
+                }
+                let packet = PcapPacket {
+                    timestamp,
+                    orig_len,
+                    data: Cow::Owned(data),
+                };
+                return Ok(Some((packet, datalink)));
+            },
         }
     }
 
+    /// Reads classic pcap and pcapng files
     pub fn new(byte_reader: impl std::io::BufRead + 'a) -> Result<Self, PcapReaderError> {
-        let byte_reader: Box<dyn std::io::BufRead + 'a> = Box::new(byte_reader);
+        let mut byte_reader: Box<dyn std::io::BufRead + 'a> = Box::new(byte_reader);
+        let is_pcapng = byte_reader
+            .fill_buf()
+            .map_err(PcapError::IoError)?
+            .starts_with(PCAPNG_HEADER);
+        if is_pcapng {
+            info!("Format: pcapng");
+            let reader = PcapNgReader::new(byte_reader)?;
+            return Ok(Self {
+                capture: Capture::PcapNg {
+                    reader,
+                    interfaces: Vec::new(),
+                },
+                header: None,
+                offset: None,
+            });
+        }
+
         let reader = pcap_file::pcap::PcapReader::new(byte_reader)?;
         let header = reader.header();
         match header.datalink {
@@ -174,6 +800,12 @@ impl<'a> PcapReader<'a>
             pcap_file::DataLink::LINUX_SLL2 => {
                 info!("Datalink: Linux Cooked Mode v2");
             }
+            pcap_file::DataLink::NULL | pcap_file::DataLink::LOOP => {
+                info!("Datalink: BSD Loopback");
+            }
+            pcap_file::DataLink::RAW | pcap_file::DataLink::IPV4 | pcap_file::DataLink::IPV6 => {
+                info!("Datalink: Raw IP");
+            }
             _ => {
                 error!(
                     "Unsupported datalink type in pcap file: {:?}",
@@ -183,7 +815,26 @@ impl<'a> PcapReader<'a>
             }
         }
 
-        Ok(Self { reader, header })
+        Ok(Self {
+            capture: Capture::Pcap(reader),
+            header: Some(header),
+            offset: Some(PCAP_HEADER_LEN as u64),
+        })
+    }
+
+    /// Offset of the next packet in the file, including the header. Only
+    /// known for classic pcap files, pcapng files may have blocks of any
+    /// kind between packets.
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// For a reader of a file that was opened at 'offset', rather than at
+    /// its first packet
+    pub(crate) fn resume_at(&mut self, offset: u64) {
+        if let Some(ref mut current) = self.offset {
+            *current = offset;
+        }
     }
 }
 
@@ -229,6 +880,21 @@ mod test {
         is_pcap(Bytes::from_static(PCAP_ETH1_SINGLE_UDP_PACKET)).unwrap();
     }
 
+    #[test]
+    fn can_read_nanosecond_timestamps() {
+        let mut reader = PcapReader::new(PCAP_ETH1_SINGLE_UDP_PACKET).unwrap();
+        let micros = reader.read_packet().unwrap().unwrap().timestamp;
+
+        // the same capture, with its fraction of a second read as nanoseconds
+        let mut pcap = PCAP_ETH1_SINGLE_UDP_PACKET.to_vec();
+        pcap[..4].copy_from_slice(PCAP_NS_HEADER_SWAPPED);
+        is_pcap(Bytes::copy_from_slice(&pcap)).unwrap();
+        let mut reader = PcapReader::new(&pcap[..]).unwrap();
+        let nanos = reader.read_packet().unwrap().unwrap().timestamp;
+        assert_eq!(nanos.as_secs(), micros.as_secs());
+        assert_eq!(nanos.subsec_nanos(), micros.subsec_micros());
+    }
+
     #[test]
     fn can_read_single_packet() {
         let mut reader = PcapReader::new(PCAP_SLLV2_SINGLE_UDP_PACKET).unwrap();
@@ -239,7 +905,7 @@ mod test {
     #[test]
     fn can_read_udp_from_sll2_packet() {
         let mut reader = PcapReader::new(PCAP_SLLV2_SINGLE_UDP_PACKET).unwrap();
-        let header = reader.header;
+        let header = reader.header.unwrap();
         let packet = reader.read_packet().unwrap().unwrap();
         let udp_payload = get_udp_payload_from_packet(packet, header)
             .unwrap()
@@ -257,7 +923,7 @@ mod test {
     #[test]
     fn can_read_udp_from_eth1_packet() {
         let mut reader = PcapReader::new(PCAP_ETH1_SINGLE_UDP_PACKET).unwrap();
-        let header = reader.header;
+        let header = reader.header.unwrap();
         let packet = reader.read_packet().unwrap().unwrap();
         let udp_payload = get_udp_payload_from_packet(packet, header)
             .unwrap()
@@ -272,6 +938,303 @@ mod test {
         assert_eq!(udp_payload, expected_udp_payload);
     }
 
+    /// A little endian pcapng block of 'block_type' with 'body', padded to 32 bits
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let padded_len = body.len().div_ceil(4) * 4;
+        let total_len = (12 + padded_len) as u32;
+        let mut block = Vec::new();
+        block.extend_from_slice(&block_type.to_le_bytes());
+        block.extend_from_slice(&total_len.to_le_bytes());
+        block.extend_from_slice(body);
+        block.resize(8 + padded_len, 0);
+        block.extend_from_slice(&total_len.to_le_bytes());
+        block
+    }
+
+    fn pcapng_interface(datalink: u16) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&datalink.to_le_bytes());
+        body.extend_from_slice(&[0, 0]); // reserved
+        body.extend_from_slice(&65535u32.to_le_bytes()); // snaplen
+        pcapng_block(1, &body)
+    }
+
+    fn pcapng_packet(interface_id: u32, data: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&interface_id.to_le_bytes());
+        body.extend_from_slice(&[0; 8]); // timestamp
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes()); // captured length
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes()); // original length
+        body.extend_from_slice(data);
+        pcapng_block(6, &body)
+    }
+
+    /// The packet data of a single packet pcap, after the file and record headers
+    fn packet_data(pcap: &[u8]) -> &[u8] {
+        &pcap[40..]
+    }
+
+    #[test]
+    fn can_read_udp_from_pcapng_interfaces() {
+        let mut section_header = Vec::new();
+        section_header.extend_from_slice(&0x1a2b3c4du32.to_le_bytes()); // byte order magic
+        section_header.extend_from_slice(&[1, 0, 0, 0]); // version 1.0
+        section_header.extend_from_slice(&(-1i64).to_le_bytes()); // unknown section length
+        let mut pcapng = pcapng_block(0x0a0d0d0a, &section_header);
+        pcapng.extend(pcapng_interface(276)); // LINKTYPE_LINUX_SLL2
+        pcapng.extend(pcapng_interface(1)); // LINKTYPE_ETHERNET
+        pcapng.extend(pcapng_packet(1, packet_data(PCAP_ETH1_SINGLE_UDP_PACKET)));
+        pcapng.extend(pcapng_packet(0, packet_data(PCAP_SLLV2_SINGLE_UDP_PACKET)));
+        pcapng.extend(pcapng_packet(2, packet_data(PCAP_ETH1_SINGLE_UDP_PACKET)));
+
+        is_pcap(Bytes::copy_from_slice(&pcapng)).unwrap();
+        let mut reader = PcapReader::new(&pcapng[..]).unwrap();
+        assert!(reader.header.is_none());
+        for expected_datalink in [DataLink::ETHERNET, DataLink::LINUX_SLL2] {
+            let (packet, datalink) = reader.read_link_packet().unwrap().unwrap();
+            assert_eq!(datalink, expected_datalink);
+            let datagram = get_udp_datagram_from_link_packet(packet, datalink)
+                .unwrap()
+                .unwrap();
+            assert_eq!(datagram.payload, &b"abc.my.fav.metric:1|c|#host:foo"[..]);
+        }
+        assert!(matches!(
+            reader.read_link_packet(),
+            Err(PcapReaderError::UnknownInterface(2))
+        ));
+    }
+
+    /// An ethernet frame of an IPv6 packet from [::1]:36225, whose first
+    /// header is 'first_header', followed by 'extension_headers' and a UDP
+    /// packet of 'udp_payload'
+    fn ipv6_ethernet_frame(
+        first_header: u8,
+        extension_headers: &[u8],
+        udp_payload: &[u8],
+    ) -> Vec<u8> {
+        let mut frame = vec![0; 12]; // destination and source mac
+        frame.extend_from_slice(&[0x86, 0xdd]); // ethertype ipv6
+        frame.extend(ipv6_packet(
+            first_header,
+            &[extension_headers, &udp_packet(udp_payload)].concat(),
+        ));
+        frame
+    }
+
+    /// A UDP packet from port 36225 to 8125
+    fn udp_packet(udp_payload: &[u8]) -> Vec<u8> {
+        let mut udp = Vec::new();
+        udp.extend_from_slice(&36225u16.to_be_bytes()); // source port
+        udp.extend_from_slice(&8125u16.to_be_bytes()); // destination port
+        udp.extend_from_slice(&(8 + udp_payload.len() as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]); // checksum
+        udp.extend_from_slice(udp_payload);
+        udp
+    }
+
+    /// An IPv6 packet from and to ::1 whose first header is 'first_header'
+    fn ipv6_packet(first_header: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0]; // version, traffic class, flow label
+        packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[first_header, 64]); // next header, hop limit
+        packet.extend_from_slice(&Ipv6Addr::LOCALHOST.octets()); // source
+        packet.extend_from_slice(&Ipv6Addr::LOCALHOST.octets()); // destination
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    /// An IPv4 fragment of UDP packet 'id' from and to 127.0.0.1, at 'offset'
+    /// 8 byte units into the payload
+    fn ipv4_fragment(id: u16, offset: u16, more_fragments: bool, data: &[u8]) -> Vec<u8> {
+        let flags = if more_fragments { 0x2000 } else { 0 };
+        let mut packet = vec![0x45, 0]; // version, header length, dscp
+        packet.extend_from_slice(&(20 + data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&id.to_be_bytes());
+        packet.extend_from_slice(&(flags | offset).to_be_bytes());
+        packet.extend_from_slice(&[64, 17, 0, 0]); // ttl, udp, checksum
+        packet.extend_from_slice(&[127, 0, 0, 1, 127, 0, 0, 1]); // source, destination
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    /// Like `ipv4_fragment`, an IPv6 packet with a fragment header
+    fn ipv6_fragment(id: u32, offset: u16, more_fragments: bool, data: &[u8]) -> Vec<u8> {
+        let mut header = vec![17, 0]; // next header udp, reserved
+        header.extend_from_slice(&((offset << 3) | more_fragments as u16).to_be_bytes());
+        header.extend_from_slice(&id.to_be_bytes());
+        ipv6_packet(44, &[&header, data].concat())
+    }
+
+    fn read_raw(
+        fragments: &mut FragmentReassembler,
+        packet: &[u8],
+        secs: u64,
+    ) -> Option<UdpDatagram> {
+        let packet = PcapPacket::new(Duration::from_secs(secs), packet.len() as u32, packet);
+        fragments.get_udp_datagram(packet, DataLink::RAW).unwrap()
+    }
+
+    #[test]
+    fn truncated_fragments_are_not_kept() {
+        let udp = udp_packet(b"abc.my.fav.metric:1|c");
+        let fragment = ipv4_fragment(1, 0, true, &udp[..16]);
+        let mut fragments = FragmentReassembler::default();
+
+        let packet = PcapPacket::new(Duration::ZERO, fragment.len() as u32 + 8, &fragment);
+        assert!(fragments
+            .get_udp_datagram(packet, DataLink::RAW)
+            .unwrap()
+            .is_none());
+        assert_eq!(fragments.pending_packets(), 0);
+    }
+
+    #[test]
+    fn fragmented_packets_are_reassembled() {
+        let payload = b"abc.my.fav.metric:1|c|#host:foo\nabc.my.other.metric:1|c";
+        let udp = udp_packet(payload);
+        let (first, rest) = udp.split_at(16);
+        let mut fragments = FragmentReassembler::default();
+
+        // in any order
+        assert!(read_raw(&mut fragments, &ipv4_fragment(1, 2, false, rest), 0).is_none());
+        assert_eq!(fragments.pending_packets(), 1);
+        let datagram = read_raw(&mut fragments, &ipv4_fragment(1, 0, true, first), 0).unwrap();
+        assert_eq!(datagram.payload, &payload[..]);
+        assert_eq!(datagram.source, Some("127.0.0.1:36225".parse().unwrap()));
+        assert_eq!(fragments.pending_packets(), 0);
+
+        assert!(read_raw(&mut fragments, &ipv6_fragment(1, 0, true, first), 0).is_none());
+        let datagram = read_raw(&mut fragments, &ipv6_fragment(1, 2, false, rest), 0).unwrap();
+        assert_eq!(datagram.payload, &payload[..]);
+        assert_eq!(datagram.source, Some("[::1]:36225".parse().unwrap()));
+
+        // fragments of packets of different protocols are kept apart
+        let mut tcp_fragment = ipv4_fragment(3, 2, false, rest);
+        tcp_fragment[9] = 6;
+        assert!(read_raw(&mut fragments, &ipv4_fragment(3, 0, true, first), 0).is_none());
+        assert!(read_raw(&mut fragments, &tcp_fragment, 0).is_none());
+        assert_eq!(fragments.pending_packets(), 2);
+        let datagram = read_raw(&mut fragments, &ipv4_fragment(3, 2, false, rest), 0).unwrap();
+        assert_eq!(datagram.payload, &payload[..]);
+        assert_eq!(fragments.pending_packets(), 1);
+
+        // the first fragment is dropped before the last one arrives
+        let mut fragments = FragmentReassembler::new(Duration::from_secs(30));
+        assert!(read_raw(&mut fragments, &ipv4_fragment(2, 0, true, first), 0).is_none());
+        assert!(read_raw(&mut fragments, &ipv4_fragment(2, 2, false, rest), 31).is_none());
+        assert_eq!(fragments.pending_packets(), 1);
+    }
+
+    #[test]
+    fn can_read_udp_from_ipv6_packet() {
+        let datagram = |frame: &[u8]| {
+            get_udp_datagram_from_link_packet(
+                PcapPacket::new(Duration::ZERO, frame.len() as u32, frame),
+                DataLink::ETHERNET,
+            )
+            .unwrap()
+        };
+        let payload = b"abc.my.fav.metric:1|c|#host:foo";
+
+        let frame = ipv6_ethernet_frame(17, &[], payload);
+        let udp = datagram(&frame).unwrap();
+        assert_eq!(udp.payload, &payload[..]);
+        assert_eq!(udp.source, Some("[::1]:36225".parse().unwrap()));
+
+        // hop-by-hop options, then destination options, both padded to 8 bytes
+        let extension_headers = [60, 0, 1, 4, 0, 0, 0, 0, 17, 0, 1, 4, 0, 0, 0, 0];
+        let frame = ipv6_ethernet_frame(0, &extension_headers, payload);
+        assert_eq!(datagram(&frame).unwrap().payload, &payload[..]);
+
+        // a packet in a single fragment is read, a fragment of a larger one isn't
+        let only_fragment = [17, 0, 0x00, 0x00, 0, 0, 0, 1];
+        let frame = ipv6_ethernet_frame(44, &only_fragment, payload);
+        assert_eq!(datagram(&frame).unwrap().payload, &payload[..]);
+        let first_fragment = [17, 0, 0x00, 0x01, 0, 0, 0, 1];
+        let frame = ipv6_ethernet_frame(44, &first_fragment, payload);
+        assert!(datagram(&frame).is_none());
+
+        // tcp
+        let frame = ipv6_ethernet_frame(6, &[], payload);
+        assert!(datagram(&frame).is_none());
+    }
+
+    #[test]
+    fn can_read_udp_from_vlan_tagged_packet() {
+        let untagged = packet_data(PCAP_ETH1_SINGLE_UDP_PACKET);
+        // 802.1Q tag of vlan 100, and QinQ with an outer tag of vlan 10
+        let dot1q: &[u8] = &[0x81, 0x00, 0x00, 0x64];
+        let qinq: &[u8] = &[0x88, 0xa8, 0x00, 0x0a, 0x81, 0x00, 0x00, 0x64];
+        for tags in [dot1q, qinq] {
+            // tags go after the mac addresses, the ethertype of the packet follows them
+            let frame = [&untagged[..12], tags, &untagged[12..]].concat();
+            let packet = PcapPacket::new(Duration::ZERO, frame.len() as u32, &frame);
+            let datagram = get_udp_datagram_from_link_packet(packet, DataLink::ETHERNET)
+                .unwrap()
+                .unwrap();
+            assert_eq!(datagram.payload, &b"abc.my.fav.metric:1|c|#host:foo"[..]);
+        }
+    }
+
+    #[test]
+    fn can_read_udp_from_loopback_and_raw_packets() {
+        // the IPv4 packet of the ethernet frame, after the mac addresses and ethertype
+        let ipv4 = &packet_data(PCAP_ETH1_SINGLE_UDP_PACKET)[14..];
+        let ipv6_frame = ipv6_ethernet_frame(17, &[], b"abc.my.fav.metric:1|c|#host:foo");
+        let ipv6 = &ipv6_frame[14..];
+        let cases: [(DataLink, &[u8], Vec<u8>); 5] = [
+            // AF_INET as written by a little endian host
+            (DataLink::NULL, &[2, 0, 0, 0], ipv4.to_vec()),
+            // AF_INET6 of macOS
+            (DataLink::NULL, &[30, 0, 0, 0], ipv6.to_vec()),
+            (DataLink::LOOP, &[0, 0, 0, 2], ipv4.to_vec()),
+            (DataLink::RAW, &[], ipv6.to_vec()),
+            (DataLink::IPV4, &[], ipv4.to_vec()),
+        ];
+        for (datalink, header, packet) in cases {
+            assert!(is_supported_datalink(datalink));
+            let frame = [header, &packet[..]].concat();
+            let packet = PcapPacket::new(Duration::ZERO, frame.len() as u32, &frame);
+            let datagram = get_udp_datagram_from_link_packet(packet, datalink)
+                .unwrap()
+                .unwrap();
+            assert_eq!(datagram.payload, &b"abc.my.fav.metric:1|c|#host:foo"[..]);
+        }
+    }
+
+    #[test]
+    fn can_read_tcp_segments() {
+        let mut tcp = Vec::new();
+        tcp.extend_from_slice(&36225u16.to_be_bytes()); // source port
+        tcp.extend_from_slice(&8125u16.to_be_bytes()); // destination port
+        tcp.extend_from_slice(&1000u32.to_be_bytes()); // sequence number
+        tcp.extend_from_slice(&0u32.to_be_bytes()); // acknowledgment number
+        tcp.extend_from_slice(&[0x50, 0x19]); // header length, fin psh ack
+        tcp.extend_from_slice(&[0xff, 0xff, 0, 0, 0, 0]); // window, checksum, urgent
+        tcp.extend_from_slice(b"abc.my.fav.metric:1|c\n");
+        let packet = ipv6_packet(6, &tcp);
+        let packet = PcapPacket::new(Duration::ZERO, packet.len() as u32, &packet);
+
+        let mut fragments = FragmentReassembler::default();
+        let Some(TransportPayload::Tcp(segment)) = fragments
+            .get_payload(packet.clone(), DataLink::RAW)
+            .unwrap()
+        else {
+            panic!("expected a TCP segment");
+        };
+        assert_eq!(segment.source, "[::1]:36225".parse().unwrap());
+        assert_eq!(segment.destination, "[::1]:8125".parse().unwrap());
+        assert_eq!(segment.sequence, 1000);
+        assert!(segment.fin && !segment.syn && !segment.rst);
+        assert_eq!(segment.payload, &b"abc.my.fav.metric:1|c\n"[..]);
+
+        // not a UDP packet
+        assert!(fragments
+            .get_udp_datagram(packet, DataLink::RAW)
+            .unwrap()
+            .is_none());
+    }
+
     #[test]
     fn can_reject_utf8() {
         let err = is_pcap(Bytes::from_static(b"abcdefg")).unwrap_err();
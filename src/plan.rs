@@ -0,0 +1,58 @@
+use std::fmt::Display;
+
+/// The resolved inputs, outputs and settings of an invocation, printed by
+/// `--dry-run` instead of doing the work
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionPlan {
+    tool: &'static str,
+    steps: Vec<(&'static str, String)>,
+}
+
+impl ExecutionPlan {
+    pub fn new(tool: &'static str) -> Self {
+        Self {
+            tool,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn step(mut self, name: &'static str, value: impl Display) -> Self {
+        self.steps.push((name, value.to_string()));
+        self
+    }
+}
+
+impl Display for ExecutionPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Dry run of {}, nothing was written or sent", self.tool)?;
+        let width = self.steps.iter().map(|(name, _)| name.len()).max();
+        for (name, value) in &self.steps {
+            writeln!(
+                f,
+                "  {:<width$}  {}",
+                format!("{}:", name),
+                value,
+                width = width.unwrap_or(0) + 1
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_are_aligned() {
+        let plan = ExecutionPlan::new("dsd-cat")
+            .step("input", "capture.zstd (zstd compressed utf-8 text)")
+            .step("max msg size", 1024);
+        assert_eq!(
+            plan.to_string(),
+            "Dry run of dsd-cat, nothing was written or sent\n\
+             \x20 input:         capture.zstd (zstd compressed utf-8 text)\n\
+             \x20 max msg size:  1024\n"
+        );
+    }
+}
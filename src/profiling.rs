@@ -0,0 +1,196 @@
+use std::io::{self, BufRead, Write};
+use std::net::{TcpListener, TcpStream};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ProfilingError {
+    #[error("Profiling needs the 'pprof' feature, rebuild with `--features pprof`")]
+    NotEnabled,
+    #[error("IO Error")]
+    Io(#[from] io::Error),
+}
+
+/// Default length of a cpu profile when the request doesn't give 'seconds'
+const DEFAULT_PROFILE_SECONDS: u64 = 30;
+/// Longest cpu profile served, requests for longer ones are cut to this
+const MAX_PROFILE_SECONDS: u64 = 300;
+
+/// What a request to the profiling server asks for
+#[derive(Debug, PartialEq)]
+enum ProfileRequest {
+    /// A cpu profile sampled for this many seconds
+    Cpu(u64),
+    /// A heap profile, which needs an allocator that records allocations
+    /// (eg jemalloc's profiling), so it is answered as not implemented
+    Heap,
+}
+
+/// Serves cpu profiles of this process in the pprof format on 'addr' from a
+/// background thread, eg
+/// `go tool pprof http://localhost:6060/debug/pprof/profile?seconds=10`.
+/// Profiles are sampled while they are requested, so there is no overhead
+/// until then. Heap profiles are not supported, `/debug/pprof/heap` says so.
+pub fn serve_pprof(addr: &str) -> Result<(), ProfilingError> {
+    if !cfg!(feature = "pprof") {
+        return Err(ProfilingError::NotEnabled);
+    }
+    let listener = TcpListener::bind(addr)?;
+    tracing::info!(
+        "Serving cpu profiles on http://{}/debug/pprof/profile",
+        listener.local_addr()?
+    );
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            // One at a time, only one profiler can run in a process
+            let res = stream.and_then(handle_request);
+            if let Err(e) = res {
+                tracing::warn!("Could not serve profile: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// The profile asked for by a request line like
+/// `GET /debug/pprof/profile?seconds=10 HTTP/1.1`, 'seconds' is clamped to
+/// between 1 and MAX_PROFILE_SECONDS. None if the request is not for a profile.
+fn parse_request(request_line: &str) -> Option<ProfileRequest> {
+    let mut parts = request_line.split_whitespace();
+    if parts.next()? != "GET" {
+        return None;
+    }
+    let target = parts.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    match path {
+        "/debug/pprof/profile" => {}
+        "/debug/pprof/heap" => return Some(ProfileRequest::Heap),
+        _ => return None,
+    }
+    let seconds = query
+        .split('&')
+        .find_map(|param| param.strip_prefix("seconds="))
+        .and_then(|seconds| seconds.parse().ok())
+        .unwrap_or(DEFAULT_PROFILE_SECONDS);
+    Some(ProfileRequest::Cpu(seconds.clamp(1, MAX_PROFILE_SECONDS)))
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+fn handle_request(mut stream: TcpStream) -> io::Result<()> {
+    let mut request_line = String::new();
+    io::BufReader::new(&stream).read_line(&mut request_line)?;
+    let seconds = match parse_request(&request_line) {
+        Some(ProfileRequest::Cpu(seconds)) => seconds,
+        Some(ProfileRequest::Heap) => {
+            return respond(
+                &mut stream,
+                "501 Not Implemented",
+                "text/plain",
+                b"Heap profiles are not supported, only cpu profiles at /debug/pprof/profile\n",
+            )
+        }
+        None => {
+            return respond(
+                &mut stream,
+                "404 Not Found",
+                "text/plain",
+                b"Only /debug/pprof/profile is served\n",
+            )
+        }
+    };
+    match cpu_profile(std::time::Duration::from_secs(seconds)) {
+        Ok(profile) => respond(&mut stream, "200 OK", "application/octet-stream", &profile),
+        Err(e) => respond(
+            &mut stream,
+            "500 Internal Server Error",
+            "text/plain",
+            e.as_bytes(),
+        ),
+    }
+}
+
+/// Samples the cpu for 'duration' and returns the pprof protobuf encoded profile
+#[cfg(feature = "pprof")]
+fn cpu_profile(duration: std::time::Duration) -> Result<Vec<u8>, String> {
+    use pprof::protos::Message;
+
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(99)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .map_err(|e| e.to_string())?;
+    std::thread::sleep(duration);
+    let profile = guard
+        .report()
+        .build()
+        .and_then(|report| report.pprof())
+        .map_err(|e| e.to_string())?;
+    let mut body = Vec::new();
+    profile.encode(&mut body).map_err(|e| e.to_string())?;
+    Ok(body)
+}
+
+#[cfg(not(feature = "pprof"))]
+fn cpu_profile(_duration: std::time::Duration) -> Result<Vec<u8>, String> {
+    Err("Built without the 'pprof' feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_profile_requests() {
+        assert_eq!(
+            parse_request("GET /debug/pprof/profile?seconds=5 HTTP/1.1\r\n"),
+            Some(ProfileRequest::Cpu(5))
+        );
+        assert_eq!(
+            parse_request("GET /debug/pprof/profile HTTP/1.1\r\n"),
+            Some(ProfileRequest::Cpu(DEFAULT_PROFILE_SECONDS))
+        );
+        assert_eq!(
+            parse_request("GET /debug/pprof/profile?debug=1&seconds=x HTTP/1.1\r\n"),
+            Some(ProfileRequest::Cpu(DEFAULT_PROFILE_SECONDS))
+        );
+        assert_eq!(
+            parse_request("GET /debug/pprof/heap HTTP/1.1\r\n"),
+            Some(ProfileRequest::Heap)
+        );
+        assert_eq!(
+            parse_request("GET /debug/pprof/goroutine HTTP/1.1\r\n"),
+            None
+        );
+        assert_eq!(
+            parse_request("POST /debug/pprof/profile HTTP/1.1\r\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn profile_seconds_are_clamped() {
+        assert_eq!(
+            parse_request("GET /debug/pprof/profile?seconds=0 HTTP/1.1\r\n"),
+            Some(ProfileRequest::Cpu(1))
+        );
+        assert_eq!(
+            parse_request("GET /debug/pprof/profile?seconds=999999999 HTTP/1.1\r\n"),
+            Some(ProfileRequest::Cpu(MAX_PROFILE_SECONDS))
+        );
+    }
+}
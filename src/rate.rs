@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use byte_unit::Byte;
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -10,7 +12,36 @@ pub enum RateSpecification {
 
 lazy_static! {
     static ref HZ_RE: Regex = Regex::new(r"(\d+)\s*(hz|HZ)").unwrap();
+    static ref INTERVAL_RE: Regex = Regex::new(r"^(\d+)\s*(ms|s|m|h)$").unwrap();
+}
+
+/// Parses an interval like '500ms', '30s', '5m' or '1h'
+pub fn parse_interval(interval: &str) -> Option<Duration> {
+    let captures = INTERVAL_RE.captures(interval.trim())?;
+    let value = captures.get(1)?.as_str().parse::<u64>().ok()?;
+    match captures.get(2)?.as_str() {
+        "ms" => Some(Duration::from_millis(value)),
+        "s" => Some(Duration::from_secs(value)),
+        "m" => Some(Duration::from_secs(value * 60)),
+        "h" => Some(Duration::from_secs(value * 60 * 60)),
+        _ => None,
+    }
+}
+
+/// Parses a point in time like '2024-05-01T12:00:00Z' (rfc3339) or
+/// '1714564800' (unix seconds) into the time since the unix epoch
+pub fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    let timestamp = timestamp.trim();
+    if let Ok(secs) = timestamp.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let datetime = chrono::DateTime::parse_from_rfc3339(timestamp).ok()?;
+    Some(Duration::new(
+        u64::try_from(datetime.timestamp()).ok()?,
+        datetime.timestamp_subsec_nanos(),
+    ))
 }
+
 pub fn parse_rate(rate: &str) -> Option<RateSpecification> {
     if let Some(hz_captures) = HZ_RE.captures(rate) {
         if let Some(hz_value) = hz_captures.get(1) {
@@ -80,4 +111,33 @@ mod tests {
             Some(RateSpecification::ThroughputBased(100 * 1_000_000))
         );
     }
+
+    #[test]
+    fn interval_string() {
+        assert_eq!(parse_interval("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_interval("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_interval("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_interval("5 m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_interval("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_interval("5"), None);
+        assert_eq!(parse_interval("abcde"), None);
+    }
+
+    #[test]
+    fn timestamp_string() {
+        assert_eq!(
+            parse_timestamp("1714564800"),
+            Some(Duration::from_secs(1714564800))
+        );
+        assert_eq!(
+            parse_timestamp("2024-05-01T12:00:00Z"),
+            Some(Duration::from_secs(1714564800))
+        );
+        assert_eq!(
+            parse_timestamp("2024-05-01T14:00:00.5+02:00"),
+            Some(Duration::from_millis(1714564800500))
+        );
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59Z"), None);
+        assert_eq!(parse_timestamp("5m"), None);
+    }
 }
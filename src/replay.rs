@@ -1,14 +1,21 @@
 use byteorder::{ByteOrder, LittleEndian};
-use std::io::{self, BufRead, Read};
+use std::io::{self, BufRead, Read, Write};
+use std::time::Duration;
 
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::{Buf, Bytes};
 use prost::{DecodeError, Message};
 use tracing::warn;
 
-use crate::dogstatsdreplayreader::dogstatsd::unix::UnixDogstatsdMsg;
+use crate::dogstatsdreader::DEFAULT_MAX_MSG_SIZE;
+use crate::dogstatsdreplayreader::dogstatsd::unix::{TaggerState, UnixDogstatsdMsg};
 
 const DATADOG_HEADER: &[u8] = &[0xD4, 0x74, 0xD0, 0x60];
-const MAX_MSG_SIZE: usize = 8192; // TODO what is the real max size?
+/// Records are read into a buffer that starts at the agent's default UDS
+/// buffer size and grows as larger records are seen, up to the max msg size
+const INITIAL_RECORD_BUF_SIZE: usize = 8192;
+/// Room for the fields of a record besides the payload, ie timestamp, pid and
+/// the ancillary data holding the sender's credentials
+const MAX_RECORD_OVERHEAD: usize = 1024;
 use thiserror::Error;
 
 pub mod dogstatsd {
@@ -17,20 +24,63 @@ pub mod dogstatsd {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CaptureFileVersion {
-    V1, // unsupported
-    V2, // unsupported, first version containing tagger state
+    V1, // records run until the end of the file
+    V2, // first version containing tagger state
     V3, // first version with nanosecond timestamps
 }
 
-// TODO currently missing ability to read tagger state from replay file
-// If this is desired, the length can be found as the last 4 bytes of the replay file
-// Only present in version 2 or greater
+impl CaptureFileVersion {
+    pub fn from_number(version: u8) -> Option<Self> {
+        match version {
+            1 => Some(CaptureFileVersion::V1),
+            2 => Some(CaptureFileVersion::V2),
+            3 => Some(CaptureFileVersion::V3),
+            _ => None,
+        }
+    }
+
+    pub fn number(self) -> u8 {
+        match self {
+            CaptureFileVersion::V1 => 1,
+            CaptureFileVersion::V2 => 2,
+            CaptureFileVersion::V3 => 3,
+        }
+    }
+
+    /// Time since epoch of a record's timestamp, which versions before 3
+    /// recorded in seconds
+    pub fn record_timestamp(self, timestamp: i64) -> Duration {
+        match self {
+            CaptureFileVersion::V1 | CaptureFileVersion::V2 => {
+                Duration::from_secs(timestamp as u64)
+            }
+            CaptureFileVersion::V3 => Duration::from_nanos(timestamp as u64),
+        }
+    }
+}
+
+// Tagger state can be read with `read_tagger_state` once all msgs have been read.
+// Its length can be found as the last 4 bytes of the replay file
+// Only present in version 2 or greater, version 1 files end after the last record
 pub struct ReplayReader<'a> {
     reader: Box<dyn std::io::BufRead + 'a>,
     read_all_unixdogstatsdmsg: bool,
     pub version: CaptureFileVersion,
-    _buf: BytesMut,
+    buf: Vec<u8>,
+    max_msg_size: usize,
+    /// Kept once read, the tagger state can only be read from the file once
+    raw_tagger_state: Option<Vec<u8>>,
+    /// Set when a newer version than supported is decoded with the layout of
+    /// an older one, records that fail to decode are then skipped
+    best_effort: bool,
+    /// Bytes consumed so far, including the header
+    offset: u64,
+    /// Records that were read and decoded
+    records_read: u64,
+    /// The file ended mid-record, there are no more records nor a tagger state
+    truncated: bool,
 }
 
 impl<'a> std::fmt::Debug for ReplayReader<'a> {
@@ -49,10 +99,14 @@ pub enum ReplayReaderError {
     UnsupportedReplayVersion(u8),
     #[error("Unexpected EOF")]
     UnexpectedEof,
+    #[error("File ends mid-record at byte {offset}, after {records_read} records")]
+    Truncated { offset: u64, records_read: u64 },
     #[error("IO Error")]
     Io(io::Error),
     #[error("Protobuf Decode error")]
     ProtoDecode(#[from] DecodeError),
+    #[error("Record of {size} bytes is larger than the max msg size of {max} bytes")]
+    MsgTooLarge { size: usize, max: usize },
 }
 
 impl From<io::Error> for ReplayReaderError {
@@ -70,7 +124,12 @@ impl From<io::Error> for ReplayReaderError {
 /// next 3 bytes are unused
 ///
 /// 8 bytes are always consumed.
-pub fn is_replay(mut header: Bytes) -> Result<(), ReplayReaderError> {
+pub fn is_replay(header: Bytes) -> Result<(), ReplayReaderError> {
+    replay_version(header).map(|_| ())
+}
+
+/// Like `is_replay`, but returns the version of a supported replay file
+pub(crate) fn replay_version(mut header: Bytes) -> Result<CaptureFileVersion, ReplayReaderError> {
     assert!(header.len() >= 8);
 
     let first_four = header.slice(0..4);
@@ -83,90 +142,371 @@ pub fn is_replay(mut header: Bytes) -> Result<(), ReplayReaderError> {
     // f0 is bitwise or'd with the file version, so to get the file version, do a bitwise xor
     let version = header.get_u8() ^ 0xF0;
 
-    if version != 3 {
+    let Some(version) = CaptureFileVersion::from_number(version) else {
         header.advance(3); // consume next 3 bytes per contract
         return Err(ReplayReaderError::UnsupportedReplayVersion(version));
-    }
+    };
     header.advance(3); // consume next 3 bytes per contract
+    Ok(version)
+}
+
+/// A corrupt record length would otherwise allocate up to 4GB
+pub(crate) fn check_record_length(
+    message_length: usize,
+    max_msg_size: usize,
+) -> Result<(), ReplayReaderError> {
+    if message_length > max_msg_size + MAX_RECORD_OVERHEAD {
+        return Err(ReplayReaderError::MsgTooLarge {
+            size: message_length,
+            max: max_msg_size,
+        });
+    }
     Ok(())
 }
 
+/// Decodes a single record using the provided .proto file, records with a
+/// payload larger than 'max_msg_size' are an error
+pub(crate) fn decode_record(
+    record: &[u8],
+    max_msg_size: usize,
+) -> Result<UnixDogstatsdMsg, ReplayReaderError> {
+    let msg = UnixDogstatsdMsg::decode(record)?;
+    if msg.payload.len() > max_msg_size {
+        return Err(ReplayReaderError::MsgTooLarge {
+            size: msg.payload.len(),
+            max: max_msg_size,
+        });
+    }
+    Ok(msg)
+}
+
 impl<'a> ReplayReader<'a> {
     pub fn supported_versions() -> &'static [u8] {
-        &[3]
+        &[1, 2, 3]
+    }
+
+    /// Version of the file, or the version it is decoded as when forced
+    pub fn version(&self) -> CaptureFileVersion {
+        self.version
     }
-    /// read_msg will return the next UnixDogstatsdMsg if it exists
+
+    /// Records read and decoded so far
+    pub fn records_read(&self) -> u64 {
+        self.records_read
+    }
+
+    /// Offset of the next record in the file, including the header. Once
+    /// every record was read, this is where the records end.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// For a reader of a file that was opened at 'offset', rather than at
+    /// its first record, after 'records_read' records
+    pub(crate) fn resume_at(&mut self, offset: u64, records_read: u64) {
+        self.offset = offset;
+        self.records_read = records_read;
+    }
+
+    /// read_msg will return the next UnixDogstatsdMsg if it exists. A file
+    /// that ends mid-record is a `Truncated` error, after which there are no
+    /// more msgs.
     pub fn read_msg(&mut self) -> Result<Option<UnixDogstatsdMsg>, ReplayReaderError> {
+        loop {
+            match self.read_record() {
+                Err(ReplayReaderError::ProtoDecode(e)) if self.best_effort => {
+                    warn!("Skipping record that doesn't decode as the forced replay version: {e}");
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<UnixDogstatsdMsg>, ReplayReaderError> {
         if self.read_all_unixdogstatsdmsg {
             return Ok(None);
         }
+        // Version 1 has no record separator, the records end with the file
+        if self.version == CaptureFileVersion::V1 && self.reader.fill_buf()?.is_empty() {
+            self.read_all_unixdogstatsdmsg = true;
+            return Ok(None);
+        }
 
         // Read the little endian uint32 that gives the length of the next protobuf message
 
         let mut msg_length_buf = [0; 4];
-        self.reader.read_exact(&mut msg_length_buf)?;
+        self.reader
+            .read_exact(&mut msg_length_buf)
+            .map_err(|e| self.truncation(e))?;
 
         let message_length = LittleEndian::read_u32(&msg_length_buf) as usize;
 
         if message_length == 0 {
             // This indicates a record separator between UnixDogStatsdMsg list
             // and the tagger state. Next bytes are all for tagger state.
+            // The offset stays at the separator, where the records end
             self.read_all_unixdogstatsdmsg = true;
             return Ok(None);
         }
 
-        // Read the protobuf message
-        // todo avoid this allocation by using the BytesMut stored in self
-        let mut msg_buf = vec![0; message_length];
-        self.reader.read_exact(&mut msg_buf)?;
+        check_record_length(message_length, self.max_msg_size)?;
 
-        let msg_buf = Bytes::from(msg_buf);
+        // Read the protobuf message, reusing the buffer from previous records
+        self.buf.resize(message_length, 0);
+        self.reader
+            .read_exact(&mut self.buf)
+            .map_err(|e| self.truncation(e))?;
+        self.offset += 4 + message_length as u64;
 
-        // Decode the protobuf message using the provided .proto file
-        match UnixDogstatsdMsg::decode(msg_buf) {
-            Ok(msg) => Ok(Some(msg)),
-            Err(e) => {
+        let record = decode_record(&self.buf, self.max_msg_size);
+        if record.is_ok() {
+            self.records_read += 1;
+        }
+        match record {
+            Err(ReplayReaderError::ProtoDecode(e)) if !self.best_effort => {
                 warn!(
                     "Unexpected error decoding msg buf: {} do you have a valid dsd capture file?",
                     e
                 );
                 Err(e.into())
             }
+            result => result.map(Some),
+        }
+    }
+
+    /// An EOF within a record means the file was cut short, eg copied while
+    /// the agent was still writing it. Nothing is read after it.
+    fn truncation(&mut self, e: io::Error) -> ReplayReaderError {
+        if e.kind() != io::ErrorKind::UnexpectedEof {
+            return ReplayReaderError::Io(e);
+        }
+        self.read_all_unixdogstatsdmsg = true;
+        self.truncated = true;
+        ReplayReaderError::Truncated {
+            offset: self.offset,
+            records_read: self.records_read,
+        }
+    }
+
+    /// read_tagger_state returns the raw tagger state stored after the msgs.
+    /// Only valid once `read_msg` has returned `None`, reads until EOF.
+    /// Version 1 files and truncated files have no tagger state, it is always
+    /// empty.
+    pub fn read_tagger_state(&mut self) -> Result<Vec<u8>, ReplayReaderError> {
+        if !self.read_all_unixdogstatsdmsg
+            || self.truncated
+            || self.version == CaptureFileVersion::V1
+        {
+            return Ok(Vec::new());
+        }
+        if let Some(ref state) = self.raw_tagger_state {
+            return Ok(state.clone());
+        }
+        let mut rest = Vec::new();
+        self.reader.read_to_end(&mut rest)?;
+        if rest.len() < 4 {
+            return Err(ReplayReaderError::UnexpectedEof);
+        }
+        let state_length = LittleEndian::read_u32(&rest[rest.len() - 4..]) as usize;
+        if state_length > rest.len() - 4 {
+            return Err(ReplayReaderError::UnexpectedEof);
+        }
+        rest.truncate(state_length);
+        self.raw_tagger_state = Some(rest.clone());
+        Ok(rest)
+    }
+
+    /// The decoded tagger state, ie the tags the agent held for every entity
+    /// and the entity of every sending pid when the capture was taken. Like
+    /// `read_tagger_state` only available once `read_msg` has returned
+    /// `None`, None when the file has no tagger state.
+    pub fn tagger_state(&mut self) -> Result<Option<TaggerState>, ReplayReaderError> {
+        let raw = self.read_tagger_state()?;
+        if raw.is_empty() {
+            return Ok(None);
         }
+        Ok(Some(TaggerState::decode(&raw[..])?))
     }
 
     // consumes 8 bytes during construction, even if construction fails
     pub fn new(byte_reader: impl BufRead + 'a) -> Result<Self, ReplayReaderError> {
+        Self::open(byte_reader, None)
+    }
+
+    /// Like `new`, but a file of an unsupported version is decoded as if it
+    /// was 'version' instead of being an error. This is best effort, records
+    /// that don't decode are skipped with a warning.
+    pub fn new_forcing_version(
+        byte_reader: impl BufRead + 'a,
+        version: u8,
+    ) -> Result<Self, ReplayReaderError> {
+        if !Self::supported_versions().contains(&version) {
+            return Err(ReplayReaderError::UnsupportedReplayVersion(version));
+        }
+        Self::open(byte_reader, Some(version))
+    }
+
+    fn open(
+        byte_reader: impl BufRead + 'a,
+        forced_version: Option<u8>,
+    ) -> Result<Self, ReplayReaderError> {
         let mut byte_reader: Box<dyn std::io::BufRead + 'a> = Box::new(byte_reader);
         let mut header_buf = [0; 8];
         byte_reader.read_exact(&mut header_buf)?;
-        is_replay(Bytes::copy_from_slice(&header_buf))?;
+        let header = replay_version(Bytes::copy_from_slice(&header_buf));
+        let (version, best_effort) = match (header, forced_version) {
+            (Ok(version), _) => (version, false),
+            (Err(ReplayReaderError::UnsupportedReplayVersion(found)), Some(forced)) => {
+                warn!("Replay file is version {found}, decoding it as version {forced}. Records that don't decode are skipped and the results may be incomplete.");
+                let forced = CaptureFileVersion::from_number(forced)
+                    .ok_or(ReplayReaderError::UnsupportedReplayVersion(forced))?;
+                (forced, true)
+            }
+            (Err(e), _) => return Err(e),
+        };
 
         Ok(Self {
             reader: byte_reader,
             read_all_unixdogstatsdmsg: false,
-            version: CaptureFileVersion::V3,
-            _buf: BytesMut::with_capacity(MAX_MSG_SIZE),
+            version,
+            buf: Vec::with_capacity(INITIAL_RECORD_BUF_SIZE),
+            max_msg_size: DEFAULT_MAX_MSG_SIZE,
+            raw_tagger_state: None,
+            best_effort,
+            offset: header_buf.len() as u64,
+            records_read: 0,
+            truncated: false,
         })
     }
+
+    /// Records with a payload larger than 'max_msg_size' are an error rather
+    /// than being read, defaults to `DEFAULT_MAX_MSG_SIZE`
+    pub fn with_max_msg_size(mut self, max_msg_size: usize) -> Self {
+        self.max_msg_size = max_msg_size;
+        self
+    }
+}
+
+/// Writes a v3 dogstatsd replay file.
+///
+/// The header is written on construction, each `write_msg` appends one
+/// length-prefixed record and `finish` appends the record separator followed
+/// by the tagger state and its length.
+pub struct ReplayWriter<W: Write> {
+    writer: W,
+    bytes_written: u64,
+}
+
+impl<W: Write> ReplayWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self, io::Error> {
+        writer.write_all(DATADOG_HEADER)?;
+        // version byte is or'd with 0xF0, remaining 3 bytes are unused
+        writer.write_all(&[0xF3, 0xFF, 0x00, 0x00])?;
+        Ok(Self {
+            writer,
+            bytes_written: 8,
+        })
+    }
+
+    /// Appends a single record, returns the number of bytes written
+    pub fn write_msg(&mut self, msg: &UnixDogstatsdMsg) -> Result<usize, io::Error> {
+        let msg_buf = msg.encode_to_vec();
+        let mut msg_length_buf = [0; 4];
+        LittleEndian::write_u32(&mut msg_length_buf, msg_buf.len() as u32);
+        self.writer.write_all(&msg_length_buf)?;
+        self.writer.write_all(&msg_buf)?;
+
+        let written = msg_length_buf.len() + msg_buf.len();
+        self.bytes_written += written as u64;
+        Ok(written)
+    }
+
+    /// Total bytes written so far, including the header
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Flushes and returns the underlying writer without writing the trailer.
+    /// `write_trailer` must be used later for the file to be complete.
+    pub fn into_inner(mut self) -> Result<W, io::Error> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Writes the trailer with the given tagger state and returns the underlying writer
+    pub fn finish(mut self, tagger_state: &[u8]) -> Result<W, io::Error> {
+        write_trailer(&mut self.writer, tagger_state)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
 }
 
-/*
-pub struct ReplayAssembler {
-    buf: Bytes,
+/// Writes the record separator, tagger state and tagger state length that
+/// terminate a replay file.
+pub fn write_trailer(mut writer: impl Write, tagger_state: &[u8]) -> Result<(), io::Error> {
+    let mut u32_buf = [0; 4];
+    // 0-length record separates the msgs from the tagger state
+    writer.write_all(&u32_buf)?;
+    writer.write_all(tagger_state)?;
+    LittleEndian::write_u32(&mut u32_buf, tagger_state.len() as u32);
+    writer.write_all(&u32_buf)?;
+    Ok(())
 }
 
-impl ReplayAssembler {
-    pub fn new() {
+// Control message constants as defined on linux
+const SOL_SOCKET: i32 = 1;
+const SCM_CREDENTIALS: i32 = 2;
+// cmsg_len (usize), cmsg_level (i32) and cmsg_type (i32) on 64 bit linux
+const CMSG_HEADER_LEN: usize = 16;
+// pid (i32), uid (u32) and gid (u32)
+const UCRED_LEN: usize = 12;
+
+/// Credentials of the process that sent a packet over a unix socket
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UnixCredentials {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
 
-        Self {
-            buf: Bytes::new(),
+/// Finds the SCM_CREDENTIALS control message in the ancillary data recorded
+/// alongside a packet. Returns None when no credentials were captured or the
+/// data is malformed.
+pub fn decode_credentials(ancillary: &[u8]) -> Option<UnixCredentials> {
+    let mut rest = ancillary;
+    while rest.len() >= CMSG_HEADER_LEN {
+        let cmsg_len = LittleEndian::read_u64(&rest[0..8]) as usize;
+        let cmsg_level = LittleEndian::read_i32(&rest[8..12]);
+        let cmsg_type = LittleEndian::read_i32(&rest[12..16]);
+        if cmsg_len < CMSG_HEADER_LEN || cmsg_len > rest.len() {
+            return None;
+        }
+        if cmsg_level == SOL_SOCKET
+            && cmsg_type == SCM_CREDENTIALS
+            && cmsg_len >= CMSG_HEADER_LEN + UCRED_LEN
+        {
+            let ucred = &rest[CMSG_HEADER_LEN..CMSG_HEADER_LEN + UCRED_LEN];
+            return Some(UnixCredentials {
+                pid: LittleEndian::read_i32(&ucred[0..4]),
+                uid: LittleEndian::read_u32(&ucred[4..8]),
+                gid: LittleEndian::read_u32(&ucred[8..12]),
+            });
         }
+        // each control message is padded to an 8 byte boundary
+        rest = rest.get((cmsg_len + 7) & !7..)?;
     }
-    pub fn add_msg(msg: UnixDogstatsdMsg) {}
+    None
+}
 
-    pub fn finalize() -> Bytes {}
-} */
+/// Credentials carried by a single record, if any were captured
+pub fn msg_credentials(msg: &UnixDogstatsdMsg) -> Option<UnixCredentials> {
+    let ancillary_size = usize::try_from(msg.ancillary_size).unwrap_or(0);
+    decode_credentials(
+        msg.ancillary
+            .get(..ancillary_size)
+            .unwrap_or(&msg.ancillary),
+    )
+}
 
 #[cfg(test)]
 mod tests {
@@ -279,6 +619,182 @@ mod tests {
         assert_eq!(None, replay.read_msg().unwrap())
     }
 
+    /// Control message as the kernel lays it out for a SCM_CREDENTIALS cmsg
+    fn credentials_cmsg(pid: i32, uid: u32, gid: u32) -> Vec<u8> {
+        let mut cmsg = vec![0; 32];
+        LittleEndian::write_u64(&mut cmsg[0..8], 28);
+        LittleEndian::write_i32(&mut cmsg[8..12], SOL_SOCKET);
+        LittleEndian::write_i32(&mut cmsg[12..16], SCM_CREDENTIALS);
+        LittleEndian::write_i32(&mut cmsg[16..20], pid);
+        LittleEndian::write_u32(&mut cmsg[20..24], uid);
+        LittleEndian::write_u32(&mut cmsg[24..28], gid);
+        cmsg
+    }
+
+    #[test]
+    fn credentials_are_decoded() {
+        let expected = UnixCredentials {
+            pid: 4242,
+            uid: 1000,
+            gid: 100,
+        };
+        assert_eq!(
+            decode_credentials(&credentials_cmsg(4242, 1000, 100)),
+            Some(expected)
+        );
+
+        // credentials following some other control message
+        let mut ancillary = vec![0; 24];
+        LittleEndian::write_u64(&mut ancillary[0..8], 20);
+        LittleEndian::write_i32(&mut ancillary[8..12], SOL_SOCKET);
+        LittleEndian::write_i32(&mut ancillary[12..16], 1);
+        ancillary.extend(credentials_cmsg(4242, 1000, 100));
+        assert_eq!(decode_credentials(&ancillary), Some(expected));
+
+        assert_eq!(decode_credentials(&[]), None);
+        assert_eq!(decode_credentials(&credentials_cmsg(1, 2, 3)[..20]), None);
+    }
+
+    #[test]
+    fn writer_round_trip() {
+        let mut writer = ReplayWriter::new(Vec::new()).unwrap();
+        let mut replay = ReplayReader::new(TWO_MSGS_ONE_LINE_EACH).unwrap();
+        while let Some(msg) = replay.read_msg().unwrap() {
+            writer.write_msg(&msg).unwrap();
+        }
+        let written = writer.finish(&[]).unwrap();
+        assert_eq!(TWO_MSGS_ONE_LINE_EACH, &written[..]);
+    }
+
+    #[test]
+    fn tagger_state_is_read_after_msgs() {
+        let msg = UnixDogstatsdMsg {
+            payload: b"my.metric:1|g\n".to_vec(),
+            payload_size: 14,
+            ..Default::default()
+        };
+
+        let mut writer = ReplayWriter::new(Vec::new()).unwrap();
+        writer.write_msg(&msg).unwrap();
+        let written = writer.finish(b"tagger").unwrap();
+
+        let mut replay = ReplayReader::new(&written[..]).unwrap();
+        assert_eq!(replay.read_msg().unwrap(), Some(msg));
+        assert_eq!(replay.read_msg().unwrap(), None);
+        assert_eq!(replay.read_tagger_state().unwrap(), b"tagger".to_vec());
+    }
+
+    #[test]
+    fn tagger_state_is_decoded() {
+        use crate::dogstatsdreplayreader::dogstatsd::unix::{Entity, EntityId};
+
+        let entity = Entity {
+            id: Some(EntityId {
+                prefix: "container_id".to_string(),
+                uid: "abc123".to_string(),
+            }),
+            low_cardinality_tags: vec!["image_name:nginx".to_string()],
+            high_cardinality_tags: vec!["container_id:abc123".to_string()],
+            ..Default::default()
+        };
+        let state = TaggerState {
+            state: [("container_id://abc123".to_string(), entity)].into(),
+            pid_map: [(4242, "container_id://abc123".to_string())].into(),
+        };
+        let writer = ReplayWriter::new(Vec::new()).unwrap();
+        let written = writer.finish(&state.encode_to_vec()).unwrap();
+
+        let mut replay = ReplayReader::new(&written[..]).unwrap();
+        assert_eq!(replay.tagger_state().unwrap(), None);
+        assert_eq!(replay.read_msg().unwrap(), None);
+        assert_eq!(replay.tagger_state().unwrap(), Some(state.clone()));
+        // the raw state is kept, it can be asked for again
+        assert_eq!(replay.read_tagger_state().unwrap(), state.encode_to_vec());
+
+        let empty = ReplayWriter::new(Vec::new()).unwrap().finish(&[]).unwrap();
+        let mut replay = ReplayReader::new(&empty[..]).unwrap();
+        assert_eq!(replay.read_msg().unwrap(), None);
+        assert_eq!(replay.tagger_state().unwrap(), None);
+    }
+
+    #[test]
+    fn older_versions_are_read() {
+        let msg = UnixDogstatsdMsg {
+            payload: b"my.metric:1|g\n".to_vec(),
+            payload_size: 14,
+            timestamp: 1692823177,
+            ..Default::default()
+        };
+
+        let mut writer = ReplayWriter::new(Vec::new()).unwrap();
+        writer.write_msg(&msg).unwrap();
+        let mut v2 = writer.finish(b"tagger").unwrap();
+        v2[4] = 0xF2;
+        let mut replay = ReplayReader::new(&v2[..]).unwrap();
+        assert_eq!(replay.version(), CaptureFileVersion::V2);
+        assert_eq!(replay.read_msg().unwrap(), Some(msg.clone()));
+        assert_eq!(replay.read_msg().unwrap(), None);
+        assert_eq!(replay.read_tagger_state().unwrap(), b"tagger".to_vec());
+
+        // no record separator or tagger state, the file ends after the records
+        let mut writer = ReplayWriter::new(Vec::new()).unwrap();
+        writer.write_msg(&msg).unwrap();
+        writer.write_msg(&msg).unwrap();
+        let mut v1 = writer.into_inner().unwrap();
+        v1[4] = 0xF1;
+        let mut replay = ReplayReader::new(&v1[..]).unwrap();
+        assert_eq!(replay.version(), CaptureFileVersion::V1);
+        assert_eq!(replay.read_msg().unwrap(), Some(msg.clone()));
+        assert_eq!(replay.read_msg().unwrap(), Some(msg));
+        assert_eq!(replay.read_msg().unwrap(), None);
+        assert!(replay.read_tagger_state().unwrap().is_empty());
+
+        assert_eq!(
+            CaptureFileVersion::V1.record_timestamp(1692823177),
+            Duration::from_secs(1692823177)
+        );
+        assert_eq!(
+            CaptureFileVersion::V3.record_timestamp(1692823177480253700),
+            Duration::from_nanos(1692823177480253700)
+        );
+    }
+
+    #[test]
+    fn records_larger_than_max_msg_size_are_errors() {
+        let large = UnixDogstatsdMsg {
+            payload: vec![b'a'; 20_000],
+            payload_size: 20_000,
+            ..Default::default()
+        };
+        let mut writer = ReplayWriter::new(Vec::new()).unwrap();
+        writer.write_msg(&large).unwrap();
+        let written = writer.finish(&[]).unwrap();
+
+        // larger than the initial buffer, but within the limit
+        let mut replay = ReplayReader::new(&written[..]).unwrap();
+        assert_eq!(replay.read_msg().unwrap(), Some(large));
+
+        let mut replay = ReplayReader::new(&written[..])
+            .unwrap()
+            .with_max_msg_size(19_500);
+        assert!(matches!(
+            replay.read_msg(),
+            Err(ReplayReaderError::MsgTooLarge {
+                size: 20_000,
+                max: 19_500
+            })
+        ));
+
+        // the record length is checked before anything is read
+        let mut replay = ReplayReader::new(&written[..])
+            .unwrap()
+            .with_max_msg_size(1024);
+        assert!(matches!(
+            replay.read_msg(),
+            Err(ReplayReaderError::MsgTooLarge { max: 1024, .. })
+        ));
+    }
+
     #[test]
     fn invalid_replay_bytes() {
         let replay = ReplayReader::new(&b"my.metric:1|g\n"[..]);
@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, HashMap};
+
+use minijinja::Environment;
+use rand::Rng;
+use serde::Serialize;
+use sketches_ddsketch::DDSketch;
+use thiserror::Error;
+
+use crate::analysis::{DogStatsDBatchStats, KindShare, MetricTypeConflict, NameContexts};
+
+#[derive(Error, Debug)]
+pub enum ReportError {
+    #[error("Report template: {0}")]
+    Template(#[from] minijinja::Error),
+}
+
+/// Summary of a sketch that is easy to use from a template, the serialized
+/// sketch itself is only useful for merging.
+/// Every field but 'count' is None when the sketch is empty.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SketchSummary {
+    pub count: usize,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    pub p50: Option<f64>,
+    pub p90: Option<f64>,
+    pub p99: Option<f64>,
+}
+
+impl From<&DDSketch> for SketchSummary {
+    fn from(sketch: &DDSketch) -> Self {
+        let quantile = |q| sketch.quantile(q).ok().flatten();
+        SketchSummary {
+            count: sketch.count(),
+            min: sketch.min(),
+            max: sketch.max(),
+            mean: sketch.sum().map(|sum| sum / sketch.count() as f64),
+            p50: quantile(0.5),
+            p90: quantile(0.9),
+            p99: quantile(0.99),
+        }
+    }
+}
+
+/// Everything available to a report template.
+///
+/// 'stats' is the full analysis as serialized by serde, the other fields are
+/// derived from it so templates don't have to re-implement them.
+#[derive(Serialize)]
+pub struct ReportModel<'a> {
+    pub stats: &'a DogStatsDBatchStats,
+    pub sketches: BTreeMap<&'static str, SketchSummary>,
+    pub kind_shares: Vec<KindShare>,
+    pub metric_type_conflicts: Vec<MetricTypeConflict>,
+    /// Every metric name with its number of contexts, most contexts first
+    pub contexts_per_name: Vec<NameContexts>,
+    pub capture_health_warnings: Vec<String>,
+    /// Seconds covered by the input, when known
+    pub duration_secs: Option<f64>,
+    /// What the input was detected as, eg 'zstd compressed dogstatsd-replay'
+    pub input_type: Option<String>,
+}
+
+impl<'a> ReportModel<'a> {
+    pub fn new(stats: &'a DogStatsDBatchStats) -> Self {
+        let sketches = BTreeMap::from([
+            ("name_length", (&stats.name_length).into()),
+            ("num_values", (&stats.num_values).into()),
+            ("value_range", (&stats.value_range).into()),
+            ("num_tags", (&stats.num_tags).into()),
+            ("tag_total_length", (&stats.tag_total_length).into()),
+            ("num_unicode_tags", (&stats.num_unicode_tags).into()),
+        ]);
+        ReportModel {
+            stats,
+            sketches,
+            kind_shares: stats.kind_shares(),
+            metric_type_conflicts: stats.metric_type_conflicts(),
+            contexts_per_name: stats.contexts_per_name(),
+            capture_health_warnings: stats
+                .reader_analytics
+                .as_ref()
+                .map(|a| a.capture_health_warnings())
+                .unwrap_or_default(),
+            duration_secs: stats
+                .reader_analytics
+                .as_ref()
+                .map(|a| a.duration().as_secs_f64()),
+            input_type: stats.input_type.as_ref().map(|t| t.to_string()),
+        }
+    }
+}
+
+/// Renders a minijinja (jinja2 syntax) 'template' against the `ReportModel` of 'stats'
+pub fn render_report(template: &str, stats: &DogStatsDBatchStats) -> Result<String, ReportError> {
+    let mut env = Environment::new();
+    env.add_template("report", template)?;
+    let report = env
+        .get_template("report")?
+        .render(ReportModel::new(stats))?;
+    Ok(report)
+}
+
+/// Options for making an analysis safe to share outside the org, by default
+/// nothing is changed
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PrivacyOptions {
+    /// Adds laplace noise with a scale of 1/epsilon to every per-name count,
+    /// smaller values add more noise. Each count gets its own noise, so a
+    /// report that exposes N counts a msg contributed to spends N * epsilon.
+    pub noise_epsilon: Option<f64>,
+    /// Metric names, tags and hosts seen less than this many times (after
+    /// noise is added) are removed
+    pub redact_below: u32,
+}
+
+impl PrivacyOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.noise_epsilon.is_some() || self.redact_below > 0
+    }
+}
+
+/// Samples the laplace distribution centered on 0 by inverting its CDF
+fn laplace_noise<R: Rng>(rng: &mut R, scale: f64) -> f64 {
+    let u: f64 = rng.gen_range(-0.5..0.5);
+    // u of exactly -0.5 would be ln(0)
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).max(f64::MIN_POSITIVE).ln()
+}
+
+fn noisy_count<R: Rng>(count: u32, epsilon: Option<f64>, rng: &mut R) -> u32 {
+    match epsilon {
+        Some(epsilon) => (count as f64 + laplace_noise(rng, 1.0 / epsilon))
+            .round()
+            .clamp(0.0, u32::MAX as f64) as u32,
+        None => count,
+    }
+}
+
+fn privatize_counts<R: Rng>(
+    counts: &mut HashMap<String, u32>,
+    options: &PrivacyOptions,
+    rng: &mut R,
+) -> usize {
+    let before = counts.len();
+    counts.retain(|_, count| {
+        *count = noisy_count(*count, options.noise_epsilon, rng);
+        *count >= options.redact_below.max(1)
+    });
+    before - counts.len()
+}
+
+/// Applies 'options' to the per-name counts of 'stats' (metric names, unique
+/// tags and hostnames) and drops the raw suspicious msg samples, returns how
+/// many names were redacted
+pub fn privatize<R: Rng>(
+    stats: &mut DogStatsDBatchStats,
+    options: &PrivacyOptions,
+    rng: &mut R,
+) -> usize {
+    if !options.is_enabled() {
+        return 0;
+    }
+    let mut redacted = 0;
+    let names_before = stats.metric_types_by_name.len();
+    stats.metric_types_by_name.retain(|_, types| {
+        types.retain(|_, count| {
+            *count = noisy_count(*count, options.noise_epsilon, rng);
+            *count > 0
+        });
+        types.values().sum::<u32>() >= options.redact_below.max(1)
+    });
+    redacted += names_before - stats.metric_types_by_name.len();
+    // Merging would count the contexts of the keys again, without the noise
+    stats.context_keys.clear();
+    let names = &stats.metric_types_by_name;
+    stats.contexts_by_name.retain(|name, contexts| {
+        *contexts = noisy_count(*contexts, options.noise_epsilon, rng);
+        *contexts > 0 && names.contains_key(name)
+    });
+    redacted += privatize_counts(&mut stats.unique_tags, options, rng);
+    redacted += privatize_counts(&mut stats.hostnames, options, rng);
+    // Samples are whole msgs, with whatever rare names and tags they carry
+    stats.suspicious_samples.clear();
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analysis::analyze_msgs, dogstatsdmsg::DogStatsDMetricType, dogstatsdreader::DogStatsDReader,
+    };
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    fn analyze(payload: &[u8]) -> DogStatsDBatchStats {
+        let mut reader = DogStatsDReader::new(payload).unwrap();
+        analyze_msgs(&mut reader).unwrap()
+    }
+
+    #[test]
+    fn renders_model_fields() {
+        let stats = analyze(b"a:1|c|#env:prod\na:1|g\n_sc|my.check|0\n");
+        let template = "\
+# {{ stats.num_msgs }} msgs, {{ stats.num_contexts }} contexts
+{% for share in kind_shares %}- {{ share.kind }}: {{ share.count }}
+{% endfor %}{% for conflict in metric_type_conflicts %}conflict: {{ conflict.name }}
+{% endfor %}tags p50: {{ sketches.num_tags.p50 is none }}";
+        let report = render_report(template, &stats).unwrap();
+        assert_eq!(
+            report,
+            "# 3 msgs, 2 contexts\n- Metric: 2\n- ServiceCheck: 1\nconflict: a\ntags p50: false"
+        );
+    }
+
+    #[test]
+    fn privatize_redacts_rare_names() {
+        let mut stats = analyze(b"a:1|c|#env:prod\na:1|c|#env:prod\nrare:1|c|@0|#feature:secret\n");
+        assert_eq!(stats.suspicious_samples.len(), 1);
+        let options = PrivacyOptions {
+            noise_epsilon: None,
+            redact_below: 2,
+        };
+        let redacted = privatize(&mut stats, &options, &mut SmallRng::seed_from_u64(1));
+        assert_eq!(redacted, 2);
+        assert!(stats.metric_types_by_name.contains_key("a"));
+        assert!(!stats.metric_types_by_name.contains_key("rare"));
+        assert!(!stats.contexts_by_name.contains_key("rare"));
+        assert!(stats.suspicious_samples.is_empty());
+        assert_eq!(
+            stats.unique_tags,
+            HashMap::from([("env:prod".to_string(), 2)])
+        );
+
+        let mut untouched = analyze(b"rare:1|c\n");
+        let redacted = privatize(
+            &mut untouched,
+            &PrivacyOptions::default(),
+            &mut SmallRng::seed_from_u64(1),
+        );
+        assert_eq!(redacted, 0);
+        assert_eq!(untouched.metric_types_by_name.len(), 1);
+    }
+
+    #[test]
+    fn privatize_adds_noise() {
+        let mut payload = Vec::new();
+        for _ in 0..1000 {
+            payload.extend_from_slice(b"a:1|c\n");
+        }
+        let mut stats = analyze(&payload);
+        let options = PrivacyOptions {
+            noise_epsilon: Some(1.0),
+            redact_below: 0,
+        };
+        privatize(&mut stats, &options, &mut SmallRng::seed_from_u64(7));
+        let count = stats.metric_types_by_name["a"][&DogStatsDMetricType::Count];
+        // the chance of laplace(1) noise of 50 or more is e^-50
+        assert!((950..=1050).contains(&count));
+
+        let mut rng = SmallRng::seed_from_u64(7);
+        let noise: Vec<f64> = (0..1000).map(|_| laplace_noise(&mut rng, 1.0)).collect();
+        assert!(noise.iter().all(|n| n.is_finite()));
+        assert!(noise.iter().any(|n| *n != 0.0));
+    }
+
+    #[test]
+    fn invalid_template_is_an_error() {
+        let stats = analyze(b"a:1|c\n");
+        assert!(render_report("{% for %}", &stats).is_err());
+    }
+}
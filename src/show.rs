@@ -0,0 +1,151 @@
+use std::{collections::VecDeque, time::Duration};
+
+use chrono::{SecondsFormat, TimeZone, Utc};
+
+use crate::dogstatsdmsg::DogStatsDMsg;
+
+/// A msg picked out by `ContextWindow`, either a match or context around one
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShownMsg {
+    /// Position of the msg in capture order, starting at 0
+    pub index: u64,
+    /// Capture time, None for inputs that don't record one
+    pub timestamp: Option<Duration>,
+    pub raw: String,
+    /// Whether this msg matched, rather than being context around a match
+    pub matched: bool,
+}
+
+impl ShownMsg {
+    /// Capture time as rfc3339 with microseconds, or '-' when there is none
+    pub fn timestamp_str(&self) -> String {
+        self.timestamp
+            .and_then(|ts| {
+                Utc.timestamp_opt(ts.as_secs() as i64, ts.subsec_nanos())
+                    .single()
+            })
+            .map(|datetime| datetime.to_rfc3339_opts(SecondsFormat::Micros, true))
+            .unwrap_or_else(|| "-".to_string())
+    }
+}
+
+/// Whether 'msg' is a metric or service check named 'name'
+pub fn msg_has_name(msg: &DogStatsDMsg, name: &str) -> bool {
+    match msg {
+        DogStatsDMsg::Metric(m) => m.name == name,
+        DogStatsDMsg::ServiceCheck(sc) => sc.name == name,
+        DogStatsDMsg::Event(_) => false,
+    }
+}
+
+/// Picks out matching msgs along with up to 'context' msgs before and after
+/// each, like `grep -C`. Windows that overlap are merged so every msg is
+/// shown at most once.
+pub struct ContextWindow {
+    context: usize,
+    /// Msgs not shown yet that may end up as context before the next match
+    before: VecDeque<ShownMsg>,
+    /// How many more msgs to show as context after the last match
+    after_remaining: usize,
+    next_index: u64,
+}
+
+impl ContextWindow {
+    pub fn new(context: usize) -> Self {
+        Self {
+            context,
+            before: VecDeque::with_capacity(context),
+            after_remaining: 0,
+            next_index: 0,
+        }
+    }
+
+    /// Feeds the next msg in capture order, returns the msgs that should be
+    /// shown now, oldest first
+    pub fn push(&mut self, raw: &str, timestamp: Option<Duration>, matched: bool) -> Vec<ShownMsg> {
+        let msg = ShownMsg {
+            index: self.next_index,
+            timestamp,
+            raw: raw.to_string(),
+            matched,
+        };
+        self.next_index += 1;
+
+        if matched {
+            self.after_remaining = self.context;
+            let mut shown: Vec<ShownMsg> = self.before.drain(..).collect();
+            shown.push(msg);
+            return shown;
+        }
+        if self.after_remaining > 0 {
+            self.after_remaining -= 1;
+            return vec![msg];
+        }
+        if self.context > 0 {
+            if self.before.len() == self.context {
+                self.before.pop_front();
+            }
+            self.before.push_back(msg);
+        }
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_are_shown_with_context() {
+        let msgs = [
+            "a", "b", "c", "M", "d", "e", "f", "g", "h", "M", "i", "M", "j", "k", "l",
+        ];
+        let mut window = ContextWindow::new(2);
+        let shown: Vec<(u64, String, bool)> = msgs
+            .iter()
+            .flat_map(|raw| window.push(raw, None, *raw == "M"))
+            .map(|msg| (msg.index, msg.raw, msg.matched))
+            .collect();
+        // 'a', 'f' and 'l' are more than 2 msgs from any match, the windows
+        // around the last two matches overlap and are merged
+        let expected: Vec<(u64, String, bool)> = [
+            (1, "b", false),
+            (2, "c", false),
+            (3, "M", true),
+            (4, "d", false),
+            (5, "e", false),
+            (7, "g", false),
+            (8, "h", false),
+            (9, "M", true),
+            (10, "i", false),
+            (11, "M", true),
+            (12, "j", false),
+            (13, "k", false),
+        ]
+        .iter()
+        .map(|(index, raw, matched)| (*index, raw.to_string(), *matched))
+        .collect();
+        assert_eq!(shown, expected);
+
+        let mut window = ContextWindow::new(0);
+        assert!(window.push("a", None, false).is_empty());
+        assert_eq!(window.push("M", None, true).len(), 1);
+        assert!(window.push("b", None, false).is_empty());
+    }
+
+    #[test]
+    fn timestamps_are_rendered() {
+        let msg = ShownMsg {
+            index: 0,
+            timestamp: Some(Duration::new(1_700_000_000, 123_456_000)),
+            raw: String::new(),
+            matched: true,
+        };
+        assert_eq!(msg.timestamp_str(), "2023-11-14T22:13:20.123456Z");
+        let untimed = ShownMsg {
+            timestamp: None,
+            ..msg
+        };
+        assert_eq!(untimed.timestamp_str(), "-");
+    }
+}
@@ -0,0 +1,82 @@
+use crate::dogstatsdmsg::DogStatsDMsg;
+
+/// Bytes of 'tags' on the wire, including the commas between them
+fn tags_len(tags: &[&str]) -> usize {
+    tags.iter().map(|tag| tag.len()).sum::<usize>() + tags.len().saturating_sub(1)
+}
+
+/// Sizes are measured on the msg as it was received, so they add up to the
+/// length of `raw_msg` without trailing whitespace. Msgs that were built
+/// rather than parsed have no raw msg to measure.
+impl DogStatsDMsg<'_> {
+    /// Name of a metric or service check, title of an event
+    pub fn name_bytes(&self) -> usize {
+        match self {
+            DogStatsDMsg::Metric(m) => m.name.len(),
+            DogStatsDMsg::Event(e) => e.title.len(),
+            DogStatsDMsg::ServiceCheck(sc) => sc.name.len(),
+        }
+    }
+
+    /// Values of a metric as sent (all of them for a multi-value msg), text
+    /// of an event, status and message of a service check
+    pub fn values_bytes(&self) -> usize {
+        match self {
+            DogStatsDMsg::Metric(m) => m
+                .raw_msg
+                .get(m.name.len() + 1..)
+                .map(|rest| rest.find('|').unwrap_or(rest.len()))
+                .unwrap_or(0),
+            DogStatsDMsg::Event(e) => e.text.len(),
+            // the status is a single digit
+            DogStatsDMsg::ServiceCheck(sc) => 1 + sc.message.map_or(0, str::len),
+        }
+    }
+
+    /// Tags, without the `|#` that introduces them
+    pub fn tags_bytes(&self) -> usize {
+        match self {
+            DogStatsDMsg::Metric(m) => tags_len(&m.tags),
+            DogStatsDMsg::Event(e) => tags_len(&e.tags),
+            DogStatsDMsg::ServiceCheck(sc) => tags_len(&sc.tags),
+        }
+    }
+
+    /// Everything that isn't the name, values or tags: separators, the
+    /// metric type and the remaining optional fields
+    pub fn overhead_bytes(&self) -> usize {
+        self.raw_msg()
+            .trim_end()
+            .len()
+            .saturating_sub(self.name_bytes() + self.values_bytes() + self.tags_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(msg: &str) -> (usize, usize, usize, usize) {
+        let msg = DogStatsDMsg::new(msg).unwrap();
+        (
+            msg.name_bytes(),
+            msg.values_bytes(),
+            msg.tags_bytes(),
+            msg.overhead_bytes(),
+        )
+    }
+
+    #[test]
+    fn sizes_add_up_to_the_msg() {
+        // ':' + '|d' + '|@0.5' + '|#' + '|T123'
+        assert_eq!(
+            sizes("my.metric:1:2.5|d|@0.5|#env:prod,team:a|T123"),
+            (9, 5, 15, 15)
+        );
+        assert_eq!(sizes("my.metric:1|c\n"), (9, 1, 0, 3));
+        // '_e{5,4}:' + '|' + '|#'
+        assert_eq!(sizes("_e{5,4}:title|text|#env:prod"), (5, 4, 8, 11));
+        // '_sc|' + '|' + '|#' + '|m:'
+        assert_eq!(sizes("_sc|db.up|2|#env:prod|m:down"), (5, 5, 8, 10));
+    }
+}
@@ -0,0 +1,37 @@
+// https://github.com/google/snappy/blob/main/framing_format.txt, 4.1 Stream identifier
+// A chunk of type 0xff and length 6 holding "sNaPpY". Inputs are detected from
+// their first 8 bytes, which are enough to tell.
+const SNAPPY_MAGIC_BYTES: [u8; 10] = [0xFF, 0x06, 0x00, 0x00, 0x73, 0x4E, 0x61, 0x50, 0x70, 0x59];
+
+pub fn is_snappy(header: &[u8]) -> bool {
+    let len = header.len().min(SNAPPY_MAGIC_BYTES.len());
+    len >= 8 && header[..len] == SNAPPY_MAGIC_BYTES[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // The stream identifier, then "hello" in an uncompressed data chunk
+    const HELLO_SNAPPY_BYTES: &[u8] = &[
+        0xff, 0x06, 0x00, 0x00, 0x73, 0x4e, 0x61, 0x50, 0x70, 0x59, 0x01, 0x09, 0x00, 0x00, 0xbb,
+        0x1f, 0x1c, 0x19, 0x68, 0x65, 0x6c, 0x6c, 0x6f,
+    ];
+
+    const HELLO_BYTES: &[u8] = &[0x68, 0x65, 0x6c, 0x6c, 0x6f];
+
+    #[test]
+    fn is_snappy_compressed_data_is_detected() {
+        assert!(is_snappy(HELLO_SNAPPY_BYTES));
+        assert!(is_snappy(&HELLO_SNAPPY_BYTES[..8]));
+    }
+
+    #[test]
+    fn is_snappy_ascii_data_is_not_detected() {
+        assert!(!is_snappy(HELLO_BYTES));
+    }
+
+    #[test]
+    fn is_snappy_short_data_is_not_detected() {
+        assert!(!is_snappy(&[0xff, 0x06, 0x00, 0x00]));
+    }
+}
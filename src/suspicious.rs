@@ -0,0 +1,130 @@
+use std::{fmt::Display, time::Duration};
+
+use crate::dogstatsdmsg::DogStatsDMsg;
+
+/// Timestamps less than this far ahead of 'now' are put down to clock skew
+const FUTURE_TIMESTAMP_TOLERANCE: Duration = Duration::from_secs(60);
+
+/// Something a msg may validly contain, but that is more likely a client bug
+/// than what the sender meant
+#[derive(Debug, Clone, PartialEq)]
+pub enum Suspicion<'a> {
+    /// `@0` scales every value up infinitely
+    ZeroSampleRate,
+    /// A tag without a value, eg `env:` or an empty tag from `#a,,b`
+    EmptyTagValue(&'a str),
+    /// Usually a client adding the `#` prefix to each tag, eg `##env:prod`
+    TagStartsWithHash(&'a str),
+    /// A value of exactly u64::MAX, usually an unsigned counter that wrapped
+    MaxValue,
+    /// Unix timestamp in seconds more than a minute after 'now'
+    FutureTimestamp(u64),
+}
+
+impl Suspicion<'_> {
+    /// What looks wrong, without the specifics of this msg
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Suspicion::ZeroSampleRate => "sample rate of 0",
+            Suspicion::EmptyTagValue(_) => "empty tag value",
+            Suspicion::TagStartsWithHash(_) => "tag starts with '#'",
+            Suspicion::MaxValue => "value of u64::MAX",
+            Suspicion::FutureTimestamp(_) => "timestamp in the future",
+        }
+    }
+}
+
+impl Display for Suspicion<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Suspicion::ZeroSampleRate => f.write_str("sample rate is 0"),
+            Suspicion::EmptyTagValue(tag) => write!(f, "tag '{}' has no value", tag),
+            Suspicion::TagStartsWithHash(tag) => write!(f, "tag '{}' starts with '#'", tag),
+            Suspicion::MaxValue => write!(f, "value is u64::MAX ({})", u64::MAX),
+            Suspicion::FutureTimestamp(ts) => write!(f, "timestamp {} is in the future", ts),
+        }
+    }
+}
+
+fn check_tags<'a>(tags: &[&'a str], suspicions: &mut Vec<Suspicion<'a>>) {
+    for tag in tags {
+        if tag.is_empty() || tag.ends_with(':') {
+            suspicions.push(Suspicion::EmptyTagValue(tag));
+        }
+        if tag.starts_with('#') {
+            suspicions.push(Suspicion::TagStartsWithHash(tag));
+        }
+    }
+}
+
+fn check_timestamp(timestamp: Option<u64>, now: Duration, suspicions: &mut Vec<Suspicion>) {
+    if let Some(ts) = timestamp {
+        if Duration::from_secs(ts) > now + FUTURE_TIMESTAMP_TOLERANCE {
+            suspicions.push(Suspicion::FutureTimestamp(ts));
+        }
+    }
+}
+
+impl<'a> DogStatsDMsg<'a> {
+    /// Everything about this msg that parsed fine but looks like a client bug.
+    /// 'now' is the time since the epoch that timestamps are compared to, eg
+    /// when the msg was captured.
+    pub fn suspicions(&self, now: Duration) -> Vec<Suspicion<'a>> {
+        let mut suspicions = Vec::new();
+        match self {
+            DogStatsDMsg::Metric(m) => {
+                if m.sample_rate == Some(0.0) {
+                    suspicions.push(Suspicion::ZeroSampleRate);
+                }
+                if m.values.iter().any(|v| *v == u64::MAX as f64) {
+                    suspicions.push(Suspicion::MaxValue);
+                }
+                check_timestamp(m.timestamp, now, &mut suspicions);
+                check_tags(&m.tags, &mut suspicions);
+            }
+            DogStatsDMsg::Event(e) => {
+                check_timestamp(e.timestamp, now, &mut suspicions);
+                check_tags(&e.tags, &mut suspicions);
+            }
+            DogStatsDMsg::ServiceCheck(sc) => {
+                check_timestamp(sc.timestamp, now, &mut suspicions);
+                check_tags(&sc.tags, &mut suspicions);
+            }
+        }
+        suspicions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: Duration = Duration::from_secs(1_700_000_000);
+
+    fn suspicions(msg: &str) -> Vec<Suspicion<'_>> {
+        DogStatsDMsg::new(msg).unwrap().suspicions(NOW)
+    }
+
+    #[test]
+    fn suspicious_msgs_are_still_valid() {
+        assert!(suspicions("my.metric:1|c|@0.5|T1700000030|#env:prod").is_empty());
+        assert_eq!(
+            suspicions("my.metric:18446744073709551615|c|@0|T1700000061|#env:,#a,"),
+            vec![
+                Suspicion::ZeroSampleRate,
+                Suspicion::MaxValue,
+                Suspicion::FutureTimestamp(1_700_000_061),
+                Suspicion::EmptyTagValue("env:"),
+                Suspicion::TagStartsWithHash("#a"),
+                Suspicion::EmptyTagValue(""),
+            ]
+        );
+        assert_eq!(
+            suspicions("_sc|my.check|0|d:1800000000|#team:"),
+            vec![
+                Suspicion::FutureTimestamp(1_800_000_000),
+                Suspicion::EmptyTagValue("team:"),
+            ]
+        );
+    }
+}
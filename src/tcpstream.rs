@@ -0,0 +1,219 @@
+use std::{collections::HashMap, net::SocketAddr};
+
+use bytes::Bytes;
+use tracing::debug;
+
+use crate::pcapreader::TcpSegment;
+
+/// Segments that arrived ahead of a gap in their stream are kept until it is
+/// filled. Past this many, the missing segments are taken to be lost.
+const MAX_PENDING_SEGMENTS: usize = 64;
+
+/// A TCP connection in one direction, from the source to the destination
+type FlowKey = (SocketAddr, SocketAddr);
+
+#[derive(Default)]
+struct Flow {
+    /// Sequence number of the next byte of the stream, None until the
+    /// first segment of the flow
+    next_seq: Option<u32>,
+    /// Segments past 'next_seq', waiting for the ones before them
+    pending: Vec<(u32, Bytes)>,
+    /// Stream data after the last newline
+    partial: Vec<u8>,
+    /// The stream wasn't read from its start or lost data, its data up to
+    /// the next newline is part of a msg that can't be read
+    resync: bool,
+}
+
+impl Flow {
+    /// Appends the data of the segment at 'seq' that wasn't read yet
+    fn append(&mut self, next_seq: u32, seq: u32, data: &[u8]) -> u32 {
+        // Retransmissions overlap data that was read already
+        let already_read = next_seq.wrapping_sub(seq) as usize;
+        let Some(data) = data.get(already_read..) else {
+            return next_seq;
+        };
+        if self.resync {
+            if let Some(newline) = data.iter().position(|b| *b == b'\n') {
+                self.resync = false;
+                self.partial.extend_from_slice(&data[newline + 1..]);
+            }
+        } else {
+            self.partial.extend_from_slice(data);
+        }
+        next_seq.wrapping_add(data.len() as u32)
+    }
+}
+
+/// Follows the TCP streams of a capture and puts their segments back in
+/// order, for dogstatsd sent over TCP as newline delimited msgs.
+/// Retransmitted data is read once, segments that arrive out of order wait
+/// for the ones before them.
+///
+/// Streams whose start wasn't captured, or that lost segments, are read from
+/// the first msg that starts after the missing data.
+#[derive(Default)]
+pub struct TcpStreams {
+    flows: HashMap<FlowKey, Flow>,
+}
+
+impl TcpStreams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Streams that are open, or that were never seen closing
+    pub fn open_streams(&self) -> usize {
+        self.flows.len()
+    }
+
+    /// Reads 'segment' into its stream. Returns the msgs of the stream that
+    /// it completes, each terminated by a newline, or None if it completes
+    /// none. A stream closing completes its last msg, newline or not.
+    pub fn add_segment(&mut self, segment: &TcpSegment) -> Option<Bytes> {
+        let key = (segment.source, segment.destination);
+        if segment.rst {
+            debug!("TCP stream {:?} was reset", key);
+            self.flows.remove(&key);
+            return None;
+        }
+        let flow = self.flows.entry(key).or_default();
+        if segment.syn {
+            *flow = Flow {
+                // The syn takes up a sequence number of its own
+                next_seq: Some(segment.sequence.wrapping_add(1)),
+                ..Flow::default()
+            };
+        }
+        let mut next_seq = match flow.next_seq {
+            Some(next_seq) => next_seq,
+            None => {
+                debug!("TCP stream {:?} started before the capture", key);
+                flow.resync = true;
+                segment.sequence
+            }
+        };
+
+        if !segment.payload.is_empty() {
+            let seq = if segment.syn {
+                next_seq
+            } else {
+                segment.sequence
+            };
+            flow.pending.push((seq, segment.payload.clone()));
+        }
+        loop {
+            // Segments that start at or before the next byte can be read
+            let readable = flow
+                .pending
+                .iter()
+                .position(|(seq, _)| (next_seq.wrapping_sub(*seq) as i32) >= 0);
+            if let Some(i) = readable {
+                let (seq, data) = flow.pending.swap_remove(i);
+                next_seq = flow.append(next_seq, seq, &data);
+                continue;
+            }
+            if flow.pending.len() <= MAX_PENDING_SEGMENTS {
+                break;
+            }
+            // Give up on the missing data, carry on from the first segment
+            // after it
+            let Some((seq, _)) = flow
+                .pending
+                .iter()
+                .min_by_key(|(seq, _)| seq.wrapping_sub(next_seq))
+            else {
+                break;
+            };
+            debug!("Lost data of TCP stream {:?}, skipping ahead", key);
+            next_seq = *seq;
+            flow.partial.clear();
+            flow.resync = true;
+        }
+        flow.next_seq = Some(next_seq);
+
+        let msgs = if segment.fin {
+            let flow = self.flows.remove(&key)?;
+            flow.partial
+        } else {
+            let end = flow.partial.iter().rposition(|b| *b == b'\n')? + 1;
+            let rest = flow.partial.split_off(end);
+            std::mem::replace(&mut flow.partial, rest)
+        };
+        (!msgs.is_empty()).then(|| Bytes::from(msgs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(sequence: u32, payload: &str) -> TcpSegment {
+        TcpSegment {
+            source: "10.0.0.1:40000".parse().unwrap(),
+            destination: "10.0.0.2:8125".parse().unwrap(),
+            sequence,
+            syn: false,
+            fin: false,
+            rst: false,
+            payload: Bytes::copy_from_slice(payload.as_bytes()),
+        }
+    }
+
+    fn syn(sequence: u32) -> TcpSegment {
+        TcpSegment {
+            syn: true,
+            ..segment(sequence, "")
+        }
+    }
+
+    #[test]
+    fn streams_are_read_in_order() {
+        let mut streams = TcpStreams::new();
+        assert!(streams.add_segment(&syn(u32::MAX - 4)).is_none());
+        // the sequence numbers wrap around
+        assert!(streams
+            .add_segment(&segment(u32::MAX - 3, "a:1|c\nb:"))
+            .is_none());
+        let msgs = streams.add_segment(&segment(4, "2|c\n")).unwrap();
+        assert_eq!(msgs, "a:1|c\nb:2|c\n");
+
+        // out of order, and retransmitted
+        assert!(streams.add_segment(&segment(14, "d:4|c\n")).is_none());
+        let msgs = streams.add_segment(&segment(8, "c:3|c\n")).unwrap();
+        assert_eq!(msgs, "c:3|c\nd:4|c\n");
+        assert!(streams.add_segment(&segment(8, "c:3|c\n")).is_none());
+
+        let fin = TcpSegment {
+            fin: true,
+            ..segment(20, "e:5|c")
+        };
+        assert_eq!(streams.add_segment(&fin).unwrap(), "e:5|c");
+        assert_eq!(streams.open_streams(), 0);
+    }
+
+    #[test]
+    fn streams_without_their_start_are_read_from_the_first_complete_msg() {
+        let mut streams = TcpStreams::new();
+        let msgs = streams
+            .add_segment(&segment(1000, "|c\nb:2|c\nc:"))
+            .unwrap();
+        assert_eq!(msgs, "b:2|c\n");
+        let msgs = streams.add_segment(&segment(1011, "3|c\n")).unwrap();
+        assert_eq!(msgs, "c:3|c\n");
+
+        // a lost segment, the msg it was part of is skipped
+        let mut sequence = 1030;
+        for i in 0..=MAX_PENDING_SEGMENTS {
+            let payload = format!("{i}|c\nm:");
+            let msgs = streams.add_segment(&segment(sequence, &payload));
+            if i < MAX_PENDING_SEGMENTS {
+                assert!(msgs.is_none());
+            } else {
+                assert!(msgs.unwrap().starts_with(b"m:1|c\nm:2|c\n"));
+            }
+            sequence += payload.len() as u32;
+        }
+    }
+}
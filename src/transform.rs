@@ -0,0 +1,130 @@
+use std::borrow::Cow;
+
+use crate::dogstatsdmsg::DogStatsDMsg;
+
+/// Byte offset of 'part' within 'whole', 'part' must be a subslice of 'whole'
+fn offset_in(whole: &str, part: &str) -> usize {
+    part.as_ptr() as usize - whole.as_ptr() as usize
+}
+
+fn insert_at<'a>(raw: &'a str, idx: usize, insertion: &str) -> Cow<'a, str> {
+    let mut out = String::with_capacity(raw.len() + insertion.len());
+    out.push_str(&raw[..idx]);
+    out.push_str(insertion);
+    out.push_str(&raw[idx..]);
+    Cow::Owned(out)
+}
+
+/// Returns the raw msg with 'host' set as its host if it doesn't have one yet.
+/// Metrics get a `host:` tag, events and service checks get a `h:` field.
+/// Msgs that already identify a host are returned unchanged, an empty `h:`
+/// field is filled in where it is instead of adding a second one.
+pub fn inject_default_host<'a>(msg: &DogStatsDMsg<'a>, host: &str) -> Cow<'a, str> {
+    if msg.host().is_some() {
+        return Cow::Borrowed(msg.raw_msg());
+    }
+
+    match msg {
+        DogStatsDMsg::Metric(m) => match m.tags.last() {
+            Some(last_tag) if last_tag.is_empty() => insert_at(
+                m.raw_msg,
+                offset_in(m.raw_msg, last_tag),
+                &format!("host:{host}"),
+            ),
+            Some(last_tag) => insert_at(
+                m.raw_msg,
+                offset_in(m.raw_msg, last_tag) + last_tag.len(),
+                &format!(",host:{host}"),
+            ),
+            None => Cow::Owned(format!("{}|#host:{host}", m.raw_msg)),
+        },
+        DogStatsDMsg::Event(e) => {
+            let raw = e.raw_msg.trim_end();
+            if let Some(hostname) = e.hostname {
+                return insert_at(raw, offset_in(raw, hostname), host);
+            }
+            // optional fields can appear in any order after the title and text
+            let text_end = offset_in(raw, e.text) + e.text.len();
+            insert_at(raw, text_end, &format!("|h:{host}"))
+        }
+        DogStatsDMsg::ServiceCheck(sc) => {
+            let raw = sc.raw_msg.trim_end();
+            if let Some(hostname) = sc.hostname {
+                return insert_at(raw, offset_in(raw, hostname), host);
+            }
+            // right after the single digit status, 'm:' must remain the last field
+            let status_end = offset_in(raw, sc.name) + sc.name.len() + 2;
+            insert_at(raw, status_end, &format!("|h:{host}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inject(raw: &str) -> String {
+        let msg = DogStatsDMsg::new(raw).unwrap();
+        inject_default_host(&msg, "default-host").into_owned()
+    }
+
+    #[test]
+    fn metric_without_tags() {
+        assert_eq!(inject("my.metric:1|g"), "my.metric:1|g|#host:default-host");
+        assert_eq!(
+            inject("my.metric:1|g|@0.5"),
+            "my.metric:1|g|@0.5|#host:default-host"
+        );
+    }
+
+    #[test]
+    fn metric_with_tags() {
+        assert_eq!(
+            inject("my.metric:1|g|#env:prod|c:abcd"),
+            "my.metric:1|g|#env:prod,host:default-host|c:abcd"
+        );
+    }
+
+    #[test]
+    fn metric_with_host_is_unchanged() {
+        let msg = DogStatsDMsg::new("my.metric:1|g|#host:web-1").unwrap();
+        assert!(matches!(
+            inject_default_host(&msg, "default-host"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn event_without_hostname() {
+        assert_eq!(
+            inject("_e{5,4}:title|text|p:low|#env:prod"),
+            "_e{5,4}:title|text|h:default-host|p:low|#env:prod"
+        );
+    }
+
+    #[test]
+    fn service_check_without_hostname() {
+        assert_eq!(
+            inject("_sc|my.check|2|#env:prod|m:it broke"),
+            "_sc|my.check|2|h:default-host|#env:prod|m:it broke"
+        );
+        let injected = inject("_sc|my.check|0");
+        assert_eq!(injected, "_sc|my.check|0|h:default-host");
+        assert_eq!(
+            DogStatsDMsg::new(&injected).unwrap().host(),
+            Some("default-host")
+        );
+    }
+
+    #[test]
+    fn empty_hostname_is_filled_in() {
+        assert_eq!(
+            inject("_e{5,4}:title|text|h:|p:low"),
+            "_e{5,4}:title|text|h:default-host|p:low"
+        );
+        assert_eq!(
+            inject("_sc|my.check|2|#env:prod|h:"),
+            "_sc|my.check|2|#env:prod|h:default-host"
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    collections::VecDeque,
+    io,
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+    str::Utf8Error,
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+
+use tracing::debug;
+
+use crate::{dogstatsdreader, replay::UnixCredentials};
+
+#[derive(Error, Debug)]
+pub enum UnixDatagramDogStatsDReaderError {
+    #[error("Unix datagram socket error")]
+    Io(#[from] io::Error),
+    #[error("Invalid UTF-8 sequence found in packet")]
+    InvalidUtf8Sequence(Utf8Error),
+    #[error("Packet of {size} bytes is larger than the max msg size of {max} bytes")]
+    MsgTooLarge { size: usize, max: usize },
+}
+
+/// Reads msgs live from a unix datagram socket, the way the agent receives
+/// them on most hosts. Every packet is timestamped when it is received and,
+/// on linux, attributed to the process that sent it.
+pub struct UnixDatagramDogStatsDReader {
+    socket: UnixDatagram,
+    path: PathBuf,
+    /// One byte larger than the max msg size, so an oversized packet is
+    /// noticed even where its real size isn't reported
+    buf: Vec<u8>,
+    current_messages: VecDeque<String>,
+    /// The msg handed out by `read_msg_ref`
+    current_msg: String,
+    /// Credentials of the packet `current_messages` were read from
+    current_credentials: Option<UnixCredentials>,
+    /// Receive time of the packet `current_messages` were read from
+    current_timestamp: Option<Duration>,
+    analytics: dogstatsdreader::Analytics,
+    max_msg_size: usize,
+}
+
+impl UnixDatagramDogStatsDReader {
+    /// Binds a new socket at 'path', which must not exist yet. The socket
+    /// file is removed again when the reader is dropped.
+    pub fn bind(path: impl AsRef<Path>) -> Result<Self, UnixDatagramDogStatsDReaderError> {
+        let socket = UnixDatagram::bind(path.as_ref())?;
+        #[cfg(target_os = "linux")]
+        credentials::enable(&socket)?;
+        Ok(Self {
+            socket,
+            path: path.as_ref().to_path_buf(),
+            buf: vec![0; dogstatsdreader::DEFAULT_MAX_MSG_SIZE + 1],
+            current_messages: VecDeque::new(),
+            current_msg: String::new(),
+            current_credentials: None,
+            current_timestamp: None,
+            analytics: dogstatsdreader::Analytics::new(dogstatsdreader::Transport::UnixDatagram),
+            max_msg_size: dogstatsdreader::DEFAULT_MAX_MSG_SIZE,
+        })
+    }
+
+    /// Packets larger than 'max_msg_size' are an error rather than being read
+    pub fn with_max_msg_size(mut self, max_msg_size: usize) -> Self {
+        self.max_msg_size = max_msg_size;
+        self.buf = vec![0; max_msg_size + 1];
+        self
+    }
+
+    /// Stop reading once no packet arrived for 'idle_timeout', without one
+    /// the reader waits for packets forever
+    pub fn with_idle_timeout(
+        self,
+        idle_timeout: Duration,
+    ) -> Result<Self, UnixDatagramDogStatsDReaderError> {
+        self.socket.set_read_timeout(Some(idle_timeout))?;
+        Ok(self)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_analytics(
+        &self,
+    ) -> Result<dogstatsdreader::Analytics, UnixDatagramDogStatsDReaderError> {
+        Ok(self.analytics.clone())
+    }
+
+    /// Credentials of the process that sent the most recently read msg, only
+    /// available on linux
+    pub fn last_msg_credentials(&self) -> Option<UnixCredentials> {
+        self.current_credentials
+    }
+
+    /// When the packet of the most recently read msg was received
+    pub fn last_msg_timestamp(&self) -> Option<Duration> {
+        self.current_timestamp
+    }
+
+    /// Blocks until a msg is available. Returns 0 once the idle timeout, if
+    /// any, passes without a packet.
+    pub fn read_msg(&mut self, s: &mut String) -> Result<usize, UnixDatagramDogStatsDReaderError> {
+        loop {
+            if let Some(line) = self.current_messages.pop_front() {
+                self.analytics.total_messages += 1;
+                self.analytics.message_length.add(line.len() as f64);
+                self.analytics.record_credentials(self.current_credentials);
+                dogstatsdreader::prepend_msg(s, line);
+                return Ok(1);
+            }
+
+            let Some(size) = self.next_packet()? else {
+                return Ok(0);
+            };
+            match std::str::from_utf8(&self.buf[..size]) {
+                Ok(v) => {
+                    for line in v.lines() {
+                        self.current_messages.push_back(String::from(line));
+                    }
+                }
+                Err(e) => return Err(UnixDatagramDogStatsDReaderError::InvalidUtf8Sequence(e)),
+            }
+        }
+    }
+
+    /// Like `read_msg`, but the msg stays in the reader, the returned slice
+    /// is valid until the next read. Saves copying every msg out.
+    pub fn read_msg_ref(&mut self) -> Result<Option<&str>, UnixDatagramDogStatsDReaderError> {
+        let mut msg = std::mem::take(&mut self.current_msg);
+        msg.clear();
+        let num_read = self.read_msg(&mut msg);
+        self.current_msg = msg;
+        Ok((num_read? > 0).then_some(self.current_msg.as_str()))
+    }
+
+    /// The msg returned by the last `read_msg_ref`
+    pub(crate) fn current_msg(&self) -> &str {
+        &self.current_msg
+    }
+
+    /// Receives the next packet whole instead of msg by msg. Msgs still left
+    /// from a packet that was started with `read_msg` are skipped.
+    pub fn read_packet(
+        &mut self,
+    ) -> Result<Option<dogstatsdreader::DogStatsDPacket>, UnixDatagramDogStatsDReaderError> {
+        self.current_messages.clear();
+        Ok(self
+            .next_packet()?
+            .map(|size| dogstatsdreader::DogStatsDPacket {
+                payload: self.buf[..size].to_vec(),
+                timestamp: self.current_timestamp,
+                transport: dogstatsdreader::Transport::UnixDatagram,
+                credentials: self.current_credentials,
+                source_addr: None,
+            }))
+    }
+
+    /// Receives the next packet into `buf` and accounts for it in the
+    /// analytics, returns its size or None once the idle timeout passed
+    fn next_packet(&mut self) -> Result<Option<usize>, UnixDatagramDogStatsDReaderError> {
+        let (size, credentials) = loop {
+            match self.recv() {
+                Ok(received) => break received,
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    debug!("No packet received before the idle timeout");
+                    return Ok(None);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        if size > self.max_msg_size {
+            return Err(UnixDatagramDogStatsDReaderError::MsgTooLarge {
+                size,
+                max: self.max_msg_size,
+            });
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.analytics.record_packet(timestamp, size as u64);
+        self.current_credentials = credentials;
+        self.current_timestamp = Some(timestamp);
+        Ok(Some(size))
+    }
+
+    /// Receives a single packet into `buf`, returns its full size, which may
+    /// be larger than `buf`, and the sender's credentials
+    #[cfg(target_os = "linux")]
+    fn recv(&mut self) -> io::Result<(usize, Option<UnixCredentials>)> {
+        credentials::recv(&self.socket, &mut self.buf)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn recv(&mut self) -> io::Result<(usize, Option<UnixCredentials>)> {
+        Ok((self.socket.recv(&mut self.buf)?, None))
+    }
+}
+
+impl Drop for UnixDatagramDogStatsDReader {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            debug!("Could not remove socket {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// SO_PASSCRED makes the kernel attach the sender's credentials to every
+/// packet, std can't read ancillary data on stable so recvmsg is called directly
+#[cfg(target_os = "linux")]
+mod credentials {
+    use std::{io, os::fd::AsRawFd, os::unix::net::UnixDatagram};
+
+    use crate::replay::{decode_credentials, UnixCredentials};
+
+    /// Room for a single SCM_CREDENTIALS control message
+    const ANCILLARY_LEN: usize = 64;
+
+    pub fn enable(socket: &UnixDatagram) -> io::Result<()> {
+        let enabled: libc::c_int = 1;
+        // SAFETY: the option value points at a c_int that outlives the call
+        let res = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PASSCRED,
+                (&enabled as *const libc::c_int).cast(),
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn recv(
+        socket: &UnixDatagram,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, Option<UnixCredentials>)> {
+        let mut ancillary = [0u8; ANCILLARY_LEN];
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        // SAFETY: msghdr is plain data, all zeroes is a valid empty header
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = ancillary.as_mut_ptr().cast();
+        msg.msg_controllen = ANCILLARY_LEN as _;
+        // MSG_TRUNC returns the real size of a packet that didn't fit in buf
+        // SAFETY: msg points at buffers that outlive the call
+        let size = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, libc::MSG_TRUNC) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ancillary_len = (msg.msg_controllen as usize).min(ANCILLARY_LEN);
+        Ok((
+            size as usize,
+            decode_credentials(&ancillary[..ancillary_len]),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn reads_msgs_from_packets() {
+        let path = socket_path("dsd-uds-reader");
+        let mut reader = UnixDatagramDogStatsDReader::bind(&path)
+            .unwrap()
+            .with_idle_timeout(Duration::from_millis(200))
+            .unwrap();
+        let client = UnixDatagram::unbound().unwrap();
+        client.send_to(b"a.b:1|c\nc.d:2|g", &path).unwrap();
+        client.send_to(b"_sc|db.up|0", &path).unwrap();
+
+        let mut msgs = Vec::new();
+        let mut s = String::new();
+        while reader.read_msg(&mut s).unwrap() > 0 {
+            msgs.push(s.clone());
+            s.clear();
+        }
+        assert_eq!(msgs, ["a.b:1|c", "c.d:2|g", "_sc|db.up|0"]);
+        assert!(reader.last_msg_timestamp().is_some());
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            reader.last_msg_credentials().map(|c| c.pid),
+            Some(std::process::id() as i32)
+        );
+
+        let analytics = reader.get_analytics().unwrap();
+        assert_eq!(analytics.total_packets, 2);
+        assert_eq!(analytics.total_messages, 3);
+
+        drop(reader);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn packets_are_read_whole() {
+        let path = socket_path("dsd-uds-reader-packets");
+        let mut reader = UnixDatagramDogStatsDReader::bind(&path)
+            .unwrap()
+            .with_idle_timeout(Duration::from_millis(200))
+            .unwrap();
+        let client = UnixDatagram::unbound().unwrap();
+        client.send_to(b"a.b:1|c\nc.d:2|g", &path).unwrap();
+
+        let packet = reader.read_packet().unwrap().unwrap();
+        assert_eq!(packet.payload, b"a.b:1|c\nc.d:2|g");
+        assert!(packet.timestamp.is_some());
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            packet.credentials.map(|c| c.pid),
+            Some(std::process::id() as i32)
+        );
+        assert!(reader.read_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn oversized_packets_are_an_error() {
+        let path = socket_path("dsd-uds-reader-oversized");
+        let mut reader = UnixDatagramDogStatsDReader::bind(&path)
+            .unwrap()
+            .with_max_msg_size(4)
+            .with_idle_timeout(Duration::from_millis(200))
+            .unwrap();
+        let client = UnixDatagram::unbound().unwrap();
+        client.send_to(b"a.b:1|c", &path).unwrap();
+
+        let mut s = String::new();
+        assert!(matches!(
+            reader.read_msg(&mut s),
+            Err(UnixDatagramDogStatsDReaderError::MsgTooLarge { size: 7, max: 4 })
+        ));
+    }
+}
@@ -0,0 +1,377 @@
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind, Read},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    str::Utf8Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+
+use tracing::{debug, warn};
+
+use crate::{dogstatsdreader, replay::UnixCredentials};
+
+/// Every frame starts with its length as a little-endian u32
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum UnixStreamDogStatsDReaderError {
+    #[error("Unix stream socket error")]
+    Io(#[from] io::Error),
+    #[error("Invalid UTF-8 sequence found in payload of frame")]
+    InvalidUtf8Sequence(Utf8Error),
+}
+
+/// A single frame read from one of the connections
+struct Frame {
+    payload: Vec<u8>,
+    credentials: Option<UnixCredentials>,
+    timestamp: Duration,
+}
+
+/// Reads msgs live from a unix stream socket, the way the agent receives them
+/// on its `dogstatsd_stream_socket`. Clients hold a connection open and send
+/// length prefixed frames over it, every connection is read on its own thread
+/// so one client can't hold up the others.
+pub struct UnixStreamDogStatsDReader {
+    path: PathBuf,
+    frames: Receiver<Frame>,
+    shutdown: Arc<AtomicBool>,
+    idle_timeout: Option<Duration>,
+    current_messages: VecDeque<String>,
+    /// The msg handed out by `read_msg_ref`
+    current_msg: String,
+    /// Credentials of the connection `current_messages` were read from
+    current_credentials: Option<UnixCredentials>,
+    /// Receive time of the frame `current_messages` were read from
+    current_timestamp: Option<Duration>,
+    analytics: dogstatsdreader::Analytics,
+}
+
+impl UnixStreamDogStatsDReader {
+    /// Binds a new socket at 'path', which must not exist yet, and starts
+    /// accepting connections. The socket file is removed again when the
+    /// reader is dropped.
+    pub fn bind(path: impl AsRef<Path>) -> Result<Self, UnixStreamDogStatsDReaderError> {
+        Self::bind_with_max_msg_size(path, dogstatsdreader::DEFAULT_MAX_MSG_SIZE)
+    }
+
+    /// Like `bind`, a connection that sends a frame larger than
+    /// 'max_msg_size' is closed, as its stream can't be trusted after that
+    pub fn bind_with_max_msg_size(
+        path: impl AsRef<Path>,
+        max_msg_size: usize,
+    ) -> Result<Self, UnixStreamDogStatsDReaderError> {
+        let listener = UnixListener::bind(path.as_ref())?;
+        let (sender, frames) = mpsc::channel();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let accept_shutdown = Arc::clone(&shutdown);
+        thread::spawn(move || accept_connections(listener, sender, accept_shutdown, max_msg_size));
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            frames,
+            shutdown,
+            idle_timeout: None,
+            current_messages: VecDeque::new(),
+            current_msg: String::new(),
+            current_credentials: None,
+            current_timestamp: None,
+            analytics: dogstatsdreader::Analytics::new(dogstatsdreader::Transport::UnixStream),
+        })
+    }
+
+    /// Stop reading once no frame arrived for 'idle_timeout', without one the
+    /// reader waits for frames forever
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_analytics(
+        &self,
+    ) -> Result<dogstatsdreader::Analytics, UnixStreamDogStatsDReaderError> {
+        Ok(self.analytics.clone())
+    }
+
+    /// Credentials of the process on the other end of the connection the most
+    /// recently read msg was sent over, only available on linux
+    pub fn last_msg_credentials(&self) -> Option<UnixCredentials> {
+        self.current_credentials
+    }
+
+    /// When the frame of the most recently read msg was received
+    pub fn last_msg_timestamp(&self) -> Option<Duration> {
+        self.current_timestamp
+    }
+
+    /// Blocks until a msg is available. Returns 0 once the idle timeout, if
+    /// any, passes without a frame.
+    pub fn read_msg(&mut self, s: &mut String) -> Result<usize, UnixStreamDogStatsDReaderError> {
+        loop {
+            if let Some(line) = self.current_messages.pop_front() {
+                self.analytics.total_messages += 1;
+                self.analytics.message_length.add(line.len() as f64);
+                self.analytics.record_credentials(self.current_credentials);
+                dogstatsdreader::prepend_msg(s, line);
+                return Ok(1);
+            }
+
+            let Some(frame) = self.next_frame() else {
+                return Ok(0);
+            };
+            let payload = std::str::from_utf8(&frame.payload)
+                .map_err(UnixStreamDogStatsDReaderError::InvalidUtf8Sequence)?;
+            self.current_messages.extend(
+                payload
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(String::from),
+            );
+        }
+    }
+
+    /// Receives the next frame whole instead of msg by msg, without its
+    /// length prefix. Msgs still left from a frame that was started with
+    /// `read_msg` are skipped.
+    pub fn read_packet(&mut self) -> Option<dogstatsdreader::DogStatsDPacket> {
+        self.current_messages.clear();
+        self.next_frame()
+            .map(|frame| dogstatsdreader::DogStatsDPacket {
+                payload: frame.payload,
+                timestamp: Some(frame.timestamp),
+                transport: dogstatsdreader::Transport::UnixStream,
+                credentials: frame.credentials,
+                source_addr: None,
+            })
+    }
+
+    /// Waits for the next frame of any connection and accounts for it in the
+    /// analytics, None once the idle timeout passed
+    fn next_frame(&mut self) -> Option<Frame> {
+        let frame = match self.idle_timeout {
+            Some(idle_timeout) => match self.frames.recv_timeout(idle_timeout) {
+                Ok(frame) => frame,
+                Err(RecvTimeoutError::Timeout) => {
+                    debug!("No frame received before the idle timeout");
+                    return None;
+                }
+                Err(RecvTimeoutError::Disconnected) => return None,
+            },
+            None => self.frames.recv().ok()?,
+        };
+        self.analytics
+            .record_packet(frame.timestamp, frame.payload.len() as u64);
+        self.current_credentials = frame.credentials;
+        self.current_timestamp = Some(frame.timestamp);
+        Some(frame)
+    }
+
+    /// Like `read_msg`, but the msg stays in the reader, the returned slice
+    /// is valid until the next read. Saves copying every msg out.
+    pub fn read_msg_ref(&mut self) -> Result<Option<&str>, UnixStreamDogStatsDReaderError> {
+        let mut msg = std::mem::take(&mut self.current_msg);
+        msg.clear();
+        let num_read = self.read_msg(&mut msg);
+        self.current_msg = msg;
+        Ok((num_read? > 0).then_some(self.current_msg.as_str()))
+    }
+
+    /// The msg returned by the last `read_msg_ref`
+    pub(crate) fn current_msg(&self) -> &str {
+        &self.current_msg
+    }
+}
+
+impl Drop for UnixStreamDogStatsDReader {
+    fn drop(&mut self) {
+        // Wake the accept thread up so it sees the shutdown and exits, the
+        // connection threads exit on their next frame
+        self.shutdown.store(true, Ordering::Relaxed);
+        let _ = UnixStream::connect(&self.path);
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            debug!("Could not remove socket {}: {e}", self.path.display());
+        }
+    }
+}
+
+fn accept_connections(
+    listener: UnixListener,
+    sender: Sender<Frame>,
+    shutdown: Arc<AtomicBool>,
+    max_msg_size: usize,
+) {
+    for conn in listener.incoming() {
+        if shutdown.load(Ordering::Relaxed) {
+            return;
+        }
+        match conn {
+            Ok(conn) => {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if let Err(e) = read_frames(conn, sender, max_msg_size) {
+                        warn!("Closing unix stream connection: {e}");
+                    }
+                });
+            }
+            Err(e) => warn!("Could not accept unix stream connection: {e}"),
+        }
+    }
+}
+
+/// Sends every frame read from 'conn' until the client closes it or the
+/// reader is dropped
+fn read_frames(mut conn: UnixStream, sender: Sender<Frame>, max_msg_size: usize) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    let credentials = peer_credentials(&conn)?;
+    #[cfg(not(target_os = "linux"))]
+    let credentials = None;
+    loop {
+        let mut prefix = [0; LENGTH_PREFIX_SIZE];
+        match conn.read_exact(&mut prefix) {
+            Ok(()) => {}
+            // The client closed the connection between frames
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        let len = u32::from_le_bytes(prefix) as usize;
+        if len > max_msg_size {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "frame of {len} bytes is larger than the max msg size of {max_msg_size} bytes"
+                ),
+            ));
+        }
+        let mut payload = vec![0; len];
+        conn.read_exact(&mut payload)?;
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let frame = Frame {
+            payload,
+            credentials,
+            timestamp,
+        };
+        if sender.send(frame).is_err() {
+            // The reader is gone
+            return Ok(());
+        }
+    }
+}
+
+/// Credentials of the process that connected, as recorded by the kernel when
+/// the connection was made
+#[cfg(target_os = "linux")]
+fn peer_credentials(conn: &UnixStream) -> io::Result<Option<UnixCredentials>> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: ucred is plain data, all zeroes is valid
+    let mut ucred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: ucred and len outlive the call, len holds the size of ucred
+    let res = unsafe {
+        libc::getsockopt(
+            conn.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut ucred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some(UnixCredentials {
+        pid: ucred.pid,
+        uid: ucred.uid,
+        gid: ucred.gid,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn framed(payloads: &[&str]) -> Vec<u8> {
+        let mut stream = Vec::new();
+        for payload in payloads {
+            stream.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            stream.extend_from_slice(payload.as_bytes());
+        }
+        stream
+    }
+
+    #[test]
+    fn reads_msgs_from_frames() {
+        let path = socket_path("dsd-uds-stream-reader");
+        let mut reader = UnixStreamDogStatsDReader::bind(&path)
+            .unwrap()
+            .with_idle_timeout(Duration::from_millis(200));
+        let mut conn = UnixStream::connect(&path).unwrap();
+        conn.write_all(&framed(&["a.b:1|c\nc.d:2|g\n"])).unwrap();
+        // A frame split across writes is still read whole
+        conn.write_all(&11u32.to_le_bytes()).unwrap();
+        conn.write_all(b"_sc|db").unwrap();
+        conn.flush().unwrap();
+        conn.write_all(b".up|0").unwrap();
+
+        let mut msgs = Vec::new();
+        let mut s = String::new();
+        while reader.read_msg(&mut s).unwrap() > 0 {
+            msgs.push(s.clone());
+            s.clear();
+        }
+        assert_eq!(msgs, ["a.b:1|c", "c.d:2|g", "_sc|db.up|0"]);
+        assert!(reader.last_msg_timestamp().is_some());
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            reader.last_msg_credentials().map(|c| c.pid),
+            Some(std::process::id() as i32)
+        );
+
+        let analytics = reader.get_analytics().unwrap();
+        assert_eq!(analytics.total_packets, 2);
+        assert_eq!(analytics.total_messages, 3);
+
+        drop(reader);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn oversized_frames_close_only_their_connection() {
+        let path = socket_path("dsd-uds-stream-reader-oversized");
+        let mut reader = UnixStreamDogStatsDReader::bind_with_max_msg_size(&path, 8)
+            .unwrap()
+            .with_idle_timeout(Duration::from_millis(200));
+        let mut bad = UnixStream::connect(&path).unwrap();
+        bad.write_all(&framed(&["a.b:1|c|#too:long", "a.b:2|c"]))
+            .unwrap();
+        let mut good = UnixStream::connect(&path).unwrap();
+        good.write_all(&framed(&["c.d:1|c"])).unwrap();
+
+        let mut s = String::new();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 1);
+        assert_eq!(s, "c.d:1|c");
+        s.clear();
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 0);
+    }
+}
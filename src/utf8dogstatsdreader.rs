@@ -1,7 +1,13 @@
-use std::io::BufRead;
+use std::io::{BufRead, Read};
+
+use crate::dogstatsdreader::DEFAULT_MAX_MSG_SIZE;
+
 pub struct Utf8DogStatsDReader<'a>
 {
     reader: Box<dyn std::io::BufRead + 'a>,
+    max_msg_size: usize,
+    /// The msg handed out by `read_msg_ref`, reused across reads
+    current_msg: String,
 }
 
 impl<'a> Utf8DogStatsDReader<'a>
@@ -9,23 +15,62 @@ impl<'a> Utf8DogStatsDReader<'a>
     pub fn new(reader: impl BufRead + 'a) -> Self {
         Utf8DogStatsDReader {
             reader: Box::new(reader),
+            max_msg_size: DEFAULT_MAX_MSG_SIZE,
+            current_msg: String::new(),
         }
     }
 
+    /// Lines longer than 'max_msg_size' are an error rather than being read
+    /// into memory in full
+    pub fn with_max_msg_size(mut self, max_msg_size: usize) -> Self {
+        self.max_msg_size = max_msg_size;
+        self
+    }
+
     pub fn read_msg(&mut self, s: &mut String) -> std::io::Result<usize> {
-        self.reader.read_line(s).map(|num_read| {
-            if num_read == 0 {
-                return num_read;
-            }
+        let start = s.len();
+        // Room for a CRLF line ending, past that the line is too long
+        let limit = self.max_msg_size + 2;
+        let num_read = (&mut self.reader).take(limit as u64).read_line(s)?;
+        if num_read == 0 {
+            return Ok(num_read);
+        }
+        // Trailing spaces can make a line that was cut off look short enough
+        let cut_off = num_read == limit && !s.ends_with('\n');
+
+        let new_len = s.trim_end().len();
+        s.truncate(new_len);
+        if cut_off || new_len.saturating_sub(start) > self.max_msg_size {
+            // The rest of the line is still to be read, none of it is a msg
+            s.truncate(start);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Msg is larger than the max msg size of {} bytes",
+                    self.max_msg_size
+                ),
+            ));
+        }
+        if new_len == 0 {
+            return Ok(0);
+        }
 
-            let new_len = s.trim_end().len();
-            s.truncate(new_len);
-            if new_len == 0 {
-                return 0;
-            }
+        Ok(1)
+    }
 
-            1
-        })
+    /// Like `read_msg`, but the msg stays in the reader, the returned slice
+    /// is valid until the next read. Saves copying every msg out.
+    pub fn read_msg_ref(&mut self) -> Result<Option<&str>, std::io::Error> {
+        let mut msg = std::mem::take(&mut self.current_msg);
+        msg.clear();
+        let num_read = self.read_msg(&mut msg);
+        self.current_msg = msg;
+        Ok((num_read? > 0).then_some(self.current_msg.as_str()))
+    }
+
+    /// The msg returned by the last `read_msg_ref`
+    pub(crate) fn current_msg(&self) -> &str {
+        &self.current_msg
     }
 }
 
@@ -133,6 +178,37 @@ mod tests {
         assert_eq!(reader.read_msg(&mut s).unwrap(), 0);
     }
 
+    #[test]
+    fn utf8_reader_max_msg_size() {
+        let payload = b"my.metric:1|g\nmy.metric:10|g\n";
+        let mut reader = Utf8DogStatsDReader::new(&payload[..]).with_max_msg_size(13);
+        let mut s = String::new();
+
+        // exactly at the limit is fine
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 1);
+        assert_eq!(s.as_str(), "my.metric:1|g");
+        s.clear();
+
+        // one byte over is an error, not a truncated msg
+        let err = reader.read_msg(&mut s).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn utf8_reader_max_msg_size_with_crlf() {
+        let payload = b"my.metric:1|g\r\nmy.metric:10|g\r\n";
+        let mut reader = Utf8DogStatsDReader::new(&payload[..]).with_max_msg_size(13);
+        let mut s = String::new();
+
+        assert_eq!(reader.read_msg(&mut s).unwrap(), 1);
+        assert_eq!(s.as_str(), "my.metric:1|g");
+        s.clear();
+
+        let err = reader.read_msg(&mut s).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
     #[test]
     fn utf8_reader_example() {
         // Given 2 msgs with a trailing newline
@@ -0,0 +1,224 @@
+use std::fmt::Display;
+
+use crate::dogstatsdmsg::DogStatsDMsg;
+
+/// Limits a msg is checked against, the defaults are the documented Datadog
+/// limits for metric names and tags
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationRules {
+    /// Longest metric or service check name, in bytes
+    pub max_name_length: usize,
+    pub max_tags: usize,
+    /// Longest single tag, key and value included, in bytes
+    pub max_tag_length: usize,
+    /// Whether tags and set values may contain non-ascii characters
+    pub allow_unicode: bool,
+}
+
+impl Default for ValidationRules {
+    fn default() -> Self {
+        Self {
+            max_name_length: 200,
+            max_tags: 100,
+            max_tag_length: 200,
+            allow_unicode: true,
+        }
+    }
+}
+
+/// A single way a msg breaks the `ValidationRules`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    NameTooLong {
+        length: usize,
+        max: usize,
+    },
+    /// Names must start with a letter
+    NameInvalidStart(char),
+    /// Names may only contain ascii alphanumerics, underscores and periods
+    NameInvalidChar(char),
+    TooManyTags {
+        count: usize,
+        max: usize,
+    },
+    TagTooLong {
+        tag: String,
+        max: usize,
+    },
+    UnicodeTag(String),
+    UnicodeSetValue(String),
+}
+
+impl Violation {
+    /// The rule that was broken, without the specifics of this msg
+    pub fn rule(&self) -> &'static str {
+        match self {
+            Violation::NameTooLong { .. } => "name too long",
+            Violation::NameInvalidStart(_) => "name doesn't start with a letter",
+            Violation::NameInvalidChar(_) => "invalid character in name",
+            Violation::TooManyTags { .. } => "too many tags",
+            Violation::TagTooLong { .. } => "tag too long",
+            Violation::UnicodeTag(_) => "non-ascii tag",
+            Violation::UnicodeSetValue(_) => "non-ascii set value",
+        }
+    }
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::NameTooLong { length, max } => {
+                write!(f, "name is {} bytes, the limit is {}", length, max)
+            }
+            Violation::NameInvalidStart(c) => {
+                write!(f, "name starts with '{}' instead of a letter", c)
+            }
+            Violation::NameInvalidChar(c) => write!(f, "name contains invalid character '{}'", c),
+            Violation::TooManyTags { count, max } => {
+                write!(f, "{} tags, the limit is {}", count, max)
+            }
+            Violation::TagTooLong { tag, max } => {
+                write!(
+                    f,
+                    "tag '{}' is {} bytes, the limit is {}",
+                    tag,
+                    tag.len(),
+                    max
+                )
+            }
+            Violation::UnicodeTag(tag) => write!(f, "tag '{}' is not ascii", tag),
+            Violation::UnicodeSetValue(value) => write!(f, "set value '{}' is not ascii", value),
+        }
+    }
+}
+
+fn validate_name(name: &str, rules: &ValidationRules, violations: &mut Vec<Violation>) {
+    if name.len() > rules.max_name_length {
+        violations.push(Violation::NameTooLong {
+            length: name.len(),
+            max: rules.max_name_length,
+        });
+    }
+    if let Some(first) = name.chars().next() {
+        if !first.is_ascii_alphabetic() {
+            violations.push(Violation::NameInvalidStart(first));
+        }
+    }
+    // Only the first invalid character, a bad name usually repeats it
+    if let Some(invalid) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '_' || *c == '.'))
+    {
+        violations.push(Violation::NameInvalidChar(invalid));
+    }
+}
+
+fn validate_tags(tags: &[&str], rules: &ValidationRules, violations: &mut Vec<Violation>) {
+    if tags.len() > rules.max_tags {
+        violations.push(Violation::TooManyTags {
+            count: tags.len(),
+            max: rules.max_tags,
+        });
+    }
+    for tag in tags {
+        if tag.len() > rules.max_tag_length {
+            violations.push(Violation::TagTooLong {
+                tag: tag.to_string(),
+                max: rules.max_tag_length,
+            });
+        }
+        if !rules.allow_unicode && !tag.is_ascii() {
+            violations.push(Violation::UnicodeTag(tag.to_string()));
+        }
+    }
+}
+
+impl DogStatsDMsg<'_> {
+    /// Every way this msg breaks 'rules', empty when the msg is valid
+    pub fn validate(&self, rules: &ValidationRules) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        match self {
+            DogStatsDMsg::Metric(m) => {
+                validate_name(m.name, rules, &mut violations);
+                if let Some(set_value) = m.set_value {
+                    if !rules.allow_unicode && !set_value.is_ascii() {
+                        violations.push(Violation::UnicodeSetValue(set_value.to_string()));
+                    }
+                }
+                validate_tags(&m.tags, rules, &mut violations);
+            }
+            DogStatsDMsg::Event(e) => validate_tags(&e.tags, rules, &mut violations),
+            DogStatsDMsg::ServiceCheck(sc) => {
+                validate_name(sc.name, rules, &mut violations);
+                validate_tags(&sc.tags, rules, &mut violations);
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(msg: &str, rules: &ValidationRules) -> Vec<Violation> {
+        DogStatsDMsg::new(msg).unwrap().validate(rules)
+    }
+
+    #[test]
+    fn valid_msgs_have_no_violations() {
+        let rules = ValidationRules::default();
+        assert!(validate("my.metric_name:1|c|#env:prod", &rules).is_empty());
+        assert!(validate("_sc|my.check|0|#env:prod", &rules).is_empty());
+        assert!(validate("_e{5,4}:Title|text|#env:prod", &rules).is_empty());
+    }
+
+    #[test]
+    fn names_are_checked() {
+        let rules = ValidationRules {
+            max_name_length: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            validate("1metric-name:1|c", &rules),
+            vec![
+                Violation::NameTooLong {
+                    length: 12,
+                    max: 10
+                },
+                Violation::NameInvalidStart('1'),
+                Violation::NameInvalidChar('-'),
+            ]
+        );
+        assert_eq!(
+            validate("_sc|check/up|0", &rules),
+            vec![Violation::NameInvalidChar('/')]
+        );
+    }
+
+    #[test]
+    fn tags_are_checked() {
+        let rules = ValidationRules {
+            max_tags: 2,
+            max_tag_length: 8,
+            allow_unicode: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            validate("a:1|c|#env:prod,team:core,ünï", &rules),
+            vec![
+                Violation::TooManyTags { count: 3, max: 2 },
+                Violation::TagTooLong {
+                    tag: "team:core".to_string(),
+                    max: 8
+                },
+                Violation::UnicodeTag("ünï".to_string()),
+            ]
+        );
+        assert_eq!(
+            validate("a:ü|s", &rules),
+            vec![Violation::UnicodeSetValue("ü".to_string())]
+        );
+        assert!(validate("a:ü|s|#ünï", &ValidationRules::default()).is_empty());
+    }
+}
@@ -0,0 +1,164 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    dogstatsdmsg::{DogStatsDMsg, DogStatsDMsgError, DogStatsDMsgWarning},
+    dogstatsdreader::{DogStatsDReader, DogStatsDReaderError},
+};
+
+/// Everything known about a msg besides its parsed fields
+pub struct MsgMeta<'a> {
+    /// The msg as it was read
+    pub raw: &'a str,
+    /// Malformed fields that were skipped to parse the msg
+    pub warnings: &'a [DogStatsDMsgWarning<'a>],
+    /// Time spent reading the msg, includes any decompression
+    pub read_duration: Duration,
+    pub parse_duration: Duration,
+    /// When the packet the msg arrived in was captured, for replay and pcap inputs
+    pub timestamp: Option<Duration>,
+}
+
+/// A per-msg analysis. Several can be run over a reader in a single pass
+/// with `visit_msgs`.
+pub trait MsgVisitor {
+    fn visit(&mut self, msg: &DogStatsDMsg, meta: &MsgMeta);
+
+    /// Called for msgs that can't be parsed, even leniently
+    fn visit_unparseable(&mut self, _error: &DogStatsDMsgError, _meta: &MsgMeta) {}
+}
+
+/// How `visit_msgs_with_mode` parses msgs
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Malformed optional fields are skipped and passed on as warnings,
+    /// see `DogStatsDMsg::new_lenient`
+    #[default]
+    Lenient,
+    /// Malformed fields and separators make the msg unparseable, see
+    /// `DogStatsDMsg::new_strict`
+    Strict,
+}
+
+/// Reads every msg from 'reader', parses it leniently and hands it to each
+/// of 'visitors' in order. Returns the number of msgs read, or the first read
+/// error, the visitors have seen the msgs before it then.
+pub fn visit_msgs(
+    reader: &mut DogStatsDReader,
+    visitors: &mut [&mut dyn MsgVisitor],
+) -> Result<u64, DogStatsDReaderError> {
+    visit_msgs_with_mode(reader, visitors, ParseMode::Lenient)
+}
+
+/// Like `visit_msgs`, but msgs are parsed according to 'mode'
+pub fn visit_msgs_with_mode(
+    reader: &mut DogStatsDReader,
+    visitors: &mut [&mut dyn MsgVisitor],
+    mode: ParseMode,
+) -> Result<u64, DogStatsDReaderError> {
+    let mut line = String::new();
+    let mut num_msgs = 0;
+    loop {
+        line.clear();
+        let read_start = Instant::now();
+        let num_read = reader.read_msg(&mut line)?;
+        let read_duration = read_start.elapsed();
+        if num_read == 0 {
+            break;
+        }
+        num_msgs += 1;
+
+        let parse_start = Instant::now();
+        let parsed = match mode {
+            ParseMode::Lenient => DogStatsDMsg::new_lenient(&line),
+            ParseMode::Strict => DogStatsDMsg::new_strict(&line).map(|msg| (msg, Vec::new())),
+        };
+        let parse_duration = parse_start.elapsed();
+        let (parsed, warnings) = match parsed {
+            Ok((msg, warnings)) => (Ok(msg), warnings),
+            Err(e) => (Err(e), Vec::new()),
+        };
+        let meta = MsgMeta {
+            raw: &line,
+            warnings: &warnings,
+            read_duration,
+            parse_duration,
+            timestamp: reader.last_msg_timestamp(),
+        };
+        for visitor in visitors.iter_mut() {
+            match parsed {
+                Ok(ref msg) => visitor.visit(msg, &meta),
+                Err(ref e) => visitor.visit_unparseable(e, &meta),
+            }
+        }
+    }
+    Ok(num_msgs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dogstatsdreader::DogStatsDReaderOptions;
+
+    #[derive(Default)]
+    struct KindCounter {
+        parsed: Vec<String>,
+        unparseable: u32,
+        warnings: usize,
+    }
+
+    impl MsgVisitor for KindCounter {
+        fn visit(&mut self, msg: &DogStatsDMsg, meta: &MsgMeta) {
+            self.parsed.push(msg.kind().to_string());
+            self.warnings += meta.warnings.len();
+        }
+
+        fn visit_unparseable(&mut self, _error: &DogStatsDMsgError, _meta: &MsgMeta) {
+            self.unparseable += 1;
+        }
+    }
+
+    #[test]
+    fn every_visitor_sees_every_msg() {
+        let payload = b"my.metric:1|g|@x\n_sc|my.check|0\nnot a msg\n_e{1,1}:a|b\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let mut first = KindCounter::default();
+        let mut second = KindCounter::default();
+        let num_msgs = visit_msgs(&mut reader, &mut [&mut first, &mut second]).unwrap();
+
+        assert_eq!(num_msgs, 4);
+        for counter in [first, second] {
+            assert_eq!(counter.parsed, vec!["Metric", "ServiceCheck", "Event"]);
+            assert_eq!(counter.unparseable, 1);
+            assert_eq!(counter.warnings, 1);
+        }
+    }
+
+    #[test]
+    fn strict_mode_rejects_what_lenient_skips() {
+        let payload = b"my.metric:1|g|@x\nmy.metric:1|g||#a\n_sc|my.check|0\n";
+        let mut reader = DogStatsDReader::new(&payload[..]).unwrap();
+        let mut counter = KindCounter::default();
+        visit_msgs_with_mode(&mut reader, &mut [&mut counter], ParseMode::Strict).unwrap();
+
+        assert_eq!(counter.parsed, vec!["ServiceCheck"]);
+        assert_eq!(counter.unparseable, 2);
+        assert_eq!(counter.warnings, 0);
+    }
+
+    #[test]
+    fn read_errors_are_returned() {
+        let options = DogStatsDReaderOptions {
+            max_msg_size: 64,
+            ..Default::default()
+        };
+        let payload = format!("my.metric:1|g\n{}:1|g\n_sc|my.check|0\n", "a".repeat(64));
+        let mut reader = DogStatsDReader::new_with_options(payload.as_bytes(), &options).unwrap();
+        let mut counter = KindCounter::default();
+
+        assert!(matches!(
+            visit_msgs(&mut reader, &mut [&mut counter]),
+            Err(DogStatsDReaderError::Io(_))
+        ));
+        assert_eq!(counter.parsed, vec!["Metric"]);
+    }
+}
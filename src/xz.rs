@@ -0,0 +1,36 @@
+// https://tukaani.org/xz/xz-file-format.txt, 2.1.1.1 Header Magic Bytes
+const XZ_MAGIC_BYTES: [u8; 6] = [0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00];
+
+pub fn is_xz(header: &[u8]) -> bool {
+    header.len() >= XZ_MAGIC_BYTES.len() && header[0..6] == XZ_MAGIC_BYTES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // echo -n "hello" | xz | xxd -i
+    const HELLO_XZ_BYTES: &[u8] = &[
+        0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00, 0x00, 0x04, 0xe6, 0xd6, 0xb4, 0x46, 0x04, 0xc0, 0x09,
+        0x05, 0x21, 0x01, 0x16, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xbf, 0x79,
+        0x25, 0x67, 0x01, 0x00, 0x04, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x00, 0x00, 0x00, 0x00, 0xb1,
+        0x37, 0xb9, 0xdb, 0xe5, 0xda, 0x1e, 0x9b, 0x00, 0x01, 0x25, 0x05, 0x43, 0x91, 0x1f, 0xb8,
+        0x1f, 0xb6, 0xf3, 0x7d, 0x01, 0x00, 0x00, 0x00, 0x00, 0x04, 0x59, 0x5a,
+    ];
+
+    const HELLO_BYTES: &[u8] = &[0x68, 0x65, 0x6c, 0x6c, 0x6f];
+
+    #[test]
+    fn is_xz_compressed_data_is_detected() {
+        assert!(is_xz(HELLO_XZ_BYTES));
+    }
+
+    #[test]
+    fn is_xz_ascii_data_is_not_detected() {
+        assert!(!is_xz(HELLO_BYTES));
+    }
+
+    #[test]
+    fn is_xz_short_data_is_not_detected() {
+        assert!(!is_xz(&[0xfd, 0x37]));
+    }
+}